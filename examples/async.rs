@@ -37,7 +37,12 @@ async fn main() -> Result<(), Box<dyn Error>> {
                     UnifiedResponse::Tpv(t) => debug!("Tpv {t:?}"),
                     UnifiedResponse::Sky(s) => debug!("Sky {s:?}"),
                     UnifiedResponse::Pps(p) => debug!("PPS {p:?}"),
+                    UnifiedResponse::Toff(t) => debug!("TOFF {t:?}"),
                     UnifiedResponse::Gst(g) => debug!("GST {g:?}"),
+                    UnifiedResponse::Unknown { class, .. } => debug!("Unknown class {class}"),
+                    // Reachable only with some message classes enabled.
+                    #[allow(unreachable_patterns)]
+                    other => debug!("{other:?}"),
                 },
                 Err(e) => {
                     error!("Error decoding: {e}");
@@ -21,9 +21,9 @@ where
             ResponseData::Device(d) => {
                 debug!(
                     "DEVICE {} {} {}",
-                    d.path.unwrap_or("".to_string()),
+                    d.path.unwrap_or_default(),
                     d.driver.unwrap_or("".to_string()),
-                    d.activated.unwrap_or("".to_string()),
+                    d.activated.unwrap_or_default(),
                 );
             }
             ResponseData::Tpv(t) => {
@@ -61,15 +61,24 @@ where
                     p.device, p.real_sec, p.real_nsec, p.clock_sec, p.clock_nsec, p.precision,
                 );
             }
+            ResponseData::Toff(t) => {
+                println!(
+                    "TOFF {} real: {} s {} ns clock: {} s {} ns",
+                    t.device, t.real_sec, t.real_nsec, t.clock_sec, t.clock_nsec,
+                );
+            }
             ResponseData::Gst(g) => {
                 println!(
                     "GST {} time: {} rms: {} major: {} m minor: {} m orient: {}° lat: {} m lon: {} m alt: {} m",
-                    g.device.unwrap_or("".to_string()), g.time.unwrap_or("".to_string()),
+                    g.device.unwrap_or_default(), g.time.map(|t| t.to_string()).unwrap_or_default(),
                     g.rms.unwrap_or(0.), g.major.unwrap_or(0.),
                     g.minor.unwrap_or(0.), g.orient.unwrap_or(0.),
                     g.lat.unwrap_or(0.), g.lon.unwrap_or(0.), g.alt.unwrap_or(0.),
                 );
             }
+            // Reachable only with some message classes enabled.
+            #[allow(unreachable_patterns)]
+            other => debug!("{other:?}"),
         }
     }
 }
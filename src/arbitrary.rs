@@ -0,0 +1,327 @@
+//! Implementations of `arbitrary::Arbitrary` generating realistic
+//! messages for property tests and fuzzing.
+//!
+//! The values are of the ranges `gpsd` reports, e.g. latitudes within
+//! ±90° and timestamps in this century, with optional fields left out
+//! at random. They stay the same when serialized to JSON and parsed
+//! again.
+
+use crate::{
+    gnss_to_prn, Gst, Health, Iso8601Timestamp, Mode, Pps, Quality, Satellite, Sky, Status, Toff,
+    Tpv, UnifiedResponse,
+};
+use ::arbitrary::{Arbitrary, Result, Unstructured};
+use compact_str::CompactString;
+use std::ops::RangeInclusive;
+use std::time::{Duration, UNIX_EPOCH};
+
+/// `Some` arbitrary value of `T` in three out of four cases.
+fn optional<'a, T: Arbitrary<'a>>(u: &mut Unstructured<'a>) -> Result<Option<T>> {
+    Ok(if u.ratio(3, 4)? {
+        Some(u.arbitrary()?)
+    } else {
+        None
+    })
+}
+
+/// Number in `range`, rounded to `decimals` decimals.
+fn float(u: &mut Unstructured, range: RangeInclusive<f64>, decimals: i32) -> Result<f64> {
+    let fraction = f64::from(u.arbitrary::<u32>()?) / f64::from(u32::MAX);
+    let scale = 10f64.powi(decimals);
+    Ok(((range.start() + (range.end() - range.start()) * fraction) * scale).round() / scale)
+}
+
+/// `Some` number in `range` in `numerator` out of `denominator` cases.
+fn float_ratio(
+    u: &mut Unstructured,
+    numerator: u8,
+    denominator: u8,
+    range: RangeInclusive<f64>,
+    decimals: i32,
+) -> Result<Option<f64>> {
+    Ok(if u.ratio(numerator, denominator)? {
+        Some(float(u, range, decimals)?)
+    } else {
+        None
+    })
+}
+
+fn optional_float(
+    u: &mut Unstructured,
+    range: RangeInclusive<f64>,
+    decimals: i32,
+) -> Result<Option<f32>> {
+    Ok(float_ratio(u, 3, 4, range, decimals)?.map(|v| v as f32))
+}
+
+fn enumerated<T: From<u8>>(u: &mut Unstructured, max: u8) -> Result<T> {
+    Ok(T::from(u.int_in_range(0..=max)?))
+}
+
+/// Path of a device.
+fn device(u: &mut Unstructured) -> Result<CompactString> {
+    const DEVICES: &[&str] = &["/dev/ttyS0", "/dev/ttyUSB0", "/dev/ttyACM0", "/dev/pps0"];
+    Ok((*u.choose(DEVICES)?).into())
+}
+
+fn optional_device(u: &mut Unstructured) -> Result<Option<CompactString>> {
+    Ok(if u.ratio(3, 4)? {
+        Some(device(u)?)
+    } else {
+        None
+    })
+}
+
+impl<'a> Arbitrary<'a> for Mode {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        enumerated(u, 3)
+    }
+}
+
+impl<'a> Arbitrary<'a> for Status {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        enumerated(u, 9)
+    }
+}
+
+impl<'a> Arbitrary<'a> for Quality {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        enumerated(u, 7)
+    }
+}
+
+impl<'a> Arbitrary<'a> for Health {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        enumerated(u, 2)
+    }
+}
+
+impl<'a> Arbitrary<'a> for Iso8601Timestamp {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        // 2000-01-01 to 2099-12-31
+        let millis = u.int_in_range(946_684_800_000..=4_102_444_799_999)?;
+        Ok(Iso8601Timestamp::from_system_time(
+            UNIX_EPOCH + Duration::from_millis(millis),
+        ))
+    }
+}
+
+impl<'a> Arbitrary<'a> for Tpv {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let mode = u.arbitrary()?;
+        let fix = matches!(mode, Mode::Fix2d | Mode::Fix3d);
+        let fix3d = mode == Mode::Fix3d;
+        let alt_hae = optional_float(u, -100.0..=9000.0, 3)?.filter(|_| fix3d);
+        let geoid_sep = optional_float(u, -110.0..=90.0, 3)?.filter(|_| fix3d);
+        Ok(Tpv {
+            device: optional_device(u)?,
+            status: optional(u)?,
+            mode,
+            time: optional(u)?,
+            ept: optional_float(u, 0.0..=1.0, 3)?,
+            leapseconds: if u.ratio(1, 2)? {
+                Some(u.int_in_range(13..=19)?)
+            } else {
+                None
+            },
+            alt_msl: alt_hae.zip(geoid_sep).map(|(hae, sep)| hae - sep),
+            alt_hae,
+            geoid_sep,
+            lat: float_ratio(u, 3, 4, -90.0..=90.0, 9)?.filter(|_| fix),
+            lon: float_ratio(u, 3, 4, -180.0..=180.0, 9)?.filter(|_| fix),
+            alt: optional_float(u, -100.0..=9000.0, 3)?.filter(|_| fix3d),
+            epx: optional_float(u, 0.0..=100.0, 3)?.filter(|_| fix),
+            epy: optional_float(u, 0.0..=100.0, 3)?.filter(|_| fix),
+            epv: optional_float(u, 0.0..=200.0, 3)?.filter(|_| fix3d),
+            track: optional_float(u, 0.0..=359.9999, 4)?.filter(|_| fix),
+            speed: optional_float(u, 0.0..=100.0, 3)?.filter(|_| fix),
+            climb: optional_float(u, -20.0..=20.0, 3)?.filter(|_| fix3d),
+            epd: optional_float(u, 0.0..=180.0, 4)?.filter(|_| fix),
+            eps: optional_float(u, 0.0..=50.0, 2)?.filter(|_| fix),
+            epc: optional_float(u, 0.0..=50.0, 2)?.filter(|_| fix3d),
+            eph: optional_float(u, 0.0..=150.0, 3)?.filter(|_| fix),
+            ecefx: float_ratio(u, 1, 2, -6.4e6..=6.4e6, 2)?.filter(|_| fix),
+            ecefy: float_ratio(u, 1, 2, -6.4e6..=6.4e6, 2)?.filter(|_| fix),
+            ecefz: float_ratio(u, 1, 2, -6.4e6..=6.4e6, 2)?.filter(|_| fix),
+            ecef_p_acc: optional_float(u, 0.0..=100.0, 2)?.filter(|_| fix),
+            ecefvx: optional_float(u, -100.0..=100.0, 2)?.filter(|_| fix),
+            ecefvy: optional_float(u, -100.0..=100.0, 2)?.filter(|_| fix),
+            ecefvz: optional_float(u, -100.0..=100.0, 2)?.filter(|_| fix),
+            ecef_v_acc: optional_float(u, 0.0..=10.0, 2)?.filter(|_| fix),
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for Satellite {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        // GPS, Galileo, BeiDou and GLONASS with their numbers of
+        // satellites.
+        const SYSTEMS: &[(u8, u16)] = &[(0, 32), (2, 36), (3, 63), (6, 24)];
+        let &(gnssid, count) = u.choose(SYSTEMS)?;
+        let svid = u.int_in_range(1..=count)?;
+        let el = optional_float(u, -5.0..=90.0, 1)?;
+        let ss = optional_float(u, 0.0..=55.0, 1)?;
+        Ok(Satellite {
+            prn: gnss_to_prn(gnssid, svid).unwrap_or_default(),
+            el,
+            az: optional_float(u, 0.0..=359.9, 1)?,
+            ss,
+            used: u.arbitrary::<bool>()? && el.is_some_and(|el| el > 5.0),
+            gnssid: Some(gnssid),
+            svid: Some(svid),
+            freqid: if gnssid == 6 {
+                Some(u.int_in_range(0..=13)?)
+            } else {
+                None
+            },
+            sigid: u.ratio(1, 2)?.then_some(0),
+            health: optional(u)?,
+            qual: optional(u)?,
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for Sky {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let count = u.int_in_range(0..=40)?;
+        Ok(Sky {
+            device: optional_device(u)?,
+            time: optional(u)?,
+            xdop: optional_float(u, 0.5..=20.0, 2)?,
+            ydop: optional_float(u, 0.5..=20.0, 2)?,
+            vdop: optional_float(u, 0.5..=20.0, 2)?,
+            tdop: optional_float(u, 0.5..=20.0, 2)?,
+            hdop: optional_float(u, 0.5..=20.0, 2)?,
+            gdop: optional_float(u, 0.5..=30.0, 2)?,
+            pdop: optional_float(u, 0.5..=25.0, 2)?,
+            satellites: if u.ratio(3, 4)? {
+                Some((0..count).map(|_| u.arbitrary()).collect::<Result<_>>()?)
+            } else {
+                None
+            },
+        })
+    }
+}
+
+/// Clock of the PPS and TOFF reports, within a second of the time.
+fn clock(u: &mut Unstructured) -> Result<[i64; 4]> {
+    let real_sec: i64 = u.int_in_range(946_684_800..=4_102_444_799)?;
+    let offset: i64 = u.int_in_range(-999_999_999..=999_999_999)?;
+    let clock = real_sec * 1_000_000_000 + offset;
+    Ok([
+        real_sec,
+        0,
+        clock.div_euclid(1_000_000_000),
+        clock.rem_euclid(1_000_000_000),
+    ])
+}
+
+impl<'a> Arbitrary<'a> for Pps {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let [real_sec, real_nsec, clock_sec, clock_nsec] = clock(u)?;
+        Ok(Pps {
+            device: device(u)?,
+            real_sec,
+            real_nsec,
+            clock_sec,
+            clock_nsec,
+            precision: f32::from(u.int_in_range(-30i8..=-1)?),
+            q_err: if u.ratio(1, 2)? {
+                Some(u.int_in_range(-20_000..=20_000)?)
+            } else {
+                None
+            },
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for Toff {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let [real_sec, real_nsec, clock_sec, clock_nsec] = clock(u)?;
+        Ok(Toff {
+            device: device(u)?,
+            real_sec,
+            real_nsec,
+            clock_sec,
+            clock_nsec,
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for Gst {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(Gst {
+            device: optional_device(u)?,
+            time: optional(u)?,
+            rms: optional_float(u, 0.0..=50.0, 3)?,
+            major: optional_float(u, 0.0..=100.0, 3)?,
+            minor: optional_float(u, 0.0..=100.0, 3)?,
+            orient: optional_float(u, 0.0..=359.9, 1)?,
+            lat: optional_float(u, 0.0..=100.0, 3)?,
+            lon: optional_float(u, 0.0..=100.0, 3)?,
+            alt: optional_float(u, 0.0..=200.0, 3)?,
+        })
+    }
+}
+
+/// TPV, SKY, PPS, TOFF or GST report, mostly TPV and SKY like the
+/// stream of `gpsd`.
+impl<'a> Arbitrary<'a> for UnifiedResponse {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(match u.int_in_range(0..=9)? {
+            0..=3 => UnifiedResponse::Tpv(Box::new(u.arbitrary()?)),
+            4..=6 => UnifiedResponse::Sky(Box::new(u.arbitrary()?)),
+            7 => UnifiedResponse::Pps(Box::new(u.arbitrary()?)),
+            8 => UnifiedResponse::Toff(Box::new(u.arbitrary()?)),
+            _ => UnifiedResponse::Gst(Box::new(u.arbitrary()?)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Mode, UnifiedResponse};
+    use ::arbitrary::Unstructured;
+
+    #[test]
+    fn generates_messages() {
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        for _ in 0..200 {
+            let data: Vec<u8> = (0..512)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    state as u8
+                })
+                .collect();
+            let msg: UnifiedResponse = Unstructured::new(&data).arbitrary().unwrap();
+            match &msg {
+                UnifiedResponse::Tpv(tpv) => {
+                    assert!(tpv.lat.is_none_or(|lat| lat.abs() <= 90.0));
+                    assert!(tpv.lon.is_none_or(|lon| lon.abs() <= 180.0));
+                    assert!(tpv.mode != Mode::NoFix || tpv.lat.is_none());
+                }
+                UnifiedResponse::Sky(sky) => {
+                    for sat in sky.satellites.iter().flatten() {
+                        assert!(sat.el.is_none_or(|el| (-5.0..=90.0).contains(&el)));
+                        assert_eq!(sat.gnss_id(), sat.gnssid.zip(sat.svid));
+                    }
+                }
+                UnifiedResponse::Pps(pps) => {
+                    assert!(pps.offset_ns().abs() < 1_000_000_000);
+                }
+                _ => {}
+            }
+            #[cfg(feature = "serialize")]
+            {
+                let json = serde_json::to_string(&msg).unwrap();
+                let parsed = UnifiedResponse::from_slice(json.as_bytes()).unwrap();
+                assert_eq!(format!("{:?}", parsed), format!("{:?}", msg));
+            }
+        }
+
+        let tpv: crate::Tpv = Unstructured::new(&[]).arbitrary().unwrap();
+        assert_eq!(tpv.mode, Mode::NotSeen);
+    }
+}
@@ -0,0 +1,250 @@
+//! Encoding of messages for compact binary serde formats.
+
+use crate::UnifiedResponse;
+use serde::de::{self, EnumAccess, VariantAccess, Visitor};
+use serde::ser::Serializer;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::value::RawValue;
+use std::fmt;
+
+/// Classes in the order of their variant index, which is part of the
+/// encoding and never changes. New classes are appended.
+const CLASSES: &[&str] = &[
+    "VERSION", "DEVICES", "WATCH", "DEVICE", "TPV", "SKY", "PPS", "TOFF", "GST", "ATT", "IMU",
+    "OSC", "RAW", "SUBFRAME", "RTCM2", "RTCM3", "AIS", "UNKNOWN",
+];
+
+/// Message serialized for non-self-describing serde formats like
+/// `postcard` or `bincode`, e.g. to forward parsed reports between
+/// processes or over radio links.
+///
+/// [`UnifiedResponse`] itself serializes to the JSON of `gpsd`, which
+/// needs a self-describing format to be read back. This wrapper
+/// encodes the message as enum variant instead of the `class`
+/// attribute, followed by the fields in declaration order. The variant
+/// index of each class is fixed regardless of the enabled features.
+/// Fields are not named in the encoding, so both ends have to use the
+/// same version of this crate.
+///
+/// SUBFRAME and unknown messages are carried as JSON text. Decoding a
+/// class whose feature is disabled fails.
+#[derive(Debug, Clone)]
+pub struct BinaryResponse(pub UnifiedResponse);
+
+impl From<UnifiedResponse> for BinaryResponse {
+    fn from(msg: UnifiedResponse) -> Self {
+        BinaryResponse(msg)
+    }
+}
+
+impl From<BinaryResponse> for UnifiedResponse {
+    fn from(msg: BinaryResponse) -> Self {
+        msg.0
+    }
+}
+
+impl Serialize for BinaryResponse {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        fn variant<S: Serializer, T: Serialize + ?Sized>(
+            serializer: S,
+            index: u32,
+            value: &T,
+        ) -> Result<S::Ok, S::Error> {
+            let name = CLASSES[index as usize];
+            serializer.serialize_newtype_variant("BinaryResponse", index, name, value)
+        }
+
+        match &self.0 {
+            UnifiedResponse::Version(v) => variant(serializer, 0, v),
+            UnifiedResponse::Devices(d) => variant(serializer, 1, d),
+            UnifiedResponse::Watch(w) => variant(serializer, 2, w),
+            UnifiedResponse::Device(d) => variant(serializer, 3, d),
+            UnifiedResponse::Tpv(t) => variant(serializer, 4, t),
+            UnifiedResponse::Sky(s) => variant(serializer, 5, s),
+            UnifiedResponse::Pps(p) => variant(serializer, 6, p),
+            UnifiedResponse::Toff(t) => variant(serializer, 7, t),
+            UnifiedResponse::Gst(g) => variant(serializer, 8, g),
+            #[cfg(feature = "att")]
+            UnifiedResponse::Att(a) => variant(serializer, 9, a),
+            #[cfg(feature = "att")]
+            UnifiedResponse::Imu(a) => variant(serializer, 10, a),
+            #[cfg(feature = "osc")]
+            UnifiedResponse::Osc(o) => variant(serializer, 11, o),
+            #[cfg(feature = "raw")]
+            UnifiedResponse::Raw(r) => variant(serializer, 12, r),
+            #[cfg(feature = "subframe")]
+            UnifiedResponse::Subframe(raw) => variant(serializer, 13, raw.get()),
+            #[cfg(feature = "rtcm")]
+            UnifiedResponse::Rtcm2(r) => variant(serializer, 14, r),
+            #[cfg(feature = "rtcm")]
+            UnifiedResponse::Rtcm3(r) => variant(serializer, 15, r),
+            #[cfg(feature = "ais")]
+            UnifiedResponse::Ais(a) => variant(serializer, 16, a),
+            UnifiedResponse::Unknown { class, raw } => variant(serializer, 17, &(class, raw.get())),
+        }
+    }
+}
+
+/// Variant index of a class, deserialized from the index or the name.
+struct ClassIndex(u32);
+
+impl<'de> Deserialize<'de> for ClassIndex {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct IndexVisitor;
+
+        impl Visitor<'_> for IndexVisitor {
+            type Value = ClassIndex;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a message class")
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+                match u32::try_from(v) {
+                    Ok(v) if (v as usize) < CLASSES.len() => Ok(ClassIndex(v)),
+                    _ => Err(E::invalid_value(de::Unexpected::Unsigned(v), &self)),
+                }
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                match CLASSES.iter().position(|c| *c == v) {
+                    Some(i) => Ok(ClassIndex(i as u32)),
+                    None => Err(E::unknown_variant(v, CLASSES)),
+                }
+            }
+        }
+
+        deserializer.deserialize_identifier(IndexVisitor)
+    }
+}
+
+impl<'de> Deserialize<'de> for BinaryResponse {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ResponseVisitor;
+
+        impl<'de> Visitor<'de> for ResponseVisitor {
+            type Value = BinaryResponse;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a gpsd message")
+            }
+
+            fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<Self::Value, A::Error> {
+                let raw_value =
+                    |json: String| RawValue::from_string(json).map_err(de::Error::custom);
+                let (ClassIndex(index), v) = data.variant()?;
+                let msg = match index {
+                    0 => UnifiedResponse::Version(v.newtype_variant()?),
+                    1 => UnifiedResponse::Devices(v.newtype_variant()?),
+                    2 => UnifiedResponse::Watch(v.newtype_variant()?),
+                    3 => UnifiedResponse::Device(v.newtype_variant()?),
+                    4 => UnifiedResponse::Tpv(v.newtype_variant()?),
+                    5 => UnifiedResponse::Sky(v.newtype_variant()?),
+                    6 => UnifiedResponse::Pps(v.newtype_variant()?),
+                    7 => UnifiedResponse::Toff(v.newtype_variant()?),
+                    8 => UnifiedResponse::Gst(v.newtype_variant()?),
+                    #[cfg(feature = "att")]
+                    9 => UnifiedResponse::Att(v.newtype_variant()?),
+                    #[cfg(feature = "att")]
+                    10 => UnifiedResponse::Imu(v.newtype_variant()?),
+                    #[cfg(feature = "osc")]
+                    11 => UnifiedResponse::Osc(v.newtype_variant()?),
+                    #[cfg(feature = "raw")]
+                    12 => UnifiedResponse::Raw(v.newtype_variant()?),
+                    #[cfg(feature = "subframe")]
+                    13 => UnifiedResponse::Subframe(raw_value(v.newtype_variant()?)?),
+                    #[cfg(feature = "rtcm")]
+                    14 => UnifiedResponse::Rtcm2(v.newtype_variant()?),
+                    #[cfg(feature = "rtcm")]
+                    15 => UnifiedResponse::Rtcm3(v.newtype_variant()?),
+                    #[cfg(feature = "ais")]
+                    16 => UnifiedResponse::Ais(v.newtype_variant()?),
+                    17 => {
+                        let (class, json): (String, String) = v.newtype_variant()?;
+                        UnifiedResponse::Unknown {
+                            class,
+                            raw: raw_value(json)?,
+                        }
+                    }
+                    _ => {
+                        return Err(de::Error::custom(format_args!(
+                            "class {} is disabled",
+                            CLASSES[index as usize]
+                        )))
+                    }
+                };
+                Ok(BinaryResponse(msg))
+            }
+        }
+
+        deserializer.deserialize_enum("BinaryResponse", CLASSES, ResponseVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BinaryResponse;
+    use crate::{parse_lines, UnifiedResponse};
+
+    #[test]
+    fn round_trips_messages() {
+        let mut lines = vec![
+            r#"{"class":"VERSION","release":"3.25","rev":"3.25","proto_major":3,"proto_minor":15}"#,
+            r#"{"class":"DEVICES","devices":[{"path":"/dev/ttyS0","activated":"2024-05-01T12:00:00.000Z"}]}"#,
+            r#"{"class":"WATCH","enable":true,"json":true}"#,
+            r#"{"class":"DEVICE","path":"/dev/ttyS0","driver":"u-blox","bps":9600}"#,
+            r#"{"class":"TPV","device":"/dev/ttyS0","mode":3,"time":"2024-05-01T12:00:00.000Z","lat":48.1,"lon":11.5}"#,
+            r#"{"class":"SKY","hdop":1.2,"satellites":[{"PRN":3,"el":45,"az":90,"ss":41,"used":true}]}"#,
+            r#"{"class":"PPS","device":"pps0","real_sec":1700000000,"real_nsec":0,"clock_sec":1699999999,"clock_nsec":999999000,"precision":-20}"#,
+            r#"{"class":"TOFF","device":"/dev/ttyS0","real_sec":1700000000,"real_nsec":0,"clock_sec":1699999999,"clock_nsec":999999000,"precision":-20}"#,
+            r#"{"class":"GST","device":"/dev/ttyS0","time":"2024-05-01T12:00:00.000Z","rms":1.5}"#,
+            r#"{"class":"FUTURE","x":1}"#,
+        ];
+        #[cfg(feature = "att")]
+        lines.extend([
+            r#"{"class":"ATT","heading":90.0,"mag_st":"N"}"#,
+            r#"{"class":"IMU","acc_x":0.1}"#,
+        ]);
+        #[cfg(feature = "osc")]
+        lines.push(
+            r#"{"class":"OSC","running":true,"reference":true,"disciplined":false,"delta":-2}"#,
+        );
+        #[cfg(feature = "raw")]
+        lines.push(r#"{"class":"RAW","device":"/dev/ttyS0","time":1700000000,"nsec":0,"rawdata":[{"gnssid":0,"svid":3,"obs":"C1C","pr":2.1e7}]}"#);
+        #[cfg(feature = "subframe")]
+        lines.push(r#"{"class":"SUBFRAME","device":"/dev/ttyS0","tSV":12,"frame":1}"#);
+        #[cfg(feature = "rtcm")]
+        lines.extend([
+            r#"{"class":"RTCM2","device":"/dev/ttyS0","type":1,"station_id":4,"zcount":12.0,"seqnum":1,"length":3,"station_health":0}"#,
+            r#"{"class":"RTCM3","device":"/dev/ttyS0","type":1005,"length":19}"#,
+        ]);
+        #[cfg(feature = "ais")]
+        lines.push(r#"{"class":"AIS","type":1,"mmsi":211234567,"scaled":true,"speed":10.0,"lat":54.0,"lon":10.0,"shipname":"SEA CLOUD"}"#);
+        let input = lines.join("\n");
+
+        let mut unknown = 0;
+        for msg in parse_lines(input.as_bytes()) {
+            let msg = msg.unwrap();
+            unknown += matches!(msg, UnifiedResponse::Unknown { .. }) as usize;
+            let encoded = serde_json::to_string(&BinaryResponse(msg.clone())).unwrap();
+            assert!(
+                encoded.starts_with(&format!("{{\"{}\":", msg.class()))
+                    || encoded.starts_with("{\"UNKNOWN\":")
+            );
+            let decoded: UnifiedResponse = serde_json::from_str::<BinaryResponse>(&encoded)
+                .unwrap()
+                .into();
+            assert_eq!(format!("{:?}", decoded), format!("{:?}", msg));
+
+            // Not self-describing.
+            let encoded = postcard::to_allocvec(&BinaryResponse(msg.clone())).unwrap();
+            let decoded: UnifiedResponse = postcard::from_bytes::<BinaryResponse>(&encoded)
+                .unwrap_or_else(|e| panic!("{}: {}", msg.class(), e))
+                .into();
+            assert_eq!(format!("{:?}", decoded), format!("{:?}", msg));
+        }
+        assert_eq!(unknown, 1);
+        assert!(serde_json::from_str::<BinaryResponse>(r#"{"NOPE":{}}"#).is_err());
+        assert!(postcard::from_bytes::<BinaryResponse>(&[18]).is_err());
+    }
+}
@@ -0,0 +1,159 @@
+//! Samples for the SOCK refclock driver of chrony.
+
+use crate::{Pps, Toff};
+use std::io;
+use std::os::raw::{c_int, c_long};
+use std::os::unix::net::UnixDatagram;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Value of the `magic` field, `SOCK` in ASCII.
+const SOCK_MAGIC: c_int = 0x534f_434b;
+
+const LONG: usize = std::mem::size_of::<c_long>();
+const INT: usize = std::mem::size_of::<c_int>();
+
+/// Leap second warning of a sample.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Leap {
+    /// No leap second is pending.
+    #[default]
+    Normal = 0,
+    /// A leap second is inserted at the end of the day.
+    Insert = 1,
+    /// A second is deleted at the end of the day.
+    Delete = 2,
+}
+
+/// A time sample as read by chrony, the `struct sock_sample` of its
+/// `refclock_sock.c`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SockSample {
+    /// System time of the sample.
+    pub time: SystemTime,
+    /// Offset of the reference time ahead of `time` in seconds.
+    pub offset: f64,
+    /// The sample only marks the start of a second, the offset is
+    /// relative to the nearest second.
+    pub pulse: bool,
+    /// Leap second pending at the end of the day.
+    pub leap: Leap,
+}
+
+impl SockSample {
+    /// Size of the encoded sample.
+    pub const SIZE: usize = 2 * LONG + 8 + 4 * INT;
+
+    /// Creates a sample from a PPS report.
+    pub fn from_pps(pps: &Pps) -> Self {
+        SockSample::new(pps.clock_sec, pps.clock_nsec, pps.offset_ns())
+    }
+
+    /// Creates a sample from a TOFF report.
+    pub fn from_toff(toff: &Toff) -> Self {
+        SockSample::new(toff.clock_sec, toff.clock_nsec, toff.offset_ns())
+    }
+
+    fn new(clock_sec: i64, clock_nsec: i64, offset_ns: i64) -> Self {
+        let since_epoch = Duration::from_secs(clock_sec.max(0) as u64)
+            + Duration::from_nanos(clock_nsec.max(0) as u64);
+        SockSample {
+            time: UNIX_EPOCH + since_epoch,
+            offset: offset_ns as f64 * 1e-9,
+            pulse: false,
+            leap: Leap::Normal,
+        }
+    }
+
+    /// Encodes the sample in the native layout of `struct sock_sample`.
+    pub fn encode(&self) -> [u8; SockSample::SIZE] {
+        let since_epoch = self.time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let mut buf = [0; SockSample::SIZE];
+        let mut pos = 0;
+        let mut put = |bytes: &[u8]| {
+            buf[pos..pos + bytes.len()].copy_from_slice(bytes);
+            pos += bytes.len();
+        };
+        put(&(since_epoch.as_secs() as c_long).to_ne_bytes());
+        put(&(since_epoch.subsec_micros() as c_long).to_ne_bytes());
+        put(&self.offset.to_ne_bytes());
+        put(&c_int::from(self.pulse).to_ne_bytes());
+        put(&(self.leap as c_int).to_ne_bytes());
+        put(&(0 as c_int).to_ne_bytes());
+        put(&SOCK_MAGIC.to_ne_bytes());
+        buf
+    }
+}
+
+/// Sends samples to the socket of a chrony SOCK refclock, e.g.
+/// configured with `refclock SOCK /run/chrony.ttyS0.sock`.
+///
+/// chrony creates the socket, so it has to be started first.
+#[derive(Debug)]
+pub struct ChronySock {
+    socket: UnixDatagram,
+}
+
+impl ChronySock {
+    /// Connects to the socket of chrony at `path`.
+    pub fn connect<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let socket = UnixDatagram::unbound()?;
+        socket.connect(path)?;
+        Ok(ChronySock { socket })
+    }
+
+    /// Sends `sample`.
+    pub fn send(&self, sample: &SockSample) -> io::Result<()> {
+        self.socket.send(&sample.encode()).map(drop)
+    }
+
+    /// Sends the sample of a PPS report.
+    pub fn send_pps(&self, pps: &Pps) -> io::Result<()> {
+        self.send(&SockSample::from_pps(pps))
+    }
+
+    /// Sends the sample of a TOFF report.
+    pub fn send_toff(&self, toff: &Toff) -> io::Result<()> {
+        self.send(&SockSample::from_toff(toff))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChronySock, SockSample, INT, LONG, SOCK_MAGIC};
+    use crate::Pps;
+    use std::os::raw::c_int;
+    use std::os::unix::net::UnixDatagram;
+
+    #[test]
+    fn sends_pps_sample() {
+        let path = std::env::temp_dir().join(format!("gpsd_proto-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let chrony = UnixDatagram::bind(&path).unwrap();
+        let sock = ChronySock::connect(&path).unwrap();
+
+        let pps: Pps = serde_json::from_str(
+            r#"{"device":"/dev/ttyS0","real_sec":1700000000,"real_nsec":0,"clock_sec":1699999999,"clock_nsec":999750000,"precision":-20}"#,
+        )
+        .unwrap();
+        sock.send_pps(&pps).unwrap();
+        let mut buf = [0; 64];
+        let n = chrony.recv(&mut buf).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(n, SockSample::SIZE);
+        let long = |at: usize| i64::from_ne_bytes(buf[at..at + 8].try_into().unwrap());
+        if LONG == 8 {
+            assert_eq!(long(0), 1_699_999_999);
+            assert_eq!(long(8), 999_750);
+        }
+        let offset = f64::from_ne_bytes(buf[2 * LONG..2 * LONG + 8].try_into().unwrap());
+        assert!((offset - 250e-6).abs() < 1e-12);
+        let magic = &buf[n - INT..n];
+        assert_eq!(magic, SOCK_MAGIC.to_ne_bytes());
+        assert_eq!(
+            &buf[2 * LONG + 8..2 * LONG + 8 + INT],
+            (0 as c_int).to_ne_bytes()
+        );
+    }
+}
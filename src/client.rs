@@ -0,0 +1,909 @@
+//! Stateful client on top of the plain protocol functions.
+
+use crate::diagnostics::Outcome;
+use crate::{
+    check_daemon_text, handshake_queued, scan_class, trace_line, ClassSet, DiagnosticsWriter,
+    GpsdError, HandshakeOptions, ParserOptions, TimeoutPhase, UnifiedResponse,
+};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, VecDeque};
+use std::io;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+#[cfg(feature = "tracing")]
+use tracing::field::{debug, Empty};
+
+/// Returns `true` for the classes of reports, which are not replies to
+/// commands, whether their cargo feature is enabled or not.
+fn is_report(class: &str) -> bool {
+    matches!(
+        class,
+        "TPV"
+            | "SKY"
+            | "GST"
+            | "PPS"
+            | "TOFF"
+            | "ATT"
+            | "IMU"
+            | "OSC"
+            | "RAW"
+            | "SUBFRAME"
+            | "RTCM2"
+            | "RTCM3"
+            | "AIS"
+    )
+}
+
+/// Counters describing the health of a `gpsd` feed.
+#[derive(Debug, Default, Clone)]
+pub struct Stats {
+    /// Number of successfully parsed messages per class.
+    pub messages: BTreeMap<String, u64>,
+    /// Number of lines which failed to parse.
+    pub parse_failures: u64,
+    /// Number of messages dropped by the class filter.
+    pub filtered: u64,
+    /// Number of bytes read from `gpsd`.
+    pub bytes_read: u64,
+    /// Number of times the connection has been replaced.
+    pub reconnects: u64,
+}
+
+impl Stats {
+    /// Total number of successfully parsed messages of all classes.
+    pub fn total_messages(&self) -> u64 {
+        self.messages.values().sum()
+    }
+
+    fn count_message(&mut self, class: &str) {
+        match self.messages.get_mut(class) {
+            Some(n) => *n += 1,
+            None => {
+                self.messages.insert(class.to_owned(), 1);
+            }
+        }
+    }
+}
+
+/// Timeouts applied by [`GpsdClient`]. `None` disables the
+/// respective timeout, which is the default.
+///
+/// Timeouts are enforced on TCP connections made by the client
+/// itself. For other transports, configure the timeout on the
+/// transport; reads failing with `WouldBlock` or `TimedOut` are
+/// reported as `GpsdError::Timeout` either way.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct Timeouts {
+    /// Timeout for establishing the TCP connection.
+    pub connect: Option<Duration>,
+    /// Timeout for each read during the handshake.
+    pub handshake: Option<Duration>,
+    /// Timeout waiting for the reply of a command.
+    pub command: Option<Duration>,
+    /// Timeout waiting for the next message.
+    pub read: Option<Duration>,
+}
+
+/// Result of reading a single line from `gpsd`, see
+/// [`GpsdClient::next_event`].
+#[derive(Debug)]
+pub enum ParseOutcome {
+    /// The line has been parsed successfully.
+    Parsed(UnifiedResponse),
+    /// The line could not be parsed. Carries the line, without the
+    /// line terminator, and the parse error.
+    Skipped(String, serde_json::Error),
+}
+
+/// Message with the local time it has been received at, see
+/// [`GpsdClient::next_timestamped`].
+///
+/// The time is taken from the monotonic clock as soon as the line of
+/// the message has been read completely, so it can be related to other
+/// sensors sampled on the same host.
+#[derive(Debug, Clone)]
+pub struct Timestamped<T> {
+    /// Time the message has been received at.
+    pub received_at: Instant,
+    /// The message.
+    pub inner: T,
+}
+
+impl<T> Timestamped<T> {
+    /// Converts the message, keeping the receive time.
+    pub fn map<U, F: FnOnce(T) -> U>(self, f: F) -> Timestamped<U> {
+        Timestamped {
+            received_at: self.received_at,
+            inner: f(self.inner),
+        }
+    }
+}
+
+impl<T> std::ops::Deref for Timestamped<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+/// Reader adapter counting the bytes consumed from the inner reader.
+struct CountingReader<'a, R> {
+    inner: &'a mut R,
+    count: &'a mut u64,
+}
+
+impl<R: io::BufRead> io::Read for CountingReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        *self.count += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R: io::BufRead> io::BufRead for CountingReader<'_, R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        *self.count += amt as u64;
+        self.inner.consume(amt)
+    }
+}
+
+/// Client for a single `gpsd` connection.
+///
+/// The client owns the reader and writer of the connection and keeps
+/// [`Stats`] about the received data.
+pub struct GpsdClient<R, W> {
+    reader: R,
+    writer: W,
+    stats: Stats,
+    options: ParserOptions,
+    handshake_options: HandshakeOptions,
+    timeouts: Timeouts,
+    classes: ClassSet,
+    #[cfg(feature = "latest-fix")]
+    latest_fix: Option<crate::LatestFix>,
+    diagnostics: Option<DiagnosticsWriter>,
+    socket: Option<TcpStream>,
+    buf: Vec<u8>,
+    queued: VecDeque<Vec<u8>>,
+    received_at: Option<Instant>,
+}
+
+impl<R, W> GpsdClient<R, W>
+where
+    R: io::BufRead,
+    W: io::Write,
+{
+    /// Creates a client from a reader and writer connected to `gpsd`.
+    pub fn new(reader: R, writer: W) -> Self {
+        GpsdClient {
+            reader,
+            writer,
+            stats: Stats::default(),
+            options: ParserOptions::default(),
+            handshake_options: HandshakeOptions::default(),
+            timeouts: Timeouts::default(),
+            classes: ClassSet::ALL,
+            #[cfg(feature = "latest-fix")]
+            latest_fix: None,
+            diagnostics: None,
+            socket: None,
+            buf: Vec::new(),
+            queued: VecDeque::new(),
+            received_at: None,
+        }
+    }
+
+    /// Sets the timeouts of the client.
+    pub fn with_timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// Sets the options used by [`GpsdClient::handshake`].
+    pub fn with_handshake_options(mut self, options: HandshakeOptions) -> Self {
+        self.handshake_options = options;
+        self
+    }
+
+    /// Sets the options applied to every parsed message.
+    pub fn with_parser_options(mut self, options: ParserOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Restricts the messages returned by [`GpsdClient::next_event`]
+    /// to the given classes. Messages of other classes are dropped
+    /// after looking at their `class` attribute, without parsing them.
+    ///
+    /// The handshake and replies to [`GpsdClient::command`] are not
+    /// filtered.
+    pub fn with_class_filter(mut self, classes: ClassSet) -> Self {
+        self.classes = classes;
+        self
+    }
+
+    /// Stores every TPV report with a fix received by
+    /// [`GpsdClient::next_event`] in `latest`, for other threads to
+    /// sample.
+    #[cfg(feature = "latest-fix")]
+    pub fn with_latest_fix(mut self, latest: crate::LatestFix) -> Self {
+        self.latest_fix = Some(latest);
+        self
+    }
+
+    /// Dumps every line read after the handshake along with what
+    /// became of it to `diagnostics`, see [`DiagnosticsWriter`].
+    pub fn with_diagnostics(mut self, diagnostics: DiagnosticsWriter) -> Self {
+        self.diagnostics = Some(diagnostics);
+        self
+    }
+
+    /// Attaches or detaches the diagnostic dump while the client is
+    /// running, see [`GpsdClient::with_diagnostics`].
+    pub fn set_diagnostics(&mut self, diagnostics: Option<DiagnosticsWriter>) {
+        self.diagnostics = diagnostics;
+    }
+
+    /// Performs the initial handshake with `gpsd`, see
+    /// [`crate::handshake_with_options`]. Its duration and outcome are
+    /// logged at debug level, and with the `tracing` feature recorded
+    /// in a `handshake` span with the fields `ok` and `elapsed`.
+    ///
+    /// Reports received before the handshake completed, like a DEVICE
+    /// before the WATCH reply, are returned first by the following
+    /// reads.
+    pub fn handshake(&mut self) -> Result<(), GpsdError> {
+        #[cfg(feature = "tracing")]
+        let span = tracing::debug_span!("handshake", ok = Empty, elapsed = Empty).entered();
+        self.set_read_timeout(self.timeouts.handshake)?;
+        let start = Instant::now();
+        let mut reader = CountingReader {
+            inner: &mut self.reader,
+            count: &mut self.stats.bytes_read,
+        };
+        self.queued.clear();
+        let result = handshake_queued(
+            &mut reader,
+            &mut self.writer,
+            &self.handshake_options,
+            &mut self.queued,
+        );
+        match &result {
+            Ok(()) => debug!("handshake ok elapsed={:?}", start.elapsed()),
+            Err(e) => debug!(
+                "handshake failed elapsed={:?} error={:?}",
+                start.elapsed(),
+                e
+            ),
+        }
+        #[cfg(feature = "tracing")]
+        span.record("ok", result.is_ok())
+            .record("elapsed", debug(start.elapsed()));
+        result
+    }
+
+    /// Get the next message from `gpsd`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `GpsdError::IoError` with `UnexpectedEof` when the
+    /// connection has been closed.
+    pub fn next_response(&mut self) -> Result<UnifiedResponse, GpsdError> {
+        match self.next_event()? {
+            ParseOutcome::Parsed(msg) => Ok(msg),
+            ParseOutcome::Skipped(_, e) => Err(e.into()),
+        }
+    }
+
+    /// Get the next message from `gpsd`, reporting lines which can't
+    /// be parsed as `ParseOutcome::Skipped` instead of failing.
+    ///
+    /// This lets the application decide per message whether to carry
+    /// on, e.g. when talking to a partially supported `gpsd` version.
+    ///
+    /// # Errors
+    ///
+    /// Only fatal errors, i.e. I/O errors, and messages rejected by
+    /// the [`ParserOptions`] are returned. The closed connection is
+    /// reported as `GpsdError::IoError` with `UnexpectedEof`.
+    pub fn next_event(&mut self) -> Result<ParseOutcome, GpsdError> {
+        self.set_read_timeout(self.timeouts.read)?;
+        let start = Instant::now();
+        loop {
+            self.read_line()
+                .map_err(|e| e.timed_out(TimeoutPhase::Read, start))?;
+            if !self.is_filtered() {
+                return self.parse();
+            }
+            self.stats.filtered += 1;
+            if let Some(diagnostics) = &self.diagnostics {
+                let class = scan_class(&self.buf).unwrap_or_default();
+                diagnostics.record(&self.buf, Outcome::Filtered(&class));
+            }
+        }
+    }
+
+    /// Get the next message from `gpsd` together with the time it has
+    /// been received at. Fails like [`GpsdClient::next_response`].
+    pub fn next_timestamped(&mut self) -> Result<Timestamped<UnifiedResponse>, GpsdError> {
+        let inner = self.next_response()?;
+        Ok(Timestamped {
+            received_at: self.received_at.unwrap_or_else(Instant::now),
+            inner,
+        })
+    }
+
+    /// Time the latest line has been received at, if any.
+    pub fn last_received(&self) -> Option<Instant> {
+        self.received_at
+    }
+
+    /// Checks the line buffer against the class filter. Lines without
+    /// a recognizable class are left to the parser to report.
+    fn is_filtered(&self) -> bool {
+        if self.classes == ClassSet::ALL {
+            return false;
+        }
+        match scan_class(&self.buf) {
+            Ok(class) => !self.classes.contains_class(&class),
+            Err(_) => false,
+        }
+    }
+
+    /// Sends a command, e.g. `?DEVICES;`, and returns the reply, the
+    /// first message received afterwards which is not a report. The
+    /// reports received before are queued for
+    /// [`GpsdClient::next_response`]. The round-trip time is logged at
+    /// debug level, and with the `tracing` feature recorded in a
+    /// `command` span with the fields `cmd`, `class` and `elapsed`.
+    ///
+    /// # Errors
+    ///
+    /// Replies which are not JSON are reported as
+    /// `GpsdError::DaemonText`, a missing reply as
+    /// `GpsdError::Timeout` if a command timeout is set.
+    pub fn command(&mut self, cmd: &str) -> Result<UnifiedResponse, GpsdError> {
+        #[cfg(feature = "tracing")]
+        let span =
+            tracing::debug_span!("command", cmd = cmd.trim(), class = Empty, elapsed = Empty)
+                .entered();
+        self.set_read_timeout(self.timeouts.command)?;
+        let start = Instant::now();
+        self.send(cmd)
+            .and_then(|_| self.read_reply())
+            .and_then(|_| {
+                check_daemon_text(&self.buf)?;
+                match self.parse()? {
+                    ParseOutcome::Parsed(msg) => Ok(msg),
+                    ParseOutcome::Skipped(_, e) => Err(e.into()),
+                }
+            })
+            .map_err(|e| e.timed_out(TimeoutPhase::Command, start))
+            .inspect(|msg| {
+                #[cfg(feature = "tracing")]
+                span.record("class", msg.class())
+                    .record("elapsed", debug(start.elapsed()));
+                debug!(
+                    "command {} class={} elapsed={:?}",
+                    cmd.trim(),
+                    msg.class(),
+                    start.elapsed()
+                )
+            })
+    }
+
+    fn send(&mut self, cmd: &str) -> Result<(), GpsdError> {
+        self.writer.write_all(cmd.as_bytes())?;
+        if !cmd.ends_with('\n') {
+            self.writer.write_all(b"\r\n")?;
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// Reads the next line into the reused line buffer.
+    fn read_line(&mut self) -> Result<(), GpsdError> {
+        if let Some(line) = self.queued.pop_front() {
+            self.buf = line;
+            self.received_at = Some(Instant::now());
+            return Ok(());
+        }
+        self.read_stream_line()
+    }
+
+    /// Reads the reply to a command into the line buffer, bypassing
+    /// the queue and queuing the reports read before it.
+    fn read_reply(&mut self) -> Result<(), GpsdError> {
+        loop {
+            self.read_stream_line()?;
+            match scan_class(&self.buf) {
+                Ok(class) if is_report(&class) => {
+                    self.queued.push_back(self.buf.clone());
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    /// Reads the next line from the connection into the line buffer.
+    fn read_stream_line(&mut self) -> Result<(), GpsdError> {
+        self.buf.clear();
+        let n = self.reader.read_until(b'\n', &mut self.buf)?;
+        self.received_at = Some(Instant::now());
+        self.stats.bytes_read += n as u64;
+        if n == 0 {
+            return Err(GpsdError::IoError(io::ErrorKind::UnexpectedEof.into()));
+        }
+        trace_line(&self.buf);
+        Ok(())
+    }
+
+    /// Parses the line in the line buffer. With the `tracing` feature
+    /// each message is parsed in a `parse` span with the fields
+    /// `class`, `device` and `elapsed`.
+    fn parse(&mut self) -> Result<ParseOutcome, GpsdError> {
+        #[cfg(feature = "tracing")]
+        let span = tracing::trace_span!("parse", class = Empty, device = Empty, elapsed = Empty);
+        #[cfg(feature = "tracing")]
+        let _entered = span.enter();
+        #[cfg(feature = "tracing")]
+        let traced = !span.is_disabled();
+        #[cfg(not(feature = "tracing"))]
+        let traced = false;
+        let start = (traced || log_enabled!(log::Level::Trace)).then(Instant::now);
+        match UnifiedResponse::from_slice(&self.buf) {
+            Ok(msg) => {
+                if let Some(start) = start {
+                    let elapsed = start.elapsed();
+                    let device = message_device(&self.buf);
+                    #[cfg(feature = "tracing")]
+                    span.record("class", msg.class())
+                        .record("device", device.as_deref())
+                        .record("elapsed", debug(elapsed));
+                    trace!(
+                        "parsed class={} device={} elapsed={:?}",
+                        msg.class(),
+                        device.as_deref().unwrap_or("-"),
+                        elapsed
+                    );
+                }
+                if let Err(e) = self.options.check(&msg) {
+                    self.stats.parse_failures += 1;
+                    if let Some(diagnostics) = &self.diagnostics {
+                        diagnostics.record(&self.buf, Outcome::Rejected(msg.class(), &e));
+                    }
+                    return Err(e);
+                }
+                self.stats.count_message(msg.class());
+                if let Some(diagnostics) = &self.diagnostics {
+                    diagnostics.record(&self.buf, Outcome::Parsed(msg.class()));
+                }
+                #[cfg(feature = "latest-fix")]
+                if let (Some(latest), UnifiedResponse::Tpv(tpv)) = (&self.latest_fix, &msg) {
+                    latest.store(tpv);
+                }
+                Ok(ParseOutcome::Parsed(msg))
+            }
+            Err(e) => {
+                self.stats.parse_failures += 1;
+                if let Some(diagnostics) = &self.diagnostics {
+                    diagnostics.record(&self.buf, Outcome::Skipped(&e));
+                }
+                let raw = String::from_utf8_lossy(&self.buf)
+                    .trim_end_matches(['\r', '\n'])
+                    .to_owned();
+                Ok(ParseOutcome::Skipped(raw, e))
+            }
+        }
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<(), GpsdError> {
+        if let Some(socket) = &self.socket {
+            socket.set_read_timeout(timeout)?;
+        }
+        Ok(())
+    }
+
+    /// Replaces the connection, e.g. after the previous one has been
+    /// lost. The handshake has to be performed again afterwards.
+    pub fn reconnect(&mut self, reader: R, writer: W) {
+        self.reader = reader;
+        self.writer = writer;
+        self.socket = None;
+        self.queued.clear();
+        self.stats.reconnects += 1;
+    }
+
+    /// Counters collected since the client has been created.
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+}
+
+/// The `device` attribute of a message, for tracing.
+fn message_device(data: &[u8]) -> Option<Cow<'_, str>> {
+    #[derive(Deserialize)]
+    struct DeviceTag<'a> {
+        #[serde(borrow)]
+        device: Option<Cow<'a, str>>,
+    }
+
+    serde_json::from_slice::<DeviceTag>(data)
+        .ok()
+        .and_then(|tag| tag.device)
+}
+
+impl GpsdClient<io::BufReader<TcpStream>, io::BufWriter<TcpStream>> {
+    /// Connects to `gpsd` via TCP, e.g. at `127.0.0.1:2947`.
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> Result<Self, GpsdError> {
+        Self::connect_with_timeouts(addr, Timeouts::default())
+    }
+
+    /// Connects to `gpsd` via TCP and applies `timeouts` to the
+    /// connection.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `GpsdError::Timeout` if no connection could be
+    /// established within the connect timeout.
+    pub fn connect_with_timeouts<A: ToSocketAddrs>(
+        addr: A,
+        timeouts: Timeouts,
+    ) -> Result<Self, GpsdError> {
+        let start = Instant::now();
+        let stream = match timeouts.connect {
+            None => TcpStream::connect(addr)?,
+            Some(timeout) => {
+                let mut last_err = io::Error::from(io::ErrorKind::AddrNotAvailable);
+                let mut stream = None;
+                for addr in addr.to_socket_addrs()? {
+                    match TcpStream::connect_timeout(&addr, timeout) {
+                        Ok(s) => {
+                            stream = Some(s);
+                            break;
+                        }
+                        Err(e) => last_err = e,
+                    }
+                }
+                match stream {
+                    Some(s) => s,
+                    None => {
+                        return Err(
+                            GpsdError::from(last_err).timed_out(TimeoutPhase::Connect, start)
+                        )
+                    }
+                }
+            }
+        };
+        let reader = io::BufReader::new(stream.try_clone()?);
+        let socket = Some(stream.try_clone()?);
+        let writer = io::BufWriter::new(stream);
+        let mut client = GpsdClient::new(reader, writer).with_timeouts(timeouts);
+        client.socket = socket;
+        Ok(client)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GpsdClient, ParseOutcome};
+    use crate::{GpsdError, UnifiedResponse};
+    use std::time::Instant;
+
+    const HANDSHAKE: &[u8] = b"{\"class\":\"VERSION\",\"release\":\"blah\",\"rev\":\"blurp\",\"proto_major\":3,\"proto_minor\":12}
+{\"class\":\"DEVICES\",\"devices\":[{\"path\":\"/dev/gps\",\"activated\":\"true\"}]}
+{\"class\":\"WATCH\",\"enable\":true,\"json\":true,\"nmea\":false}
+";
+
+    #[test]
+    fn stats_count_messages_and_failures() {
+        let data = b"{\"class\":\"TPV\",\"mode\":3}
+{\"class\":\"TPV\",\"mode\":2}
+{\"class\":\"SKY\"}
+{\"class\":broken
+";
+        let mut client = GpsdClient::new(&data[..], Vec::new());
+        assert!(matches!(
+            client.next_response(),
+            Ok(UnifiedResponse::Tpv(_))
+        ));
+        assert!(matches!(
+            client.next_response(),
+            Ok(UnifiedResponse::Tpv(_))
+        ));
+        assert!(matches!(
+            client.next_response(),
+            Ok(UnifiedResponse::Sky(_))
+        ));
+        assert!(matches!(
+            client.next_response(),
+            Err(GpsdError::JsonError(_))
+        ));
+        assert!(matches!(client.next_response(), Err(GpsdError::IoError(_))));
+
+        let stats = client.stats();
+        assert_eq!(stats.messages["TPV"], 2);
+        assert_eq!(stats.messages["SKY"], 1);
+        assert_eq!(stats.total_messages(), 3);
+        assert_eq!(stats.parse_failures, 1);
+        assert_eq!(stats.bytes_read, data.len() as u64);
+    }
+
+    #[test]
+    fn stats_count_handshake_bytes_and_reconnects() {
+        let mut client = GpsdClient::new(HANDSHAKE, Vec::new());
+        client.handshake().unwrap();
+        assert_eq!(client.stats().bytes_read, HANDSHAKE.len() as u64);
+
+        client.reconnect(HANDSHAKE, Vec::new());
+        client.handshake().unwrap();
+        assert_eq!(client.stats().bytes_read, 2 * HANDSHAKE.len() as u64);
+        assert_eq!(client.stats().reconnects, 1);
+    }
+
+    #[test]
+    fn handshake_queues_early_reports() {
+        let data = b"{\"class\":\"VERSION\",\"release\":\"blah\",\"rev\":\"blurp\",\"proto_major\":3,\"proto_minor\":12}
+{\"class\":\"DEVICE\",\"path\":\"/dev/gps\"}
+{\"class\":\"DEVICES\",\"devices\":[{\"path\":\"/dev/gps\",\"activated\":\"true\"}]}
+{\"class\":\"TPV\",\"mode\":3}
+{\"class\":\"WATCH\",\"enable\":true,\"json\":true,\"nmea\":false}
+{\"class\":\"SKY\"}
+";
+        let mut client = GpsdClient::new(&data[..], Vec::new());
+        client.handshake().unwrap();
+        assert!(matches!(
+            client.next_response(),
+            Ok(UnifiedResponse::Device(_))
+        ));
+        assert!(matches!(
+            client.next_response(),
+            Ok(UnifiedResponse::Tpv(_))
+        ));
+        assert!(matches!(
+            client.next_response(),
+            Ok(UnifiedResponse::Sky(_))
+        ));
+        assert_eq!(client.stats().bytes_read, data.len() as u64);
+    }
+
+    #[test]
+    fn next_event_skips_broken_lines() {
+        let data = b"{\"class\":broken\r\n{\"class\":\"TPV\",\"mode\":3}\n";
+        let mut client = GpsdClient::new(&data[..], Vec::new());
+        match client.next_event() {
+            Ok(ParseOutcome::Skipped(raw, _)) => assert_eq!(raw, "{\"class\":broken"),
+            _ => panic!("expected skipped line"),
+        }
+        assert!(matches!(
+            client.next_event(),
+            Ok(ParseOutcome::Parsed(UnifiedResponse::Tpv(_)))
+        ));
+        assert!(matches!(client.next_event(), Err(GpsdError::IoError(_))));
+    }
+
+    #[test]
+    fn next_event_filters_classes() {
+        use crate::ClassSet;
+
+        let data = b"{\"class\":\"AIS\",\"type\":1}
+{\"class\":\"FOO\"}
+{\"class\":\"SKY\"}
+{\"class\":\"TPV\",\"mode\":3}
+";
+        let mut client =
+            GpsdClient::new(&data[..], Vec::new()).with_class_filter(ClassSet::TPV | ClassSet::SKY);
+        assert!(matches!(
+            client.next_response(),
+            Ok(UnifiedResponse::Sky(_))
+        ));
+        assert!(matches!(
+            client.next_response(),
+            Ok(UnifiedResponse::Tpv(_))
+        ));
+        assert_eq!(client.stats().filtered, 2);
+        assert_eq!(client.stats().total_messages(), 2);
+    }
+
+    #[test]
+    fn dumps_diagnostics() {
+        use crate::{ClassSet, DiagnosticsWriter};
+        use std::sync::{Arc, Mutex};
+
+        #[derive(Clone, Default)]
+        struct Shared(Arc<Mutex<Vec<u8>>>);
+
+        impl std::io::Write for Shared {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let data = b"{\"class\":\"AIS\",\"type\":1}
+{\"class\":broken
+{\"class\":\"TPV\",\"mode\":3}\r
+{\"class\":\"TPV\",\"mode\":2}
+";
+        let out = Shared::default();
+        let diagnostics = DiagnosticsWriter::new(out.clone());
+        let mut client = GpsdClient::new(&data[..], Vec::new())
+            .with_class_filter(ClassSet::TPV)
+            .with_diagnostics(diagnostics.clone());
+        assert!(matches!(client.next_event(), Ok(ParseOutcome::Skipped(..))));
+        assert!(matches!(
+            client.next_response(),
+            Ok(UnifiedResponse::Tpv(_))
+        ));
+        diagnostics.set_enabled(false);
+        assert!(matches!(
+            client.next_response(),
+            Ok(UnifiedResponse::Tpv(_))
+        ));
+
+        let records: Vec<serde_json::Value> = String::from_utf8(out.0.lock().unwrap().clone())
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0]["result"], "filtered");
+        assert_eq!(records[0]["class"], "AIS");
+        assert_eq!(records[1]["result"], "skipped");
+        assert_eq!(records[1]["raw"], "{\"class\":broken");
+        assert!(records[1]["error"].is_string());
+        assert_eq!(records[2]["result"], "parsed");
+        assert_eq!(records[2]["raw"], "{\"class\":\"TPV\",\"mode\":3}");
+        assert!(records[2]["time"].as_str().unwrap().ends_with('Z'));
+    }
+
+    #[test]
+    fn command_round_trip() {
+        let data = b"{\"class\":\"DEVICES\",\"devices\":[]}\nGPSD,X=?\n";
+        let mut client = GpsdClient::new(&data[..], Vec::new());
+        assert!(matches!(
+            client.command("?DEVICES;"),
+            Ok(UnifiedResponse::Devices(_))
+        ));
+        assert!(matches!(
+            client.command("?DEVICES;"),
+            Err(GpsdError::DaemonText(_))
+        ));
+        assert_eq!(client.writer, b"?DEVICES;\r\n?DEVICES;\r\n");
+    }
+
+    #[test]
+    fn command_skips_queued_reports() {
+        let data = b"{\"class\":\"VERSION\",\"release\":\"blah\",\"rev\":\"blurp\",\"proto_major\":3,\"proto_minor\":12}
+{\"class\":\"TPV\",\"mode\":3}
+{\"class\":\"DEVICES\",\"devices\":[{\"path\":\"/dev/gps\",\"activated\":\"true\"}]}
+{\"class\":\"WATCH\",\"enable\":true,\"json\":true,\"nmea\":false}
+{\"class\":\"SKY\"}
+{\"class\":\"DEVICES\",\"devices\":[]}
+";
+        let mut client = GpsdClient::new(&data[..], Vec::new());
+        client.handshake().unwrap();
+        assert!(matches!(
+            client.command("?DEVICES;"),
+            Ok(UnifiedResponse::Devices(_))
+        ));
+        assert!(matches!(
+            client.next_response(),
+            Ok(UnifiedResponse::Tpv(_))
+        ));
+        assert!(matches!(
+            client.next_response(),
+            Ok(UnifiedResponse::Sky(_))
+        ));
+        assert!(client.next_response().is_err());
+    }
+
+    #[test]
+    fn read_timeout() {
+        use super::Timeouts;
+        use crate::TimeoutPhase;
+        use std::net::TcpListener;
+        use std::time::Duration;
+
+        // Connection accepted but gpsd never answers.
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let timeouts = Timeouts {
+            read: Some(Duration::from_millis(10)),
+            ..Default::default()
+        };
+        let mut client =
+            GpsdClient::connect_with_timeouts(listener.local_addr().unwrap(), timeouts).unwrap();
+        assert!(matches!(
+            client.next_response(),
+            Err(GpsdError::Timeout {
+                phase: TimeoutPhase::Read,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn timestamps_messages() {
+        let data = b"{\"class\":\"TPV\",\"mode\":3}\n{\"class\":\"SKY\"}\n";
+        let mut client = GpsdClient::new(&data[..], Vec::new());
+        let before = Instant::now();
+        let tpv = client.next_timestamped().unwrap();
+        let sky = client.next_timestamped().unwrap();
+        assert!(matches!(tpv.inner, UnifiedResponse::Tpv(_)));
+        assert_eq!(sky.class(), "SKY");
+        assert!(before <= tpv.received_at && tpv.received_at <= sky.received_at);
+        assert_eq!(client.last_received(), Some(sky.received_at));
+        assert_eq!(sky.map(|m| m.class().len()).inner, 3);
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn traces_spans() {
+        use std::sync::{Arc, Mutex};
+        use tracing::field::{Field, Visit};
+        use tracing::span::{Attributes, Id, Record};
+        use tracing::{Event, Metadata};
+
+        /// Name and recorded fields of a span.
+        type Span = (String, Vec<String>);
+
+        /// Subscriber collecting the spans.
+        #[derive(Clone, Default)]
+        struct Spans(Arc<Mutex<Vec<Span>>>);
+
+        /// Fields of the span at the index.
+        struct Fields<'a>(&'a mut Vec<String>);
+
+        impl Visit for Fields<'_> {
+            fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+                self.0.push(format!("{}={:?}", field.name(), value));
+            }
+        }
+
+        impl tracing::Subscriber for Spans {
+            fn enabled(&self, _: &Metadata) -> bool {
+                true
+            }
+            fn new_span(&self, span: &Attributes) -> Id {
+                let mut fields = Vec::new();
+                span.record(&mut Fields(&mut fields));
+                let mut spans = self.0.lock().unwrap();
+                spans.push((span.metadata().name().to_owned(), fields));
+                Id::from_u64(spans.len() as u64)
+            }
+            fn record(&self, id: &Id, values: &Record) {
+                let mut spans = self.0.lock().unwrap();
+                values.record(&mut Fields(&mut spans[id.into_u64() as usize - 1].1));
+            }
+            fn record_follows_from(&self, _: &Id, _: &Id) {}
+            fn event(&self, _: &Event) {}
+            fn enter(&self, _: &Id) {}
+            fn exit(&self, _: &Id) {}
+        }
+
+        let spans = Spans::default();
+        let mut data = HANDSHAKE.to_vec();
+        data.extend_from_slice(b"{\"class\":\"TPV\",\"device\":\"/dev/gps\",\"mode\":3}\n");
+        data.extend_from_slice(b"{\"class\":\"DEVICES\",\"devices\":[]}\n");
+        tracing::subscriber::with_default(spans.clone(), || {
+            let mut client = GpsdClient::new(&data[..], Vec::new());
+            client.handshake().unwrap();
+            client.next_response().unwrap();
+            client.command("?DEVICES;").unwrap();
+        });
+        let spans = spans.0.lock().unwrap();
+        let names: Vec<&str> = spans.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["handshake", "parse", "command", "parse"]);
+        assert!(spans[0].1[0] == "ok=true" && spans[0].1[1].starts_with("elapsed="));
+        assert_eq!(spans[1].1[..2], ["class=\"TPV\"", "device=\"/dev/gps\""]);
+        assert_eq!(spans[2].1[..2], ["cmd=\"?DEVICES;\"", "class=\"DEVICES\""]);
+    }
+}
@@ -0,0 +1,339 @@
+//! Fixes broadcast on D-Bus like the DBUS export of `gpsd`.
+//!
+//! Only the small part of the D-Bus protocol needed to emit signals is
+//! implemented: authentication with `EXTERNAL`, the `Hello` call and
+//! marshalling of the signal.
+
+use crate::{Tpv, UnifiedResponse};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::unix::net::UnixStream;
+
+/// Object path of the signals.
+pub const DBUS_PATH: &str = "/org/gpsd";
+/// Interface of the signals.
+pub const DBUS_INTERFACE: &str = "org.gpsd";
+/// Member of the signal carrying a fix.
+pub const DBUS_FIX_SIGNAL: &str = "fix";
+
+/// Signature of the fix signal: time, mode, ept, lat, lon, eph,
+/// altitude, epv, track, epd, speed, eps, climb, epc and device.
+const FIX_SIGNATURE: &str = "didddddddddddds";
+
+const METHOD_CALL: u8 = 1;
+const METHOD_RETURN: u8 = 2;
+const SIGNAL: u8 = 4;
+
+const FIELD_PATH: u8 = 1;
+const FIELD_INTERFACE: u8 = 2;
+const FIELD_MEMBER: u8 = 3;
+const FIELD_DESTINATION: u8 = 6;
+const FIELD_SIGNATURE: u8 = 8;
+
+/// Default address of the system bus.
+const SYSTEM_BUS: &str = "unix:path=/var/run/dbus/system_bus_socket";
+
+/// Connection to a D-Bus message bus emitting the `fix` signal of
+/// `gpsd`, for consumers of its DBUS export.
+///
+/// Like `gpsd`, each signal carries the time in seconds since the Unix
+/// epoch, the mode as integer, the position, altitude, track, speed
+/// and climb with their error estimates, unknown values as NaN, and the
+/// device name.
+#[derive(Debug)]
+pub struct DbusExport {
+    stream: UnixStream,
+    serial: u32,
+}
+
+impl DbusExport {
+    /// Connects to the system bus, at `DBUS_SYSTEM_BUS_ADDRESS` if set.
+    pub fn connect_system() -> io::Result<Self> {
+        let address = std::env::var("DBUS_SYSTEM_BUS_ADDRESS");
+        Self::connect(address.as_deref().unwrap_or(SYSTEM_BUS))
+    }
+
+    /// Connects to the session bus at `DBUS_SESSION_BUS_ADDRESS`.
+    pub fn connect_session() -> io::Result<Self> {
+        let address = std::env::var("DBUS_SESSION_BUS_ADDRESS").map_err(|_| {
+            io::Error::new(io::ErrorKind::NotFound, "DBUS_SESSION_BUS_ADDRESS not set")
+        })?;
+        Self::connect(&address)
+    }
+
+    /// Connects to the first `unix:path=` transport of the bus
+    /// `address`, e.g. `unix:path=/run/user/1000/bus`.
+    pub fn connect(address: &str) -> io::Result<Self> {
+        let path = address
+            .split(';')
+            .filter_map(|transport| transport.strip_prefix("unix:"))
+            .flat_map(|params| params.split(','))
+            .find_map(|param| param.strip_prefix("path="))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!("no unix:path transport in {:?}", address),
+                )
+            })?;
+        Self::from_stream(UnixStream::connect(unescape(path))?)
+    }
+
+    /// Authenticates on the connected `stream` and registers with the
+    /// bus.
+    pub fn from_stream(stream: UnixStream) -> io::Result<Self> {
+        let mut export = DbusExport { stream, serial: 0 };
+        // SAFETY: `getuid` never fails.
+        let uid = unsafe { libc::getuid() }.to_string();
+        let hex: String = uid.bytes().map(|b| format!("{:02x}", b)).collect();
+        write!(export.stream, "\0AUTH EXTERNAL {}\r\n", hex)?;
+        let mut reader = BufReader::new(export.stream.try_clone()?);
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        if !line.starts_with("OK ") {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("D-Bus authentication failed: {}", line.trim_end()),
+            ));
+        }
+        export.stream.write_all(b"BEGIN\r\n")?;
+
+        let hello = export.message(
+            METHOD_CALL,
+            &[
+                (FIELD_PATH, 'o', "/org/freedesktop/DBus"),
+                (FIELD_INTERFACE, 's', "org.freedesktop.DBus"),
+                (FIELD_MEMBER, 's', "Hello"),
+                (FIELD_DESTINATION, 's', "org.freedesktop.DBus"),
+            ],
+            &[],
+        );
+        export.stream.write_all(&hello)?;
+        // The reply carries the unique name of the connection, which is
+        // not needed. The bus may send signals like `NameAcquired`
+        // first, which are skipped.
+        loop {
+            let (kind, _) = read_message(&mut reader)?;
+            match kind {
+                METHOD_RETURN => return Ok(export),
+                SIGNAL => continue,
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::ConnectionRefused,
+                        "D-Bus Hello failed",
+                    ))
+                }
+            }
+        }
+    }
+
+    /// Emits the fix signal of `tpv`.
+    pub fn emit_fix(&mut self, tpv: &Tpv) -> io::Result<()> {
+        let signal = self.fix_signal(tpv);
+        self.stream.write_all(&signal)
+    }
+
+    /// Emits the fix signal if `msg` is a TPV report, returning `false`
+    /// otherwise.
+    pub fn emit(&mut self, msg: &UnifiedResponse) -> io::Result<bool> {
+        match msg {
+            UnifiedResponse::Tpv(tpv) => self.emit_fix(tpv).map(|()| true),
+            _ => Ok(false),
+        }
+    }
+
+    fn fix_signal(&mut self, tpv: &Tpv) -> Vec<u8> {
+        let f = |v: Option<f32>| v.map_or(f64::NAN, f64::from);
+        let time = tpv.time.map_or(f64::NAN, |t| {
+            let since = t.to_system_time().duration_since(std::time::UNIX_EPOCH);
+            since.map_or(f64::NAN, |d| d.as_secs_f64())
+        });
+        let mut body = Marshal::default();
+        body.f64(time);
+        body.u32(u8::from(tpv.mode).into());
+        for v in [
+            f(tpv.ept),
+            tpv.lat.unwrap_or(f64::NAN),
+            tpv.lon.unwrap_or(f64::NAN),
+            f(tpv.eph),
+            f(tpv.alt_hae.or(tpv.alt)),
+            f(tpv.epv),
+            f(tpv.track),
+            f(tpv.epd),
+            f(tpv.speed),
+            f(tpv.eps),
+            f(tpv.climb),
+            f(tpv.epc),
+        ] {
+            body.f64(v);
+        }
+        body.string(tpv.device.as_deref().unwrap_or_default());
+        self.message(
+            SIGNAL,
+            &[
+                (FIELD_PATH, 'o', DBUS_PATH),
+                (FIELD_INTERFACE, 's', DBUS_INTERFACE),
+                (FIELD_MEMBER, 's', DBUS_FIX_SIGNAL),
+                (FIELD_SIGNATURE, 'g', FIX_SIGNATURE),
+            ],
+            &body.buf,
+        )
+    }
+
+    /// Marshals a message of `kind` with header `fields` of code, type
+    /// and value.
+    fn message(&mut self, kind: u8, fields: &[(u8, char, &str)], body: &[u8]) -> Vec<u8> {
+        self.serial = self.serial.wrapping_add(1).max(1);
+        let mut m = Marshal::default();
+        m.buf.extend_from_slice(&[b'l', kind, 0, 1]);
+        m.u32(body.len() as u32);
+        m.u32(self.serial);
+        let len_at = m.buf.len();
+        m.u32(0);
+        let start = m.buf.len();
+        for &(code, kind, value) in fields {
+            m.align(8);
+            m.buf.push(code);
+            m.signature(kind.encode_utf8(&mut [0; 4]));
+            match kind {
+                'g' => m.signature(value),
+                _ => m.string(value),
+            }
+        }
+        let len = (m.buf.len() - start) as u32;
+        m.buf[len_at..len_at + 4].copy_from_slice(&len.to_le_bytes());
+        m.align(8);
+        m.buf.extend_from_slice(body);
+        m.buf
+    }
+}
+
+/// Little endian marshalling of D-Bus values, aligned relative to the
+/// start of the buffer.
+#[derive(Default)]
+struct Marshal {
+    buf: Vec<u8>,
+}
+
+impl Marshal {
+    fn align(&mut self, n: usize) {
+        while !self.buf.len().is_multiple_of(n) {
+            self.buf.push(0);
+        }
+    }
+
+    fn u32(&mut self, v: u32) {
+        self.align(4);
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn f64(&mut self, v: f64) {
+        self.align(8);
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn string(&mut self, s: &str) {
+        self.u32(s.len() as u32);
+        self.buf.extend_from_slice(s.as_bytes());
+        self.buf.push(0);
+    }
+
+    fn signature(&mut self, s: &str) {
+        self.buf.push(s.len() as u8);
+        self.buf.extend_from_slice(s.as_bytes());
+        self.buf.push(0);
+    }
+}
+
+/// Reads a message, returning its type and serial.
+fn read_message<R: Read>(reader: &mut R) -> io::Result<(u8, u32)> {
+    let mut fixed = [0; 16];
+    reader.read_exact(&mut fixed)?;
+    let u32_at = |i: usize| {
+        let b = [fixed[i], fixed[i + 1], fixed[i + 2], fixed[i + 3]];
+        match fixed[0] {
+            b'B' => u32::from_be_bytes(b),
+            _ => u32::from_le_bytes(b),
+        }
+    };
+    let (body, serial, fields) = (u32_at(4), u32_at(8), u32_at(12));
+    let rest = (fields as u64).next_multiple_of(8) + u64::from(body);
+    io::copy(&mut reader.take(rest), &mut io::sink())?;
+    Ok((fixed[1], serial))
+}
+
+/// Decodes the `%xx` escapes of a D-Bus address value.
+fn unescape(value: &str) -> String {
+    let mut out = Vec::with_capacity(value.len());
+    let mut bytes = value.bytes();
+    while let Some(b) = bytes.next() {
+        if b == b'%' {
+            let hex: Vec<u8> = bytes.by_ref().take(2).collect();
+            let hex = std::str::from_utf8(&hex).unwrap_or_default();
+            if let Ok(b) = u8::from_str_radix(hex, 16) {
+                out.push(b);
+                continue;
+            }
+        }
+        out.push(b);
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_message, unescape, DbusExport, FIX_SIGNATURE, METHOD_RETURN, SIGNAL};
+    use crate::Tpv;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::os::unix::net::UnixStream;
+
+    #[test]
+    fn emits_fix_signal() {
+        assert_eq!(unescape("/tmp/dbus%2dtest"), "/tmp/dbus-test");
+
+        let (client, server) = UnixStream::pair().unwrap();
+        let bus = std::thread::spawn(move || {
+            let mut reader = BufReader::new(server.try_clone().unwrap());
+            let mut line = Vec::new();
+            reader.read_until(b'\n', &mut line).unwrap();
+            assert!(line.starts_with(b"\0AUTH EXTERNAL "));
+            (&server).write_all(b"OK 1234deadbeef\r\n").unwrap();
+            line.clear();
+            reader.read_until(b'\n', &mut line).unwrap();
+            assert_eq!(line, b"BEGIN\r\n");
+            assert_eq!(read_message(&mut reader).unwrap().0, 1);
+            // The reply to Hello, with its header fields left out.
+            let mut reply = vec![b'l', METHOD_RETURN, 0, 1];
+            reply.extend_from_slice(&[0; 12]);
+            (&server).write_all(&reply).unwrap();
+
+            let mut fixed = [0; 16];
+            reader.read_exact(&mut fixed).unwrap();
+            let body = u32::from_le_bytes(fixed[4..8].try_into().unwrap()) as usize;
+            let fields = u32::from_le_bytes(fixed[12..16].try_into().unwrap()) as usize;
+            let mut rest = vec![0; fields.next_multiple_of(8) + body];
+            reader.read_exact(&mut rest).unwrap();
+            (fixed, rest, fields)
+        });
+
+        let mut export = DbusExport::from_stream(client).unwrap();
+        let tpv: Tpv = serde_json::from_str(
+            r#"{"device":"/dev/ttyS0","mode":3,"time":"2024-05-01T12:00:00.500Z","lat":48.1,"lon":11.5,"speed":1.5}"#,
+        )
+        .unwrap();
+        export.emit_fix(&tpv).unwrap();
+        let (fixed, rest, fields) = bus.join().unwrap();
+
+        assert_eq!(&fixed[..4], &[b'l', SIGNAL, 0, 1]);
+        let header = String::from_utf8_lossy(&rest[..fields]);
+        assert!(header.contains("/org/gpsd") && header.contains("org.gpsd"));
+        assert!(header.contains(FIX_SIGNATURE));
+        let body = &rest[fields.next_multiple_of(8)..];
+        let f64_at = |i: usize| f64::from_le_bytes(body[i..i + 8].try_into().unwrap());
+        assert_eq!(f64_at(0), 1_714_564_800.5);
+        assert_eq!(u32::from_le_bytes(body[8..12].try_into().unwrap()), 3);
+        assert!(f64_at(16).is_nan());
+        assert_eq!((f64_at(24), f64_at(32)), (48.1, 11.5));
+        assert_eq!(f64_at(80), 1.5);
+        assert_eq!(&body[112..116], &10u32.to_le_bytes());
+        assert_eq!(&body[116..], b"/dev/ttyS0\0");
+    }
+}
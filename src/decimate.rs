@@ -0,0 +1,396 @@
+//! Down-sampling of high-rate report streams.
+
+use crate::geo::distance_m;
+use crate::{ClassSet, Iso8601Timestamp, Mode, Tpv, UnifiedResponse};
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy)]
+enum Rule {
+    Interval(Duration),
+    EveryNth(u64),
+}
+
+#[derive(Debug, Default, Clone)]
+struct ClassState {
+    last: Option<Instant>,
+    seen: u64,
+}
+
+/// Down-samples reports before delivery, e.g. for user interfaces and
+/// uploads which need a fix every 10 s of a 10 Hz receiver.
+///
+/// Each class is limited either to one report per interval or to every
+/// Nth report, counting from the first report of the class. Classes
+/// without a rule are passed. If a class matches several rules, the
+/// first one added applies.
+///
+/// ```
+/// use gpsd_proto::{ClassSet, Decimator};
+/// use std::time::Duration;
+///
+/// let decimator = Decimator::new()
+///     .with_interval(ClassSet::TPV, Duration::from_secs(10))
+///     .with_every_nth(ClassSet::SKY, 50);
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct Decimator {
+    rules: Vec<(ClassSet, Rule)>,
+    classes: BTreeMap<String, ClassState>,
+    dropped: u64,
+}
+
+impl Decimator {
+    /// Creates a decimator passing all reports.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Passes at most one report of the `classes` per `interval`.
+    pub fn with_interval(mut self, classes: ClassSet, interval: Duration) -> Self {
+        self.rules.push((classes, Rule::Interval(interval)));
+        self
+    }
+
+    /// Passes every `n`th report of the `classes`, starting with the
+    /// first. `n` of 0 is treated as 1.
+    pub fn with_every_nth(mut self, classes: ClassSet, n: u64) -> Self {
+        self.rules.push((classes, Rule::EveryNth(n.max(1))));
+        self
+    }
+
+    /// Returns `true` if `msg`, received now, is to be delivered.
+    pub fn accept(&mut self, msg: &UnifiedResponse) -> bool {
+        self.accept_at(msg, Instant::now())
+    }
+
+    /// Returns `true` if `msg`, received at `at`, is to be delivered,
+    /// e.g. with the time of [`crate::Timestamped`] messages.
+    pub fn accept_at(&mut self, msg: &UnifiedResponse, at: Instant) -> bool {
+        let class = msg.class();
+        let rule = self
+            .rules
+            .iter()
+            .find(|(classes, _)| classes.contains_class(class))
+            .map(|(_, rule)| *rule);
+        let Some(rule) = rule else {
+            return true;
+        };
+        let state = match self.classes.get_mut(class) {
+            Some(state) => state,
+            None => self.classes.entry(class.to_owned()).or_default(),
+        };
+        let accept = match rule {
+            Rule::Interval(interval) => match state.last {
+                Some(last) if at.saturating_duration_since(last) < interval => false,
+                _ => {
+                    state.last = Some(at);
+                    true
+                }
+            },
+            Rule::EveryNth(n) => state.seen % n == 0,
+        };
+        state.seen += 1;
+        if !accept {
+            self.dropped += 1;
+        }
+        accept
+    }
+
+    /// Number of reports dropped so far.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Forgets the reports seen, e.g. after a reconnect, so that the
+    /// next report of each class is passed.
+    pub fn reset(&mut self) {
+        self.classes.clear();
+    }
+
+    /// Wraps a stream of parse results, e.g. of
+    /// [`crate::parse_stream`], passing errors through.
+    pub fn decimate<I, E>(self, iter: I) -> Decimate<I>
+    where
+        I: Iterator<Item = Result<UnifiedResponse, E>>,
+    {
+        Decimate {
+            iter,
+            decimator: self,
+        }
+    }
+}
+
+/// Stream of parse results down-sampled by a [`Decimator`], see
+/// [`Decimator::decimate`].
+#[derive(Debug)]
+pub struct Decimate<I> {
+    iter: I,
+    decimator: Decimator,
+}
+
+impl<I> Decimate<I> {
+    /// The decimator, e.g. to read its counters.
+    pub fn decimator(&self) -> &Decimator {
+        &self.decimator
+    }
+}
+
+impl<I, E> Iterator for Decimate<I>
+where
+    I: Iterator<Item = Result<UnifiedResponse, E>>,
+{
+    type Item = Result<UnifiedResponse, E>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.find(|item| match item {
+            Ok(msg) => self.decimator.accept(msg),
+            Err(_) => true,
+        })
+    }
+}
+
+/// Last TPV report passed by a [`Deduplicator`].
+#[derive(Debug, Clone)]
+struct Passed {
+    mode: Mode,
+    time: Option<Iso8601Timestamp>,
+    pos: Option<(f64, f64)>,
+    alt: Option<f32>,
+}
+
+/// Drops consecutive TPV reports of a device whose position hasn't
+/// changed, e.g. to reduce log and network volume of stationary
+/// devices.
+///
+/// A report is dropped if it has the same mode as the last report
+/// passed, and its position and altitude differ less than the epsilon
+/// from it. Reports without a position are dropped if the last one had
+/// none either. Messages of other classes are passed.
+#[derive(Debug, Clone)]
+pub struct Deduplicator {
+    epsilon_m: f64,
+    max_interval: Option<Duration>,
+    devices: BTreeMap<String, Passed>,
+    dropped: u64,
+}
+
+impl Default for Deduplicator {
+    fn default() -> Self {
+        Deduplicator {
+            epsilon_m: 0.5,
+            max_interval: None,
+            devices: BTreeMap::new(),
+            dropped: 0,
+        }
+    }
+}
+
+impl Deduplicator {
+    /// Creates a deduplicator with an epsilon of 0.5 m.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the smallest horizontal and vertical change in meters which
+    /// passes a report.
+    pub fn with_epsilon(mut self, epsilon_m: f64) -> Self {
+        self.epsilon_m = epsilon_m;
+        self
+    }
+
+    /// Passes a report, changed or not, once its time is `interval`
+    /// after the report last passed, as a heartbeat of stationary
+    /// devices.
+    pub fn with_max_interval(mut self, interval: Duration) -> Self {
+        self.max_interval = Some(interval);
+        self
+    }
+
+    /// Returns `true` if `msg` is to be delivered.
+    pub fn accept(&mut self, msg: &UnifiedResponse) -> bool {
+        match msg {
+            UnifiedResponse::Tpv(tpv) => self.accept_tpv(tpv),
+            _ => true,
+        }
+    }
+
+    /// Returns `true` if `tpv` is to be delivered.
+    pub fn accept_tpv(&mut self, tpv: &Tpv) -> bool {
+        let passed = Passed {
+            mode: tpv.mode,
+            time: tpv.time,
+            pos: tpv.lat.zip(tpv.lon),
+            alt: tpv.alt_hae.or(tpv.alt),
+        };
+        let device = tpv.device.as_deref().unwrap_or_default();
+        let accept = match self.devices.get(device) {
+            Some(last) => self.changed(last, &passed),
+            None => true,
+        };
+        if accept {
+            self.devices.insert(device.to_owned(), passed);
+        } else {
+            self.dropped += 1;
+        }
+        accept
+    }
+
+    fn changed(&self, last: &Passed, tpv: &Passed) -> bool {
+        if last.mode != tpv.mode {
+            return true;
+        }
+        if let (Some(max), Some(last), Some(time)) = (self.max_interval, last.time, tpv.time) {
+            let elapsed = time.to_system_time().duration_since(last.to_system_time());
+            if elapsed.is_ok_and(|elapsed| elapsed >= max) {
+                return true;
+            }
+        }
+        let moved = match (last.pos, tpv.pos) {
+            (Some(a), Some(b)) => distance_m(a, b).is_some_and(|d| d >= self.epsilon_m),
+            (None, None) => false,
+            _ => true,
+        };
+        let climbed = match (last.alt, tpv.alt) {
+            (Some(a), Some(b)) => f64::from((a - b).abs()) >= self.epsilon_m,
+            (None, None) => false,
+            _ => true,
+        };
+        moved || climbed
+    }
+
+    /// Number of reports dropped so far.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Forgets the reports passed, so that the next report of each
+    /// device is passed.
+    pub fn reset(&mut self) {
+        self.devices.clear();
+    }
+
+    /// Wraps a stream of parse results, passing errors through, see
+    /// [`Decimator::decimate`].
+    pub fn dedup<I, E>(self, iter: I) -> Dedup<I>
+    where
+        I: Iterator<Item = Result<UnifiedResponse, E>>,
+    {
+        Dedup { iter, dedup: self }
+    }
+}
+
+/// Stream of parse results without duplicate TPV reports, see
+/// [`Deduplicator::dedup`].
+#[derive(Debug)]
+pub struct Dedup<I> {
+    iter: I,
+    dedup: Deduplicator,
+}
+
+impl<I> Dedup<I> {
+    /// The deduplicator, e.g. to read its counters.
+    pub fn deduplicator(&self) -> &Deduplicator {
+        &self.dedup
+    }
+}
+
+impl<I, E> Iterator for Dedup<I>
+where
+    I: Iterator<Item = Result<UnifiedResponse, E>>,
+{
+    type Item = Result<UnifiedResponse, E>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.find(|item| match item {
+            Ok(msg) => self.dedup.accept(msg),
+            Err(_) => true,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Decimator, Deduplicator};
+    use crate::{parse_lines, ClassSet, UnifiedResponse};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn decimates_reports() {
+        let tpv = UnifiedResponse::from_slice(br#"{"class":"TPV","mode":3}"#).unwrap();
+        let sky = UnifiedResponse::from_slice(br#"{"class":"SKY"}"#).unwrap();
+        let devices = UnifiedResponse::from_slice(br#"{"class":"DEVICES","devices":[]}"#).unwrap();
+        let mut decimator = Decimator::new()
+            .with_interval(ClassSet::TPV, Duration::from_secs(1))
+            .with_every_nth(ClassSet::SKY | ClassSet::TPV, 3);
+        let start = Instant::now();
+        let passed: Vec<bool> = (0..25)
+            .map(|i| decimator.accept_at(&tpv, start + Duration::from_millis(i * 100)))
+            .collect();
+        assert_eq!(passed.iter().filter(|p| **p).count(), 3);
+        assert!(passed[0] && passed[10] && passed[20]);
+        let passed: Vec<bool> = (0..7).map(|_| decimator.accept_at(&sky, start)).collect();
+        assert_eq!(passed, [true, false, false, true, false, false, true]);
+        assert!(decimator.accept(&devices) && decimator.accept(&devices));
+        assert_eq!(decimator.dropped(), 22 + 4);
+        decimator.reset();
+        assert!(decimator.accept_at(&tpv, start));
+
+        let data = b"{\"class\":\"SKY\"}\n{\"class\":broken\n{\"class\":\"SKY\"}\n{\"class\":\"TPV\",\"mode\":2}\n";
+        let mut stream = Decimator::new()
+            .with_every_nth(ClassSet::SKY, 2)
+            .decimate(parse_lines(data));
+        assert!(matches!(stream.next(), Some(Ok(UnifiedResponse::Sky(_)))));
+        assert!(matches!(stream.next(), Some(Err(_))));
+        assert!(matches!(stream.next(), Some(Ok(UnifiedResponse::Tpv(_)))));
+        assert!(stream.next().is_none());
+        assert_eq!(stream.decimator().dropped(), 1);
+    }
+
+    #[test]
+    fn drops_duplicate_fixes() {
+        let data = concat!(
+            r#"{"class":"TPV","mode":3,"time":"2024-05-01T12:00:00Z","lat":48.1,"lon":11.5,"altHAE":500}"#,
+            "\n",
+            r#"{"class":"TPV","mode":3,"time":"2024-05-01T12:00:01Z","lat":48.100001,"lon":11.5,"altHAE":500.2}"#,
+            "\n",
+            r#"{"class":"SKY"}"#,
+            "\n",
+            r#"{"class":"TPV","device":"/dev/ttyS1","mode":3,"time":"2024-05-01T12:00:01Z","lat":48.100001,"lon":11.5}"#,
+            "\n",
+            r#"{"class":"TPV","mode":3,"time":"2024-05-01T12:00:02Z","lat":48.10001,"lon":11.5,"altHAE":500}"#,
+            "\n",
+            r#"{"class":"TPV","mode":3,"time":"2024-05-01T12:00:03Z","lat":48.10001,"lon":11.5,"altHAE":501}"#,
+            "\n",
+            r#"{"class":"TPV","mode":2,"time":"2024-05-01T12:00:04Z","lat":48.10001,"lon":11.5}"#,
+            "\n",
+            r#"{"class":"TPV","mode":2,"time":"2024-05-01T12:00:09Z","lat":48.10001,"lon":11.5}"#,
+            "\n",
+            r#"{"class":"TPV","mode":2,"time":"2024-05-01T12:00:14Z","lat":48.10001,"lon":11.5}"#,
+            "\n",
+        );
+        let mut stream = Deduplicator::new()
+            .with_max_interval(Duration::from_secs(10))
+            .dedup(parse_lines(data.as_bytes()));
+        let times: Vec<String> = stream
+            .by_ref()
+            .map(|msg| match msg.unwrap() {
+                UnifiedResponse::Tpv(tpv) => tpv.time.unwrap().to_string(),
+                msg => msg.class().to_owned(),
+            })
+            .collect();
+        assert_eq!(
+            times,
+            [
+                "2024-05-01T12:00:00Z",
+                "SKY",
+                "2024-05-01T12:00:01Z",
+                "2024-05-01T12:00:02Z",
+                "2024-05-01T12:00:03Z",
+                "2024-05-01T12:00:04Z",
+                "2024-05-01T12:00:14Z",
+            ]
+        );
+        assert_eq!(stream.deduplicator().dropped(), 2);
+    }
+}
@@ -0,0 +1,111 @@
+//! Diagnostic dump of the lines received by a client.
+
+use crate::Iso8601Timestamp;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// What became of a line received by the client.
+#[derive(Clone, Copy)]
+pub(crate) enum Outcome<'a> {
+    /// Parsed as message of the class.
+    Parsed(&'a str),
+    /// Dropped by the class filter before parsing.
+    Filtered(&'a str),
+    /// Parsed, but rejected by the parser options.
+    Rejected(&'a str, &'a dyn std::fmt::Display),
+    /// Failed to parse.
+    Skipped(&'a dyn std::fmt::Display),
+}
+
+struct Shared {
+    enabled: AtomicBool,
+    out: Mutex<Box<dyn Write + Send>>,
+}
+
+/// Writer dumping each line received by a [`GpsdClient`] along with
+/// what became of it, for debugging a feed in the field, see
+/// [`GpsdClient::with_diagnostics`].
+///
+/// Each record is a JSON object on a line of its own, e.g.
+///
+/// ```text
+/// {"time":"2024-05-01T12:00:00.123Z","result":"parsed","class":"TPV","raw":"{\"class\":\"TPV\",\"mode\":3}"}
+/// {"time":"2024-05-01T12:00:00.125Z","result":"skipped","error":"expected value at line 1 column 10","raw":"{\"class\":broken"}
+/// ```
+///
+/// with the local receive time, the `result`, one of `parsed`,
+/// `filtered`, `rejected` and `skipped`, the `class` if known, the
+/// `error` if any, and the `raw` line without line terminator.
+///
+/// The writer is cloned to switch the dump on and off from other
+/// threads while the client is running, e.g. from a signal handler.
+/// A failure to write a record is logged and disables the dump.
+///
+/// [`GpsdClient`]: crate::GpsdClient
+/// [`GpsdClient::with_diagnostics`]: crate::GpsdClient::with_diagnostics
+#[derive(Clone)]
+pub struct DiagnosticsWriter(Arc<Shared>);
+
+impl DiagnosticsWriter {
+    /// Creates an enabled dump writing the records to `out`.
+    pub fn new<W: Write + Send + 'static>(out: W) -> Self {
+        DiagnosticsWriter(Arc::new(Shared {
+            enabled: AtomicBool::new(true),
+            out: Mutex::new(Box::new(out)),
+        }))
+    }
+
+    /// Switches the dump on or off.
+    pub fn set_enabled(&self, enabled: bool) {
+        self.0.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Returns `true` if the dump is on.
+    pub fn is_enabled(&self) -> bool {
+        self.0.enabled.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn record(&self, raw: &[u8], outcome: Outcome) {
+        if !self.is_enabled() {
+            return;
+        }
+        let (result, class, error) = match outcome {
+            Outcome::Parsed(class) => ("parsed", Some(class), None),
+            Outcome::Filtered(class) => ("filtered", Some(class), None),
+            Outcome::Rejected(class, e) => ("rejected", Some(class), Some(e.to_string())),
+            Outcome::Skipped(e) => ("skipped", None, Some(e.to_string())),
+        };
+        let mut record = serde_json::Map::new();
+        let time = Iso8601Timestamp::from_system_time(SystemTime::now());
+        record.insert("time".into(), time.as_str().into());
+        record.insert("result".into(), result.into());
+        if let Some(class) = class {
+            record.insert("class".into(), class.into());
+        }
+        if let Some(error) = error {
+            record.insert("error".into(), error.into());
+        }
+        let raw = String::from_utf8_lossy(raw);
+        record.insert("raw".into(), raw.trim_end_matches(['\r', '\n']).into());
+
+        let mut out = self.0.out.lock().unwrap_or_else(|e| e.into_inner());
+        let written = serde_json::to_writer(&mut *out, &record)
+            .map_err(std::io::Error::from)
+            .and_then(|_| out.write_all(b"\n"))
+            .and_then(|_| out.flush());
+        if let Err(e) = written {
+            warn!("Diagnostics disabled, failed to write record: {}", e);
+            self.set_enabled(false);
+        }
+    }
+}
+
+impl std::fmt::Debug for DiagnosticsWriter {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("DiagnosticsWriter")
+            .field("enabled", &self.is_enabled())
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,234 @@
+//! Receiver velocity from the doppler measurements of RAW reports.
+
+use crate::geo::lla_to_ecef;
+#[cfg(feature = "subframe")]
+use crate::{NavDecoder, Raw, GPS_EPOCH_UNIX_SECS};
+
+/// Speed of light in m/s.
+#[cfg(feature = "subframe")]
+const SPEED_OF_LIGHT: f64 = 299_792_458.0;
+
+/// Range rate of a single satellite, see [`solve_velocity`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DopplerObs {
+    /// ECEF position of the satellite in meters.
+    pub sat_pos: (f64, f64, f64),
+    /// ECEF velocity of the satellite in m/s.
+    pub sat_vel: (f64, f64, f64),
+    /// Rate of change of the pseudorange in m/s, i.e. the doppler
+    /// shift times the negative wavelength.
+    pub range_rate: f64,
+}
+
+/// Velocity solution of [`solve_velocity`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DopplerVelocity {
+    /// ECEF velocity in m/s.
+    pub ecef: (f64, f64, f64),
+    /// East, north and up velocity in m/s.
+    pub enu: (f64, f64, f64),
+    /// Drift of the receiver clock, in m/s.
+    pub clock_drift: f64,
+    /// Root mean square of the range rate residuals in m/s.
+    pub residual_rms: f64,
+    /// Number of satellites used.
+    pub satellites: usize,
+}
+
+impl DopplerVelocity {
+    /// Horizontal speed in m/s.
+    pub fn speed(&self) -> f64 {
+        self.enu.0.hypot(self.enu.1)
+    }
+
+    /// Course over ground in degrees from true north.
+    pub fn track(&self) -> f64 {
+        self.enu.0.atan2(self.enu.1).to_degrees().rem_euclid(360.0)
+    }
+}
+
+/// Solves the receiver velocity and clock drift by least squares from
+/// the range rates of at least four satellites, for a receiver at
+/// latitude, longitude and height above the ellipsoid `receiver`.
+///
+/// Returns `None` with too few satellites or a degenerate geometry.
+pub fn solve_velocity(receiver: (f64, f64, f64), obs: &[DopplerObs]) -> Option<DopplerVelocity> {
+    if obs.len() < 4 {
+        return None;
+    }
+    let pos = lla_to_ecef(receiver);
+    // Each range rate is u · (v_sat - v) + drift with the unit vector u
+    // from the receiver to the satellite.
+    let rows: Vec<([f64; 4], f64)> = obs
+        .iter()
+        .map(|o| {
+            let d = [
+                o.sat_pos.0 - pos.0,
+                o.sat_pos.1 - pos.1,
+                o.sat_pos.2 - pos.2,
+            ];
+            let range = (d[0] * d[0] + d[1] * d[1] + d[2] * d[2]).sqrt();
+            let u = [d[0] / range, d[1] / range, d[2] / range];
+            let sat_rate = u[0] * o.sat_vel.0 + u[1] * o.sat_vel.1 + u[2] * o.sat_vel.2;
+            ([-u[0], -u[1], -u[2], 1.0], o.range_rate - sat_rate)
+        })
+        .collect();
+
+    let mut normal = [[0.0; 5]; 4];
+    for (h, y) in &rows {
+        for i in 0..4 {
+            for j in 0..4 {
+                normal[i][j] += h[i] * h[j];
+            }
+            normal[i][4] += h[i] * y;
+        }
+    }
+    let x = solve4(normal)?;
+
+    let sum_sq: f64 = rows
+        .iter()
+        .map(|(h, y)| (y - h.iter().zip(&x).map(|(a, b)| a * b).sum::<f64>()).powi(2))
+        .sum();
+    let (sin_lat, cos_lat) = receiver.0.to_radians().sin_cos();
+    let (sin_lon, cos_lon) = receiver.1.to_radians().sin_cos();
+    let enu = (
+        -sin_lon * x[0] + cos_lon * x[1],
+        -sin_lat * cos_lon * x[0] - sin_lat * sin_lon * x[1] + cos_lat * x[2],
+        cos_lat * cos_lon * x[0] + cos_lat * sin_lon * x[1] + sin_lat * x[2],
+    );
+    Some(DopplerVelocity {
+        ecef: (x[0], x[1], x[2]),
+        enu,
+        clock_drift: x[3],
+        residual_rms: (sum_sq / rows.len() as f64).sqrt(),
+        satellites: rows.len(),
+    })
+}
+
+/// Solves the augmented 4x4 system by Gaussian elimination with
+/// partial pivoting.
+fn solve4(mut m: [[f64; 5]; 4]) -> Option<[f64; 4]> {
+    for col in 0..4 {
+        let pivot = (col..4).max_by(|&a, &b| m[a][col].abs().total_cmp(&m[b][col].abs()))?;
+        if m[pivot][col].abs() < 1e-12 {
+            return None;
+        }
+        m.swap(col, pivot);
+        let (upper, lower) = m.split_at_mut(col + 1);
+        let pivot_row = &upper[col];
+        for row in lower {
+            let f = row[col] / pivot_row[col];
+            for (r, p) in row[col..].iter_mut().zip(&pivot_row[col..]) {
+                *r -= f * p;
+            }
+        }
+    }
+    let mut x = [0.0; 4];
+    for row in (0..4).rev() {
+        let sum: f64 = (row + 1..4).map(|k| m[row][k] * x[k]).sum();
+        x[row] = (m[row][4] - sum) / m[row][row];
+    }
+    Some(x)
+}
+
+/// Carrier frequency in Hz of a GPS signal by u-blox `sigid`.
+#[cfg(feature = "subframe")]
+fn gps_carrier_hz(sigid: u8) -> Option<f64> {
+    match sigid {
+        0 => Some(1_575.42e6),
+        3 | 4 => Some(1_227.60e6),
+        6 | 7 => Some(1_176.45e6),
+        _ => None,
+    }
+}
+
+/// Solves the velocity from the doppler measurements of GPS
+/// satellites in `raw`, with satellite orbits from the ephemerides of
+/// `nav`. `leapseconds` is the offset of GPS time ahead of UTC, as
+/// reported in TPV.
+///
+/// Only GPS satellites are used, as `nav` only decodes GPS subframes.
+#[cfg(feature = "subframe")]
+pub fn raw_velocity(
+    nav: &NavDecoder,
+    raw: &Raw,
+    receiver: (f64, f64, f64),
+    leapseconds: u32,
+) -> Option<DopplerVelocity> {
+    let gps_secs = raw.time? - GPS_EPOCH_UNIX_SECS as i64 + i64::from(leapseconds);
+    let tow = gps_secs.rem_euclid(604_800) as f64 + raw.nsec.unwrap_or(0) as f64 * 1e-9;
+    let obs: Vec<_> = raw
+        .rawdata
+        .iter()
+        .filter(|m| m.gnssid == 0)
+        .filter_map(|m| {
+            let carrier = gps_carrier_hz(m.sigid.unwrap_or(0))?;
+            let eph = nav.ephemeris(u8::try_from(m.svid).ok()?)?;
+            // The transmission time is about 70 ms earlier, a negligible
+            // error for the velocity.
+            let (p0, p1) = (eph.position_ecef(tow - 0.5), eph.position_ecef(tow + 0.5));
+            Some(DopplerObs {
+                sat_pos: eph.position_ecef(tow),
+                sat_vel: (p1.0 - p0.0, p1.1 - p0.1, p1.2 - p0.2),
+                range_rate: -m.doppler? * SPEED_OF_LIGHT / carrier,
+            })
+        })
+        .collect();
+    solve_velocity(receiver, &obs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{solve_velocity, DopplerObs};
+    use crate::geo::lla_to_ecef;
+
+    #[test]
+    fn solves_velocity() {
+        let receiver = (48.0, 11.0, 500.0);
+        let pos = lla_to_ecef(receiver);
+        // Moving north-east at 20 m/s with 1 m/s upward and 150 m/s
+        // clock drift.
+        let (sin_lat, cos_lat) = 48f64.to_radians().sin_cos();
+        let (sin_lon, cos_lon) = 11f64.to_radians().sin_cos();
+        let (e, n, u) = (14.142, 14.142, 1.0);
+        let vel = (
+            -sin_lon * e - sin_lat * cos_lon * n + cos_lat * cos_lon * u,
+            cos_lon * e - sin_lat * sin_lon * n + cos_lat * sin_lon * u,
+            cos_lat * n + sin_lat * u,
+        );
+        let drift = 150.0;
+
+        let sats = [
+            ((15e6, 5e6, 20e6), (1000.0, -2500.0, 300.0)),
+            ((20e6, -8e6, 15e6), (-1500.0, 500.0, 2800.0)),
+            ((5e6, 12e6, 22e6), (2900.0, -300.0, -900.0)),
+            ((22e6, 8e6, 5e6), (-500.0, 2000.0, -2500.0)),
+            ((10e6, 20e6, 14e6), (1800.0, -1200.0, 1500.0)),
+        ];
+        let mut obs: Vec<_> = sats
+            .iter()
+            .map(|&(s, v)| {
+                let d = (s.0 - pos.0, s.1 - pos.1, s.2 - pos.2);
+                let r = (d.0 * d.0 + d.1 * d.1 + d.2 * d.2).sqrt();
+                let rel = (v.0 - vel.0, v.1 - vel.1, v.2 - vel.2);
+                DopplerObs {
+                    sat_pos: s,
+                    sat_vel: v,
+                    range_rate: (d.0 * rel.0 + d.1 * rel.1 + d.2 * rel.2) / r + drift,
+                }
+            })
+            .collect();
+
+        let sol = solve_velocity(receiver, &obs).unwrap();
+        assert_eq!(sol.satellites, 5);
+        assert!(sol.residual_rms < 1e-6, "{}", sol.residual_rms);
+        assert!((sol.enu.0 - e).abs() < 1e-6 && (sol.enu.1 - n).abs() < 1e-6);
+        assert!((sol.enu.2 - u).abs() < 1e-6);
+        assert!((sol.clock_drift - drift).abs() < 1e-6);
+        assert!((sol.speed() - 20.0).abs() < 1e-3);
+        assert!((sol.track() - 45.0).abs() < 1e-6);
+
+        obs.truncate(3);
+        assert_eq!(solve_velocity(receiver, &obs), None);
+    }
+}
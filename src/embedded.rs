@@ -0,0 +1,1033 @@
+//! Parsing of TPV and SKY reports without heap allocation.
+//!
+//! For microcontrollers which need only a handful of fields and can't
+//! afford allocations per message. [`from_slice`] deserializes with
+//! `serde-json-core` from the borrowed input, [`Tpv`] and [`Sky`]
+//! hold the commonly used fields in fixed-capacity structs, and
+//! [`TpvFixed`] and [`SkyFixed`] all of them. Unknown fields are
+//! skipped. [`Client`] reads the reports over a transport
+//! implementing `embedded_io::Read` and `embedded_io::Write`, and with
+//! the `embedded-async` feature `AsyncClient` over
+//! `embedded_io_async`.
+//!
+//! `serde-json-core` limits what is accepted:
+//!
+//! - Strings are borrowed from the input and escape sequences are not
+//!   decoded, they are kept as received. `gpsd` doesn't emit escapes
+//!   in the fields provided here.
+//! - Unknown fields are skipped recursively, so the stack used grows
+//!   with the nesting of their values, and scalar values of unknown
+//!   fields are skipped without being validated.
+//! - Errors don't carry the position in the input.
+//!
+//! With the `defmt` feature the reports and errors implement
+//! `defmt::Format`, so they can be logged over RTT without the
+//! `core::fmt` machinery.
+//!
+//! ```
+//! use gpsd_proto::embedded::{parse_report, Report};
+//!
+//! let line = br#"{"class":"TPV","device":"/dev/ttyS0","mode":3,"lat":48.1,"lon":11.5}"#;
+//! match parse_report::<16>(line) {
+//!     Ok(Report::Tpv(tpv)) => assert_eq!(tpv.lat, Some(48.1)),
+//!     _ => unreachable!(),
+//! }
+//! ```
+
+use crate::{Iso8601Timestamp, Mode, Status};
+use core::fmt;
+use core::ops::Range;
+use embedded_io::{Read, Write};
+use serde::de::{self, IgnoredAny, SeqAccess, Visitor};
+use serde::Deserialize;
+
+/// Error of [`from_slice`], see `serde_json_core::de::Error`.
+pub use serde_json_core::de::Error;
+
+/// Deserializes `T` from the JSON message in `data` with
+/// `serde-json-core`. A trailing line terminator is accepted.
+pub fn from_slice<'a, T: Deserialize<'a>>(data: &'a [u8]) -> Result<T, Error> {
+    serde_json_core::from_slice(data).map(|(value, _)| value)
+}
+
+/// Subset of a TPV report, see [`crate::Tpv`].
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Deserialize, Clone, Copy)]
+pub struct Tpv {
+    /// GPS fix status.
+    pub status: Option<Status>,
+    /// NMEA mode, see `Mode` enum.
+    pub mode: Mode,
+    /// Time/date stamp in ISO8601 format, UTC.
+    pub time: Option<Iso8601Timestamp>,
+    /// Latitude in degrees: +/- signifies North/South.
+    pub lat: Option<f64>,
+    /// Longitude in degrees: +/- signifies East/West.
+    pub lon: Option<f64>,
+    /// Altitude, height above ellipsoid, in meters.
+    #[serde(rename = "altHAE")]
+    pub alt_hae: Option<f32>,
+    /// MSL altitude in meters.
+    #[serde(rename = "altMSL")]
+    pub alt_msl: Option<f32>,
+    /// Course over ground, degrees from true north.
+    pub track: Option<f32>,
+    /// Speed over ground, meters per second.
+    pub speed: Option<f32>,
+    /// Climb (positive) or sink (negative) rate, meters per second.
+    pub climb: Option<f32>,
+    /// Estimated horizontal position (2D) error in meters.
+    pub eph: Option<f32>,
+    /// Estimated vertical error in meters, 95% confidence.
+    pub epv: Option<f32>,
+}
+
+/// Subset of a satellite of a SKY report, see [`crate::Satellite`].
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+pub struct Satellite {
+    /// PRN ID of the satellite.
+    #[serde(rename = "PRN")]
+    pub prn: i16,
+    /// Elevation in degrees.
+    pub el: Option<f32>,
+    /// Azimuth, degrees from true north.
+    pub az: Option<f32>,
+    /// Signal strength in dB.
+    pub ss: Option<f32>,
+    /// Used in current solution?
+    pub used: bool,
+    /// GNSS ID, as defined by u-blox.
+    pub gnssid: Option<u8>,
+    /// Satellite ID within its constellation.
+    pub svid: Option<u16>,
+}
+
+/// Satellites of a SKY report, holding up to `N` of them.
+pub type Satellites<const N: usize> = FixedVec<Satellite, N>;
+
+/// Array of up to `N` elements stored inline. Further elements are
+/// skipped when deserializing.
+#[derive(Clone, Copy)]
+pub struct FixedVec<T, const N: usize> {
+    items: [T; N],
+    len: usize,
+    truncated: bool,
+}
+
+impl<T: Default, const N: usize> Default for FixedVec<T, N> {
+    fn default() -> Self {
+        FixedVec {
+            items: core::array::from_fn(|_| T::default()),
+            len: 0,
+            truncated: false,
+        }
+    }
+}
+
+impl<T, const N: usize> FixedVec<T, N> {
+    /// The elements held.
+    pub fn as_slice(&self) -> &[T] {
+        &self.items[..self.len]
+    }
+
+    /// Returns `true` if elements have been skipped for lack of
+    /// capacity.
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+}
+
+impl<T, const N: usize> core::ops::Deref for FixedVec<T, N> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        self.as_slice()
+    }
+}
+
+impl<T: fmt::Debug, const N: usize> fmt::Debug for FixedVec<T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.as_slice()).finish()
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl<T: defmt::Format, const N: usize> defmt::Format for FixedVec<T, N> {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "{=[?]}", self.as_slice())
+    }
+}
+
+impl<'de, T: Deserialize<'de> + Default, const N: usize> Deserialize<'de> for FixedVec<T, N> {
+    fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct FixedVecVisitor<T, const N: usize>(core::marker::PhantomData<T>);
+
+        impl<'de, T: Deserialize<'de> + Default, const N: usize> Visitor<'de> for FixedVecVisitor<T, N> {
+            type Value = FixedVec<T, N>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("an array")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                let mut vec = FixedVec::default();
+                while vec.len < N {
+                    match seq.next_element()? {
+                        Some(item) => {
+                            vec.items[vec.len] = item;
+                            vec.len += 1;
+                        }
+                        None => return Ok(vec),
+                    }
+                }
+                while seq.next_element::<IgnoredAny>()?.is_some() {
+                    vec.truncated = true;
+                }
+                Ok(vec)
+            }
+        }
+
+        deserializer.deserialize_seq(FixedVecVisitor(core::marker::PhantomData))
+    }
+}
+
+/// String of up to `N` bytes stored inline.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FixedString<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> FixedString<N> {
+    /// Copies `text`, `None` if it's longer than `N` bytes.
+    pub fn new(text: &str) -> Option<Self> {
+        let mut buf = [0; N];
+        buf.get_mut(..text.len())?.copy_from_slice(text.as_bytes());
+        Some(FixedString {
+            buf,
+            len: text.len(),
+        })
+    }
+
+    /// The text.
+    pub fn as_str(&self) -> &str {
+        // Copied from a `str`.
+        core::str::from_utf8(&self.buf[..self.len]).unwrap_or_default()
+    }
+}
+
+impl<const N: usize> core::ops::Deref for FixedString<N> {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl<const N: usize> fmt::Display for FixedString<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl<const N: usize> fmt::Debug for FixedString<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl<const N: usize> defmt::Format for FixedString<N> {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "{=str}", self.as_str())
+    }
+}
+
+impl<const N: usize> PartialEq<str> for FixedString<N> {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl<const N: usize> PartialEq<&str> for FixedString<N> {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl<'de, const N: usize> Deserialize<'de> for FixedString<N> {
+    fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct FixedStringVisitor<const N: usize>;
+
+        impl<const N: usize> Visitor<'_> for FixedStringVisitor<N> {
+            type Value = FixedString<N>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                write!(formatter, "a string of up to {} bytes", N)
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+                FixedString::new(value).ok_or_else(|| E::invalid_length(value.len(), &self))
+            }
+        }
+
+        deserializer.deserialize_str(FixedStringVisitor)
+    }
+}
+
+/// Subset of a SKY report with up to `N` satellites, see
+/// [`crate::Sky`].
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Deserialize, Clone, Copy)]
+pub struct Sky<const N: usize> {
+    /// Time/date stamp in ISO8601 format, UTC.
+    pub time: Option<Iso8601Timestamp>,
+    /// Vertical dilution of precision.
+    pub vdop: Option<f32>,
+    /// Horizontal dilution of precision.
+    pub hdop: Option<f32>,
+    /// Position (spherical/3D) dilution of precision.
+    pub pdop: Option<f32>,
+    /// Time dilution of precision.
+    pub tdop: Option<f32>,
+    /// Geometric (hyperspherical) dilution of precision.
+    pub gdop: Option<f32>,
+    /// Satellites in view, empty if not reported.
+    #[serde(default)]
+    pub satellites: Satellites<N>,
+}
+
+/// Longest device name of [`TpvFixed`] and [`SkyFixed`], in bytes.
+pub const DEVICE_CAPACITY: usize = 64;
+
+/// TPV report stored without heap allocation, with all fields of
+/// [`crate::Tpv`]. Device names longer than [`DEVICE_CAPACITY`] fail
+/// to parse.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Deserialize, Clone, Copy)]
+pub struct TpvFixed {
+    /// Name of the originating device.
+    pub device: Option<FixedString<DEVICE_CAPACITY>>,
+    /// GPS fix status.
+    pub status: Option<Status>,
+    /// NMEA mode, see `Mode` enum.
+    pub mode: Mode,
+    /// Time/date stamp in ISO8601 format, UTC.
+    pub time: Option<Iso8601Timestamp>,
+    /// Estimated timestamp error (%f, seconds, 95% confidence).
+    pub ept: Option<f32>,
+    /// Leap seconds, the offset of UTC to GPS time.
+    pub leapseconds: Option<i32>,
+    /// MSL altitude in meters.
+    #[serde(rename = "altMSL")]
+    pub alt_msl: Option<f32>,
+    /// Altitude height above ellipsoid in meters.
+    #[serde(rename = "altHAE")]
+    pub alt_hae: Option<f32>,
+    /// Geoid separation in meters.
+    #[serde(rename = "geoidSep")]
+    pub geoid_sep: Option<f32>,
+    /// Latitude in degrees: +/- signifies North/South.
+    pub lat: Option<f64>,
+    /// Longitude in degrees: +/- signifies East/West.
+    pub lon: Option<f64>,
+    /// Altitude in meters, deprecated.
+    pub alt: Option<f32>,
+    /// Longitude error estimate in meters, 95% confidence.
+    pub epx: Option<f32>,
+    /// Latitude error estimate in meters, 95% confidence.
+    pub epy: Option<f32>,
+    /// Estimated vertical error in meters, 95% confidence.
+    pub epv: Option<f32>,
+    /// Course over ground, degrees from true north.
+    pub track: Option<f32>,
+    /// Speed over ground, meters per second.
+    pub speed: Option<f32>,
+    /// Climb (positive) or sink (negative) rate, meters per second.
+    pub climb: Option<f32>,
+    /// Direction error estimate in degrees, 95% confidence.
+    pub epd: Option<f32>,
+    /// Speed error estimate in meters/sec, 95% confidence.
+    pub eps: Option<f32>,
+    /// Climb/sink error estimate in meters/sec, 95% confidence.
+    pub epc: Option<f32>,
+    /// Horizontal 2D position error in meters.
+    pub eph: Option<f32>,
+    /// ECEF X position in meters.
+    pub ecefx: Option<f64>,
+    /// ECEF Y position in meters.
+    pub ecefy: Option<f64>,
+    /// ECEF Z position in meters.
+    pub ecefz: Option<f64>,
+    /// ECEF position error in meters.
+    #[serde(rename = "ecefpAcc")]
+    pub ecef_p_acc: Option<f32>,
+    /// ECEF X velocity in meters per second.
+    pub ecefvx: Option<f32>,
+    /// ECEF Y velocity in meters per second.
+    pub ecefvy: Option<f32>,
+    /// ECEF Z velocity in meters per second.
+    pub ecefvz: Option<f32>,
+    /// ECEF velocity error in meters per second.
+    #[serde(rename = "ecefvAcc")]
+    pub ecef_v_acc: Option<f32>,
+}
+
+impl From<TpvFixed> for crate::Tpv {
+    fn from(tpv: TpvFixed) -> Self {
+        crate::Tpv {
+            device: tpv.device.map(|d| d.as_str().into()),
+            status: tpv.status,
+            mode: tpv.mode,
+            time: tpv.time,
+            ept: tpv.ept,
+            leapseconds: tpv.leapseconds,
+            alt_msl: tpv.alt_msl,
+            alt_hae: tpv.alt_hae,
+            geoid_sep: tpv.geoid_sep,
+            lat: tpv.lat,
+            lon: tpv.lon,
+            alt: tpv.alt,
+            epx: tpv.epx,
+            epy: tpv.epy,
+            epv: tpv.epv,
+            track: tpv.track,
+            speed: tpv.speed,
+            climb: tpv.climb,
+            epd: tpv.epd,
+            eps: tpv.eps,
+            epc: tpv.epc,
+            eph: tpv.eph,
+            ecefx: tpv.ecefx,
+            ecefy: tpv.ecefy,
+            ecefz: tpv.ecefz,
+            ecef_p_acc: tpv.ecef_p_acc,
+            ecefvx: tpv.ecefvx,
+            ecefvy: tpv.ecefvy,
+            ecefvz: tpv.ecefvz,
+            ecef_v_acc: tpv.ecef_v_acc,
+        }
+    }
+}
+
+/// SKY report stored without heap allocation, with all fields of
+/// [`crate::Sky`] and up to `N` satellites. Device names longer than
+/// [`DEVICE_CAPACITY`] fail to parse.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Deserialize, Clone)]
+pub struct SkyFixed<const N: usize> {
+    /// Name of originating device.
+    pub device: Option<FixedString<DEVICE_CAPACITY>>,
+    /// Time/date stamp in ISO8601 format, UTC.
+    pub time: Option<Iso8601Timestamp>,
+    /// Longitudinal dilution of precision.
+    pub xdop: Option<f32>,
+    /// Latitudinal dilution of precision.
+    pub ydop: Option<f32>,
+    /// Altitude dilution of precision.
+    pub vdop: Option<f32>,
+    /// Time dilution of precision.
+    pub tdop: Option<f32>,
+    /// Horizontal dilution of precision.
+    pub hdop: Option<f32>,
+    /// Hyperspherical dilution of precision.
+    pub gdop: Option<f32>,
+    /// Spherical dilution of precision.
+    pub pdop: Option<f32>,
+    /// Satellites in skyview, `None` if not reported.
+    pub satellites: Option<FixedVec<crate::Satellite, N>>,
+}
+
+impl<const N: usize> From<SkyFixed<N>> for crate::Sky {
+    fn from(sky: SkyFixed<N>) -> Self {
+        crate::Sky {
+            device: sky.device.map(|d| d.as_str().into()),
+            time: sky.time,
+            xdop: sky.xdop,
+            ydop: sky.ydop,
+            vdop: sky.vdop,
+            tdop: sky.tdop,
+            hdop: sky.hdop,
+            gdop: sky.gdop,
+            pdop: sky.pdop,
+            satellites: sky.satellites.map(|s| s.to_vec()),
+        }
+    }
+}
+
+/// Report parsed by [`parse_report`].
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy)]
+pub enum Report<const N: usize> {
+    /// TPV report.
+    Tpv(Tpv),
+    /// SKY report with up to `N` satellites.
+    Sky(Sky<N>),
+    /// Message of any other class.
+    Unknown,
+}
+
+/// Parses a TPV or SKY report, keeping up to `N` satellites of SKY
+/// reports. Messages of other classes are reported as
+/// `Report::Unknown`.
+pub fn parse_report<const N: usize>(data: &[u8]) -> Result<Report<N>, Error> {
+    #[derive(Deserialize)]
+    struct ClassTag<'a> {
+        class: &'a str,
+    }
+
+    match from_slice::<ClassTag>(data)?.class {
+        "TPV" => from_slice(data).map(Report::Tpv),
+        "SKY" => from_slice(data).map(Report::Sky),
+        _ => Ok(Report::Unknown),
+    }
+}
+
+/// Error of [`Client`], generic over the error `E` of the transport.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientError<E> {
+    /// Transport error.
+    Io(E),
+    /// The stream ended.
+    Eof,
+    /// A line didn't fit in the line buffer and has been dropped.
+    LineTooLong,
+    /// A message failed to parse.
+    Parse(Error),
+    /// The protocol version reported by `gpsd` is smaller
+    /// `PROTO_MAJOR_MIN`.
+    UnsupportedGpsdProtocolVersion,
+    /// Unexpected reply of `gpsd` during the handshake.
+    UnexpectedGpsdReply,
+    /// `gpsd` didn't enable the WATCH.
+    WatchFail,
+}
+
+impl<E> From<Error> for ClientError<E> {
+    fn from(e: Error) -> Self {
+        ClientError::Parse(e)
+    }
+}
+
+impl<E: fmt::Display> fmt::Display for ClientError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ClientError::Io(e) => write!(f, "IoError: {}", e),
+            ClientError::Eof => write!(f, "Eof"),
+            ClientError::LineTooLong => write!(f, "LineTooLong"),
+            ClientError::Parse(e) => write!(f, "Parse: {}", e),
+            ClientError::UnsupportedGpsdProtocolVersion => {
+                write!(f, "UnsupportedGpsdProtocolVersion")
+            }
+            ClientError::UnexpectedGpsdReply => write!(f, "UnexpectedGpsdReply"),
+            ClientError::WatchFail => write!(f, "WatchFail"),
+        }
+    }
+}
+
+/// Line buffer of `N` bytes shared by the clients.
+struct LineBuf<const N: usize> {
+    buf: [u8; N],
+    start: usize,
+    end: usize,
+    too_long: bool,
+}
+
+impl<const N: usize> LineBuf<N> {
+    fn new() -> Self {
+        LineBuf {
+            buf: [0; N],
+            start: 0,
+            end: 0,
+            too_long: false,
+        }
+    }
+
+    /// Takes the next complete line buffered, if any. A line longer
+    /// than the buffer is taken as `Err`.
+    fn take_line(&mut self) -> Option<Result<Range<usize>, ()>> {
+        let n = self.buf[self.start..self.end]
+            .iter()
+            .position(|b| *b == b'\n')?;
+        let line = self.start..self.start + n;
+        self.start += n + 1;
+        if core::mem::take(&mut self.too_long) {
+            return Some(Err(()));
+        }
+        Some(Ok(line))
+    }
+
+    /// Returns the line taken, without line terminator.
+    fn line(&self, line: Range<usize>) -> &[u8] {
+        let line = &self.buf[line];
+        line.strip_suffix(b"\r").unwrap_or(line)
+    }
+
+    /// Returns the free space to read into, dropping the buffered part
+    /// of a line longer than the buffer.
+    fn space(&mut self) -> &mut [u8] {
+        if self.start == 0 && self.end == N {
+            self.too_long = true;
+            self.end = 0;
+        }
+        self.buf.copy_within(self.start..self.end, 0);
+        self.end -= self.start;
+        self.start = 0;
+        &mut self.buf[self.end..]
+    }
+
+    /// Adds `n` bytes read into [`LineBuf::space`].
+    fn fill(&mut self, n: usize) {
+        self.end += n;
+    }
+
+    /// Takes the next line, reading with `read` until it is complete.
+    fn next_line<E>(
+        &mut self,
+        mut read: impl FnMut(&mut [u8]) -> Result<usize, E>,
+    ) -> Result<&[u8], ClientError<E>> {
+        loop {
+            if let Some(line) = self.take_line() {
+                let line = line.map_err(|_| ClientError::LineTooLong)?;
+                return Ok(self.line(line));
+            }
+            match read(self.space()) {
+                Ok(0) => return Err(ClientError::Eof),
+                Ok(n) => self.fill(n),
+                Err(e) => return Err(ClientError::Io(e)),
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Handshake<'a> {
+    class: &'a str,
+    proto_major: Option<u8>,
+    enable: Option<bool>,
+}
+
+/// Number of messages before the WATCH reply skipped by the handshake.
+const HANDSHAKE_SKIP: usize = 8;
+
+/// Checks the VERSION message starting the handshake.
+fn check_version<E>(line: &[u8]) -> Result<(), ClientError<E>> {
+    let version: Handshake = from_slice(line)?;
+    if version.class != "VERSION" {
+        return Err(ClientError::UnexpectedGpsdReply);
+    }
+    if version.proto_major.unwrap_or_default() < crate::PROTO_MAJOR_MIN {
+        return Err(ClientError::UnsupportedGpsdProtocolVersion);
+    }
+    Ok(())
+}
+
+/// Returns `true` if `line` is the WATCH reply enabling the stream.
+fn check_watch<E>(line: &[u8]) -> Result<bool, ClientError<E>> {
+    let msg: Handshake = from_slice(line)?;
+    match (msg.class, msg.enable) {
+        ("WATCH", Some(false)) => Err(ClientError::WatchFail),
+        ("WATCH", _) => Ok(true),
+        _ => Ok(false),
+    }
+}
+
+/// Client reading reports over an `embedded_io` transport into a line
+/// buffer of `N` bytes, without heap allocation.
+///
+/// This is the counterpart of [`crate::GpsdClient`] for bare-metal
+/// TCP stacks and serial links carrying forwarded `gpsd` JSON. The
+/// buffer must hold a complete line; SKY reports of many satellites
+/// need about 100 bytes per satellite. With the `embedded-async`
+/// feature `AsyncClient` does the same over `embedded_io_async`.
+pub struct Client<R, W, const N: usize> {
+    reader: R,
+    writer: W,
+    buf: LineBuf<N>,
+}
+
+impl<R, W, E, const N: usize> Client<R, W, N>
+where
+    R: Read<Error = E>,
+    W: Write<Error = E>,
+{
+    /// Creates a client on the connection.
+    pub fn new(reader: R, writer: W) -> Self {
+        Client {
+            reader,
+            writer,
+            buf: LineBuf::new(),
+        }
+    }
+
+    /// Performs the initial handshake with `gpsd`: checks the VERSION
+    /// and enables the JSON stream by [`crate::ENABLE_WATCH_CMD`].
+    /// Up to 8 messages before the WATCH reply, like DEVICES, are
+    /// skipped. Without a `gpsd` in between, e.g. on a serial link
+    /// carrying the reports only, the handshake is to be left out.
+    pub fn handshake(&mut self) -> Result<(), ClientError<E>> {
+        check_version(self.next_line()?)?;
+        self.send(crate::ENABLE_WATCH_CMD)?;
+        for _ in 0..HANDSHAKE_SKIP {
+            if check_watch(self.next_line()?)? {
+                return Ok(());
+            }
+        }
+        Err(ClientError::UnexpectedGpsdReply)
+    }
+
+    /// Sends a command, e.g. `?POLL;`, without waiting for the reply.
+    pub fn send(&mut self, cmd: &str) -> Result<(), ClientError<E>> {
+        self.writer
+            .write_all(cmd.as_bytes())
+            .and_then(|_| self.writer.flush())
+            .map_err(ClientError::Io)
+    }
+
+    /// Reads the next report, keeping up to `S` satellites of SKY
+    /// reports, see [`parse_report`].
+    pub fn next_report<const S: usize>(&mut self) -> Result<Report<S>, ClientError<E>> {
+        Ok(parse_report(self.next_line()?)?)
+    }
+
+    /// Reads the next line, without line terminator. Lines longer than
+    /// the buffer are dropped and reported as
+    /// `ClientError::LineTooLong`.
+    pub fn next_line(&mut self) -> Result<&[u8], ClientError<E>> {
+        let reader = &mut self.reader;
+        self.buf.next_line(|buf| reader.read(buf))
+    }
+
+    /// Returns the transport.
+    pub fn into_inner(self) -> (R, W) {
+        (self.reader, self.writer)
+    }
+}
+
+/// Asynchronous [`Client`] over an `embedded_io_async` transport, e.g.
+/// a TCP socket of `embassy-net`.
+#[cfg(feature = "embedded-async")]
+pub struct AsyncClient<R, W, const N: usize> {
+    reader: R,
+    writer: W,
+    buf: LineBuf<N>,
+}
+
+#[cfg(feature = "embedded-async")]
+impl<R, W, E, const N: usize> AsyncClient<R, W, N>
+where
+    R: embedded_io_async::Read<Error = E>,
+    W: embedded_io_async::Write<Error = E>,
+{
+    /// Creates a client on the connection.
+    pub fn new(reader: R, writer: W) -> Self {
+        AsyncClient {
+            reader,
+            writer,
+            buf: LineBuf::new(),
+        }
+    }
+
+    /// Performs the initial handshake with `gpsd`, see
+    /// [`Client::handshake`].
+    pub async fn handshake(&mut self) -> Result<(), ClientError<E>> {
+        check_version(self.next_line().await?)?;
+        self.send(crate::ENABLE_WATCH_CMD).await?;
+        for _ in 0..HANDSHAKE_SKIP {
+            if check_watch(self.next_line().await?)? {
+                return Ok(());
+            }
+        }
+        Err(ClientError::UnexpectedGpsdReply)
+    }
+
+    /// Sends a command, e.g. `?POLL;`, without waiting for the reply.
+    pub async fn send(&mut self, cmd: &str) -> Result<(), ClientError<E>> {
+        self.writer
+            .write_all(cmd.as_bytes())
+            .await
+            .map_err(ClientError::Io)?;
+        self.writer.flush().await.map_err(ClientError::Io)
+    }
+
+    /// Reads the next report, see [`Client::next_report`].
+    pub async fn next_report<const S: usize>(&mut self) -> Result<Report<S>, ClientError<E>> {
+        Ok(parse_report(self.next_line().await?)?)
+    }
+
+    /// Reads the next line, see [`Client::next_line`].
+    pub async fn next_line(&mut self) -> Result<&[u8], ClientError<E>> {
+        loop {
+            if let Some(line) = self.buf.take_line() {
+                let line = line.map_err(|_| ClientError::LineTooLong)?;
+                return Ok(self.buf.line(line));
+            }
+            match self.reader.read(self.buf.space()).await {
+                Ok(0) => return Err(ClientError::Eof),
+                Ok(n) => self.buf.fill(n),
+                Err(e) => return Err(ClientError::Io(e)),
+            }
+        }
+    }
+
+    /// Returns the transport.
+    pub fn into_inner(self) -> (R, W) {
+        (self.reader, self.writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        from_slice, parse_report, Client, ClientError, Error, FixedString, Report, SkyFixed, Tpv,
+        TpvFixed,
+    };
+    use crate::Mode;
+    use alloc::vec::Vec;
+    use core::convert::Infallible;
+    use embedded_io::{ErrorType, Read, Write};
+
+    /// Transport reading `input` in chunks of 7 bytes.
+    struct Chunked<'a>(&'a [u8]);
+
+    impl ErrorType for Chunked<'_> {
+        type Error = Infallible;
+    }
+
+    impl Read for Chunked<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Infallible> {
+            let n = buf.len().min(self.0.len()).min(7);
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    /// Transport collecting the bytes written.
+    #[derive(Default)]
+    struct Sink(Vec<u8>);
+
+    impl ErrorType for Sink {
+        type Error = Infallible;
+    }
+
+    impl Write for Sink {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Infallible> {
+            self.0.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> Result<(), Infallible> {
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "defmt")]
+    #[test]
+    fn implements_defmt_format() {
+        fn is_format<T: defmt::Format>() {}
+        is_format::<Report<4>>();
+        is_format::<TpvFixed>();
+        is_format::<SkyFixed<4>>();
+        is_format::<ClientError<()>>();
+    }
+
+    #[test]
+    fn parses_without_allocation() {
+        let tpv =
+            br#"{"class":"TPV","device":"/dev/ttyS0","mode":3,"time":"2024-05-01T12:00:00.000Z",
+            "ept":0.005,"lat":48.1,"lon":-11.5,"altHAE":512.25,"eph":1.5e0,"ecefx":4177990.41,
+            "raw":[{"x":[1,{"y":"a\"b"}]}],"status":null}"#;
+        match parse_report::<4>(tpv) {
+            Ok(Report::Tpv(tpv)) => {
+                assert_eq!(tpv.mode, Mode::Fix3d);
+                assert_eq!(tpv.lat, Some(48.1));
+                assert_eq!(tpv.lon, Some(-11.5));
+                assert_eq!(tpv.alt_hae, Some(512.25));
+                assert_eq!(tpv.eph, Some(1.5));
+                assert!(tpv.status.is_none() && tpv.speed.is_none());
+                assert_eq!(tpv.time.unwrap(), "2024-05-01T12:00:00.000Z");
+            }
+            r => panic!("unexpected {:?}", r),
+        }
+
+        let sky = b"{\"class\":\"SKY\",\"hdop\":0.9,\"satellites\":[\
+            {\"PRN\":1,\"el\":45,\"az\":90,\"ss\":40,\"used\":true,\"gnssid\":0,\"svid\":1},\
+            {\"PRN\":2,\"used\":false},{\"PRN\":3,\"used\":true}]}\r\n";
+        match parse_report::<2>(sky) {
+            Ok(Report::Sky(sky)) => {
+                assert_eq!(sky.hdop, Some(0.9));
+                assert_eq!(sky.satellites.len(), 2);
+                assert!(sky.satellites.is_truncated());
+                assert_eq!(sky.satellites[0].el, Some(45.0));
+                assert!(sky.satellites[0].used && !sky.satellites[1].used);
+            }
+            r => panic!("unexpected {:?}", r),
+        }
+        match parse_report::<8>(br#"{"class":"SKY"}"#) {
+            Ok(Report::Sky(sky)) => assert!(sky.satellites.is_empty()),
+            r => panic!("unexpected {:?}", r),
+        }
+
+        assert!(matches!(
+            parse_report::<8>(br#"{"class":"VERSION","release":"3.25"}"#),
+            Ok(Report::Unknown)
+        ));
+        assert!(matches!(
+            from_slice::<Tpv>(br#"{"class":"TPV","lat":1}"#),
+            Err(Error::CustomError)
+        ));
+        assert!(matches!(
+            from_slice::<Tpv>(br#"{"class":"TPV","mode":3"#),
+            Err(Error::EofWhileParsingObject)
+        ));
+        assert!(matches!(
+            from_slice::<Tpv>(br#"{"mode":3} x"#),
+            Err(Error::TrailingCharacters)
+        ));
+        // Escapes are kept as received.
+        assert!(matches!(
+            parse_report::<8>(br#"{"class":"T\u0050V","mode":3}"#),
+            Ok(Report::Unknown)
+        ));
+        let nested = br#"{"mode":3,"x":[[{"y":[1,2]}],"z"]}"#;
+        assert!(matches!(
+            from_slice::<Tpv>(nested),
+            Ok(Tpv {
+                mode: Mode::Fix3d,
+                ..
+            })
+        ));
+    }
+
+    /// Session of a `gpsd` with a SKY report too long for the buffer.
+    const SESSION: &str = concat!(
+        r#"{"class":"VERSION","release":"3.25","rev":"3.25","proto_major":3,"proto_minor":15}"#,
+        "\r\n",
+        r#"{"class":"DEVICES","devices":[{"path":"/dev/ttyS0"}]}"#,
+        "\n",
+        r#"{"class":"WATCH","enable":true,"json":true}"#,
+        "\n",
+        r#"{"class":"SKY","satellites":[{"PRN":1,"used":true},{"PRN":2,"used":true},{"PRN":3,"used":true}]}"#,
+        "\n",
+        r#"{"class":"TPV","mode":2,"lat":1.5}"#,
+        "\n",
+    );
+
+    #[test]
+    fn reads_over_transport() {
+        let mut client = Client::<_, _, 96>::new(Chunked(SESSION.as_bytes()), Sink::default());
+        client.handshake().unwrap();
+        assert!(matches!(
+            client.next_report::<4>(),
+            Err(ClientError::LineTooLong)
+        ));
+        match client.next_report::<4>() {
+            Ok(Report::Tpv(tpv)) => assert_eq!(tpv.lat, Some(1.5)),
+            r => panic!("unexpected {:?}", r),
+        }
+        assert!(matches!(client.next_line(), Err(ClientError::Eof)));
+        let (_, sent) = client.into_inner();
+        assert_eq!(sent.0, crate::ENABLE_WATCH_CMD.as_bytes());
+
+        let version = b"{\"class\":\"VERSION\",\"proto_major\":2}\n";
+        let mut client = Client::<_, _, 96>::new(Chunked(version), Sink::default());
+        assert!(matches!(
+            client.handshake(),
+            Err(ClientError::UnsupportedGpsdProtocolVersion)
+        ));
+    }
+
+    #[cfg(feature = "embedded-async")]
+    impl embedded_io_async::Read for Chunked<'_> {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Infallible> {
+            Read::read(self, buf)
+        }
+    }
+
+    #[cfg(feature = "embedded-async")]
+    impl embedded_io_async::Write for Sink {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, Infallible> {
+            Write::write(self, buf)
+        }
+
+        async fn flush(&mut self) -> Result<(), Infallible> {
+            Ok(())
+        }
+    }
+
+    /// Polls `future` to completion, for transports never pending.
+    #[cfg(feature = "embedded-async")]
+    fn block_on<F: core::future::Future>(future: F) -> F::Output {
+        let mut future = core::pin::pin!(future);
+        let mut cx = core::task::Context::from_waker(core::task::Waker::noop());
+        loop {
+            if let core::task::Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+        }
+    }
+
+    #[cfg(feature = "embedded-async")]
+    #[test]
+    fn reads_over_async_transport() {
+        use super::AsyncClient;
+
+        let mut client = AsyncClient::<_, _, 96>::new(Chunked(SESSION.as_bytes()), Sink::default());
+        block_on(async {
+            client.handshake().await.unwrap();
+            assert!(matches!(
+                client.next_report::<4>().await,
+                Err(ClientError::LineTooLong)
+            ));
+            match client.next_report::<4>().await {
+                Ok(Report::Tpv(tpv)) => assert_eq!(tpv.lat, Some(1.5)),
+                r => panic!("unexpected {:?}", r),
+            }
+            assert!(matches!(client.next_line().await, Err(ClientError::Eof)));
+        });
+        let (_, sent) = client.into_inner();
+        assert_eq!(sent.0, crate::ENABLE_WATCH_CMD.as_bytes());
+    }
+
+    #[test]
+    fn parses_fixed_reports() {
+        let tpv = br#"{"class":"TPV","device":"/dev/ttyACM0","mode":3,"leapseconds":18,
+            "lat":48.1,"lon":11.5,"altMSL":510.5,"geoidSep":47.0,"eps":0.5,"ecefvAcc":0.02}"#;
+        let fixed: TpvFixed = from_slice(tpv).unwrap();
+        assert_eq!(fixed.device.unwrap(), "/dev/ttyACM0");
+        assert_eq!(fixed.leapseconds, Some(18));
+        assert_eq!(fixed.ecef_v_acc, Some(0.02));
+        let full = crate::Tpv::from(fixed);
+        assert_eq!(full.device.as_deref(), Some("/dev/ttyACM0"));
+        assert_eq!(
+            (full.lat, full.alt_msl, full.eps),
+            (Some(48.1), Some(510.5), Some(0.5))
+        );
+
+        let sky = br#"{"class":"SKY","device":"/dev/ttyACM0","xdop":0.6,"satellites":[
+            {"PRN":7,"el":30,"az":120,"ss":33,"used":true,"gnssid":0,"svid":7,"health":1},
+            {"PRN":8,"used":false},{"PRN":9,"used":false}]}"#;
+        let fixed: SkyFixed<2> = from_slice(sky).unwrap();
+        let sats = fixed.satellites.as_ref().unwrap();
+        assert_eq!(sats.len(), 2);
+        assert!(sats.is_truncated());
+        assert_eq!(sats[0].prn, 7);
+        assert_eq!(sats[0].health, Some(crate::Health::Ok));
+        let full = crate::Sky::from(fixed);
+        assert_eq!(full.xdop, Some(0.6));
+        assert_eq!(full.satellites.unwrap().len(), 2);
+        let fixed: SkyFixed<2> = from_slice(br#"{"class":"SKY"}"#).unwrap();
+        assert!(fixed.satellites.is_none());
+
+        let long = [&br#"{"mode":3,"device":""#[..], &[b'x'; 65], b"\"}"].concat();
+        assert_eq!(
+            from_slice::<TpvFixed>(&long).err(),
+            Some(Error::CustomError)
+        );
+        assert_eq!(FixedString::<4>::new("abcd").unwrap(), "abcd");
+        assert!(FixedString::<4>::new("abcde").is_none());
+    }
+}
@@ -0,0 +1,1209 @@
+//! Writers exporting TPV reports to common file formats.
+//!
+//! GeoJSON output is available with the `geojson` feature.
+
+use crate::{Iso8601Timestamp, Mode, Tpv, UnifiedResponse};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+/// Escapes the XML special characters of `s`.
+fn escape_xml(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Horizontal error of a fix in meters, `eph` or the larger of `epx`
+/// and `epy`.
+pub(crate) fn horizontal_error(tpv: &Tpv) -> Option<f32> {
+    tpv.eph.or_else(|| Some(tpv.epx?.max(tpv.epy?)))
+}
+
+/// Writes TPV reports as KML document, e.g. for Google Earth.
+///
+/// The document contains the track as `LineString` and, if enabled,
+/// a placemark per fix styled by its horizontal error: green up to
+/// 5 m, yellow up to 20 m, red above and gray without estimate. The
+/// track is kept in memory and written by [`KmlWriter::finish`],
+/// placemarks are written right away.
+pub struct KmlWriter<W: Write> {
+    out: W,
+    name: String,
+    placemarks: bool,
+    thresholds: (f32, f32),
+    started: bool,
+    track: Vec<(f64, f64, Option<f64>)>,
+}
+
+impl<W: Write> KmlWriter<W> {
+    /// Creates a writer of a document named `name`.
+    pub fn new(out: W, name: &str) -> Self {
+        KmlWriter {
+            out,
+            name: name.to_owned(),
+            placemarks: false,
+            thresholds: (5.0, 20.0),
+            started: false,
+            track: Vec::new(),
+        }
+    }
+
+    /// Enables a placemark per fix.
+    pub fn with_placemarks(mut self, placemarks: bool) -> Self {
+        self.placemarks = placemarks;
+        self
+    }
+
+    /// Sets the horizontal errors in meters up to which placemarks are
+    /// styled good and fair, 5 m and 20 m by default.
+    pub fn with_accuracy_thresholds(mut self, good: f32, fair: f32) -> Self {
+        self.thresholds = (good, fair);
+        self
+    }
+
+    fn start(&mut self) -> io::Result<()> {
+        if self.started {
+            return Ok(());
+        }
+        self.started = true;
+        writeln!(self.out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(self.out, r#"<kml xmlns="http://www.opengis.net/kml/2.2">"#)?;
+        writeln!(self.out, "<Document>")?;
+        writeln!(self.out, "<name>{}</name>", escape_xml(&self.name))?;
+        for (id, color) in [
+            ("good", "ff00ff00"),
+            ("fair", "ff00ffff"),
+            ("poor", "ff0000ff"),
+            ("unknown", "ff888888"),
+        ] {
+            writeln!(
+                self.out,
+                "<Style id=\"{}\"><IconStyle><color>{}</color></IconStyle></Style>",
+                id, color
+            )?;
+        }
+        writeln!(
+            self.out,
+            "<Style id=\"track\"><LineStyle><color>ffff0000</color><width>3</width></LineStyle></Style>"
+        )
+    }
+
+    /// Adds `tpv` to the track. Reports without a 2D or 3D fix are
+    /// skipped, returning `false`.
+    pub fn add(&mut self, tpv: &Tpv) -> io::Result<bool> {
+        let (Some(lat), Some(lon)) = (tpv.lat, tpv.lon) else {
+            return Ok(false);
+        };
+        if !matches!(tpv.mode, Mode::Fix2d | Mode::Fix3d) {
+            return Ok(false);
+        }
+        self.start()?;
+        let alt = if tpv.mode == Mode::Fix3d {
+            tpv.altitude_msl()
+        } else {
+            None
+        };
+        self.track.push((lon, lat, alt));
+        if !self.placemarks {
+            return Ok(true);
+        }
+
+        let error = horizontal_error(tpv);
+        let style = match error {
+            Some(e) if e <= self.thresholds.0 => "good",
+            Some(e) if e <= self.thresholds.1 => "fair",
+            Some(_) => "poor",
+            None => "unknown",
+        };
+        write!(self.out, "<Placemark>")?;
+        if let Some(time) = tpv.time {
+            write!(
+                self.out,
+                "<name>{}</name><TimeStamp><when>{}</when></TimeStamp>",
+                time, time
+            )?;
+        }
+        write!(self.out, "<styleUrl>#{}</styleUrl>", style)?;
+        if let Some(e) = error {
+            write!(self.out, "<description>error {:.1} m</description>", e)?;
+        }
+        writeln!(
+            self.out,
+            "<Point><coordinates>{}</coordinates></Point></Placemark>",
+            coordinates(lon, lat, alt)
+        )?;
+        Ok(true)
+    }
+
+    /// Writes the track and closes the document, returning the writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.start()?;
+        if !self.track.is_empty() {
+            // Altitudes are only used if known for every point.
+            let absolute = self.track.iter().all(|p| p.2.is_some());
+            writeln!(
+                self.out,
+                "<Placemark><name>Track</name><styleUrl>#track</styleUrl>"
+            )?;
+            write!(self.out, "<LineString><tessellate>1</tessellate>")?;
+            if absolute {
+                write!(self.out, "<altitudeMode>absolute</altitudeMode>")?;
+            }
+            write!(self.out, "<coordinates>")?;
+            for &(lon, lat, alt) in &self.track {
+                let alt = alt.filter(|_| absolute);
+                writeln!(self.out, "{}", coordinates(lon, lat, alt))?;
+            }
+            writeln!(self.out, "</coordinates></LineString></Placemark>")?;
+        }
+        writeln!(self.out, "</Document>")?;
+        writeln!(self.out, "</kml>")?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+fn coordinates(lon: f64, lat: f64, alt: Option<f64>) -> String {
+    match alt {
+        Some(alt) => format!("{:.7},{:.7},{:.1}", lon, lat, alt),
+        None => format!("{:.7},{:.7}", lon, lat),
+    }
+}
+
+/// Column of a [`CsvWriter`], named in the header as given by
+/// [`CsvColumn::name`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CsvColumn {
+    /// Time of the fix, see [`TimeFormat`].
+    Time,
+    /// Name of the device.
+    Device,
+    /// Fix mode as reported by `gpsd`, 1 for no fix up to 3 for 3D.
+    Mode,
+    /// Fix status as reported by `gpsd`.
+    Status,
+    /// Latitude in degrees.
+    Lat,
+    /// Longitude in degrees.
+    Lon,
+    /// Altitude above mean sea level.
+    AltMsl,
+    /// Altitude above the ellipsoid.
+    AltHae,
+    /// Speed over ground.
+    Speed,
+    /// Course over ground in degrees from true north.
+    Track,
+    /// Vertical speed.
+    Climb,
+    /// Horizontal error estimate, see [`KmlWriter`].
+    Eph,
+    /// Vertical error estimate.
+    Epv,
+}
+
+impl CsvColumn {
+    /// Columns written by default.
+    pub const DEFAULT: &'static [CsvColumn] = &[
+        CsvColumn::Time,
+        CsvColumn::Mode,
+        CsvColumn::Lat,
+        CsvColumn::Lon,
+        CsvColumn::AltMsl,
+        CsvColumn::Speed,
+        CsvColumn::Track,
+        CsvColumn::Eph,
+    ];
+
+    /// Name of the column in the header.
+    pub fn name(self) -> &'static str {
+        match self {
+            CsvColumn::Time => "time",
+            CsvColumn::Device => "device",
+            CsvColumn::Mode => "mode",
+            CsvColumn::Status => "status",
+            CsvColumn::Lat => "lat",
+            CsvColumn::Lon => "lon",
+            CsvColumn::AltMsl => "alt_msl",
+            CsvColumn::AltHae => "alt_hae",
+            CsvColumn::Speed => "speed",
+            CsvColumn::Track => "track",
+            CsvColumn::Climb => "climb",
+            CsvColumn::Eph => "eph",
+            CsvColumn::Epv => "epv",
+        }
+    }
+}
+
+/// Format of the [`CsvColumn::Time`] column.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum TimeFormat {
+    /// ISO 8601 as reported by `gpsd`.
+    #[default]
+    Iso8601,
+    /// Seconds since the Unix epoch, with millisecond fraction.
+    UnixSecs,
+    /// Milliseconds since the Unix epoch.
+    UnixMillis,
+}
+
+/// Unit of the speed columns of a [`CsvWriter`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum SpeedUnit {
+    /// Meters per second, as reported by `gpsd`.
+    #[default]
+    MetersPerSecond,
+    /// Kilometers per hour.
+    KilometersPerHour,
+    /// Knots.
+    Knots,
+    /// Statute miles per hour.
+    MilesPerHour,
+}
+
+impl SpeedUnit {
+    fn factor(self) -> f64 {
+        match self {
+            SpeedUnit::MetersPerSecond => 1.0,
+            SpeedUnit::KilometersPerHour => 3.6,
+            SpeedUnit::Knots => 3600.0 / 1852.0,
+            SpeedUnit::MilesPerHour => 3600.0 / 1609.344,
+        }
+    }
+}
+
+/// Unit of the altitude and error columns of a [`CsvWriter`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum DistanceUnit {
+    /// Meters, as reported by `gpsd`.
+    #[default]
+    Meters,
+    /// International feet.
+    Feet,
+}
+
+impl DistanceUnit {
+    fn factor(self) -> f64 {
+        match self {
+            DistanceUnit::Meters => 1.0,
+            DistanceUnit::Feet => 1.0 / 0.3048,
+        }
+    }
+}
+
+/// Opens the output of a [`CsvWriter`] after rotation, given the
+/// number of the new output counting from 1.
+type Rotate<W> = Box<dyn FnMut(u32) -> io::Result<W> + Send>;
+
+/// Writes TPV reports as CSV, one row per fix.
+///
+/// Fields are separated by commas by default and quoted if needed,
+/// missing values are left empty. Each output starts with a header
+/// naming the columns. Rows are flushed right away unless configured
+/// by [`CsvWriter::with_flush_every`], so a log survives the process
+/// being killed.
+pub struct CsvWriter<W: Write> {
+    out: W,
+    columns: Vec<CsvColumn>,
+    delimiter: char,
+    header: bool,
+    time_format: TimeFormat,
+    speed_unit: SpeedUnit,
+    distance_unit: DistanceUnit,
+    fixes_only: bool,
+    flush_every: usize,
+    unflushed: usize,
+    rows: usize,
+    rotation: Option<(usize, u32, Rotate<W>)>,
+}
+
+impl<W: Write> CsvWriter<W> {
+    /// Creates a writer of [`CsvColumn::DEFAULT`].
+    pub fn new(out: W) -> Self {
+        CsvWriter {
+            out,
+            columns: CsvColumn::DEFAULT.to_vec(),
+            delimiter: ',',
+            header: true,
+            time_format: TimeFormat::default(),
+            speed_unit: SpeedUnit::default(),
+            distance_unit: DistanceUnit::default(),
+            fixes_only: true,
+            flush_every: 1,
+            unflushed: 0,
+            rows: 0,
+            rotation: None,
+        }
+    }
+
+    /// Sets the columns to write.
+    pub fn with_columns(mut self, columns: &[CsvColumn]) -> Self {
+        self.columns = columns.to_vec();
+        self
+    }
+
+    /// Sets the field delimiter, `,` by default.
+    pub fn with_delimiter(mut self, delimiter: char) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Enables the header row, enabled by default.
+    pub fn with_header(mut self, header: bool) -> Self {
+        self.header = header;
+        self
+    }
+
+    /// Sets the format of [`CsvColumn::Time`].
+    pub fn with_time_format(mut self, time_format: TimeFormat) -> Self {
+        self.time_format = time_format;
+        self
+    }
+
+    /// Sets the unit of [`CsvColumn::Speed`] and [`CsvColumn::Climb`].
+    pub fn with_speed_unit(mut self, speed_unit: SpeedUnit) -> Self {
+        self.speed_unit = speed_unit;
+        self
+    }
+
+    /// Sets the unit of the altitude and error columns.
+    pub fn with_distance_unit(mut self, distance_unit: DistanceUnit) -> Self {
+        self.distance_unit = distance_unit;
+        self
+    }
+
+    /// Writes reports without a 2D or 3D fix too, skipped by default.
+    pub fn with_fixes_only(mut self, fixes_only: bool) -> Self {
+        self.fixes_only = fixes_only;
+        self
+    }
+
+    /// Flushes the output after every `rows` rows, after each row by
+    /// default. Zero only flushes on rotation and
+    /// [`CsvWriter::finish`].
+    pub fn with_flush_every(mut self, rows: usize) -> Self {
+        self.flush_every = rows;
+        self
+    }
+
+    /// Continues in a new output opened by `open` after every
+    /// `max_rows` rows, the header repeated. `open` gets the number of
+    /// the new output, counting from 1.
+    pub fn with_rotation<F>(mut self, max_rows: usize, open: F) -> Self
+    where
+        F: FnMut(u32) -> io::Result<W> + Send + 'static,
+    {
+        self.rotation = Some((max_rows.max(1), 0, Box::new(open)));
+        self
+    }
+
+    /// Writes a row of `tpv`, returning `false` if it has been skipped.
+    pub fn add(&mut self, tpv: &Tpv) -> io::Result<bool> {
+        if self.fixes_only && !matches!(tpv.mode, Mode::Fix2d | Mode::Fix3d) {
+            return Ok(false);
+        }
+        if let Some((max_rows, n, open)) = &mut self.rotation {
+            if self.rows == *max_rows {
+                self.out.flush()?;
+                *n += 1;
+                self.out = open(*n)?;
+                self.rows = 0;
+                self.unflushed = 0;
+            }
+        }
+        if self.rows == 0 && self.header {
+            let names: Vec<_> = self.columns.iter().map(|c| c.name()).collect();
+            self.write_row(&names)?;
+        }
+
+        let fields: Vec<_> = self.columns.iter().map(|&c| self.field(c, tpv)).collect();
+        let fields: Vec<_> = fields.iter().map(String::as_str).collect();
+        self.write_row(&fields)?;
+        self.rows += 1;
+        self.unflushed += 1;
+        if self.flush_every > 0 && self.unflushed >= self.flush_every {
+            self.out.flush()?;
+            self.unflushed = 0;
+        }
+        Ok(true)
+    }
+
+    /// Writes a row if `msg` is a TPV report, for attaching the writer
+    /// to a stream of messages.
+    pub fn add_response(&mut self, msg: &UnifiedResponse) -> io::Result<bool> {
+        match msg {
+            UnifiedResponse::Tpv(tpv) => self.add(tpv),
+            _ => Ok(false),
+        }
+    }
+
+    /// Flushes the output and returns it.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.out.flush()?;
+        Ok(self.out)
+    }
+
+    fn write_row(&mut self, fields: &[&str]) -> io::Result<()> {
+        let mut line = String::new();
+        for (i, field) in fields.iter().enumerate() {
+            if i > 0 {
+                line.push(self.delimiter);
+            }
+            if field.contains([self.delimiter, '"', '\n', '\r']) {
+                line.push('"');
+                line.push_str(&field.replace('"', "\"\""));
+                line.push('"');
+            } else {
+                line.push_str(field);
+            }
+        }
+        line.push('\n');
+        self.out.write_all(line.as_bytes())
+    }
+
+    fn field(&self, column: CsvColumn, tpv: &Tpv) -> String {
+        let speed =
+            |v: Option<f32>| v.map(|v| format!("{:.3}", f64::from(v) * self.speed_unit.factor()));
+        let distance =
+            |v: Option<f64>| v.map(|v| format!("{:.3}", v * self.distance_unit.factor()));
+        let value = match column {
+            CsvColumn::Time => tpv.time.map(|t| match self.time_format {
+                TimeFormat::Iso8601 => t.to_string(),
+                TimeFormat::UnixSecs | TimeFormat::UnixMillis => {
+                    let ms = t
+                        .to_system_time()
+                        .duration_since(UNIX_EPOCH)
+                        .map_or(0, |d| d.as_millis());
+                    if self.time_format == TimeFormat::UnixSecs {
+                        format!("{}.{:03}", ms / 1000, ms % 1000)
+                    } else {
+                        ms.to_string()
+                    }
+                }
+            }),
+            CsvColumn::Device => tpv.device.as_ref().map(|d| d.to_string()),
+            CsvColumn::Mode => Some(u8::from(tpv.mode).to_string()),
+            CsvColumn::Status => tpv.status.map(|s| u8::from(s).to_string()),
+            CsvColumn::Lat => tpv.lat.map(|v| format!("{:.7}", v)),
+            CsvColumn::Lon => tpv.lon.map(|v| format!("{:.7}", v)),
+            CsvColumn::AltMsl => distance(tpv.altitude_msl()),
+            CsvColumn::AltHae => distance(tpv.alt_hae.map(f64::from)),
+            CsvColumn::Speed => speed(tpv.speed),
+            CsvColumn::Track => tpv.track.map(|v| format!("{:.1}", v)),
+            CsvColumn::Climb => speed(tpv.climb),
+            CsvColumn::Eph => distance(horizontal_error(tpv).map(f64::from)),
+            CsvColumn::Epv => distance(tpv.epv.map(f64::from)),
+        };
+        value.unwrap_or_default()
+    }
+}
+
+impl CsvWriter<BufWriter<File>> {
+    /// Creates a writer of the file at `path` which is rotated after
+    /// `max_rows` rows, continuing in files numbered like
+    /// `track.1.csv` for a `path` of `track.csv`.
+    pub fn create_rotating<P: AsRef<Path>>(path: P, max_rows: usize) -> io::Result<Self> {
+        let path = path.as_ref().to_owned();
+        let out = BufWriter::new(File::create(&path)?);
+        Ok(CsvWriter::new(out).with_rotation(max_rows, move |n| {
+            let mut name = path.file_stem().unwrap_or_default().to_owned();
+            name.push(format!(".{}", n));
+            if let Some(ext) = path.extension() {
+                name.push(".");
+                name.push(ext);
+            }
+            File::create(path.with_file_name(name)).map(BufWriter::new)
+        }))
+    }
+}
+
+/// Converts TPV, ATT and AIS reports to Signal K delta messages, in
+/// the SI units of Signal K.
+///
+/// TPV and ATT reports update the own vessel, AIS reports the vessel
+/// of their MMSI. AIS reports are only converted from scaled reports,
+/// see [`crate::WatchOptions`], except for the vessel name, call sign
+/// and destination.
+#[derive(Debug, Clone)]
+pub struct SignalKConverter {
+    label: String,
+    context: String,
+}
+
+impl Default for SignalKConverter {
+    fn default() -> Self {
+        SignalKConverter {
+            label: "gpsd".into(),
+            context: "vessels.self".into(),
+        }
+    }
+}
+
+impl SignalKConverter {
+    /// Creates a converter with `gpsd` as source label.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the label of the source of all updates.
+    pub fn with_source_label(mut self, label: &str) -> Self {
+        self.label = label.into();
+        self
+    }
+
+    /// Sets the context of the own vessel, `vessels.self` by default.
+    pub fn with_self_context(mut self, context: &str) -> Self {
+        self.context = context.into();
+        self
+    }
+
+    /// Delta message of `msg`, or `None` for other classes and reports
+    /// without any value.
+    pub fn delta(&self, msg: &UnifiedResponse) -> Option<serde_json::Value> {
+        let mut values = Vec::new();
+        let mut push = |path: &str, value: Option<serde_json::Value>| {
+            if let Some(value) = value {
+                values.push(serde_json::json!({ "path": path, "value": value }));
+            }
+        };
+        let rad = |deg: Option<f32>| deg.map(|v| f64::from(v).to_radians().into());
+        let (context, time, device) = match msg {
+            UnifiedResponse::Tpv(tpv) => {
+                if matches!(tpv.mode, Mode::Fix2d | Mode::Fix3d) {
+                    let position = tpv.lat.zip(tpv.lon).map(|(lat, lon)| {
+                        let mut position = serde_json::json!({ "latitude": lat, "longitude": lon });
+                        if let Some(alt) = tpv.altitude_msl().filter(|_| tpv.mode == Mode::Fix3d) {
+                            position["altitude"] = alt.into();
+                        }
+                        position
+                    });
+                    push("navigation.position", position);
+                    push(
+                        "navigation.speedOverGround",
+                        tpv.speed.map(|v| f64::from(v).into()),
+                    );
+                    push("navigation.courseOverGroundTrue", rad(tpv.track));
+                }
+                if let Some(time) = tpv.time {
+                    push("navigation.datetime", Some(time.as_str().into()));
+                }
+                (self.context.clone(), tpv.time, tpv.device.as_deref())
+            }
+            #[cfg(feature = "att")]
+            UnifiedResponse::Att(att) => {
+                push("navigation.headingTrue", rad(att.heading));
+                push("navigation.headingMagnetic", rad(att.mheading));
+                if att.roll.is_some() || att.pitch.is_some() || att.yaw.is_some() {
+                    push(
+                        "navigation.attitude",
+                        Some(serde_json::json!({
+                            "roll": rad(att.roll),
+                            "pitch": rad(att.pitch),
+                            "yaw": rad(att.yaw),
+                        })),
+                    );
+                }
+                // Degrees per minute to radians per second.
+                push(
+                    "navigation.rateOfTurn",
+                    att.rot.map(|v| (f64::from(v).to_radians() / 60.0).into()),
+                );
+                push(
+                    "environment.depth.belowTransducer",
+                    att.depth.map(|v| f64::from(v).into()),
+                );
+                push(
+                    "environment.water.temperature",
+                    att.temp.map(|v| (f64::from(v) + 273.15).into()),
+                );
+                (self.context.clone(), att.time, att.device.as_deref())
+            }
+            #[cfg(feature = "ais")]
+            UnifiedResponse::Ais(ais) => {
+                if ais.scaled == Some(true) {
+                    // Values reserved for "not available".
+                    let position = ais
+                        .lat
+                        .zip(ais.lon)
+                        .filter(|&(lat, lon)| lat.abs() <= 90.0 && lon.abs() <= 180.0)
+                        .map(|(lat, lon)| serde_json::json!({ "latitude": lat, "longitude": lon }));
+                    push("navigation.position", position);
+                    push(
+                        "navigation.speedOverGround",
+                        ais.speed
+                            .filter(|&v| v < 102.3)
+                            .map(|v| (v * 1852.0 / 3600.0).into()),
+                    );
+                    push(
+                        "navigation.courseOverGroundTrue",
+                        ais.course
+                            .filter(|&v| v < 360.0)
+                            .map(|v| v.to_radians().into()),
+                    );
+                    push(
+                        "navigation.headingTrue",
+                        ais.heading
+                            .filter(|&v| v < 360)
+                            .map(|v| f64::from(v).to_radians().into()),
+                    );
+                }
+                let name = ais.shipname.as_deref().map(|name| name.trim_end().into());
+                let callsign = ais.callsign.as_deref().map(|c| c.trim_end().into());
+                let destination = ais.destination.as_deref().map(|d| d.trim_end().into());
+                push(
+                    "",
+                    name.map(|name: serde_json::Value| serde_json::json!({ "name": name })),
+                );
+                push("communication.callsignVhf", callsign);
+                push("navigation.destination.commonName", destination);
+                let context = format!("vessels.urn:mrn:imo:mmsi:{:09}", ais.mmsi);
+                (context, None, ais.device.as_deref())
+            }
+            _ => return None,
+        };
+        if values.is_empty() {
+            return None;
+        }
+
+        let mut source = serde_json::json!({ "label": self.label });
+        if let Some(device) = device {
+            source["src"] = device.into();
+        }
+        let mut update = serde_json::json!({ "source": source, "values": values });
+        if let Some(time) = time {
+            update["timestamp"] = time.as_str().into();
+        }
+        Some(serde_json::json!({ "context": context, "updates": [update] }))
+    }
+}
+
+/// GeoJSON `Point` feature of `tpv`, or `None` without a 2D or 3D fix.
+///
+/// The altitude above mean sea level is the third coordinate of 3D
+/// fixes. The properties hold the time, mode, speed, track, climb and
+/// the horizontal and vertical error, as far as reported.
+#[cfg(feature = "geojson")]
+pub fn tpv_feature(tpv: &Tpv) -> Option<serde_json::Value> {
+    let (lat, lon) = (tpv.lat?, tpv.lon?);
+    if !matches!(tpv.mode, Mode::Fix2d | Mode::Fix3d) {
+        return None;
+    }
+    let mut properties = serde_json::Map::new();
+    properties.insert("mode".into(), u8::from(tpv.mode).into());
+    if let Some(time) = tpv.time {
+        properties.insert("time".into(), time.as_str().into());
+    }
+    for (name, value) in [
+        ("speed", tpv.speed),
+        ("track", tpv.track),
+        ("climb", tpv.climb),
+        ("eph", horizontal_error(tpv)),
+        ("epv", tpv.epv),
+    ] {
+        if let Some(v) = value {
+            properties.insert(name.into(), f64::from(v).into());
+        }
+    }
+    Some(serde_json::json!({
+        "type": "Feature",
+        "geometry": {
+            "type": "Point",
+            "coordinates": geojson_position(tpv, lon, lat),
+        },
+        "properties": properties,
+    }))
+}
+
+#[cfg(feature = "geojson")]
+fn geojson_position(tpv: &Tpv, lon: f64, lat: f64) -> Vec<f64> {
+    match tpv.altitude_msl().filter(|_| tpv.mode == Mode::Fix3d) {
+        Some(alt) => vec![lon, lat, alt],
+        None => vec![lon, lat],
+    }
+}
+
+/// Track accumulated from TPV reports, serialized as GeoJSON
+/// `LineString` feature.
+#[cfg(feature = "geojson")]
+#[derive(Debug, Clone, Default)]
+pub struct GeoJsonTrack {
+    coordinates: Vec<Vec<f64>>,
+    times: Vec<Option<String>>,
+}
+
+#[cfg(feature = "geojson")]
+impl GeoJsonTrack {
+    /// Creates an empty track.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `tpv` to the track. Reports without a 2D or 3D fix are
+    /// skipped, returning `false`.
+    pub fn add(&mut self, tpv: &Tpv) -> bool {
+        let (Some(lat), Some(lon)) = (tpv.lat, tpv.lon) else {
+            return false;
+        };
+        if !matches!(tpv.mode, Mode::Fix2d | Mode::Fix3d) {
+            return false;
+        }
+        self.coordinates.push(geojson_position(tpv, lon, lat));
+        self.times.push(tpv.time.map(|t| t.to_string()));
+        true
+    }
+
+    /// Number of points of the track.
+    pub fn len(&self) -> usize {
+        self.coordinates.len()
+    }
+
+    /// `true` if no point has been added.
+    pub fn is_empty(&self) -> bool {
+        self.coordinates.is_empty()
+    }
+
+    /// GeoJSON `LineString` feature of the track. The time of each
+    /// point is kept in the `times` property, as in the common
+    /// `coordTimes` convention.
+    pub fn to_feature(&self) -> serde_json::Value {
+        serde_json::json!({
+            "type": "Feature",
+            "geometry": {
+                "type": "LineString",
+                "coordinates": self.coordinates,
+            },
+            "properties": { "times": self.times },
+        })
+    }
+}
+
+/// GeoJSON `FeatureCollection` of `features`.
+#[cfg(feature = "geojson")]
+pub fn feature_collection<I>(features: I) -> serde_json::Value
+where
+    I: IntoIterator<Item = serde_json::Value>,
+{
+    serde_json::json!({
+        "type": "FeatureCollection",
+        "features": features.into_iter().collect::<Vec<_>>(),
+    })
+}
+
+/// Value of an InfluxDB field.
+enum InfluxValue {
+    Float(f64),
+    Integer(i64),
+}
+
+/// Formats TPV, SKY and PPS reports as InfluxDB line protocol.
+///
+/// Each report becomes one line of measurement `tpv`, `sky` or `pps`,
+/// tagged with the device. The fields carry the names of the JSON
+/// attributes, except for `used` and `satellites` of SKY, counting
+/// the satellites used and in view, and `offset_ns` of PPS. Missing
+/// values are omitted. The timestamp is the report time in
+/// nanoseconds, or the PPS edge; lines of reports without time get
+/// the time of the database server.
+#[derive(Debug, Clone)]
+pub struct InfluxFormatter {
+    measurements: [String; 3],
+    device_tag: Option<String>,
+    tags: Vec<(String, String)>,
+    fields: BTreeMap<String, Option<String>>,
+}
+
+impl Default for InfluxFormatter {
+    fn default() -> Self {
+        InfluxFormatter {
+            measurements: ["tpv".into(), "sky".into(), "pps".into()],
+            device_tag: Some("device".into()),
+            tags: Vec::new(),
+            fields: BTreeMap::new(),
+        }
+    }
+}
+
+impl InfluxFormatter {
+    /// Creates a formatter with the default mapping.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the measurement names of TPV, SKY and PPS reports.
+    pub fn with_measurements(mut self, tpv: &str, sky: &str, pps: &str) -> Self {
+        self.measurements = [tpv.into(), sky.into(), pps.into()];
+        self
+    }
+
+    /// Sets the tag key of the device, `device` by default, or `None`
+    /// to leave the device out.
+    pub fn with_device_tag(mut self, key: Option<&str>) -> Self {
+        self.device_tag = key.map(str::to_owned);
+        self
+    }
+
+    /// Adds a tag to every line, e.g. the host name.
+    pub fn with_tag(mut self, key: &str, value: &str) -> Self {
+        self.tags.push((key.into(), value.into()));
+        self
+    }
+
+    /// Writes the field `field` under the key `key`.
+    pub fn rename_field(mut self, field: &str, key: &str) -> Self {
+        self.fields.insert(field.into(), Some(key.into()));
+        self
+    }
+
+    /// Leaves the field `field` out.
+    pub fn skip_field(mut self, field: &str) -> Self {
+        self.fields.insert(field.into(), None);
+        self
+    }
+
+    /// Formats `msg` as a line, without line terminator. Returns `None`
+    /// for other classes and reports without any field.
+    pub fn format(&self, msg: &UnifiedResponse) -> Option<String> {
+        let real = |name, v: Option<f32>| v.map(|v| (name, InfluxValue::Float(f64::from(v))));
+        let int = |name, v: Option<i64>| v.map(|v| (name, InfluxValue::Integer(v)));
+        let (measurement, device, time, fields) = match msg {
+            UnifiedResponse::Tpv(tpv) => {
+                let fields = vec![
+                    int("mode", Some(u8::from(tpv.mode).into())),
+                    int("status", tpv.status.map(|s| u8::from(s).into())),
+                    tpv.lat.map(|v| ("lat", InfluxValue::Float(v))),
+                    tpv.lon.map(|v| ("lon", InfluxValue::Float(v))),
+                    tpv.altitude_msl()
+                        .map(|v| ("alt_msl", InfluxValue::Float(v))),
+                    real("alt_hae", tpv.alt_hae),
+                    real("speed", tpv.speed),
+                    real("track", tpv.track),
+                    real("climb", tpv.climb),
+                    real("eph", horizontal_error(tpv)),
+                    real("epv", tpv.epv),
+                ];
+                (0, tpv.device.as_deref(), tpv.time.map(unix_ns), fields)
+            }
+            UnifiedResponse::Sky(sky) => {
+                let sats = sky.satellites.as_deref().unwrap_or_default();
+                let fields = vec![
+                    real("hdop", sky.hdop),
+                    real("vdop", sky.vdop),
+                    real("pdop", sky.pdop),
+                    int("satellites", Some(sats.len() as i64)),
+                    int("used", Some(sats.iter().filter(|s| s.used).count() as i64)),
+                ];
+                (1, sky.device.as_deref(), sky.time.map(unix_ns), fields)
+            }
+            UnifiedResponse::Pps(pps) => {
+                let fields = vec![
+                    int("offset_ns", Some(pps.offset_ns())),
+                    real("precision", Some(pps.precision)),
+                    int("qErr", pps.q_err),
+                ];
+                let time = pps.real_sec as i128 * 1_000_000_000 + pps.real_nsec as i128;
+                (2, Some(pps.device.as_str()), Some(time), fields)
+            }
+            _ => return None,
+        };
+
+        let mut line = escape_influx(&self.measurements[measurement], ", ");
+        let device = self.device_tag.as_deref().zip(device);
+        for (key, value) in device.into_iter().chain(
+            self.tags
+                .iter()
+                .map(|(key, value)| (key.as_str(), value.as_str())),
+        ) {
+            line.push(',');
+            line.push_str(&escape_influx(key, ",= "));
+            line.push('=');
+            line.push_str(&escape_influx(value, ",= "));
+        }
+        let mut sep = ' ';
+        for (field, value) in fields.into_iter().flatten() {
+            let key = match self.fields.get(field) {
+                Some(Some(key)) => key.as_str(),
+                Some(None) => continue,
+                None => field,
+            };
+            line.push(sep);
+            sep = ',';
+            line.push_str(&escape_influx(key, ",= "));
+            match value {
+                InfluxValue::Float(v) => line.push_str(&format!("={}", v)),
+                InfluxValue::Integer(v) => line.push_str(&format!("={}i", v)),
+            }
+        }
+        if sep == ' ' {
+            return None;
+        }
+        if let Some(time) = time {
+            line.push_str(&format!(" {}", time));
+        }
+        Some(line)
+    }
+
+    /// Writes the line of `msg` to `out`, returning `false` if there
+    /// is none.
+    pub fn write<W: Write>(&self, out: &mut W, msg: &UnifiedResponse) -> io::Result<bool> {
+        match self.format(msg) {
+            Some(line) => writeln!(out, "{}", line).map(|()| true),
+            None => Ok(false),
+        }
+    }
+}
+
+/// Escapes the characters of `special` and backslashes in `s`.
+fn escape_influx(s: &str, special: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        if c == '\\' || special.contains(c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+fn unix_ns(time: Iso8601Timestamp) -> i128 {
+    match time.to_system_time().duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_nanos() as i128,
+        Err(e) => -(e.duration().as_nanos() as i128),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        CsvColumn, CsvWriter, DistanceUnit, InfluxFormatter, KmlWriter, SignalKConverter,
+        SpeedUnit, TimeFormat,
+    };
+    use crate::{Tpv, UnifiedResponse};
+
+    fn tpv(json: &str) -> Tpv {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn writes_kml() {
+        let mut kml = KmlWriter::new(Vec::new(), "Drive <1>").with_placemarks(true);
+        assert!(kml
+            .add(&tpv(
+                r#"{"mode":3,"time":"2024-05-01T12:00:00.000Z","lat":48.1,"lon":11.5,"altMSL":520.0,"eph":3.2}"#
+            ))
+            .unwrap());
+        assert!(kml
+            .add(&tpv(
+                r#"{"mode":3,"lat":48.2,"lon":11.6,"altMSL":530.0,"epx":30.0,"epy":12.0}"#
+            ))
+            .unwrap());
+        assert!(!kml.add(&tpv(r#"{"mode":1}"#)).unwrap());
+        let out = String::from_utf8(kml.finish().unwrap()).unwrap();
+
+        assert!(out.contains("<name>Drive &lt;1&gt;</name>"));
+        assert!(out.contains(
+            "<name>2024-05-01T12:00:00.000Z</name><TimeStamp><when>2024-05-01T12:00:00.000Z</when></TimeStamp><styleUrl>#good</styleUrl><description>error 3.2 m</description>"
+        ));
+        assert!(out.contains("<styleUrl>#poor</styleUrl>"));
+        assert!(out.contains("<altitudeMode>absolute</altitudeMode>"));
+        assert!(out.contains(
+            "<coordinates>11.5000000,48.1000000,520.0\n11.6000000,48.2000000,530.0\n</coordinates>"
+        ));
+        assert!(out.ends_with("</Document>\n</kml>\n"));
+
+        let out = KmlWriter::new(Vec::new(), "Empty").finish().unwrap();
+        assert!(!String::from_utf8(out).unwrap().contains("LineString"));
+    }
+
+    #[test]
+    fn writes_csv() {
+        let fix = tpv(
+            r#"{"device":"/dev/tty,0","mode":3,"time":"2024-05-01T12:00:00.250Z","lat":48.1,"lon":11.5,"altMSL":100.0,"speed":10.0,"epx":3.0,"epy":4.0}"#,
+        );
+        let mut csv = CsvWriter::new(Vec::new())
+            .with_columns(&[
+                CsvColumn::Time,
+                CsvColumn::Device,
+                CsvColumn::Lat,
+                CsvColumn::AltMsl,
+                CsvColumn::Speed,
+                CsvColumn::Eph,
+                CsvColumn::Track,
+            ])
+            .with_time_format(TimeFormat::UnixSecs)
+            .with_speed_unit(SpeedUnit::KilometersPerHour)
+            .with_distance_unit(DistanceUnit::Feet);
+        assert!(csv.add(&fix).unwrap());
+        assert!(!csv.add(&tpv(r#"{"mode":1}"#)).unwrap());
+        let msg: UnifiedResponse =
+            serde_json::from_str(r#"{"class":"TPV","mode":2,"lat":1.0,"lon":2.0}"#).unwrap();
+        assert!(csv.add_response(&msg).unwrap());
+        assert_eq!(
+            String::from_utf8(csv.finish().unwrap()).unwrap(),
+            "time,device,lat,alt_msl,speed,eph,track\n\
+             1714564800.250,\"/dev/tty,0\",48.1000000,328.084,36.000,13.123,\n\
+             ,,1.0000000,,,,\n"
+        );
+
+        let mut csv = CsvWriter::new(Vec::new())
+            .with_columns(&[CsvColumn::Time, CsvColumn::Lat])
+            .with_time_format(TimeFormat::UnixMillis)
+            .with_delimiter(';')
+            .with_rotation(2, |_| Ok(Vec::new()));
+        for _ in 0..3 {
+            csv.add(&fix).unwrap();
+        }
+        assert_eq!(
+            String::from_utf8(csv.finish().unwrap()).unwrap(),
+            "time;lat\n1714564800250;48.1000000\n"
+        );
+    }
+
+    #[test]
+    fn formats_influx_lines() {
+        let msg = |json: &str| serde_json::from_str::<UnifiedResponse>(json).unwrap();
+        let tpv = msg(
+            r#"{"class":"TPV","device":"/dev/tty S0","mode":3,"time":"2024-05-01T12:00:00.500Z","lat":48.1,"lon":11.5,"speed":1.5,"epx":2.0,"epy":3.0}"#,
+        );
+        let influx = InfluxFormatter::new();
+        assert_eq!(
+            influx.format(&tpv).unwrap(),
+            r"tpv,device=/dev/tty\ S0 mode=3i,lat=48.1,lon=11.5,speed=1.5,eph=3 1714564800500000000"
+        );
+        let sky = msg(r#"{"class":"SKY","hdop":1.5,"satellites":[{"PRN":3,"used":true}]}"#);
+        assert_eq!(
+            influx.format(&sky).unwrap(),
+            "sky hdop=1.5,satellites=1i,used=1i"
+        );
+        let pps = msg(
+            r#"{"class":"PPS","device":"pps0","real_sec":1700000000,"real_nsec":0,"clock_sec":1699999999,"clock_nsec":999999000,"precision":-20}"#,
+        );
+        assert_eq!(
+            influx.format(&pps).unwrap(),
+            "pps,device=pps0 offset_ns=1000i,precision=-20 1700000000000000000"
+        );
+        assert_eq!(
+            influx.format(&msg(r#"{"class":"ERROR","message":"x"}"#)),
+            None
+        );
+
+        let influx = InfluxFormatter::new()
+            .with_measurements("fix", "sats", "pulse")
+            .with_device_tag(None)
+            .with_tag("host", "boat,1")
+            .rename_field("lat", "latitude")
+            .skip_field("mode")
+            .skip_field("speed")
+            .skip_field("eph");
+        let mut out = Vec::new();
+        assert!(influx.write(&mut out, &tpv).unwrap());
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "fix,host=boat\\,1 latitude=48.1,lon=11.5 1714564800500000000\n"
+        );
+    }
+
+    #[test]
+    fn converts_to_signalk() {
+        let msg = |json: &str| serde_json::from_str::<UnifiedResponse>(json).unwrap();
+        let signalk = SignalKConverter::new();
+        let delta = signalk
+            .delta(&msg(
+                r#"{"class":"TPV","device":"/dev/ttyS0","mode":2,"time":"2024-05-01T12:00:00.000Z","lat":54.3,"lon":10.1,"speed":2.5,"track":180.0}"#,
+            ))
+            .unwrap();
+        assert_eq!(delta["context"], "vessels.self");
+        let update = &delta["updates"][0];
+        assert_eq!(update["source"]["src"], "/dev/ttyS0");
+        assert_eq!(update["timestamp"], "2024-05-01T12:00:00.000Z");
+        assert_eq!(update["values"][0]["path"], "navigation.position");
+        assert_eq!(
+            update["values"][0]["value"],
+            serde_json::json!({"latitude": 54.3, "longitude": 10.1})
+        );
+        assert_eq!(update["values"][1]["value"], 2.5);
+        assert_eq!(update["values"][2]["value"], std::f64::consts::PI);
+        assert_eq!(signalk.delta(&msg(r#"{"class":"TPV","mode":1}"#)), None);
+
+        #[cfg(feature = "att")]
+        {
+            let delta = signalk
+                .delta(&msg(
+                    r#"{"class":"ATT","heading":90.0,"depth":4.5,"temp":15.0}"#,
+                ))
+                .unwrap();
+            let values = &delta["updates"][0]["values"];
+            assert_eq!(values[0]["path"], "navigation.headingTrue");
+            assert_eq!(values[0]["value"], std::f64::consts::FRAC_PI_2);
+            assert_eq!(values[1]["path"], "environment.depth.belowTransducer");
+            assert_eq!(values[1]["value"], 4.5);
+            assert_eq!(values[2]["value"], 288.15);
+        }
+
+        #[cfg(feature = "ais")]
+        {
+            let delta = signalk
+                .delta(&msg(
+                    r#"{"class":"AIS","type":1,"mmsi":211234567,"scaled":true,"speed":10.0,"lat":54.0,"lon":10.0,"course":360.0,"heading":511,"shipname":"SEA CLOUD  "}"#,
+                ))
+                .unwrap();
+            assert_eq!(delta["context"], "vessels.urn:mrn:imo:mmsi:211234567");
+            let values = delta["updates"][0]["values"].as_array().unwrap();
+            assert_eq!(values.len(), 3, "{:?}", values);
+            assert!((values[1]["value"].as_f64().unwrap() - 5.144_444).abs() < 1e-6);
+            assert_eq!(
+                values[2],
+                serde_json::json!({"path": "", "value": {"name": "SEA CLOUD"}})
+            );
+        }
+    }
+
+    #[cfg(feature = "geojson")]
+    #[test]
+    fn converts_to_geojson() {
+        use super::{feature_collection, tpv_feature, GeoJsonTrack};
+
+        let fix = tpv(
+            r#"{"mode":3,"time":"2024-05-01T12:00:00.000Z","lat":48.1,"lon":11.5,"altMSL":500.0,"speed":2.5,"eph":4.0}"#,
+        );
+        let point = tpv_feature(&fix).unwrap();
+        assert_eq!(point["geometry"]["type"], "Point");
+        assert_eq!(
+            point["geometry"]["coordinates"],
+            serde_json::json!([11.5, 48.1, 500.0])
+        );
+        assert_eq!(point["properties"]["speed"], 2.5);
+        assert_eq!(point["properties"]["eph"], 4.0);
+        assert_eq!(point["properties"]["time"], "2024-05-01T12:00:00.000Z");
+        assert!(point["properties"].get("track").is_none());
+        assert_eq!(tpv_feature(&tpv(r#"{"mode":1,"lat":1.0,"lon":2.0}"#)), None);
+
+        let mut track = GeoJsonTrack::new();
+        assert!(track.add(&fix));
+        assert!(track.add(&tpv(r#"{"mode":2,"lat":48.2,"lon":11.6}"#)));
+        assert!(!track.add(&tpv(r#"{"mode":1}"#)));
+        assert_eq!(track.len(), 2);
+        let line = track.to_feature();
+        assert_eq!(
+            line["geometry"]["coordinates"],
+            serde_json::json!([[11.5, 48.1, 500.0], [11.6, 48.2]])
+        );
+        assert_eq!(
+            line["properties"]["times"],
+            serde_json::json!(["2024-05-01T12:00:00.000Z", null])
+        );
+        let collection = feature_collection([point, line]);
+        assert_eq!(collection["features"].as_array().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,830 @@
+//! Smoothing and plausibility filters for TPV reports.
+
+use crate::geo::{distance_m, EARTH_RADIUS_M};
+#[cfg(feature = "att")]
+use crate::Att;
+use crate::{Iso8601Timestamp, Mode, Tpv};
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// Position error in meters assumed for reports without estimate.
+const DEFAULT_POSITION_ERROR_M: f64 = 10.0;
+
+/// Smoothed position and velocity, see [`KalmanFilter`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Estimate {
+    /// Time of the report the estimate is based on.
+    pub time: Iso8601Timestamp,
+    /// Latitude in degrees.
+    pub lat: f64,
+    /// Longitude in degrees.
+    pub lon: f64,
+    /// Speed over ground in meters per second.
+    pub speed: f64,
+    /// Course over ground in degrees from true north.
+    pub track: f64,
+}
+
+/// Position and velocity along one axis with its covariance.
+#[derive(Debug, Clone, Copy)]
+struct Axis {
+    pos: f64,
+    vel: f64,
+    p: [[f64; 2]; 2],
+}
+
+impl Axis {
+    fn new(pos: f64, pos_var: f64) -> Self {
+        Axis {
+            pos,
+            vel: 0.0,
+            // Velocity is unknown initially.
+            p: [[pos_var, 0.0], [0.0, 100.0]],
+        }
+    }
+
+    fn predict(&mut self, dt: f64, q: f64) {
+        let p = self.p;
+        self.pos += self.vel * dt;
+        let p00 = p[0][0] + dt * (p[1][0] + p[0][1]) + dt * dt * p[1][1];
+        let p01 = p[0][1] + dt * p[1][1];
+        let p10 = p[1][0] + dt * p[1][1];
+        self.p = [
+            [p00 + q * dt.powi(4) / 4.0, p01 + q * dt.powi(3) / 2.0],
+            [p10 + q * dt.powi(3) / 2.0, p[1][1] + q * dt * dt],
+        ];
+    }
+
+    fn update_pos(&mut self, z: f64, r: f64) {
+        let p = self.p;
+        let s = p[0][0] + r;
+        let (k0, k1) = (p[0][0] / s, p[1][0] / s);
+        let y = z - self.pos;
+        self.pos += k0 * y;
+        self.vel += k1 * y;
+        self.p = [
+            [(1.0 - k0) * p[0][0], (1.0 - k0) * p[0][1]],
+            [p[1][0] - k1 * p[0][0], p[1][1] - k1 * p[0][1]],
+        ];
+    }
+
+    fn update_vel(&mut self, z: f64, r: f64) {
+        let p = self.p;
+        let s = p[1][1] + r;
+        let (k0, k1) = (p[0][1] / s, p[1][1] / s);
+        let y = z - self.vel;
+        self.pos += k0 * y;
+        self.vel += k1 * y;
+        self.p = [
+            [p[0][0] - k0 * p[1][0], p[0][1] - k0 * p[1][1]],
+            [(1.0 - k1) * p[1][0], (1.0 - k1) * p[1][1]],
+        ];
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct KalmanState {
+    /// Origin of the local east/north plane in degrees.
+    origin: (f64, f64),
+    time: Iso8601Timestamp,
+    east: Axis,
+    north: Axis,
+}
+
+/// Constant-velocity Kalman filter smoothing TPV reports.
+///
+/// Positions are filtered in a local east/north plane around the
+/// first fix. The error estimates `epx`, `epy` and `eps` of the
+/// reports are used as measurement noise. Reports without a fix or
+/// without time are ignored.
+#[derive(Debug, Clone)]
+pub struct KalmanFilter {
+    accel_noise: f64,
+    state: Option<KalmanState>,
+}
+
+impl KalmanFilter {
+    /// Creates a filter expecting accelerations with the standard
+    /// deviation `accel_noise` in m/s², e.g. 1 for pedestrians or 3
+    /// for cars. Larger values follow the fixes more closely.
+    pub fn new(accel_noise: f64) -> Self {
+        KalmanFilter {
+            accel_noise,
+            state: None,
+        }
+    }
+
+    /// Forgets the current estimate, e.g. after a fix outage.
+    pub fn reset(&mut self) {
+        self.state = None;
+    }
+
+    /// Feeds a report and returns the updated estimate, `None` if the
+    /// report is ignored.
+    pub fn update(&mut self, tpv: &Tpv) -> Option<Estimate> {
+        if !matches!(tpv.mode, Mode::Fix2d | Mode::Fix3d) {
+            return None;
+        }
+        let (lat, lon, time) = (tpv.lat?, tpv.lon?, tpv.time?);
+        // The error estimates are 95% confidence, about two sigma.
+        let var = |ep: Option<f32>, default: f64| (ep.map_or(default, f64::from) / 2.0).powi(2);
+        let (var_x, var_y) = (
+            var(tpv.epx, DEFAULT_POSITION_ERROR_M),
+            var(tpv.epy, DEFAULT_POSITION_ERROR_M),
+        );
+
+        let state = match &mut self.state {
+            Some(state) => state,
+            None => {
+                let state = self.state.insert(KalmanState {
+                    origin: (lat, lon),
+                    time,
+                    east: Axis::new(0.0, var_x),
+                    north: Axis::new(0.0, var_y),
+                });
+                return Some(state.estimate());
+            }
+        };
+
+        let dt = time
+            .to_system_time()
+            .duration_since(state.time.to_system_time())
+            .map_or(0.0, |d| d.as_secs_f64());
+        let q = self.accel_noise * self.accel_noise;
+        state.east.predict(dt, q);
+        state.north.predict(dt, q);
+        state.time = time;
+
+        let (east, north) = state.project_plane(lat, lon);
+        state.east.update_pos(east, var_x);
+        state.north.update_pos(north, var_y);
+        if let (Some(speed), Some(track)) = (tpv.speed, tpv.track) {
+            let var_v = var(tpv.eps, f64::from(speed).max(1.0));
+            let (sin, cos) = f64::from(track).to_radians().sin_cos();
+            state.east.update_vel(f64::from(speed) * sin, var_v);
+            state.north.update_vel(f64::from(speed) * cos, var_v);
+        }
+        Some(state.estimate())
+    }
+}
+
+impl KalmanState {
+    fn project_plane(&self, lat: f64, lon: f64) -> (f64, f64) {
+        let (lat0, lon0) = self.origin;
+        let east = (lon - lon0).to_radians() * lat0.to_radians().cos() * EARTH_RADIUS_M;
+        let north = (lat - lat0).to_radians() * EARTH_RADIUS_M;
+        (east, north)
+    }
+
+    fn estimate(&self) -> Estimate {
+        let (lat0, lon0) = self.origin;
+        let lat = lat0 + (self.north.pos / EARTH_RADIUS_M).to_degrees();
+        let lon = lon0 + (self.east.pos / (EARTH_RADIUS_M * lat0.to_radians().cos())).to_degrees();
+        Estimate {
+            time: self.time,
+            lat,
+            lon,
+            speed: self.east.vel.hypot(self.north.vel),
+            track: self
+                .east
+                .vel
+                .atan2(self.north.vel)
+                .to_degrees()
+                .rem_euclid(360.0),
+        }
+    }
+}
+
+/// Physically impossible jump between consecutive fixes, see
+/// [`JumpDetector`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Jump {
+    /// Distance from the last accepted fix in meters.
+    pub distance_m: f64,
+    /// Time since the last accepted fix.
+    pub elapsed: Duration,
+    /// Speed implied by the jump in meters per second.
+    pub implied_speed: f64,
+}
+
+/// Result of [`JumpDetector::check`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FixCheck {
+    /// The fix is plausible.
+    Accepted,
+    /// The fix is rejected as jump.
+    Jump(Jump),
+}
+
+/// Detects fixes implying a speed above a limit, as caused by
+/// multipath or cold starts.
+///
+/// The distance between fixes is reduced by their error estimates
+/// `epx` and `epy` before comparing. Rejected fixes don't replace the
+/// last accepted one. After a number of consecutive rejections the
+/// fix is accepted anyway, assuming the receiver has really moved.
+#[derive(Debug, Clone)]
+pub struct JumpDetector {
+    max_speed: f64,
+    max_rejections: u32,
+    rejections: u32,
+    last: Option<((f64, f64), Iso8601Timestamp)>,
+}
+
+impl JumpDetector {
+    /// Creates a detector rejecting fixes implying more than
+    /// `max_speed` meters per second.
+    pub fn new(max_speed: f64) -> Self {
+        JumpDetector {
+            max_speed,
+            max_rejections: 5,
+            rejections: 0,
+            last: None,
+        }
+    }
+
+    /// Sets the number of consecutive rejections after which a fix is
+    /// accepted anyway, 5 by default.
+    pub fn with_max_rejections(mut self, max_rejections: u32) -> Self {
+        self.max_rejections = max_rejections;
+        self
+    }
+
+    /// Checks a report against the last accepted fix. Returns `None`
+    /// for reports without fix or time.
+    pub fn check(&mut self, tpv: &Tpv) -> Option<FixCheck> {
+        if !matches!(tpv.mode, Mode::Fix2d | Mode::Fix3d) {
+            return None;
+        }
+        let (pos, time) = ((tpv.lat?, tpv.lon?), tpv.time?);
+        let Some((last_pos, last_time)) = self.last else {
+            self.last = Some((pos, time));
+            return Some(FixCheck::Accepted);
+        };
+
+        let elapsed = time
+            .to_system_time()
+            .duration_since(last_time.to_system_time())
+            .unwrap_or_default();
+        let distance = distance_m(last_pos, pos)?;
+        let error = f64::from(tpv.epx.unwrap_or(0.0).max(tpv.epy.unwrap_or(0.0)));
+        let implied_speed = if elapsed.is_zero() {
+            f64::INFINITY
+        } else {
+            (distance - error).max(0.0) / elapsed.as_secs_f64()
+        };
+        if distance > error
+            && implied_speed > self.max_speed
+            && self.rejections < self.max_rejections
+        {
+            self.rejections += 1;
+            return Some(FixCheck::Jump(Jump {
+                distance_m: distance,
+                elapsed,
+                implied_speed,
+            }));
+        }
+        self.rejections = 0;
+        self.last = Some((pos, time));
+        Some(FixCheck::Accepted)
+    }
+}
+
+/// Outlier flags of a report, see [`SpikeFilter`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SpikeCheck {
+    /// `speed` is an outlier.
+    pub speed: bool,
+    /// `climb` is an outlier.
+    pub climb: bool,
+}
+
+impl SpikeCheck {
+    /// Checks whether any value is an outlier.
+    pub fn any(&self) -> bool {
+        self.speed || self.climb
+    }
+}
+
+/// Recent values of one quantity.
+#[derive(Debug, Clone)]
+struct Window {
+    values: VecDeque<f64>,
+    rejections: u32,
+}
+
+impl Window {
+    /// Checks `v` against the median of the window, scaled by the
+    /// median absolute deviation, and adds it unless it is an outlier.
+    /// After `max_rejections` consecutive outliers the window restarts
+    /// with `v`, which is then no outlier.
+    fn check(&mut self, v: f64, limits: &Limits) -> bool {
+        let outlier = self.values.len() >= MIN_SAMPLES && {
+            let m = median(self.values.iter().copied());
+            let mad = median(self.values.iter().map(|x| (x - m).abs()));
+            // Scaled MAD estimates the standard deviation.
+            (v - m).abs() > (limits.threshold * 1.4826 * mad).max(limits.min_deviation)
+        };
+        if outlier && self.rejections < limits.max_rejections {
+            self.rejections += 1;
+            return true;
+        }
+        if outlier {
+            self.values.clear();
+        }
+        self.rejections = 0;
+        if self.values.len() == limits.len {
+            self.values.pop_front();
+        }
+        self.values.push_back(v);
+        false
+    }
+}
+
+/// Configuration of a [`SpikeFilter`].
+#[derive(Debug, Clone, Copy)]
+struct Limits {
+    len: usize,
+    threshold: f64,
+    min_deviation: f64,
+    max_rejections: u32,
+}
+
+/// Number of values needed before outliers are detected.
+const MIN_SAMPLES: usize = 3;
+
+fn median(values: impl Iterator<Item = f64>) -> f64 {
+    let mut v: Vec<f64> = values.collect();
+    v.sort_by(f64::total_cmp);
+    let n = v.len();
+    if n % 2 == 1 {
+        v[n / 2]
+    } else {
+        (v[n / 2 - 1] + v[n / 2]) / 2.0
+    }
+}
+
+/// Detects `speed` and `climb` values of TPV reports deviating from
+/// the recent values, as caused by single glitched epochs.
+///
+/// A value is an outlier if it deviates from the median of the window
+/// by more than `threshold` robust standard deviations and at least by
+/// `min_deviation`. Outliers are not added to the window. A value
+/// persisting for more than `max_rejections` consecutive reports, like
+/// the speed of a vehicle starting to drive, is taken as a step change:
+/// the window restarts with it.
+#[derive(Debug, Clone)]
+pub struct SpikeFilter {
+    limits: Limits,
+    speed: Window,
+    climb: Window,
+}
+
+impl SpikeFilter {
+    /// Creates a filter comparing against the last `len` values, with
+    /// a threshold of 5 and a minimum deviation of 2 m/s.
+    pub fn new(len: usize) -> Self {
+        let window = Window {
+            values: VecDeque::with_capacity(len),
+            rejections: 0,
+        };
+        SpikeFilter {
+            limits: Limits {
+                len: len.max(MIN_SAMPLES),
+                threshold: 5.0,
+                min_deviation: 2.0,
+                max_rejections: 5,
+            },
+            speed: window.clone(),
+            climb: window,
+        }
+    }
+
+    /// Sets the threshold in robust standard deviations.
+    pub fn with_threshold(mut self, threshold: f64) -> Self {
+        self.limits.threshold = threshold;
+        self
+    }
+
+    /// Sets the minimum deviation of an outlier in m/s, which avoids
+    /// flagging tiny variations while the values are steady.
+    pub fn with_min_deviation(mut self, min_deviation: f64) -> Self {
+        self.limits.min_deviation = min_deviation;
+        self
+    }
+
+    /// Sets the number of consecutive outliers after which a value is
+    /// taken as step change, 5 by default.
+    pub fn with_max_rejections(mut self, max_rejections: u32) -> Self {
+        self.limits.max_rejections = max_rejections;
+        self
+    }
+
+    /// Flags the outliers of `tpv` without changing it.
+    pub fn check(&mut self, tpv: &Tpv) -> SpikeCheck {
+        let limits = &self.limits;
+        SpikeCheck {
+            speed: tpv
+                .speed
+                .is_some_and(|v| self.speed.check(f64::from(v), limits)),
+            climb: tpv
+                .climb
+                .is_some_and(|v| self.climb.check(f64::from(v), limits)),
+        }
+    }
+
+    /// Removes the outliers from `tpv` and returns which values have
+    /// been removed.
+    pub fn suppress(&mut self, tpv: &mut Tpv) -> SpikeCheck {
+        let check = self.check(tpv);
+        if check.speed {
+            tpv.speed = None;
+        }
+        if check.climb {
+            tpv.climb = None;
+        }
+        check
+    }
+}
+
+/// Motion state of the receiver, see [`MotionDetector`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Motion {
+    /// The receiver stands still.
+    Stationary,
+    /// The receiver moves.
+    Moving,
+}
+
+/// Classifies the receiver as stationary or moving.
+///
+/// Each report is evidence for moving if its speed clearly exceeds
+/// the moving speed, or if the recent positions scatter more than
+/// their error estimates. It is evidence for being stationary if the
+/// speed is below the stationary speed or within its error, and the
+/// positions don't scatter. Reports without speed are only evidence
+/// for moving, by the scatter. The state only changes after a number of
+/// consecutive reports of evidence for the other state.
+#[derive(Debug, Clone)]
+pub struct MotionDetector {
+    moving_speed: f64,
+    stationary_speed: f64,
+    hold: u32,
+    len: usize,
+    positions: VecDeque<(f64, f64)>,
+    state: Option<Motion>,
+    pending: u32,
+}
+
+impl MotionDetector {
+    /// Creates a detector switching to moving above 1 m/s and to
+    /// stationary below 0.3 m/s after 3 consecutive reports, looking
+    /// at the scatter of the last 10 positions.
+    pub fn new() -> Self {
+        MotionDetector {
+            moving_speed: 1.0,
+            stationary_speed: 0.3,
+            hold: 3,
+            len: 10,
+            positions: VecDeque::new(),
+            state: None,
+            pending: 0,
+        }
+    }
+
+    /// Sets the speeds in m/s above which the receiver is moving and
+    /// below which it is stationary.
+    pub fn with_speeds(mut self, stationary: f64, moving: f64) -> Self {
+        self.stationary_speed = stationary;
+        self.moving_speed = moving;
+        self
+    }
+
+    /// Sets the number of consecutive reports needed to change the
+    /// state.
+    pub fn with_hold(mut self, hold: u32) -> Self {
+        self.hold = hold;
+        self
+    }
+
+    /// The current state, `None` until the first report with a fix.
+    pub fn state(&self) -> Option<Motion> {
+        self.state
+    }
+
+    /// Feeds a report and returns the current state. Reports without
+    /// fix or position don't change the state.
+    pub fn update(&mut self, tpv: &Tpv) -> Option<Motion> {
+        if !matches!(tpv.mode, Mode::Fix2d | Mode::Fix3d) {
+            return self.state;
+        }
+        let (Some(lat), Some(lon)) = (tpv.lat, tpv.lon) else {
+            return self.state;
+        };
+        let pos = (lat, lon);
+        if self.positions.len() == self.len {
+            self.positions.pop_front();
+        }
+        self.positions.push_back(pos);
+
+        let accuracy = tpv
+            .epx
+            .zip(tpv.epy)
+            .map_or(DEFAULT_POSITION_ERROR_M, |(x, y)| f64::from(x.max(y)));
+        let scattered = self.scatter() > accuracy;
+        let speed = tpv.speed.map(f64::from);
+        let speed_error = tpv.eps.map_or(0.0, f64::from);
+        let evidence = match speed {
+            Some(v) if v > self.moving_speed && v > speed_error => Some(Motion::Moving),
+            _ if scattered => Some(Motion::Moving),
+            Some(v) if v < self.stationary_speed || v < speed_error => Some(Motion::Stationary),
+            // Without speed only the scatter tells.
+            _ => None,
+        };
+
+        match (self.state, evidence) {
+            (None, Some(e)) => self.state = Some(e),
+            (Some(s), Some(e)) if s != e => {
+                self.pending += 1;
+                if self.pending >= self.hold {
+                    self.state = Some(e);
+                    self.pending = 0;
+                }
+            }
+            _ => self.pending = 0,
+        }
+        self.state
+    }
+
+    /// Largest distance of the recent positions from their mean.
+    fn scatter(&self) -> f64 {
+        let n = self.positions.len() as f64;
+        let (lat, lon) = self
+            .positions
+            .iter()
+            .fold((0.0, 0.0), |(a, b), (lat, lon)| (a + lat, b + lon));
+        let mean = (lat / n, lon / n);
+        self.positions
+            .iter()
+            .filter_map(|p| distance_m(mean, *p))
+            .fold(0.0, f64::max)
+    }
+}
+
+impl Default for MotionDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Smooths headings with an exponentially weighted circular mean,
+/// which averages correctly across north, e.g. 350° and 10° to 0°.
+#[derive(Debug, Clone)]
+pub struct HeadingSmoother {
+    weight: f64,
+    min_speed: f64,
+    mean: Option<(f64, f64)>,
+}
+
+impl HeadingSmoother {
+    /// Creates a smoother giving each new heading the `weight`, from
+    /// 0 (ignore) to 1 (no smoothing).
+    pub fn new(weight: f64) -> Self {
+        HeadingSmoother {
+            weight: weight.clamp(0.0, 1.0),
+            min_speed: 0.5,
+            mean: None,
+        }
+    }
+
+    /// Sets the speed in m/s below which the track of TPV reports is
+    /// ignored, 0.5 m/s by default. The track is meaningless while
+    /// standing still.
+    pub fn with_min_speed(mut self, min_speed: f64) -> Self {
+        self.min_speed = min_speed;
+        self
+    }
+
+    /// The smoothed heading in degrees from true north, 0 to 360.
+    pub fn heading(&self) -> Option<f64> {
+        let (sin, cos) = self.mean?;
+        Some(sin.atan2(cos).to_degrees().rem_euclid(360.0))
+    }
+
+    /// Adds a heading in degrees and returns the smoothed heading.
+    pub fn update(&mut self, heading: f64) -> f64 {
+        let (sin, cos) = heading.to_radians().sin_cos();
+        self.mean = Some(match self.mean {
+            None => (sin, cos),
+            Some((s, c)) => (s + self.weight * (sin - s), c + self.weight * (cos - c)),
+        });
+        self.heading().unwrap_or(heading)
+    }
+
+    /// Adds the track of a TPV report if moving fast enough.
+    pub fn update_tpv(&mut self, tpv: &Tpv) -> Option<f64> {
+        if tpv.speed.is_some_and(|v| f64::from(v) >= self.min_speed) {
+            Some(self.update(f64::from(tpv.track?)))
+        } else {
+            self.heading()
+        }
+    }
+
+    /// Adds the heading of an ATT report.
+    #[cfg(feature = "att")]
+    pub fn update_att(&mut self, att: &Att) -> Option<f64> {
+        match att.heading {
+            Some(heading) => Some(self.update(f64::from(heading))),
+            None => self.heading(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        FixCheck, HeadingSmoother, JumpDetector, KalmanFilter, Motion, MotionDetector, SpikeCheck,
+        SpikeFilter,
+    };
+    use crate::geo::distance_m;
+    use crate::Tpv;
+
+    fn tpv(sec: u32, lat: f64, lon: f64) -> Tpv {
+        serde_json::from_str(&format!(
+            r#"{{"mode":3,"time":"2024-01-01T00:{:02}:{:02}Z","lat":{},"lon":{},"epx":10,"epy":10}}"#,
+            sec / 60,
+            sec % 60,
+            lat,
+            lon
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn smooths_noisy_track() {
+        // Moving north at about 10 m/s with +/- 5 m of east/west jitter.
+        let mut filter = KalmanFilter::new(1.0);
+        let mut last = None;
+        for i in 0..60 {
+            let jitter = if i % 2 == 0 { 0.000_07 } else { -0.000_07 };
+            let lat = 0.000_09 * f64::from(i);
+            last = filter.update(&tpv(i, lat, 10.0 + jitter));
+        }
+        let est = last.unwrap();
+        assert!((est.lon - 10.0).abs() < 0.000_03, "{}", est.lon);
+        assert!((est.speed - 10.0).abs() < 0.5, "{}", est.speed);
+        assert!(est.track < 5.0 || est.track > 355.0, "{}", est.track);
+        assert!(distance_m((est.lat, est.lon), (0.000_09 * 59.0, 10.0)).unwrap() < 5.0);
+
+        let no_fix: Tpv = serde_json::from_str(r#"{"mode":1}"#).unwrap();
+        assert!(filter.update(&no_fix).is_none());
+        filter.reset();
+        let est = filter.update(&tpv(0, 1.0, 2.0)).unwrap();
+        assert_eq!((est.lat, est.lon, est.speed), (1.0, 2.0, 0.0));
+    }
+
+    #[test]
+    fn detects_jumps() {
+        let mut detector = JumpDetector::new(50.0).with_max_rejections(2);
+        assert_eq!(detector.check(&tpv(0, 0.0, 0.0)), Some(FixCheck::Accepted));
+        assert_eq!(
+            detector.check(&tpv(1, 0.0002, 0.0)),
+            Some(FixCheck::Accepted)
+        );
+        // About 11 km in a second.
+        match detector.check(&tpv(2, 0.1, 0.0)) {
+            Some(FixCheck::Jump(jump)) => {
+                assert!(jump.implied_speed > 10_000.0);
+                assert_eq!(jump.elapsed.as_secs(), 1);
+            }
+            other => panic!("expected jump, got {:?}", other),
+        }
+        assert_eq!(
+            detector.check(&tpv(3, 0.0004, 0.0)),
+            Some(FixCheck::Accepted)
+        );
+        // Persisting at the new position is accepted eventually.
+        assert!(matches!(
+            detector.check(&tpv(4, 0.1, 0.0)),
+            Some(FixCheck::Jump(_))
+        ));
+        assert!(matches!(
+            detector.check(&tpv(5, 0.1, 0.0)),
+            Some(FixCheck::Jump(_))
+        ));
+        assert_eq!(detector.check(&tpv(6, 0.1, 0.0)), Some(FixCheck::Accepted));
+        assert_eq!(detector.check(&tpv(7, 0.1, 0.0)), Some(FixCheck::Accepted));
+    }
+
+    #[test]
+    fn rejects_speed_spikes() {
+        let mut filter = SpikeFilter::new(8);
+        let report = |speed: f32, climb: f32| {
+            let mut t = tpv(0, 0.0, 0.0);
+            t.speed = Some(speed);
+            t.climb = Some(climb);
+            t
+        };
+        for speed in [10.0, 10.5, 9.8, 10.2, 10.1] {
+            assert!(!filter.check(&report(speed, 0.1)).any());
+        }
+        let mut glitch = report(80.0, 0.2);
+        assert_eq!(
+            filter.suppress(&mut glitch),
+            SpikeCheck {
+                speed: true,
+                climb: false
+            }
+        );
+        assert_eq!((glitch.speed, glitch.climb), (None, Some(0.2)));
+        assert!(filter.check(&report(1.0, 30.0)).speed);
+        assert!(filter.check(&report(1.0, 30.0)).climb);
+        // Accelerating smoothly is no outlier.
+        assert!(!filter.check(&report(11.5, 0.1)).any());
+
+        // Parked, then driving: a persisting value is a step change.
+        let mut filter = SpikeFilter::new(8).with_max_rejections(3);
+        for _ in 0..5 {
+            assert!(!filter.check(&report(0.0, 0.0)).speed);
+        }
+        let flagged: Vec<bool> = [25.0, 25.2, 24.9, 25.1, 25.3, 24.8, 25.0]
+            .into_iter()
+            .map(|speed| filter.check(&report(speed, 0.0)).speed)
+            .collect();
+        assert_eq!(flagged, [true, true, true, false, false, false, false]);
+        assert!(filter.check(&report(80.0, 0.0)).speed);
+    }
+
+    #[test]
+    fn detects_motion_with_hysteresis() {
+        let report = |i: u32, lat: f64, speed: f32| {
+            let mut t = tpv(i, lat, 0.0);
+            t.speed = Some(speed);
+            t
+        };
+        let mut detector = MotionDetector::new();
+        assert_eq!(detector.state(), None);
+        // Parked with drifting speed below the error.
+        for i in 0..5 {
+            let drift = if i % 2 == 0 { 0.000_01 } else { -0.000_01 };
+            assert_eq!(
+                detector.update(&report(i, drift, 0.2)),
+                Some(Motion::Stationary)
+            );
+        }
+        // A single noisy report doesn't change the state.
+        assert_eq!(
+            detector.update(&report(5, 0.0, 2.0)),
+            Some(Motion::Stationary)
+        );
+        assert_eq!(
+            detector.update(&report(6, 0.0, 0.1)),
+            Some(Motion::Stationary)
+        );
+        // Driving off.
+        assert_eq!(
+            detector.update(&report(7, 0.0001, 5.0)),
+            Some(Motion::Stationary)
+        );
+        assert_eq!(
+            detector.update(&report(8, 0.0002, 5.0)),
+            Some(Motion::Stationary)
+        );
+        assert_eq!(
+            detector.update(&report(9, 0.0003, 5.0)),
+            Some(Motion::Moving)
+        );
+        // Slowing down within the hysteresis band keeps moving.
+        assert_eq!(
+            detector.update(&report(10, 0.0004, 0.5)),
+            Some(Motion::Moving)
+        );
+        // A fix without position keeps the state.
+        let mut no_position = report(11, 0.0, 0.1);
+        no_position.lat = None;
+        assert_eq!(detector.update(&no_position), Some(Motion::Moving));
+
+        // A missing speed is no evidence for being stationary.
+        let mut detector = MotionDetector::new();
+        assert_eq!(detector.update(&tpv(0, 0.0, 0.0)), None);
+    }
+
+    #[test]
+    fn smooths_heading_across_north() {
+        let mut smoother = HeadingSmoother::new(0.5);
+        assert_eq!(smoother.heading(), None);
+        assert!((smoother.update(350.0) - 350.0).abs() < 1e-9);
+        let h = smoother.update(10.0);
+        assert!(h.min(360.0 - h) < 1e-9, "{}", h);
+        let h = smoother.update(10.0);
+        assert!(h > 0.0 && h < 10.0, "{}", h);
+
+        let mut t = tpv(0, 0.0, 0.0);
+        t.track = Some(180.0);
+        t.speed = Some(0.1);
+        assert_eq!(smoother.update_tpv(&t), smoother.heading());
+        t.speed = Some(5.0);
+        let h = smoother.update_tpv(&t).unwrap();
+        assert!(h > 10.0 && h < 180.0, "{}", h);
+    }
+}
@@ -0,0 +1,612 @@
+//! Correlation of the reports of a reporting cycle.
+
+use crate::{ClassSet, CompactString, Gst, Iso8601Timestamp, Mode, Sky, Tpv, UnifiedResponse};
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+/// TPV, SKY and GST reports of a single device and GNSS epoch, see
+/// [`FixAggregator`].
+#[derive(Debug, Clone, Default)]
+pub struct FixReport {
+    /// Name of the originating device.
+    pub device: Option<CompactString>,
+    /// Time of the epoch, if any report carried one.
+    pub time: Option<Iso8601Timestamp>,
+    pub tpv: Option<Box<Tpv>>,
+    pub sky: Option<Box<Sky>>,
+    pub gst: Option<Box<Gst>>,
+}
+
+impl FixReport {
+    fn is_empty(&self) -> bool {
+        self.tpv.is_none() && self.sky.is_none() && self.gst.is_none()
+    }
+
+    /// Checks whether a report with `time` belongs to another epoch.
+    /// Reports without a time belong to the current one.
+    fn is_other_epoch(&self, time: Option<&Iso8601Timestamp>) -> bool {
+        matches!((self.time.as_ref(), time), (Some(a), Some(b)) if a != b)
+    }
+}
+
+/// Groups the TPV, SKY and GST reports `gpsd` sends per device in each
+/// reporting cycle into a single [`FixReport`].
+///
+/// Reports belong to the same epoch if they are from the same device
+/// and have the same time. An epoch is complete as soon as a report of
+/// the next epoch, or a second report of the same class, arrives for
+/// the device.
+#[derive(Debug, Default)]
+pub struct FixAggregator {
+    pending: BTreeMap<CompactString, FixReport>,
+}
+
+impl FixAggregator {
+    /// Creates an aggregator without pending reports.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a message and returns the epoch it has completed, if any.
+    /// Messages other than TPV, SKY and GST are ignored.
+    pub fn push<M: Into<UnifiedResponse>>(&mut self, msg: M) -> Option<FixReport> {
+        match msg.into() {
+            UnifiedResponse::Tpv(tpv) => {
+                let (device, time) = (tpv.device.clone(), tpv.time);
+                self.add(device, time, |r| r.tpv.is_some(), |r| r.tpv = Some(tpv))
+            }
+            UnifiedResponse::Sky(sky) => {
+                let (device, time) = (sky.device.clone(), sky.time);
+                self.add(device, time, |r| r.sky.is_some(), |r| r.sky = Some(sky))
+            }
+            UnifiedResponse::Gst(gst) => {
+                let (device, time) = (gst.device.clone(), gst.time);
+                self.add(device, time, |r| r.gst.is_some(), |r| r.gst = Some(gst))
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the incomplete epochs of all devices, e.g. at the end
+    /// of a capture.
+    pub fn flush(&mut self) -> Vec<FixReport> {
+        std::mem::take(&mut self.pending)
+            .into_values()
+            .filter(|r| !r.is_empty())
+            .collect()
+    }
+
+    fn add(
+        &mut self,
+        device: Option<CompactString>,
+        time: Option<Iso8601Timestamp>,
+        has: impl Fn(&FixReport) -> bool,
+        set: impl FnOnce(&mut FixReport),
+    ) -> Option<FixReport> {
+        let pending = self
+            .pending
+            .entry(device.clone().unwrap_or_default())
+            .or_default();
+        let done = if has(pending) || pending.is_other_epoch(time.as_ref()) {
+            Some(std::mem::take(pending))
+        } else {
+            None
+        };
+        pending.device = device;
+        if pending.time.is_none() {
+            pending.time = time;
+        }
+        set(pending);
+        done
+    }
+}
+
+/// Start of a reporting cycle detected by [`EpochTracker`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct EpochStart {
+    /// Name of the device.
+    pub device: Option<CompactString>,
+    /// Time of the new epoch, if reported by its first message.
+    pub time: Option<Iso8601Timestamp>,
+    /// Duration of the previous cycle, see [`CycleStats`]. `None`
+    /// for the first cycle of a device.
+    pub previous: Option<Duration>,
+}
+
+/// Cycle duration statistics of a device.
+///
+/// Durations are taken from the timestamps of `gpsd` if the first
+/// messages of both cycles carry one, from the time of reception
+/// otherwise.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CycleStats {
+    /// Number of completed cycles.
+    pub cycles: u64,
+    /// Shortest cycle.
+    pub min: Option<Duration>,
+    /// Longest cycle.
+    pub max: Option<Duration>,
+    /// Sum of all cycle durations.
+    pub total: Duration,
+}
+
+impl CycleStats {
+    /// Mean cycle duration.
+    pub fn mean(&self) -> Option<Duration> {
+        match self.cycles {
+            0 => None,
+            n => Some(self.total / n as u32),
+        }
+    }
+
+    fn add(&mut self, d: Duration) {
+        self.cycles += 1;
+        self.min = Some(self.min.map_or(d, |min| min.min(d)));
+        self.max = Some(self.max.map_or(d, |max| max.max(d)));
+        self.total += d;
+    }
+}
+
+#[derive(Debug)]
+struct Cycle {
+    time: Option<Iso8601Timestamp>,
+    received: Instant,
+    seen: ClassSet,
+    stats: CycleStats,
+}
+
+/// Detects the start of each reporting cycle per device.
+///
+/// A new cycle starts with a message carrying a different time than
+/// the current cycle, or with a class already seen in the current
+/// cycle for messages without time.
+#[derive(Debug, Default)]
+pub struct EpochTracker {
+    devices: BTreeMap<CompactString, Cycle>,
+}
+
+impl EpochTracker {
+    /// Creates a tracker without any devices.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Observes a message received just now, see
+    /// [`EpochTracker::observe_at`].
+    pub fn observe(&mut self, msg: &UnifiedResponse) -> Option<EpochStart> {
+        self.observe_at(msg, Instant::now())
+    }
+
+    /// Observes a message received at `received` and returns the
+    /// cycle started by it, if any. Only messages of classes reported
+    /// per cycle (TPV, SKY, GST, ATT and IMU) are taken into account.
+    pub fn observe_at(&mut self, msg: &UnifiedResponse, received: Instant) -> Option<EpochStart> {
+        let (device, time) = match msg {
+            UnifiedResponse::Tpv(t) => (&t.device, t.time),
+            UnifiedResponse::Sky(s) => (&s.device, s.time),
+            UnifiedResponse::Gst(g) => (&g.device, g.time),
+            #[cfg(feature = "att")]
+            UnifiedResponse::Att(a) | UnifiedResponse::Imu(a) => (&a.device, a.time),
+            _ => return None,
+        };
+        let class = ClassSet::from_class(msg.class());
+        let key = device.clone().unwrap_or_default();
+        let Some(cycle) = self.devices.get_mut(&key) else {
+            self.devices.insert(
+                key,
+                Cycle {
+                    time,
+                    received,
+                    seen: class,
+                    stats: CycleStats::default(),
+                },
+            );
+            return Some(EpochStart {
+                device: device.clone(),
+                time,
+                previous: None,
+            });
+        };
+
+        let new_cycle = match (cycle.time, time) {
+            (Some(a), Some(b)) => a != b,
+            _ => cycle.seen.contains(class),
+        };
+        if !new_cycle {
+            cycle.seen |= class;
+            if cycle.time.is_none() {
+                cycle.time = time;
+            }
+            return None;
+        }
+
+        let previous = match (cycle.time, time) {
+            (Some(a), Some(b)) => b.to_system_time().duration_since(a.to_system_time()).ok(),
+            _ => Some(received.saturating_duration_since(cycle.received)),
+        };
+        if let Some(d) = previous {
+            cycle.stats.add(d);
+        }
+        cycle.time = time;
+        cycle.received = received;
+        cycle.seen = class;
+        Some(EpochStart {
+            device: device.clone(),
+            time,
+            previous,
+        })
+    }
+
+    /// Cycle statistics of the device at `path`, `""` for messages
+    /// without device.
+    pub fn stats(&self, path: &str) -> Option<&CycleStats> {
+        self.devices.get(path).map(|c| &c.stats)
+    }
+}
+
+/// Change of the fix reported by [`FixWatcher`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FixEvent {
+    /// A fix has been acquired. Carries the duration without fix
+    /// before, measured from the first report without fix, or `None`
+    /// if there was none, i.e. the first report had a fix.
+    FixAcquired(Option<Duration>),
+    /// The fix has been lost after having been held for the given
+    /// duration.
+    FixLost(Duration),
+    /// The 3D fix dropped to a 2D fix.
+    Downgraded3dTo2d,
+    /// The 2D fix improved to a 3D fix.
+    Upgraded2dTo3d,
+}
+
+/// Turns the modes of a stream of TPV reports into [`FixEvent`]s.
+///
+/// Reports aren't told apart by device, so the reports of a single
+/// device are to be fed, e.g. by filtering on `device`.
+///
+/// A new mode has to be reported a number of consecutive times before
+/// it is taken over, so that single reports don't cause events. The
+/// durations are measured from the first report of the new mode.
+#[derive(Debug, Clone)]
+pub struct FixWatcher {
+    debounce: u32,
+    mode: Option<Mode>,
+    candidate: Option<(Mode, Instant, u32)>,
+    fixed_at: Option<Instant>,
+    lost_at: Option<Instant>,
+}
+
+impl FixWatcher {
+    /// Creates a watcher taking over a new mode after 2 consecutive
+    /// reports.
+    pub fn new() -> Self {
+        FixWatcher {
+            debounce: 2,
+            mode: None,
+            candidate: None,
+            fixed_at: None,
+            lost_at: None,
+        }
+    }
+
+    /// Sets the number of consecutive reports needed to change the
+    /// mode, at least 1.
+    pub fn with_debounce(mut self, debounce: u32) -> Self {
+        self.debounce = debounce.max(1);
+        self
+    }
+
+    /// The current mode, `None` before the first report.
+    pub fn mode(&self) -> Option<Mode> {
+        self.mode
+    }
+
+    /// Feeds a report received just now, see
+    /// [`FixWatcher::update_at`].
+    pub fn update(&mut self, tpv: &Tpv) -> Option<FixEvent> {
+        self.update_at(tpv, Instant::now())
+    }
+
+    /// Feeds a report received at `received` and returns the event
+    /// caused by it, if any.
+    pub fn update_at(&mut self, tpv: &Tpv, received: Instant) -> Option<FixEvent> {
+        let mode = match tpv.mode {
+            Mode::Fix2d | Mode::Fix3d => tpv.mode,
+            _ => Mode::NoFix,
+        };
+        if self.mode().is_some_and(|m| m == mode) {
+            self.candidate = None;
+            return None;
+        }
+        let (_, since, count) = match self.candidate {
+            Some((m, since, count)) if m == mode => (m, since, count + 1),
+            _ => (mode, received, 1),
+        };
+        if count < self.debounce {
+            self.candidate = Some((mode, since, count));
+            return None;
+        }
+        self.candidate = None;
+        match (self.mode.replace(mode), mode) {
+            (_, Mode::NoFix) => {
+                self.lost_at = Some(since);
+                self.fixed_at
+                    .take()
+                    .map(|fixed| FixEvent::FixLost(since.saturating_duration_since(fixed)))
+            }
+            (None | Some(Mode::NoFix), _) => {
+                self.fixed_at = Some(since);
+                Some(FixEvent::FixAcquired(
+                    self.lost_at
+                        .take()
+                        .map(|lost| since.saturating_duration_since(lost)),
+                ))
+            }
+            (Some(_), Mode::Fix2d) => Some(FixEvent::Downgraded3dTo2d),
+            (Some(_), _) => Some(FixEvent::Upgraded2dTo3d),
+        }
+    }
+}
+
+impl Default for FixWatcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Kind of receiver start, see [`StartClassifier`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StartKind {
+    /// Time, position and ephemerides were known.
+    Hot,
+    /// Time, position and almanac were known, but the ephemerides had
+    /// to be downloaded.
+    Warm,
+    /// Nothing was known.
+    Cold,
+}
+
+/// Startup behavior of a receiver, see [`StartClassifier`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct StartReport {
+    /// Kind of the start, judged by the time to first fix.
+    pub kind: StartKind,
+    /// Time to first fix.
+    pub ttff: Duration,
+    /// Time until the first satellite with a signal has been reported.
+    pub first_tracked: Option<Duration>,
+    /// Number of satellites used according to the latest SKY report
+    /// at the time of the fix.
+    pub used_at_fix: Option<usize>,
+}
+
+/// Classifies the start of a receiver by its time to first fix.
+///
+/// Create the classifier when the receiver is powered on or reset and
+/// feed it all messages. With the default thresholds a fix within
+/// 10 s is a hot start, within 45 s a warm start and anything slower a
+/// cold start; typical receivers need 1 s, 30 s and up to a few
+/// minutes, respectively.
+#[derive(Debug, Clone)]
+pub struct StartClassifier {
+    start: Instant,
+    hot: Duration,
+    warm: Duration,
+    first_tracked: Option<Duration>,
+    used: Option<usize>,
+    report: Option<StartReport>,
+}
+
+impl StartClassifier {
+    /// Creates a classifier for a receiver started at `start`.
+    pub fn new(start: Instant) -> Self {
+        StartClassifier {
+            start,
+            hot: Duration::from_secs(10),
+            warm: Duration::from_secs(45),
+            first_tracked: None,
+            used: None,
+            report: None,
+        }
+    }
+
+    /// Sets the longest times to first fix of hot and warm starts.
+    pub fn with_thresholds(mut self, hot: Duration, warm: Duration) -> Self {
+        self.hot = hot;
+        self.warm = warm;
+        self
+    }
+
+    /// The report, once the first fix has been seen.
+    pub fn report(&self) -> Option<StartReport> {
+        self.report
+    }
+
+    /// Observes a message received just now, see
+    /// [`StartClassifier::observe_at`].
+    pub fn observe(&mut self, msg: &UnifiedResponse) -> Option<StartReport> {
+        self.observe_at(msg, Instant::now())
+    }
+
+    /// Observes a message received at `received` and returns the
+    /// report when the first fix arrives.
+    pub fn observe_at(&mut self, msg: &UnifiedResponse, received: Instant) -> Option<StartReport> {
+        if self.report.is_some() {
+            return None;
+        }
+        let elapsed = received.saturating_duration_since(self.start);
+        match msg {
+            UnifiedResponse::Sky(sky) => {
+                let sats = sky.satellites.as_deref().unwrap_or_default();
+                if self.first_tracked.is_none()
+                    && sats.iter().any(|s| s.ss.is_some_and(|ss| ss > 0.0))
+                {
+                    self.first_tracked = Some(elapsed);
+                }
+                self.used = Some(sats.iter().filter(|s| s.used).count());
+                None
+            }
+            UnifiedResponse::Tpv(tpv) if matches!(tpv.mode, Mode::Fix2d | Mode::Fix3d) => {
+                let kind = if elapsed <= self.hot {
+                    StartKind::Hot
+                } else if elapsed <= self.warm {
+                    StartKind::Warm
+                } else {
+                    StartKind::Cold
+                };
+                self.report = Some(StartReport {
+                    kind,
+                    ttff: elapsed,
+                    first_tracked: self.first_tracked,
+                    used_at_fix: self.used,
+                });
+                self.report
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        EpochTracker, FixAggregator, FixEvent, FixWatcher, StartClassifier, StartKind, StartReport,
+    };
+    use crate::parse_lines;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn groups_epochs_per_device() {
+        let data = br#"{"class":"TPV","device":"/dev/a","mode":3,"time":"2024-01-01T00:00:00.000Z"}
+{"class":"TPV","device":"/dev/b","mode":2,"time":"2024-01-01T00:00:00.000Z"}
+{"class":"SKY","device":"/dev/a"}
+{"class":"GST","device":"/dev/a","time":"2024-01-01T00:00:00.000Z"}
+{"class":"VERSION","release":"3","rev":"3","proto_major":3,"proto_minor":14}
+{"class":"TPV","device":"/dev/a","mode":3,"time":"2024-01-01T00:00:01.000Z"}
+{"class":"TPV","device":"/dev/b","mode":2,"time":"2024-01-01T00:00:01.000Z"}
+"#;
+        let mut agg = FixAggregator::new();
+        let reports: Vec<_> = parse_lines(data)
+            .filter_map(|msg| agg.push(msg.unwrap()))
+            .collect();
+        assert_eq!(reports.len(), 2);
+        let a = &reports[0];
+        assert_eq!(a.device.as_deref(), Some("/dev/a"));
+        assert_eq!(a.time.unwrap(), "2024-01-01T00:00:00.000Z");
+        assert!(a.tpv.is_some() && a.sky.is_some() && a.gst.is_some());
+        let b = &reports[1];
+        assert_eq!(b.device.as_deref(), Some("/dev/b"));
+        assert!(b.tpv.is_some() && b.sky.is_none());
+
+        let rest = agg.flush();
+        assert_eq!(rest.len(), 2);
+        assert!(rest
+            .iter()
+            .all(|r| r.time.unwrap() == "2024-01-01T00:00:01.000Z"));
+        assert!(agg.flush().is_empty());
+    }
+
+    #[test]
+    fn tracks_cycles() {
+        let data = br#"{"class":"TPV","device":"/dev/a","mode":3,"time":"2024-01-01T00:00:00.000Z"}
+{"class":"SKY","device":"/dev/a"}
+{"class":"TPV","device":"/dev/a","mode":3,"time":"2024-01-01T00:00:01.000Z"}
+{"class":"SKY","device":"/dev/a"}
+{"class":"TPV","device":"/dev/a","mode":3,"time":"2024-01-01T00:00:01.500Z"}
+{"class":"SKY"}
+{"class":"SKY"}
+"#;
+        let mut tracker = EpochTracker::new();
+        let start = Instant::now();
+        let starts: Vec<_> = parse_lines(data)
+            .enumerate()
+            .filter_map(|(i, msg)| {
+                tracker.observe_at(&msg.unwrap(), start + Duration::from_millis(100 * i as u64))
+            })
+            .collect();
+        assert_eq!(starts.len(), 5);
+        assert_eq!(starts[0].previous, None);
+        assert_eq!(starts[1].previous, Some(Duration::from_secs(1)));
+        assert_eq!(starts[2].previous, Some(Duration::from_millis(500)));
+        assert_eq!(starts[4].previous, Some(Duration::from_millis(100)));
+
+        let stats = tracker.stats("/dev/a").unwrap();
+        assert_eq!(stats.cycles, 2);
+        assert_eq!(stats.min, Some(Duration::from_millis(500)));
+        assert_eq!(stats.max, Some(Duration::from_secs(1)));
+        assert_eq!(stats.mean(), Some(Duration::from_millis(750)));
+        assert_eq!(tracker.stats("").unwrap().cycles, 1);
+    }
+
+    #[test]
+    fn watches_fix_changes() {
+        let data = br#"{"class":"TPV","mode":1}
+{"class":"TPV","mode":1}
+{"class":"TPV","mode":3}
+{"class":"TPV","mode":3}
+{"class":"TPV","mode":3}
+{"class":"TPV","mode":1}
+{"class":"TPV","mode":3}
+{"class":"TPV","mode":2}
+{"class":"TPV","mode":2}
+{"class":"TPV","mode":1}
+{"class":"TPV","mode":1}
+{"class":"TPV","mode":1}
+{"class":"TPV","mode":3}
+{"class":"TPV","mode":3}
+"#;
+        let mut watcher = FixWatcher::new();
+        let start = Instant::now();
+        let events: Vec<_> = parse_lines(data)
+            .enumerate()
+            .filter_map(|(i, msg)| match msg.unwrap() {
+                crate::UnifiedResponse::Tpv(tpv) => {
+                    watcher.update_at(&tpv, start + Duration::from_secs(i as u64))
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            events,
+            [
+                FixEvent::FixAcquired(Some(Duration::from_secs(2))),
+                FixEvent::Downgraded3dTo2d,
+                FixEvent::FixLost(Duration::from_secs(7)),
+                FixEvent::FixAcquired(Some(Duration::from_secs(3))),
+            ]
+        );
+        assert_eq!(watcher.mode(), Some(crate::Mode::Fix3d));
+
+        let mut watcher = FixWatcher::new().with_debounce(1);
+        let tpv: crate::Tpv = serde_json::from_str(r#"{"mode":3}"#).unwrap();
+        assert_eq!(watcher.update(&tpv), Some(FixEvent::FixAcquired(None)));
+    }
+
+    #[test]
+    fn classifies_start() {
+        let data = br#"{"class":"TPV","mode":1}
+{"class":"SKY","satellites":[{"PRN":5,"ss":0,"used":false}]}
+{"class":"SKY","satellites":[{"PRN":5,"ss":30,"used":false}]}
+{"class":"SKY","satellites":[{"PRN":5,"ss":30,"used":true},{"PRN":7,"ss":35,"used":true},{"PRN":9,"ss":25,"used":true},{"PRN":11,"ss":20,"used":true}]}
+{"class":"TPV","mode":3}
+{"class":"TPV","mode":3}
+"#;
+        let start = Instant::now();
+        let mut classifier = StartClassifier::new(start);
+        let reports: Vec<_> = parse_lines(data)
+            .enumerate()
+            .filter_map(|(i, msg)| {
+                classifier.observe_at(&msg.unwrap(), start + Duration::from_secs(10 * i as u64))
+            })
+            .collect();
+        let report = StartReport {
+            kind: StartKind::Warm,
+            ttff: Duration::from_secs(40),
+            first_tracked: Some(Duration::from_secs(20)),
+            used_at_fix: Some(4),
+        };
+        assert_eq!(reports, [report]);
+        assert_eq!(classifier.report(), Some(report));
+    }
+}
@@ -0,0 +1,174 @@
+//! Parse entry point for fuzzing and replay of fuzzing corpora.
+
+use crate::{GpsdError, UnifiedResponse};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::panic;
+use std::path::{Path, PathBuf};
+
+/// Longest message accepted by [`parse_any`], in bytes. `gpsd` limits
+/// its replies to 10240 bytes, POLL replies with several devices may
+/// be somewhat longer.
+pub const MAX_MESSAGE_LEN: usize = 32 * 1024;
+
+/// Error of [`parse_any`].
+#[derive(Debug)]
+pub enum ParseError {
+    /// The input is longer than [`MAX_MESSAGE_LEN`]. Carries the
+    /// length.
+    TooLong(usize),
+    /// The input is not a JSON object, e.g. the plain text of an old
+    /// daemon.
+    NotJson,
+    /// The input is not a valid message.
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::TooLong(len) => write!(
+                f,
+                "message of {} bytes exceeds {} bytes",
+                len, MAX_MESSAGE_LEN
+            ),
+            ParseError::NotJson => f.write_str("message is not a JSON object"),
+            ParseError::Json(e) => write!(f, "invalid message: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ParseError::Json(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<serde_json::Error> for ParseError {
+    fn from(e: serde_json::Error) -> Self {
+        ParseError::Json(e)
+    }
+}
+
+impl From<ParseError> for GpsdError {
+    fn from(e: ParseError) -> Self {
+        match e {
+            ParseError::Json(e) => GpsdError::JsonError(e),
+            e => GpsdError::IoError(io::Error::new(io::ErrorKind::InvalidData, e.to_string())),
+        }
+    }
+}
+
+/// Parses a single message of arbitrary input, e.g. of a fuzzer.
+///
+/// Unlike the reading functions, no I/O is done and nothing is logged.
+/// Inputs longer than [`MAX_MESSAGE_LEN`] are rejected before parsing,
+/// which bounds the memory allocated for the message. An optional line
+/// terminator is accepted.
+pub fn parse_any(data: &[u8]) -> Result<UnifiedResponse, ParseError> {
+    if data.len() > MAX_MESSAGE_LEN {
+        return Err(ParseError::TooLong(data.len()));
+    }
+    let data = data.strip_suffix(b"\n").unwrap_or(data);
+    let data = data.strip_suffix(b"\r").unwrap_or(data);
+    match data.iter().find(|b| !b.is_ascii_whitespace()) {
+        Some(b'{') => Ok(UnifiedResponse::from_slice(data)?),
+        _ => Err(ParseError::NotJson),
+    }
+}
+
+/// Inputs of a fuzzing corpus, i.e. all files in `dir`, sorted by
+/// path. Subdirectories are not searched.
+pub fn load_corpus<P: AsRef<Path>>(dir: P) -> io::Result<Vec<(PathBuf, Vec<u8>)>> {
+    let mut inputs = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() {
+            let data = fs::read(&path)?;
+            inputs.push((path, data));
+        }
+    }
+    inputs.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(inputs)
+}
+
+/// Outcome of [`replay_corpus`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CorpusReport {
+    /// Number of inputs parsed successfully.
+    pub parsed: usize,
+    /// Number of inputs rejected with an error.
+    pub rejected: usize,
+    /// Inputs on which [`parse_any`] panicked.
+    pub panicked: Vec<PathBuf>,
+}
+
+impl CorpusReport {
+    /// Total number of inputs.
+    pub fn inputs(&self) -> usize {
+        self.parsed + self.rejected + self.panicked.len()
+    }
+}
+
+/// Runs [`parse_any`] on every input of the corpus in `dir`, see
+/// [`load_corpus`], e.g. as regression test of the inputs found by a
+/// fuzzer. Panics are caught and reported.
+///
+/// Lines of multi-line inputs, like captured `gpsd` sessions, are not
+/// split but parsed as one input.
+pub fn replay_corpus<P: AsRef<Path>>(dir: P) -> io::Result<CorpusReport> {
+    let mut report = CorpusReport::default();
+    for (path, data) in load_corpus(dir)? {
+        match panic::catch_unwind(|| parse_any(&data).is_ok()) {
+            Ok(true) => report.parsed += 1,
+            Ok(false) => report.rejected += 1,
+            Err(_) => report.panicked.push(path),
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_any, replay_corpus, ParseError, MAX_MESSAGE_LEN};
+    use crate::UnifiedResponse;
+    use std::fs;
+
+    #[test]
+    fn parses_arbitrary_input() {
+        assert!(matches!(
+            parse_any(b"{\"class\":\"TPV\",\"mode\":3}\r\n"),
+            Ok(UnifiedResponse::Tpv(_))
+        ));
+        assert!(matches!(
+            parse_any(br#"{"class":"FUTURE"}"#),
+            Ok(UnifiedResponse::Unknown { .. })
+        ));
+        assert!(matches!(parse_any(b"GPSD,O=?"), Err(ParseError::NotJson)));
+        assert!(matches!(parse_any(b""), Err(ParseError::NotJson)));
+        assert!(matches!(
+            parse_any(br#"{"class":"TPV","mode":"x"}"#),
+            Err(ParseError::Json(_))
+        ));
+        let long = vec![b' '; MAX_MESSAGE_LEN + 1];
+        assert!(matches!(parse_any(&long), Err(ParseError::TooLong(_))));
+        for len in 0..40 {
+            let _ = parse_any(&br#"{"class":"SKY","satellites":[{"PRN":1,"used":true}]}"#[..len]);
+        }
+
+        let dir = std::env::temp_dir().join(format!("gpsd_proto-{}.corpus", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a"), br#"{"class":"DEVICES","devices":[]}"#).unwrap();
+        fs::write(dir.join("b"), b"\xff\xfe{").unwrap();
+        fs::write(dir.join("c"), br#"{"class":"PPS"}"#).unwrap();
+        let report = replay_corpus(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!((report.parsed, report.rejected), (1, 2));
+        assert!(report.panicked.is_empty());
+        assert_eq!(report.inputs(), 3);
+    }
+}
@@ -0,0 +1,625 @@
+//! Geographic helpers working on positions reported by `gpsd`.
+//!
+//! Positions are given in degrees, either as `(lat, lon)` tuples or as
+//! a TPV report.
+
+use crate::Tpv;
+
+/// Mean radius of the earth in meters.
+pub const EARTH_RADIUS_M: f64 = 6_371_008.8;
+
+/// Semi-major axis of the WGS84 ellipsoid in meters.
+pub const WGS84_A: f64 = 6_378_137.0;
+
+/// Flattening of the WGS84 ellipsoid.
+pub const WGS84_F: f64 = 1.0 / 298.257_223_563;
+
+/// Semi-minor axis of the WGS84 ellipsoid in meters.
+pub const WGS84_B: f64 = WGS84_A * (1.0 - WGS84_F);
+
+/// A position with latitude and longitude in degrees.
+pub trait GeoPoint {
+    /// Latitude and longitude, `None` if not known.
+    fn lat_lon(&self) -> Option<(f64, f64)>;
+}
+
+impl GeoPoint for (f64, f64) {
+    fn lat_lon(&self) -> Option<(f64, f64)> {
+        Some(*self)
+    }
+}
+
+impl GeoPoint for Tpv {
+    fn lat_lon(&self) -> Option<(f64, f64)> {
+        Some((self.lat?, self.lon?))
+    }
+}
+
+impl<T: GeoPoint + ?Sized> GeoPoint for &T {
+    fn lat_lon(&self) -> Option<(f64, f64)> {
+        (**self).lat_lon()
+    }
+}
+
+/// Great-circle distance in meters using the haversine formula on a
+/// spherical earth. Accurate to about 0.5%, see [`geodesic_inverse`]
+/// for precise distances.
+///
+/// Returns `None` if either position is unknown.
+pub fn distance_m(a: impl GeoPoint, b: impl GeoPoint) -> Option<f64> {
+    let (lat1, lon1) = radians(a.lat_lon()?);
+    let (lat2, lon2) = radians(b.lat_lon()?);
+    let h = ((lat2 - lat1) / 2.0).sin().powi(2)
+        + lat1.cos() * lat2.cos() * ((lon2 - lon1) / 2.0).sin().powi(2);
+    Some(2.0 * EARTH_RADIUS_M * h.sqrt().min(1.0).asin())
+}
+
+/// Initial bearing of the great circle from `a` to `b` in degrees
+/// from true north, 0 to 360.
+///
+/// Returns `None` if either position is unknown.
+pub fn initial_bearing(a: impl GeoPoint, b: impl GeoPoint) -> Option<f64> {
+    let (lat1, lon1) = radians(a.lat_lon()?);
+    let (lat2, lon2) = radians(b.lat_lon()?);
+    let dlon = lon2 - lon1;
+    let y = dlon.sin() * lat2.cos();
+    let x = lat1.cos() * lat2.sin() - lat1.sin() * lat2.cos() * dlon.cos();
+    Some(y.atan2(x).to_degrees().rem_euclid(360.0))
+}
+
+/// Solution of a geodesic problem on the WGS84 ellipsoid.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Geodesic {
+    /// Distance along the geodesic in meters.
+    pub distance_m: f64,
+    /// Bearing at the start in degrees from true north, 0 to 360.
+    pub initial_bearing: f64,
+    /// Bearing at the end in degrees from true north, 0 to 360.
+    pub final_bearing: f64,
+}
+
+/// Maximum number of iterations of Vincenty's formulae.
+const MAX_ITERATIONS: usize = 200;
+
+/// Distance and bearings of the geodesic from `a` to `b` on the WGS84
+/// ellipsoid using Vincenty's inverse formula, accurate to within a
+/// millimeter.
+///
+/// Returns `None` if either position is unknown or if the formula
+/// doesn't converge, which may happen for nearly antipodal points.
+pub fn geodesic_inverse(a: impl GeoPoint, b: impl GeoPoint) -> Option<Geodesic> {
+    let (lat1, lon1) = radians(a.lat_lon()?);
+    let (lat2, lon2) = radians(b.lat_lon()?);
+    let f = WGS84_F;
+    let l = lon2 - lon1;
+    let (sin_u1, cos_u1) = ((1.0 - f) * lat1.tan()).atan().sin_cos();
+    let (sin_u2, cos_u2) = ((1.0 - f) * lat2.tan()).atan().sin_cos();
+
+    let mut lambda = l;
+    for _ in 0..MAX_ITERATIONS {
+        let (sin_lambda, cos_lambda) = lambda.sin_cos();
+        let sin_sigma = ((cos_u2 * sin_lambda).powi(2)
+            + (cos_u1 * sin_u2 - sin_u1 * cos_u2 * cos_lambda).powi(2))
+        .sqrt();
+        if sin_sigma == 0.0 {
+            // Coincident points.
+            return Some(Geodesic {
+                distance_m: 0.0,
+                initial_bearing: 0.0,
+                final_bearing: 0.0,
+            });
+        }
+        let cos_sigma = sin_u1 * sin_u2 + cos_u1 * cos_u2 * cos_lambda;
+        let sigma = sin_sigma.atan2(cos_sigma);
+        let sin_alpha = cos_u1 * cos_u2 * sin_lambda / sin_sigma;
+        let cos2_alpha = 1.0 - sin_alpha * sin_alpha;
+        let cos_2sigma_m = if cos2_alpha != 0.0 {
+            cos_sigma - 2.0 * sin_u1 * sin_u2 / cos2_alpha
+        } else {
+            // Equatorial line.
+            0.0
+        };
+        let c = f / 16.0 * cos2_alpha * (4.0 + f * (4.0 - 3.0 * cos2_alpha));
+        let prev = lambda;
+        lambda = l
+            + (1.0 - c)
+                * f
+                * sin_alpha
+                * (sigma
+                    + c * sin_sigma
+                        * (cos_2sigma_m + c * cos_sigma * (-1.0 + 2.0 * cos_2sigma_m.powi(2))));
+        if (lambda - prev).abs() > 1e-12 {
+            continue;
+        }
+
+        let (big_a, big_b) = series(cos2_alpha);
+        let delta_sigma = delta_sigma(big_b, sin_sigma, cos_sigma, cos_2sigma_m);
+        let (sin_lambda, cos_lambda) = lambda.sin_cos();
+        let alpha1 = (cos_u2 * sin_lambda).atan2(cos_u1 * sin_u2 - sin_u1 * cos_u2 * cos_lambda);
+        let alpha2 = (cos_u1 * sin_lambda).atan2(-sin_u1 * cos_u2 + cos_u1 * sin_u2 * cos_lambda);
+        return Some(Geodesic {
+            distance_m: WGS84_B * big_a * (sigma - delta_sigma),
+            initial_bearing: bearing(alpha1),
+            final_bearing: bearing(alpha2),
+        });
+    }
+    None
+}
+
+/// Destination reached from `start` following the geodesic with the
+/// initial `bearing` in degrees for `distance_m` meters on the WGS84
+/// ellipsoid, using Vincenty's direct formula.
+///
+/// Returns the destination and the final bearing in degrees, `None`
+/// if the start position is unknown.
+pub fn geodesic_direct(
+    start: impl GeoPoint,
+    bearing_deg: f64,
+    distance_m: f64,
+) -> Option<((f64, f64), f64)> {
+    let (lat1, lon1) = radians(start.lat_lon()?);
+    let f = WGS84_F;
+    let (sin_alpha1, cos_alpha1) = bearing_deg.to_radians().sin_cos();
+    let tan_u1 = (1.0 - f) * lat1.tan();
+    let cos_u1 = 1.0 / (1.0 + tan_u1 * tan_u1).sqrt();
+    let sin_u1 = tan_u1 * cos_u1;
+    let sigma1 = tan_u1.atan2(cos_alpha1);
+    let sin_alpha = cos_u1 * sin_alpha1;
+    let cos2_alpha = 1.0 - sin_alpha * sin_alpha;
+    let (big_a, big_b) = series(cos2_alpha);
+
+    let mut sigma = distance_m / (WGS84_B * big_a);
+    let (mut sin_sigma, mut cos_sigma, mut cos_2sigma_m);
+    let mut iterations = 0;
+    loop {
+        cos_2sigma_m = (2.0 * sigma1 + sigma).cos();
+        (sin_sigma, cos_sigma) = sigma.sin_cos();
+        let prev = sigma;
+        sigma =
+            distance_m / (WGS84_B * big_a) + delta_sigma(big_b, sin_sigma, cos_sigma, cos_2sigma_m);
+        iterations += 1;
+        if (sigma - prev).abs() <= 1e-12 || iterations == MAX_ITERATIONS {
+            break;
+        }
+    }
+    (sin_sigma, cos_sigma) = sigma.sin_cos();
+    cos_2sigma_m = (2.0 * sigma1 + sigma).cos();
+
+    let x = sin_u1 * sin_sigma - cos_u1 * cos_sigma * cos_alpha1;
+    let lat2 = (sin_u1 * cos_sigma + cos_u1 * sin_sigma * cos_alpha1)
+        .atan2((1.0 - f) * (sin_alpha * sin_alpha + x * x).sqrt());
+    let lambda =
+        (sin_sigma * sin_alpha1).atan2(cos_u1 * cos_sigma - sin_u1 * sin_sigma * cos_alpha1);
+    let c = f / 16.0 * cos2_alpha * (4.0 + f * (4.0 - 3.0 * cos2_alpha));
+    let l = lambda
+        - (1.0 - c)
+            * f
+            * sin_alpha
+            * (sigma
+                + c * sin_sigma
+                    * (cos_2sigma_m + c * cos_sigma * (-1.0 + 2.0 * cos_2sigma_m.powi(2))));
+    let lon2 = (lon1 + l + std::f64::consts::PI).rem_euclid(2.0 * std::f64::consts::PI)
+        - std::f64::consts::PI;
+    let alpha2 = sin_alpha.atan2(-x);
+    Some(((lat2.to_degrees(), lon2.to_degrees()), bearing(alpha2)))
+}
+
+/// Destination reached from `position` after `distance_m` meters
+/// on the initial `bearing` in degrees from true north, on the WGS84
+/// ellipsoid. Returns latitude and longitude in degrees.
+///
+/// Returns `None` if the position is unknown. See
+/// [`geodesic_direct`] for the final bearing.
+pub fn project(position: impl GeoPoint, bearing: f64, distance_m: f64) -> Option<(f64, f64)> {
+    geodesic_direct(position, bearing, distance_m).map(|(dest, _)| dest)
+}
+
+/// A route as sequence of waypoints `(lat, lon)` in degrees.
+///
+/// Computations use a spherical earth like [`distance_m`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Route {
+    waypoints: Vec<(f64, f64)>,
+}
+
+/// Position relative to the nearest leg of a route.
+struct Located {
+    /// Signed cross-track distance in meters.
+    cross_track: f64,
+    /// Distance from the start of the route in meters.
+    along: f64,
+}
+
+impl Route {
+    /// Creates a route through `waypoints`.
+    pub fn new(waypoints: Vec<(f64, f64)>) -> Self {
+        Route { waypoints }
+    }
+
+    /// The waypoints of the route.
+    pub fn waypoints(&self) -> &[(f64, f64)] {
+        &self.waypoints
+    }
+
+    /// Length of the route in meters.
+    pub fn length_m(&self) -> f64 {
+        self.waypoints
+            .windows(2)
+            .filter_map(|leg| distance_m(leg[0], leg[1]))
+            .sum()
+    }
+
+    /// Distance of `position` from the nearest leg of the route in
+    /// meters, positive to the right and negative to the left of the
+    /// direction of travel.
+    ///
+    /// Returns `None` if the position is unknown or the route has
+    /// less than two waypoints.
+    pub fn cross_track_error(&self, position: impl GeoPoint) -> Option<f64> {
+        self.locate(position).map(|l| l.cross_track)
+    }
+
+    /// Distance in meters from the start of the route to `position`
+    /// projected onto the nearest leg.
+    ///
+    /// Returns `None` if the position is unknown or the route has
+    /// less than two waypoints.
+    pub fn distance_along_route(&self, position: impl GeoPoint) -> Option<f64> {
+        self.locate(position).map(|l| l.along)
+    }
+
+    fn locate(&self, position: impl GeoPoint) -> Option<Located> {
+        let p = position.lat_lon()?;
+        let mut start = 0.0;
+        let mut nearest: Option<(f64, Located)> = None;
+        for leg in self.waypoints.windows(2) {
+            let (a, b) = (leg[0], leg[1]);
+            let len = distance_m(a, b)?;
+            let d13 = distance_m(a, p)? / EARTH_RADIUS_M;
+            let theta = (initial_bearing(a, p)? - initial_bearing(a, b)?).to_radians();
+            let xt = (d13.sin() * theta.sin()).asin();
+            let at = (d13.cos() / xt.cos()).clamp(-1.0, 1.0).acos() * theta.cos().signum();
+            let (along, dist) = if at < 0.0 {
+                (0.0, distance_m(a, p)?)
+            } else if at * EARTH_RADIUS_M > len {
+                (len, distance_m(b, p)?)
+            } else {
+                (at * EARTH_RADIUS_M, (xt * EARTH_RADIUS_M).abs())
+            };
+            if nearest.as_ref().is_none_or(|(d, _)| dist < *d) {
+                let located = Located {
+                    cross_track: dist.copysign(xt),
+                    along: start + along,
+                };
+                nearest = Some((dist, located));
+            }
+            start += len;
+        }
+        nearest.map(|(_, l)| l)
+    }
+}
+
+/// Converts ECEF coordinates `(x, y, z)` in meters to latitude and
+/// longitude in degrees and height above the WGS84 ellipsoid in
+/// meters, using Heikkinen's closed-form solution.
+pub fn ecef_to_lla((x, y, z): (f64, f64, f64)) -> (f64, f64, f64) {
+    let (a, b) = (WGS84_A, WGS84_B);
+    let e2 = WGS84_F * (2.0 - WGS84_F);
+    let ep2 = (a * a - b * b) / (b * b);
+    let p = x.hypot(y);
+    if p < 1e-9 {
+        // On the polar axis.
+        return (90f64.copysign(z), 0.0, z.abs() - b);
+    }
+    let f = 54.0 * b * b * z * z;
+    let g = p * p + (1.0 - e2) * z * z - e2 * (a * a - b * b);
+    let c = e2 * e2 * f * p * p / (g * g * g);
+    let s = (1.0 + c + (c * c + 2.0 * c).sqrt()).cbrt();
+    let k = s + 1.0 + 1.0 / s;
+    let big_p = f / (3.0 * k * k * g * g);
+    let q = (1.0 + 2.0 * e2 * e2 * big_p).sqrt();
+    let r0 = -(big_p * e2 * p) / (1.0 + q)
+        + (a * a / 2.0 * (1.0 + 1.0 / q)
+            - big_p * (1.0 - e2) * z * z / (q * (1.0 + q))
+            - big_p * p * p / 2.0)
+            .sqrt();
+    let u = ((p - e2 * r0).powi(2) + z * z).sqrt();
+    let v = ((p - e2 * r0).powi(2) + (1.0 - e2) * z * z).sqrt();
+    let z0 = b * b * z / (a * v);
+    let h = u * (1.0 - b * b / (a * v));
+    let lat = ((z + ep2 * z0) / p).atan();
+    (lat.to_degrees(), y.atan2(x).to_degrees(), h)
+}
+
+/// Converts latitude and longitude in degrees and height above the
+/// WGS84 ellipsoid in meters to ECEF coordinates `(x, y, z)` in
+/// meters.
+pub fn lla_to_ecef((lat, lon, alt): (f64, f64, f64)) -> (f64, f64, f64) {
+    let e2 = WGS84_F * (2.0 - WGS84_F);
+    let (sin_lat, cos_lat) = lat.to_radians().sin_cos();
+    let (sin_lon, cos_lon) = lon.to_radians().sin_cos();
+    let n = WGS84_A / (1.0 - e2 * sin_lat * sin_lat).sqrt();
+    (
+        (n + alt) * cos_lat * cos_lon,
+        (n + alt) * cos_lat * sin_lon,
+        (n * (1.0 - e2) + alt) * sin_lat,
+    )
+}
+
+/// Azimuth in degrees from true north and elevation in degrees of
+/// the ECEF position `target`, as seen from `observer` given as
+/// latitude, longitude and height above the ellipsoid.
+pub fn look_angles(observer: (f64, f64, f64), target: (f64, f64, f64)) -> (f64, f64) {
+    let (ox, oy, oz) = lla_to_ecef(observer);
+    let (dx, dy, dz) = (target.0 - ox, target.1 - oy, target.2 - oz);
+    let (sin_lat, cos_lat) = observer.0.to_radians().sin_cos();
+    let (sin_lon, cos_lon) = observer.1.to_radians().sin_cos();
+    let east = -sin_lon * dx + cos_lon * dy;
+    let north = -sin_lat * cos_lon * dx - sin_lat * sin_lon * dy + cos_lat * dz;
+    let up = cos_lat * cos_lon * dx + cos_lat * sin_lon * dy + sin_lat * dz;
+    let az = east.atan2(north).to_degrees().rem_euclid(360.0);
+    (az, up.atan2(east.hypot(north)).to_degrees())
+}
+
+/// Converts a height above the ellipsoid to a height above mean sea
+/// level, using the geoid separation `geoid_sep` (geoid height above
+/// the ellipsoid) in meters.
+pub fn hae_to_msl(hae: f64, geoid_sep: f64) -> f64 {
+    hae - geoid_sep
+}
+
+/// Converts a height above mean sea level to a height above the
+/// ellipsoid, see [`hae_to_msl`].
+pub fn msl_to_hae(msl: f64, geoid_sep: f64) -> f64 {
+    msl + geoid_sep
+}
+
+/// Altitudes reported by `gpsd`.
+///
+/// `gpsd` 3.20 and later report `altHAE` (height above the WGS84
+/// ellipsoid), `altMSL` (height above mean sea level) and `geoidSep`,
+/// though not every receiver provides all of them. Older versions
+/// only report `alt`, which is usually, but depending on the receiver
+/// not always, the height above mean sea level. Since 3.20 `alt` is
+/// deprecated and its meaning undefined.
+impl Tpv {
+    /// Height above the ellipsoid in meters, `alt_hae` or derived from
+    /// `alt_msl` and `geoid_sep`.
+    pub fn altitude_hae(&self) -> Option<f64> {
+        match self.alt_hae {
+            Some(hae) => Some(f64::from(hae)),
+            None => Some(msl_to_hae(
+                f64::from(self.alt_msl?),
+                f64::from(self.geoid_sep?),
+            )),
+        }
+    }
+
+    /// Height above mean sea level in meters, `alt_msl` or derived
+    /// from `alt_hae` and `geoid_sep`. Falls back to `alt` as reported
+    /// by `gpsd` before 3.20.
+    pub fn altitude_msl(&self) -> Option<f64> {
+        self.alt_msl
+            .map(f64::from)
+            .or_else(|| {
+                Some(hae_to_msl(
+                    f64::from(self.alt_hae?),
+                    f64::from(self.geoid_sep?),
+                ))
+            })
+            .or_else(|| self.alt.map(f64::from))
+    }
+
+    /// Position as ECEF coordinates `(x, y, z)` in meters, converted
+    /// from latitude, longitude and [`altitude_hae`](Tpv::altitude_hae)
+    /// if the receiver didn't report ECEF.
+    pub fn position_ecef(&self) -> Option<(f64, f64, f64)> {
+        match (self.ecefx, self.ecefy, self.ecefz) {
+            (Some(x), Some(y), Some(z)) => Some((x, y, z)),
+            _ => Some(lla_to_ecef((self.lat?, self.lon?, self.altitude_hae()?))),
+        }
+    }
+
+    /// Position as latitude and longitude in degrees and height
+    /// above the ellipsoid in meters, converted from ECEF if the
+    /// receiver didn't report them.
+    pub fn position_lla(&self) -> Option<(f64, f64, f64)> {
+        match (self.lat, self.lon, self.altitude_hae()) {
+            (Some(lat), Some(lon), Some(alt)) => Some((lat, lon, alt)),
+            _ => Some(ecef_to_lla((self.ecefx?, self.ecefy?, self.ecefz?))),
+        }
+    }
+}
+
+/// Vincenty's series coefficients A and B.
+fn series(cos2_alpha: f64) -> (f64, f64) {
+    let u2 = cos2_alpha * (WGS84_A * WGS84_A - WGS84_B * WGS84_B) / (WGS84_B * WGS84_B);
+    let a = 1.0 + u2 / 16384.0 * (4096.0 + u2 * (-768.0 + u2 * (320.0 - 175.0 * u2)));
+    let b = u2 / 1024.0 * (256.0 + u2 * (-128.0 + u2 * (74.0 - 47.0 * u2)));
+    (a, b)
+}
+
+fn delta_sigma(b: f64, sin_sigma: f64, cos_sigma: f64, cos_2sigma_m: f64) -> f64 {
+    let c2 = cos_2sigma_m * cos_2sigma_m;
+    b * sin_sigma
+        * (cos_2sigma_m
+            + b / 4.0
+                * (cos_sigma * (-1.0 + 2.0 * c2)
+                    - b / 6.0
+                        * cos_2sigma_m
+                        * (-3.0 + 4.0 * sin_sigma * sin_sigma)
+                        * (-3.0 + 4.0 * c2)))
+}
+
+fn bearing(rad: f64) -> f64 {
+    rad.to_degrees().rem_euclid(360.0)
+}
+
+fn radians((lat, lon): (f64, f64)) -> (f64, f64) {
+    (lat.to_radians(), lon.to_radians())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        distance_m, ecef_to_lla, geodesic_direct, geodesic_inverse, hae_to_msl, initial_bearing,
+        lla_to_ecef, look_angles, msl_to_hae, project, Route,
+    };
+    use crate::Tpv;
+
+    #[test]
+    fn haversine() {
+        // Paris to London.
+        let paris = (48.8566, 2.3522);
+        let london = (51.5074, -0.1278);
+        let d = distance_m(paris, london).unwrap();
+        assert!((d - 343_550.0).abs() < 500.0, "{}", d);
+        let b = initial_bearing(paris, london).unwrap();
+        assert!((b - 330.0).abs() < 1.0, "{}", b);
+        assert_eq!(distance_m(paris, paris), Some(0.0));
+        assert_eq!(initial_bearing((0.0, 0.0), (0.0, 1.0)), Some(90.0));
+
+        let tpv: Tpv = serde_json::from_str(r#"{"mode":3,"lat":48.8566,"lon":2.3522}"#).unwrap();
+        assert_eq!(distance_m(&tpv, paris), Some(0.0));
+        let tpv: Tpv = serde_json::from_str(r#"{"mode":1}"#).unwrap();
+        assert_eq!(distance_m(&tpv, paris), None);
+    }
+
+    fn dms(d: f64, m: f64, s: f64) -> f64 {
+        d.signum() * (d.abs() + m / 60.0 + s / 3600.0)
+    }
+
+    #[test]
+    fn vincenty() {
+        // Flinders Peak to Buninyong, the example of Vincenty's paper.
+        let flinders = (dms(-37.0, 57.0, 3.72030), dms(144.0, 25.0, 29.52440));
+        let buninyong = (dms(-37.0, 39.0, 10.15610), dms(143.0, 55.0, 35.38390));
+        let g = geodesic_inverse(flinders, buninyong).unwrap();
+        assert!((g.distance_m - 54_972.271).abs() < 1e-3, "{}", g.distance_m);
+        assert!((g.initial_bearing - dms(306.0, 52.0, 5.37)).abs() < 1e-5);
+        assert!((g.final_bearing - dms(307.0, 10.0, 25.07)).abs() < 1e-5);
+
+        let ((lat, lon), final_bearing) =
+            geodesic_direct(flinders, g.initial_bearing, g.distance_m).unwrap();
+        assert!((lat - buninyong.0).abs() < 1e-8);
+        assert!((lon - buninyong.1).abs() < 1e-8);
+        assert!((final_bearing - g.final_bearing).abs() < 1e-6);
+
+        assert_eq!(
+            geodesic_inverse(flinders, flinders).unwrap().distance_m,
+            0.0
+        );
+        // Across the antimeridian.
+        let ((_, lon), _) = geodesic_direct((0.0, 179.5), 90.0, 111_319.5).unwrap();
+        assert!((lon + 179.5).abs() < 1e-3, "{}", lon);
+    }
+
+    #[test]
+    fn ecef_conversion() {
+        for lla in [
+            (48.8566, 2.3522, 35.0),
+            (-33.8688, 151.2093, -20.0),
+            (0.0, -179.0, 10_000.0),
+            (89.999, 45.0, 0.0),
+        ] {
+            let (lat, lon, alt) = ecef_to_lla(lla_to_ecef(lla));
+            assert!((lat - lla.0).abs() < 1e-9, "{} {}", lat, lla.0);
+            assert!((lon - lla.1).abs() < 1e-9, "{} {}", lon, lla.1);
+            assert!((alt - lla.2).abs() < 1e-4, "{} {}", alt, lla.2);
+        }
+        let (x, y, z) = lla_to_ecef((0.0, 0.0, 0.0));
+        assert_eq!((x, y, z), (super::WGS84_A, 0.0, 0.0));
+        let (lat, _, alt) = ecef_to_lla((0.0, 0.0, -super::WGS84_B));
+        assert_eq!((lat, alt), (-90.0, 0.0));
+
+        let tpv: Tpv = serde_json::from_str(
+            r#"{"mode":3,"ecefx":6378137.0,"ecefy":0.0,"ecefz":0.0,"ecefpAcc":1.5}"#,
+        )
+        .unwrap();
+        assert_eq!(tpv.ecef_p_acc, Some(1.5));
+        let (lat, lon, alt) = tpv.position_lla().unwrap();
+        assert!(lat.abs() < 1e-9 && lon.abs() < 1e-9 && alt.abs() < 1e-6);
+        let tpv: Tpv =
+            serde_json::from_str(r#"{"mode":3,"lat":0.0,"lon":0.0,"altHAE":0.0}"#).unwrap();
+        assert_eq!(tpv.position_ecef(), Some((super::WGS84_A, 0.0, 0.0)));
+        let tpv: Tpv = serde_json::from_str(r#"{"mode":2,"lat":0.0,"lon":0.0}"#).unwrap();
+        assert_eq!(tpv.position_ecef(), None);
+    }
+
+    #[test]
+    fn satellite_look_angles() {
+        let observer = (48.0, 11.0, 500.0);
+        let above = lla_to_ecef((48.0, 11.0, 20_000_000.0));
+        let (_, el) = look_angles(observer, above);
+        assert!((el - 90.0).abs() < 1e-9, "{}", el);
+        // Due east on the equator, on the horizon of the antipode.
+        let (az, el) = look_angles((0.0, 0.0, 0.0), (super::WGS84_A, 1e6, 0.0));
+        assert!((az - 90.0).abs() < 1e-9 && el.abs() < 1e-9, "{} {}", az, el);
+        let (az, el) = look_angles((0.0, 0.0, 0.0), (super::WGS84_A, 0.0, -1e6));
+        assert!(
+            (az - 180.0).abs() < 1e-9 && el.abs() < 1e-9,
+            "{} {}",
+            az,
+            el
+        );
+    }
+
+    #[test]
+    fn geoid_separation() {
+        assert_eq!(hae_to_msl(100.0, 47.5), 52.5);
+        assert_eq!(msl_to_hae(52.5, 47.5), 100.0);
+
+        let tpv: Tpv =
+            serde_json::from_str(r#"{"mode":3,"altHAE":100.0,"geoidSep":47.5}"#).unwrap();
+        assert_eq!(tpv.altitude_hae(), Some(100.0));
+        assert_eq!(tpv.altitude_msl(), Some(52.5));
+        let tpv: Tpv = serde_json::from_str(r#"{"mode":3,"altMSL":52.5,"geoidSep":47.5}"#).unwrap();
+        assert_eq!(tpv.altitude_hae(), Some(100.0));
+        let tpv: Tpv = serde_json::from_str(r#"{"mode":3,"alt":52.5}"#).unwrap();
+        assert_eq!(tpv.altitude_msl(), Some(52.5));
+        assert_eq!(tpv.altitude_hae(), None);
+    }
+
+    #[test]
+    fn project_destination() {
+        let start = (48.8566, 2.3522);
+        let dest = project(start, 45.0, 10_000.0).unwrap();
+        let g = geodesic_inverse(start, dest).unwrap();
+        assert!((g.distance_m - 10_000.0).abs() < 1e-6);
+        assert!((g.initial_bearing - 45.0).abs() < 1e-9);
+        // Due north along a meridian.
+        let (lat, lon) = project((0.0, 10.0), 0.0, 110_574.4).unwrap();
+        assert!((lat - 1.0).abs() < 1e-4 && (lon - 10.0).abs() < 1e-12);
+        let (lat, lon) = project(start, 90.0, 0.0).unwrap();
+        assert!((lat - start.0).abs() < 1e-12 && (lon - start.1).abs() < 1e-12);
+    }
+
+    #[test]
+    fn route_cross_track() {
+        // Along the equator, then north along the meridian 1°E.
+        let route = Route::new(vec![(0.0, 0.0), (0.0, 1.0), (1.0, 1.0)]);
+        let leg = distance_m((0.0, 0.0), (0.0, 1.0)).unwrap();
+        assert!((route.length_m() - 2.0 * leg).abs() < 1e-6);
+
+        // North of the first leg is left of travelling east.
+        let xte = route.cross_track_error((0.01, 0.5)).unwrap();
+        assert!((xte + leg / 100.0).abs() < 1.0, "{}", xte);
+        let along = route.distance_along_route((0.01, 0.5)).unwrap();
+        assert!((along - leg / 2.0).abs() < 1.0, "{}", along);
+
+        // East of the second leg is right of travelling north.
+        let xte = route.cross_track_error((0.5, 1.01)).unwrap();
+        assert!(xte > 0.0 && (xte - leg / 100.0).abs() < 1.0, "{}", xte);
+        let along = route.distance_along_route((0.5, 1.01)).unwrap();
+        assert!((along - 1.5 * leg).abs() < 10.0, "{}", along);
+
+        // Before the start the distance to the first waypoint counts.
+        assert_eq!(route.distance_along_route((0.0, -0.5)), Some(0.0));
+        assert!((route.cross_track_error((0.0, -0.5)).unwrap().abs() - leg / 2.0).abs() < 1.0);
+
+        assert_eq!(
+            Route::new(vec![(0.0, 0.0)]).cross_track_error((1.0, 1.0)),
+            None
+        );
+    }
+}
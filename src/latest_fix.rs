@@ -0,0 +1,71 @@
+//! Latest fix shared between threads.
+
+use crate::{Mode, Tpv};
+use arc_swap::ArcSwapOption;
+use std::sync::Arc;
+
+/// Cell holding the latest TPV report with a fix.
+///
+/// The handle is cheap to clone. [`GpsdClient`](crate::GpsdClient)
+/// stores into it, see
+/// [`GpsdClient::with_latest_fix`](crate::GpsdClient::with_latest_fix),
+/// while any number of threads load from it without ever blocking
+/// the reading thread.
+#[derive(Debug, Clone, Default)]
+pub struct LatestFix {
+    cell: Arc<ArcSwapOption<Tpv>>,
+}
+
+impl LatestFix {
+    /// Creates an empty cell.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The latest report with a fix, if any has been stored yet.
+    pub fn load(&self) -> Option<Arc<Tpv>> {
+        self.cell.load_full()
+    }
+
+    /// Stores `tpv` if it has a 2D or 3D fix. Reports without a fix
+    /// are ignored, keeping the last known position.
+    ///
+    /// Returns whether `tpv` has been stored.
+    pub fn store(&self, tpv: &Tpv) -> bool {
+        match tpv.mode {
+            Mode::Fix2d | Mode::Fix3d => {
+                self.cell.store(Some(Arc::new(tpv.clone())));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Forgets the stored report, e.g. after the connection to
+    /// `gpsd` has been lost.
+    pub fn clear(&self) {
+        self.cell.store(None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LatestFix;
+    use crate::Tpv;
+
+    #[test]
+    fn keeps_last_fix() {
+        let fix = LatestFix::new();
+        let reader = fix.clone();
+        assert!(reader.load().is_none());
+
+        let tpv: Tpv = serde_json::from_str(r#"{"mode":3,"lat":66.123}"#).unwrap();
+        assert!(fix.store(&tpv));
+        let no_fix: Tpv = serde_json::from_str(r#"{"mode":1}"#).unwrap();
+        assert!(!fix.store(&no_fix));
+        assert_eq!(reader.load().unwrap().lat, Some(66.123));
+
+        fix.clear();
+        assert!(reader.load().is_none());
+    }
+}
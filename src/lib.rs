@@ -10,6 +10,28 @@
 //! A example demo application is provided in the `example` sub
 //! directory. Check the repository for up to date sample code.
 //!
+//! # Features
+//!
+//! Rarely used message classes can be disabled to reduce code size.
+//! Messages of disabled classes are reported as
+//! `UnifiedResponse::Unknown`. All of them are enabled by default:
+//!
+//! - `ais`: AIS
+//! - `att`: ATT and IMU
+//! - `osc`: OSC
+//! - `raw`: RAW
+//! - `rtcm`: RTCM2 and RTCM3
+//! - `subframe`: SUBFRAME
+//!
+//! # `no_std`
+//!
+//! Without the default `std` feature the crate is `no_std` and only
+//! requires `alloc`. What remains are the message types and their
+//! parsing, e.g. by [`UnifiedResponse::from_slice`], for devices
+//! receiving the JSON of `gpsd` over a radio or UART bridge. The
+//! client, the protocol functions and all other features require
+//! `std`.
+//!
 //! # Testing
 //!
 //! `gpsd_proto` has been tested against `gpsd` version 3.17 on macOS
@@ -45,16 +67,250 @@
 //! ?WATCH={"enable":true,"json":true};
 //! ```
 
-#[macro_use]
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "log")]
+#[cfg_attr(feature = "std", macro_use)]
 extern crate log;
 
+/// Stand-ins for the macros of `log` without the `log` feature. The
+/// arguments are still type checked, but nothing is formatted.
+#[cfg(not(feature = "log"))]
+#[macro_use]
+#[allow(unused_macros)]
+mod log_shim {
+    macro_rules! log_enabled {
+        ($($arg:tt)*) => {
+            false
+        };
+    }
+
+    macro_rules! trace {
+        ($($arg:tt)*) => {{
+            let _ = format_args!($($arg)*);
+        }};
+    }
+
+    macro_rules! debug {
+        ($($arg:tt)*) => {{
+            let _ = format_args!($($arg)*);
+        }};
+    }
+
+    macro_rules! warn {
+        ($($arg:tt)*) => {{
+            let _ = format_args!($($arg)*);
+        }};
+    }
+}
+
 #[macro_use]
 extern crate serde_derive;
 
+use alloc::borrow::{Cow, ToOwned};
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::time::Duration;
 use serde::de::*;
 use serde::Deserializer;
-use std::fmt;
+use serde_json::value::RawValue;
+#[cfg(feature = "std")]
+use std::collections::VecDeque;
+#[cfg(feature = "std")]
 use std::io;
+#[cfg(feature = "std")]
+use std::time::Instant;
+
+#[cfg(feature = "std")]
+mod client;
+
+#[cfg(feature = "std")]
+pub use client::{GpsdClient, ParseOutcome, Stats, Timeouts, Timestamped};
+
+#[cfg(feature = "std")]
+mod diagnostics;
+
+#[cfg(feature = "std")]
+pub use diagnostics::DiagnosticsWriter;
+
+#[cfg(feature = "embedded")]
+pub mod embedded;
+#[cfg(feature = "std")]
+pub mod export;
+#[cfg(feature = "std")]
+pub mod filter;
+#[cfg(feature = "std")]
+pub mod geo;
+#[cfg(feature = "std")]
+pub mod nmea;
+#[cfg(feature = "std")]
+pub mod timing;
+
+#[cfg(feature = "std")]
+mod fix;
+
+#[cfg(feature = "std")]
+pub use fix::{
+    CycleStats, EpochStart, EpochTracker, FixAggregator, FixEvent, FixReport, FixWatcher,
+    StartClassifier, StartKind, StartReport,
+};
+
+#[cfg(feature = "std")]
+mod sky;
+
+#[cfg(feature = "std")]
+pub use sky::{
+    glonass_l1_hz, glonass_l2_hz, glonass_slot, gnss_to_prn, prn_to_gnss, sbas_satellite,
+    Constellation, ConstellationStats, DopEvent, DopLevel, DopMonitor, SatHistory, SatTracker,
+    SbasSystem, SignalId, SkyStats,
+};
+
+#[cfg(all(feature = "std", feature = "subframe"))]
+mod nav;
+
+#[cfg(all(feature = "std", feature = "subframe"))]
+pub use nav::{AlmanacEntry, Ephemeris, NavDecoder, NavUpdate};
+
+#[cfg(all(feature = "std", feature = "raw"))]
+mod doppler;
+
+#[cfg(all(feature = "std", feature = "raw"))]
+pub use doppler::{solve_velocity, DopplerObs, DopplerVelocity};
+
+#[cfg(all(feature = "std", feature = "raw", feature = "subframe"))]
+pub use doppler::raw_velocity;
+
+#[cfg(feature = "std")]
+mod state;
+
+#[cfg(feature = "std")]
+pub use state::{DeviceState, GpsState, SharedGpsState};
+
+#[cfg(feature = "std")]
+mod trip;
+
+#[cfg(feature = "std")]
+pub use trip::TripStats;
+
+#[cfg(feature = "std")]
+mod decimate;
+
+#[cfg(feature = "std")]
+pub use decimate::{Decimate, Decimator, Dedup, Deduplicator};
+
+#[cfg(feature = "std")]
+mod quality;
+
+#[cfg(feature = "std")]
+pub use quality::{Gated, QualityEvent, QualityFailure, QualityGate};
+
+#[cfg(feature = "latest-fix")]
+mod latest_fix;
+
+#[cfg(feature = "latest-fix")]
+pub use latest_fix::LatestFix;
+
+#[cfg(feature = "std")]
+mod replay;
+
+#[cfg(feature = "std")]
+pub use replay::{PlaybackSpeed, Recorder, Replayer};
+
+#[cfg(feature = "mmap")]
+pub use replay::ReplayFile;
+
+#[cfg(feature = "std")]
+mod relay;
+
+#[cfg(feature = "std")]
+pub use relay::Relay;
+
+#[cfg(feature = "std")]
+mod fuzz;
+
+#[cfg(feature = "std")]
+pub use fuzz::{load_corpus, parse_any, replay_corpus, CorpusReport, ParseError, MAX_MESSAGE_LEN};
+
+#[cfg(feature = "testing")]
+mod testing;
+
+#[cfg(feature = "testing")]
+pub use testing::{MockGpsd, MockReader, MockServer, MockWriter};
+
+#[cfg(feature = "testing")]
+mod simulator;
+
+#[cfg(feature = "testing")]
+pub use simulator::Scenario;
+
+#[cfg(feature = "arbitrary")]
+mod arbitrary;
+
+#[cfg(all(feature = "chrony", unix))]
+mod chrony;
+
+#[cfg(all(feature = "chrony", unix))]
+pub use chrony::{ChronySock, Leap, SockSample};
+
+#[cfg(all(feature = "ntpshm", unix))]
+mod ntpshm;
+
+#[cfg(all(feature = "ntpshm", unix))]
+pub use ntpshm::NtpShm;
+
+#[cfg(all(feature = "dbus", unix))]
+mod dbus;
+
+#[cfg(all(feature = "dbus", unix))]
+pub use dbus::{DbusExport, DBUS_FIX_SIGNAL, DBUS_INTERFACE, DBUS_PATH};
+
+#[cfg(feature = "pcap")]
+mod pcap;
+
+#[cfg(feature = "pcap")]
+pub use pcap::{open_pcap, read_pcap, PcapStream, GPSD_PORT};
+
+#[cfg(feature = "ros")]
+mod ros;
+
+#[cfg(feature = "ros")]
+pub use ros::{NavSatFix, NavSatStatus, RosHeader, RosTime};
+
+#[cfg(feature = "binary")]
+mod binary;
+
+#[cfg(feature = "binary")]
+pub use binary::BinaryResponse;
+
+#[cfg(feature = "schemars")]
+mod schema;
+
+#[cfg(feature = "schemars")]
+pub use schema::{message_schemas, SchemaError};
+
+#[cfg(feature = "spoofing")]
+mod spoofing;
+
+#[cfg(feature = "spoofing")]
+pub use spoofing::{Almanac, ExpectedSat, SpoofDetector, SpoofIndicator};
+
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+#[cfg(feature = "sqlite")]
+pub use sqlite::{TrackLogger, SCHEMA};
+
+/// Compact string type used for device paths and timestamps.
+///
+/// These strings are short and repeated in nearly every message;
+/// strings of up to 24 bytes are stored inline without a heap
+/// allocation.
+pub use compact_str::CompactString;
 
 /// Minimum supported version of `gpsd`.
 pub const PROTO_MAJOR_MIN: u8 = 3;
@@ -62,9 +318,14 @@ pub const PROTO_MAJOR_MIN: u8 = 3;
 /// Command to enable watch.
 pub const ENABLE_WATCH_CMD: &str = "?WATCH={\"enable\":true,\"json\":true};\r\n";
 
+/// Command polling the latest fix of all devices.
+pub const POLL_CMD: &str = "?POLL;\r\n";
+
 /// `gpsd` ships a VERSION response to each client when the client
 /// first connects to it.
 #[derive(Debug, Deserialize, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[cfg_attr(feature = "serialize", derive(Serialize))]
 pub struct Version {
     /// Public release level.
@@ -82,6 +343,8 @@ pub struct Version {
 
 /// Device information (i.e. device enumeration).
 #[derive(Debug, Deserialize, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[cfg_attr(feature = "serialize", derive(Serialize))]
 pub struct Devices {
     pub devices: Vec<DeviceInfo>,
@@ -89,19 +352,36 @@ pub struct Devices {
 
 /// Single device information as reported by `gpsd`.
 #[derive(Debug, Deserialize, Clone)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[cfg_attr(feature = "serialize", derive(Serialize))]
 pub struct DeviceInfo {
     /// Name the device for which the control bits are being reported,
     /// or for which they are to be applied. This attribute may be
     /// omitted only when there is exactly one subscribed channel.
-    pub path: Option<String>,
+    #[cfg_attr(feature = "schemars", schemars(with = "Option<String>"))]
+    pub path: Option<CompactString>,
     /// Time the device was activated as an ISO8601 timestamp. If the
     /// device is inactive this attribute is absent.
-    pub activated: Option<String>,
+    #[cfg_attr(feature = "schemars", schemars(with = "Option<String>"))]
+    pub activated: Option<CompactString>,
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for DeviceInfo {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "DeviceInfo {{ path: {}, activated: {} }}",
+            self.path.as_deref(),
+            self.activated.as_deref()
+        )
+    }
 }
 
 /// Watch response. Elicits a report of per-subscriber policy.
 #[derive(Debug, Deserialize, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[cfg_attr(feature = "serialize", derive(Serialize))]
 pub struct Watch {
     /// Enable (true) or disable (false) watcher mode. Default is
@@ -135,10 +415,261 @@ pub struct Watch {
     /// PPS JSON message when the device issues 1PPS. Default is
     /// false.
     pub pps: Option<bool>,
+    /// If present, enable watching only of the specified device
+    /// rather than all devices.
+    pub device: Option<String>,
+}
+
+/// Policy requested with the WATCH command.
+///
+/// The default enables watcher mode with JSON reports, which is
+/// equivalent to `ENABLE_WATCH_CMD`. Attributes set to `None` are
+/// not sent and are left to the defaults of `gpsd`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WatchOptions {
+    /// Enable watcher mode.
+    pub enable: bool,
+    /// Enable dumping of JSON reports.
+    pub json: bool,
+    /// Enable dumping of binary packets as pseudo-NMEA.
+    pub nmea: bool,
+    /// Raw mode, see `Watch::raw`.
+    pub raw: Option<u8>,
+    /// Apply scaling divisors to output before dumping.
+    pub scaled: Option<bool>,
+    /// Aggregate AIS type24 sentence parts.
+    pub split24: Option<bool>,
+    /// Emit TOFF and PPS messages.
+    pub pps: Option<bool>,
+    /// Watch only the specified device.
+    pub device: Option<String>,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        WatchOptions {
+            enable: true,
+            json: true,
+            nmea: false,
+            raw: None,
+            scaled: None,
+            split24: None,
+            pps: None,
+            device: None,
+        }
+    }
+}
+
+impl WatchOptions {
+    /// The WATCH command requesting this policy, including the line
+    /// terminator.
+    pub fn to_command(&self) -> String {
+        let mut cmd = String::with_capacity(ENABLE_WATCH_CMD.len());
+        // Writing to a `String` can't fail.
+        self.write_command(&mut cmd).unwrap();
+        cmd
+    }
+
+    /// Writes the WATCH command, see `to_command`, without allocating.
+    pub fn write_command<W: fmt::Write>(&self, out: &mut W) -> fmt::Result {
+        write!(
+            out,
+            "?WATCH={{\"enable\":{},\"json\":{}",
+            self.enable, self.json
+        )?;
+        if self.nmea {
+            out.write_str(",\"nmea\":true")?;
+        }
+        if let Some(raw) = self.raw {
+            write!(out, ",\"raw\":{}", raw)?;
+        }
+        if let Some(scaled) = self.scaled {
+            write!(out, ",\"scaled\":{}", scaled)?;
+        }
+        if let Some(split24) = self.split24 {
+            write!(out, ",\"split24\":{}", split24)?;
+        }
+        if let Some(pps) = self.pps {
+            write!(out, ",\"pps\":{}", pps)?;
+        }
+        if let Some(device) = &self.device {
+            out.write_str(",\"device\":")?;
+            write_json_str(out, device)?;
+        }
+        out.write_str("};\r\n")
+    }
+
+    /// Encodes the WATCH command into `buf` and returns the number of
+    /// bytes written.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `buf` is too small for the command.
+    pub fn encode_command(&self, buf: &mut [u8]) -> Result<usize, fmt::Error> {
+        let mut out = SliceWriter { buf, len: 0 };
+        self.write_command(&mut out)?;
+        Ok(out.len)
+    }
+
+    /// Compares the policy reported by `gpsd` with the requested
+    /// one and returns the first difference found. Attributes absent
+    /// in `reported` are assumed to have the default value documented
+    /// by `gpsd`.
+    pub fn check(&self, reported: &Watch) -> Result<(), WatchFailure> {
+        fn check<T: PartialEq>(
+            field: &'static str,
+            requested: T,
+            reported: T,
+        ) -> Result<(), WatchFailure> {
+            if requested != reported {
+                return Err(WatchFailure::PolicyMismatch { field });
+            }
+            Ok(())
+        }
+
+        let enable = reported.enable.unwrap_or(true);
+        if self.enable && !enable {
+            return Err(WatchFailure::Disabled);
+        }
+        check("enable", self.enable, enable)?;
+        let json = reported.json.unwrap_or(false);
+        if self.json && !json {
+            return Err(WatchFailure::JsonNotEnabled);
+        }
+        check("json", self.json, json)?;
+        check("nmea", self.nmea, reported.nmea.unwrap_or(false))?;
+        if let Some(raw) = self.raw {
+            check("raw", raw, reported.raw.unwrap_or(0))?;
+        }
+        if let Some(scaled) = self.scaled {
+            check("scaled", scaled, reported.scaled.unwrap_or(false))?;
+        }
+        if let Some(split24) = self.split24 {
+            check("split24", split24, reported.split24.unwrap_or(false))?;
+        }
+        if let Some(pps) = self.pps {
+            check("pps", pps, reported.pps.unwrap_or(false))?;
+        }
+        if let (Some(device), Some(reported)) = (&self.device, &reported.device) {
+            check("device", device, reported)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes `s` as JSON string literal.
+fn write_json_str<W: fmt::Write>(out: &mut W, s: &str) -> fmt::Result {
+    out.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => out.write_str("\\\"")?,
+            '\\' => out.write_str("\\\\")?,
+            '\n' => out.write_str("\\n")?,
+            '\r' => out.write_str("\\r")?,
+            '\t' => out.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32)?,
+            c => out.write_char(c)?,
+        }
+    }
+    out.write_char('"')
+}
+
+/// `fmt::Write` into a fixed buffer, failing when it is full.
+struct SliceWriter<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl fmt::Write for SliceWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        if end > self.buf.len() {
+            return Err(fmt::Error);
+        }
+        self.buf[self.len..end].copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+/// Reason why the watch could not be enabled as requested.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum WatchFailure {
+    /// `gpsd` reports watcher mode as disabled.
+    Disabled,
+    /// `gpsd` reports JSON reports as disabled.
+    JsonNotEnabled,
+    /// The requested device is not known to `gpsd`.
+    DeviceNotFound(String),
+    /// An attribute of the reported policy differs from the requested
+    /// one.
+    PolicyMismatch {
+        /// Name of the attribute.
+        field: &'static str,
+    },
+}
+
+impl fmt::Display for WatchFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WatchFailure::Disabled => write!(f, "Disabled"),
+            WatchFailure::JsonNotEnabled => write!(f, "JsonNotEnabled"),
+            WatchFailure::DeviceNotFound(d) => write!(f, "DeviceNotFound: {}", d),
+            WatchFailure::PolicyMismatch { field } => write!(f, "PolicyMismatch: {}", field),
+        }
+    }
+}
+
+/// Handling of an empty DEVICES list during handshake, i.e. `gpsd`
+/// is running but has no receiver attached.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum NoDevicesPolicy {
+    /// Complete the handshake anyway.
+    #[default]
+    Accept,
+    /// Fail the handshake with `GpsdError::NoDevices`.
+    Fail,
+    /// After enabling the watch, wait up to the given duration for a
+    /// DEVICE message reporting an activated device. The messages
+    /// received meanwhile, including that DEVICE message, are queued
+    /// and delivered afterwards by `GpsdClient`; `handshake` and
+    /// `handshake_with_options` discard them. Fails with
+    /// `GpsdError::NoDevices` if no device shows up in time.
+    ///
+    /// The deadline is checked whenever a message arrives; set a read
+    /// timeout on the underlying socket to bound a silent connection.
+    /// A read timing out is treated as the deadline having passed.
+    Wait(Duration),
+}
+
+/// Options for the handshake with `gpsd`.
+#[derive(Debug, Clone)]
+pub struct HandshakeOptions {
+    /// Policy requested with the WATCH command.
+    pub watch: WatchOptions,
+    /// Handling of an empty DEVICES list.
+    pub no_devices: NoDevicesPolicy,
+    /// Number of messages tolerated out of order, i.e. WATCH before
+    /// DEVICES or any other message before either of them. Zero
+    /// requires the exact order. Default is 4.
+    pub reorder_window: usize,
+}
+
+impl Default for HandshakeOptions {
+    fn default() -> Self {
+        HandshakeOptions {
+            watch: WatchOptions::default(),
+            no_devices: NoDevicesPolicy::default(),
+            reorder_window: 4,
+        }
+    }
 }
 
 /// Responses from `gpsd` during handshake..
 #[derive(Debug, Deserialize, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[cfg_attr(feature = "serialize", derive(Serialize))]
 #[serde(tag = "class")]
 #[serde(rename_all = "UPPERCASE")]
@@ -150,16 +681,19 @@ pub enum ResponseHandshake {
 
 /// Device information.
 #[derive(Debug, Deserialize, Clone)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[cfg_attr(feature = "serialize", derive(Serialize))]
 pub struct Device {
     /// Name the device for which the control bits are being
     /// reported, or for which they are to be applied. This
     /// attribute may be omitted only when there is exactly one
     /// subscribed channel.
-    pub path: Option<String>,
+    #[cfg_attr(feature = "schemars", schemars(with = "Option<String>"))]
+    pub path: Option<CompactString>,
     /// Time the device was activated as an ISO8601 timestamp. If
     /// the device is inactive this attribute is absent.
-    pub activated: Option<String>,
+    #[cfg_attr(feature = "schemars", schemars(with = "Option<String>"))]
+    pub activated: Option<CompactString>,
     /// Bit vector of property flags. Currently defined flags are:
     /// describe packet types seen so far (GPS, RTCM2, RTCM3,
     /// AIS). Won't be reported if empty, e.g. before gpsd has
@@ -189,155 +723,780 @@ pub struct Device {
     pub mincycle: Option<f32>,
 }
 
+#[cfg(feature = "defmt")]
+impl defmt::Format for Device {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "Device {{ path: {}, activated: {}, flags: {}, driver: {}, subtype: {}, \
+             bps: {}, parity: {}, stopbits: {}, native: {}, cycle: {}, \
+             mincycle: {} }}",
+            self.path.as_deref(),
+            self.activated.as_deref(),
+            self.flags,
+            self.driver,
+            self.subtype,
+            self.bps,
+            self.parity,
+            self.stopbits,
+            self.native,
+            self.cycle,
+            self.mincycle
+        )
+    }
+}
+
 /// Type of GPS fix.
-#[derive(Debug, Copy, Clone)]
+///
+/// Encoded as integer by `gpsd`.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serialize", derive(Serialize), serde(into = "u8"))]
+#[serde(from = "u8")]
 pub enum Mode {
+    /// Mode not known yet, reported as 0 before the first fix.
+    NotSeen,
     /// No fix at all.
     NoFix,
     /// Two dimensional fix, 2D.
     Fix2d,
     /// Three dimensional fix, 3D (i.e. with altitude).
     Fix3d,
+    /// Value not known to this crate.
+    Unrecognized(u8),
 }
 
 impl fmt::Display for Mode {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
+            Mode::NotSeen => write!(f, "NotSeen"),
             Mode::NoFix => write!(f, "NoFix"),
             Mode::Fix2d => write!(f, "2d"),
             Mode::Fix3d => write!(f, "3d"),
+            Mode::Unrecognized(v) => write!(f, "Unrecognized({})", v),
         }
     }
 }
 
-fn mode_from_str<'de, D>(deserializer: D) -> Result<Mode, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    let s = u8::deserialize(deserializer)?;
-    match s {
-        2 => Ok(Mode::Fix2d),
-        3 => Ok(Mode::Fix3d),
-        _ => Ok(Mode::NoFix),
+impl From<u8> for Mode {
+    fn from(v: u8) -> Mode {
+        match v {
+            0 => Mode::NotSeen,
+            1 => Mode::NoFix,
+            2 => Mode::Fix2d,
+            3 => Mode::Fix3d,
+            _ => Mode::Unrecognized(v),
+        }
     }
 }
 
-/// GPS position.
-///
-/// A TPV object is a time-position-velocity report. The "mode"
-/// field will be emitted before optional fields that may be
-/// absent when there is no fix. Error estimates will be emitted
-/// after the fix components they're associated with. Others may
-/// be reported or not depending on the fix quality.
-#[derive(Debug, Deserialize, Clone)]
-#[cfg_attr(feature = "serialize", derive(Serialize))]
-pub struct Tpv {
-    /// Name of the originating device.
-    pub device: Option<String>,
-    /// GPS fix status.
-    pub status: Option<i32>,
-    /// NMEA mode, see `Mode` enum.
-    #[serde(deserialize_with = "mode_from_str")]
-    pub mode: Mode,
-    /// Time/date stamp in ISO8601 format, UTC. May have a
-    /// fractional part of up to .001sec precision. May be absent
-    /// if mode is not 2 or 3.
-    pub time: Option<String>,
-    /// Estimated timestamp error (%f, seconds, 95% confidence).
-    /// Present if time is present.
-    pub ept: Option<f32>,
-    pub leapseconds: Option<i32>,
-    /// MSL altitude in meters.
-    #[serde(rename = "altMSL")]
-    pub alt_msl: Option<f32>,
-    /// Altitude height above ellipsoid (elipsoid is unspecified, but probably WGS48)
-    #[serde(rename = "altHAE")]
-    pub alt_hae: Option<f32>,
-    /// Geoid separation between whatever geoid the device uses and WGS84, in metres
-    #[serde(rename = "geoidSep")]
-    pub geoid_sep: Option<f32>,
-    /// Latitude in degrees: +/- signifies North/South. Present
-    /// when mode is 2 or 3.
-    pub lat: Option<f64>,
-    /// Longitude in degrees: +/- signifies East/West. Present
-    /// when mode is 2 or 3.
-    pub lon: Option<f64>,
-    /// Altitude in meters. Present if mode is 3.
-    pub alt: Option<f32>,
-    /// Longitude error estimate in meters, 95% confidence.
-    /// Present if mode is 2 or 3 and DOPs can be calculated from
-    /// the satellite view.
-    pub epx: Option<f32>,
-    /// Latitude error estimate in meters, 95% confidence. Present
-    /// if mode is 2 or 3 and DOPs can be calculated from the
-    /// satellite view.
-    pub epy: Option<f32>,
-    /// Estimated vertical error in meters, 95% confidence.
-    /// Present if mode is 3 and DOPs can be calculated from the
-    /// satellite view.
-    pub epv: Option<f32>,
-    /// Course over ground, degrees from true north.
-    pub track: Option<f32>,
-    /// Speed over ground, meters per second.
-    pub speed: Option<f32>,
-    /// Climb (positive) or sink (negative) rate, meters per
-    /// second.
-    pub climb: Option<f32>,
-    /// Direction error estimate in degrees, 95% confidence.
-    pub epd: Option<f32>,
-    /// Speed error estinmate in meters/sec, 95% confidence.
-    pub eps: Option<f32>,
-    /// Climb/sink error estimate in meters/sec, 95% confidence.
-    pub epc: Option<f32>,
-    /// Horizontal 2D position error in meters.
-    pub eph: Option<f32>,
+impl From<Mode> for u8 {
+    fn from(m: Mode) -> u8 {
+        match m {
+            Mode::NotSeen => 0,
+            Mode::NoFix => 1,
+            Mode::Fix2d => 2,
+            Mode::Fix3d => 3,
+            Mode::Unrecognized(v) => v,
+        }
+    }
 }
 
-/// Detailed satellite information.
-#[derive(Debug, Deserialize, Clone)]
-#[cfg_attr(feature = "serialize", derive(Serialize))]
-pub struct Satellite {
-    /// PRN ID of the satellite. 1-63 are GNSS satellites, 64-96 are
-    /// GLONASS satellites, 100-164 are SBAS satellites.
-    #[serde(rename = "PRN")]
-    pub prn: i16,
-    /// Elevation in degrees.
-    pub el: Option<f32>,
-    /// Azimuth, degrees from true north.
-    pub az: Option<f32>,
-    /// Signal strength in dB.
-    pub ss: Option<f32>,
-    /// Used in current solution? (SBAS/WAAS/EGNOS satellites may be
-    /// flagged used if the solution has corrections from them, but
-    /// not all drivers make this information available.).
-    pub used: bool,
-    pub gnssid: Option<u8>,
-    pub svid: Option<u16>,
-    pub health: Option<u8>,
+/// Time/date stamp in ISO8601 format, UTC, as reported by `gpsd`,
+/// e.g. `2005-06-08T10:34:48.283Z`.
+///
+/// The text is stored inline, so no heap allocation is needed per
+/// message. The layout is validated on construction, the date and
+/// time components are parsed on access.
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Iso8601Timestamp {
+    buf: [u8; Iso8601Timestamp::CAPACITY],
+    len: u8,
 }
 
-/// Satellites information.
-///
-/// A SKY object reports a sky view of the GPS satellite
-/// positions. If there is no GPS device available, or no skyview
-/// has been reported yet.
-///
-/// Many devices compute dilution of precision factors but do not
-/// include them in their reports. Many that do report DOPs report
-/// only HDOP, two-dimensional circular error. gpsd always passes
-/// through whatever the device actually reports, then attempts to
-/// fill in other DOPs by calculating the appropriate determinants
-/// in a covariance matrix based on the satellite view. DOPs may
-/// be missing if some of these determinants are singular. It can
-/// even happen that the device reports an error estimate in
-/// meters when the corresponding DOP is unavailable; some devices
-/// use more sophisticated error modeling than the covariance
-/// calculation.
-#[derive(Debug, Deserialize, Clone)]
+impl Iso8601Timestamp {
+    /// Maximum length of the text, enough for nanosecond precision.
+    pub const CAPACITY: usize = 32;
+
+    /// Validates `text` as `YYYY-MM-DDTHH:MM:SS[.f+]Z`.
+    ///
+    /// Returns `None` if the layout doesn't match or `text` is
+    /// longer than `CAPACITY`.
+    pub fn new(text: &str) -> Option<Self> {
+        let b = text.as_bytes();
+        if b.len() < 20 || b.len() > Self::CAPACITY {
+            return None;
+        }
+        let digits = |r: core::ops::Range<usize>| b[r].iter().all(u8::is_ascii_digit);
+        let layout = digits(0..4)
+            && b[4] == b'-'
+            && digits(5..7)
+            && b[7] == b'-'
+            && digits(8..10)
+            && b[10] == b'T'
+            && digits(11..13)
+            && b[13] == b':'
+            && digits(14..16)
+            && b[16] == b':'
+            && digits(17..19);
+        let tail = &b[19..];
+        let tail_ok = match tail {
+            [b'Z'] => true,
+            [b'.', frac @ .., b'Z'] => !frac.is_empty() && frac.iter().all(u8::is_ascii_digit),
+            _ => false,
+        };
+        if !(layout && tail_ok) {
+            return None;
+        }
+        let mut buf = [0; Self::CAPACITY];
+        buf[..b.len()].copy_from_slice(b);
+        Some(Iso8601Timestamp {
+            buf,
+            len: b.len() as u8,
+        })
+    }
+
+    /// The timestamp as reported by `gpsd`.
+    pub fn as_str(&self) -> &str {
+        // Only ASCII has been copied in by `new`.
+        core::str::from_utf8(&self.buf[..self.len as usize]).unwrap_or_default()
+    }
+
+    fn number(&self, from: usize, to: usize) -> u32 {
+        self.buf[from..to]
+            .iter()
+            .fold(0, |n, d| n * 10 + u32::from(d - b'0'))
+    }
+
+    /// Year, e.g. 2005.
+    pub fn year(&self) -> u16 {
+        self.number(0, 4) as u16
+    }
+
+    /// Month, 1 to 12.
+    pub fn month(&self) -> u8 {
+        self.number(5, 7) as u8
+    }
+
+    /// Day of the month, 1 to 31.
+    pub fn day(&self) -> u8 {
+        self.number(8, 10) as u8
+    }
+
+    /// Hour, 0 to 23.
+    pub fn hour(&self) -> u8 {
+        self.number(11, 13) as u8
+    }
+
+    /// Minute, 0 to 59.
+    pub fn minute(&self) -> u8 {
+        self.number(14, 16) as u8
+    }
+
+    /// Second, 0 to 60 (leap second).
+    pub fn second(&self) -> u8 {
+        self.number(17, 19) as u8
+    }
+
+    /// Fractional part of the second in nanoseconds. Digits beyond
+    /// nanosecond precision are ignored.
+    pub fn nanosecond(&self) -> u32 {
+        let len = self.len as usize;
+        if len == 20 {
+            return 0;
+        }
+        let frac = &self.buf[20..len - 1];
+        let digits = frac.len().min(9);
+        self.number(20, 20 + digits) * 10u32.pow((9 - digits) as u32)
+    }
+
+    /// The timestamp as system time. Leap seconds are not accounted
+    /// for, like with Unix time.
+    #[cfg(feature = "std")]
+    pub fn to_system_time(&self) -> std::time::SystemTime {
+        let secs = self.days() * 86_400
+            + i64::from(self.hour()) * 3_600
+            + i64::from(self.minute()) * 60
+            + i64::from(self.second());
+        let whole = Duration::from_secs(secs.unsigned_abs());
+        let frac = Duration::from_nanos(u64::from(self.nanosecond()));
+        if secs >= 0 {
+            std::time::UNIX_EPOCH + whole + frac
+        } else {
+            std::time::UNIX_EPOCH - whole + frac
+        }
+    }
+
+    /// Corrects a date shifted back by GPS week number rollovers.
+    ///
+    /// Old receivers count GPS weeks in 10 bits and report dates 1024
+    /// weeks (about 19.6 years) too early after a rollover, e.g. in
+    /// 1999 or 2019. Multiples of 1024 weeks are added until the
+    /// timestamp is not before `not_before`, e.g. the build date of
+    /// the application. Timestamps not before `not_before` are
+    /// returned unchanged. No rollover is added past the year 9999,
+    /// the largest year of the layout.
+    #[cfg(feature = "std")]
+    pub fn correct_week_rollover(&self, not_before: std::time::SystemTime) -> Self {
+        // 9999-12-31
+        const MAX_DAYS: i64 = 2_932_896;
+        const ROLLOVER_DAYS: i64 = GPS_WEEK_ROLLOVER.as_secs() as i64 / 86_400;
+        let mut days = self.days();
+        let time = self.to_system_time();
+        let mut shift = Duration::ZERO;
+        while time + shift < not_before && days + ROLLOVER_DAYS <= MAX_DAYS {
+            shift += GPS_WEEK_ROLLOVER;
+            days += ROLLOVER_DAYS;
+        }
+        if shift.is_zero() {
+            return *self;
+        }
+        let (y, m, d) = civil_date(days);
+        let date = format!("{:04}-{:02}-{:02}", y, m, d);
+        let mut corrected = *self;
+        corrected.buf[..10].copy_from_slice(date.as_bytes());
+        corrected
+    }
+
+    /// Timestamp of `time` with millisecond precision, like `gpsd`
+    /// reports it. Times before the Unix epoch or after the year 9999
+    /// are clamped.
+    #[cfg(feature = "std")]
+    pub fn from_system_time(time: std::time::SystemTime) -> Self {
+        let since = time
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .min(Duration::from_secs(253_402_300_799));
+        let secs = since.as_secs();
+        let (y, m, d) = civil_date((secs / 86_400) as i64);
+        let text = format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+            y,
+            m,
+            d,
+            secs % 86_400 / 3_600,
+            secs % 3_600 / 60,
+            secs % 60,
+            since.subsec_millis()
+        );
+        // The layout is valid by construction.
+        Self::new(&text).unwrap()
+    }
+
+    /// Days since the Unix epoch of the civil date, see
+    /// http://howardhinnant.github.io/date_algorithms.html
+    #[cfg(feature = "std")]
+    fn days(&self) -> i64 {
+        let (m, d) = (i64::from(self.month()), i64::from(self.day()));
+        let y = i64::from(self.year()) - i64::from(m <= 2);
+        let era = y.div_euclid(400);
+        let yoe = y - era * 400;
+        let doy = (153 * (m + if m > 2 { -3 } else { 9 }) + 2) / 5 + d - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        era * 146_097 + doe - 719_468
+    }
+}
+
+/// Civil date `(year, month, day)` of the days since the Unix epoch,
+/// see http://howardhinnant.github.io/date_algorithms.html
+#[cfg(feature = "std")]
+fn civil_date(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    (yoe + era * 400 + i64::from(m <= 2), m, d)
+}
+
+impl fmt::Display for Iso8601Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Debug for Iso8601Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for Iso8601Timestamp {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "{=str}", self.as_str())
+    }
+}
+
+#[cfg(feature = "schemars")]
+impl schemars::JsonSchema for Iso8601Timestamp {
+    fn schema_name() -> Cow<'static, str> {
+        "Iso8601Timestamp".into()
+    }
+
+    fn json_schema(_: &mut schemars::SchemaGenerator) -> schemars::Schema {
+        schemars::json_schema!({"type": "string", "format": "date-time"})
+    }
+}
+
+impl AsRef<str> for Iso8601Timestamp {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl PartialEq<str> for Iso8601Timestamp {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Iso8601Timestamp {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl<'de> Deserialize<'de> for Iso8601Timestamp {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct TimestampVisitor;
+
+        impl Visitor<'_> for TimestampVisitor {
+            type Value = Iso8601Timestamp;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("an ISO8601 timestamp")
+            }
+
+            fn visit_str<E: Error>(self, value: &str) -> Result<Self::Value, E> {
+                Iso8601Timestamp::new(value)
+                    .ok_or_else(|| E::invalid_value(Unexpected::Str(value), &self))
+            }
+        }
+
+        deserializer.deserialize_str(TimestampVisitor)
+    }
+}
+
+#[cfg(feature = "serialize")]
+impl serde::Serialize for Iso8601Timestamp {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+/// GPS fix status.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serialize", derive(Serialize), serde(into = "u8"))]
+#[serde(from = "u8")]
+pub enum Status {
+    /// Status is unknown.
+    Unknown,
+    /// Normal fix.
+    Normal,
+    /// Differential GPS fix.
+    Dgps,
+    /// RTK fixed solution.
+    RtkFixed,
+    /// RTK floating solution.
+    RtkFloat,
+    /// Dead reckoning.
+    DeadReckoning,
+    /// GNSS plus dead reckoning.
+    GnssDeadReckoning,
+    /// Time only (surveyed-in, fixed location).
+    Time,
+    /// Simulated.
+    Simulated,
+    /// P(Y) fix.
+    PY,
+    /// Value not known to this crate.
+    Unrecognized(u8),
+}
+
+impl From<u8> for Status {
+    fn from(v: u8) -> Status {
+        match v {
+            0 => Status::Unknown,
+            1 => Status::Normal,
+            2 => Status::Dgps,
+            3 => Status::RtkFixed,
+            4 => Status::RtkFloat,
+            5 => Status::DeadReckoning,
+            6 => Status::GnssDeadReckoning,
+            7 => Status::Time,
+            8 => Status::Simulated,
+            9 => Status::PY,
+            _ => Status::Unrecognized(v),
+        }
+    }
+}
+
+impl From<Status> for u8 {
+    fn from(s: Status) -> u8 {
+        match s {
+            Status::Unknown => 0,
+            Status::Normal => 1,
+            Status::Dgps => 2,
+            Status::RtkFixed => 3,
+            Status::RtkFloat => 4,
+            Status::DeadReckoning => 5,
+            Status::GnssDeadReckoning => 6,
+            Status::Time => 7,
+            Status::Simulated => 8,
+            Status::PY => 9,
+            Status::Unrecognized(v) => v,
+        }
+    }
+}
+
+/// Signal quality of a satellite.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serialize", derive(Serialize), serde(into = "u8"))]
+#[serde(from = "u8")]
+pub enum Quality {
+    /// No signal.
+    NoSignal,
+    /// Searching signal.
+    Searching,
+    /// Signal acquired.
+    Acquired,
+    /// Signal detected but unusable.
+    Unusable,
+    /// Code locked and time synchronized.
+    CodeLocked,
+    /// Code and carrier locked and time synchronized. Carries the value
+    /// reported by `gpsd`, 5, 6 or 7.
+    CodeCarrierLocked(u8),
+    /// Value not known to this crate.
+    Unrecognized(u8),
+}
+
+impl From<u8> for Quality {
+    fn from(v: u8) -> Quality {
+        match v {
+            0 => Quality::NoSignal,
+            1 => Quality::Searching,
+            2 => Quality::Acquired,
+            3 => Quality::Unusable,
+            4 => Quality::CodeLocked,
+            5..=7 => Quality::CodeCarrierLocked(v),
+            _ => Quality::Unrecognized(v),
+        }
+    }
+}
+
+impl From<Quality> for u8 {
+    fn from(q: Quality) -> u8 {
+        match q {
+            Quality::NoSignal => 0,
+            Quality::Searching => 1,
+            Quality::Acquired => 2,
+            Quality::Unusable => 3,
+            Quality::CodeLocked => 4,
+            Quality::CodeCarrierLocked(v) => v,
+            Quality::Unrecognized(v) => v,
+        }
+    }
+}
+
+/// Health of a satellite.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serialize", derive(Serialize), serde(into = "u8"))]
+#[serde(from = "u8")]
+pub enum Health {
+    /// Health is unknown.
+    Unknown,
+    /// Satellite is healthy.
+    Ok,
+    /// Satellite is unhealthy.
+    Unhealthy,
+    /// Value not known to this crate.
+    Unrecognized(u8),
+}
+
+impl From<u8> for Health {
+    fn from(v: u8) -> Health {
+        match v {
+            0 => Health::Unknown,
+            1 => Health::Ok,
+            2 => Health::Unhealthy,
+            _ => Health::Unrecognized(v),
+        }
+    }
+}
+
+impl From<Health> for u8 {
+    fn from(h: Health) -> u8 {
+        match h {
+            Health::Unknown => 0,
+            Health::Ok => 1,
+            Health::Unhealthy => 2,
+            Health::Unrecognized(v) => v,
+        }
+    }
+}
+
+/// GPS position.
+///
+/// A TPV object is a time-position-velocity report. The "mode"
+/// field will be emitted before optional fields that may be
+/// absent when there is no fix. Error estimates will be emitted
+/// after the fix components they're associated with. Others may
+/// be reported or not depending on the fix quality.
+#[derive(Debug, Deserialize, Clone)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct Tpv {
+    /// Name of the originating device.
+    #[cfg_attr(feature = "schemars", schemars(with = "Option<String>"))]
+    pub device: Option<CompactString>,
+    /// GPS fix status.
+    pub status: Option<Status>,
+    /// NMEA mode, see `Mode` enum.
+    pub mode: Mode,
+    /// Time/date stamp in ISO8601 format, UTC. May have a
+    /// fractional part of up to .001sec precision. May be absent
+    /// if mode is not 2 or 3.
+    pub time: Option<Iso8601Timestamp>,
+    /// Estimated timestamp error (%f, seconds, 95% confidence).
+    /// Present if time is present.
+    pub ept: Option<f32>,
+    pub leapseconds: Option<i32>,
+    /// MSL altitude in meters.
+    #[serde(rename = "altMSL")]
+    pub alt_msl: Option<f32>,
+    /// Altitude height above ellipsoid (elipsoid is unspecified, but probably WGS48)
+    #[serde(rename = "altHAE")]
+    pub alt_hae: Option<f32>,
+    /// Geoid separation between whatever geoid the device uses and WGS84, in metres
+    #[serde(rename = "geoidSep")]
+    pub geoid_sep: Option<f32>,
+    /// Latitude in degrees: +/- signifies North/South. Present
+    /// when mode is 2 or 3.
+    pub lat: Option<f64>,
+    /// Longitude in degrees: +/- signifies East/West. Present
+    /// when mode is 2 or 3.
+    pub lon: Option<f64>,
+    /// Altitude in meters. Present if mode is 3. Deprecated since
+    /// `gpsd` 3.20, use `alt_msl` or `alt_hae` instead.
+    pub alt: Option<f32>,
+    /// Longitude error estimate in meters, 95% confidence.
+    /// Present if mode is 2 or 3 and DOPs can be calculated from
+    /// the satellite view.
+    pub epx: Option<f32>,
+    /// Latitude error estimate in meters, 95% confidence. Present
+    /// if mode is 2 or 3 and DOPs can be calculated from the
+    /// satellite view.
+    pub epy: Option<f32>,
+    /// Estimated vertical error in meters, 95% confidence.
+    /// Present if mode is 3 and DOPs can be calculated from the
+    /// satellite view.
+    pub epv: Option<f32>,
+    /// Course over ground, degrees from true north.
+    pub track: Option<f32>,
+    /// Speed over ground, meters per second.
+    pub speed: Option<f32>,
+    /// Climb (positive) or sink (negative) rate, meters per
+    /// second.
+    pub climb: Option<f32>,
+    /// Direction error estimate in degrees, 95% confidence.
+    pub epd: Option<f32>,
+    /// Speed error estinmate in meters/sec, 95% confidence.
+    pub eps: Option<f32>,
+    /// Climb/sink error estimate in meters/sec, 95% confidence.
+    pub epc: Option<f32>,
+    /// Horizontal 2D position error in meters.
+    pub eph: Option<f32>,
+    /// ECEF X position in meters.
+    pub ecefx: Option<f64>,
+    /// ECEF Y position in meters.
+    pub ecefy: Option<f64>,
+    /// ECEF Z position in meters.
+    pub ecefz: Option<f64>,
+    /// ECEF position error in meters.
+    #[serde(rename = "ecefpAcc")]
+    pub ecef_p_acc: Option<f32>,
+    /// ECEF X velocity in meters per second.
+    pub ecefvx: Option<f32>,
+    /// ECEF Y velocity in meters per second.
+    pub ecefvy: Option<f32>,
+    /// ECEF Z velocity in meters per second.
+    pub ecefvz: Option<f32>,
+    /// ECEF velocity error in meters per second.
+    #[serde(rename = "ecefvAcc")]
+    pub ecef_v_acc: Option<f32>,
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for Tpv {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "Tpv {{ device: {}, status: {}, mode: {}, time: {}, ept: {}, \
+             leapseconds: {}, alt_msl: {}, alt_hae: {}, geoid_sep: {}, lat: {}, \
+             lon: {}, alt: {}, epx: {}, epy: {}, epv: {}, track: {}, speed: {}, \
+             climb: {}, epd: {}, eps: {}, epc: {}, eph: {}, ecefx: {}, ecefy: {}, \
+             ecefz: {}, ecef_p_acc: {}, ecefvx: {}, ecefvy: {}, ecefvz: {}, \
+             ecef_v_acc: {} }}",
+            self.device.as_deref(),
+            self.status,
+            self.mode,
+            self.time,
+            self.ept,
+            self.leapseconds,
+            self.alt_msl,
+            self.alt_hae,
+            self.geoid_sep,
+            self.lat,
+            self.lon,
+            self.alt,
+            self.epx,
+            self.epy,
+            self.epv,
+            self.track,
+            self.speed,
+            self.climb,
+            self.epd,
+            self.eps,
+            self.epc,
+            self.eph,
+            self.ecefx,
+            self.ecefy,
+            self.ecefz,
+            self.ecef_p_acc,
+            self.ecefvx,
+            self.ecefvy,
+            self.ecefvz,
+            self.ecef_v_acc
+        )
+    }
+}
+
+/// Start of GPS time, 1980-01-06T00:00:00Z, as Unix time in seconds.
+pub const GPS_EPOCH_UNIX_SECS: u64 = 315_964_800;
+
+/// Period of the 10 bit GPS week number, 1024 weeks.
+pub const GPS_WEEK_ROLLOVER: Duration = Duration::from_secs(1024 * 7 * 86_400);
+
+/// Offset of TAI ahead of GPS time in seconds. Unlike the offset of
+/// GPS time to UTC this never changes.
+pub const TAI_GPS_OFFSET_SECS: u64 = 19;
+
+impl Tpv {
+    /// The time of the report as UTC system time.
+    #[cfg(feature = "std")]
+    pub fn utc_time(&self) -> Option<std::time::SystemTime> {
+        Some(self.time?.to_system_time())
+    }
+
+    /// The time of the report as GPS time, i.e. the time since the GPS
+    /// epoch including the leap seconds since then. Requires the
+    /// `leapseconds` reported by the receiver.
+    #[cfg(feature = "std")]
+    pub fn gps_time(&self) -> Option<Duration> {
+        let utc = self
+            .utc_time()?
+            .duration_since(std::time::UNIX_EPOCH)
+            .ok()?
+            .checked_sub(Duration::from_secs(GPS_EPOCH_UNIX_SECS))?;
+        let leap = Duration::from_secs(u64::try_from(self.leapseconds?).ok()?);
+        Some(utc + leap)
+    }
+
+    /// The time of the report as TAI since 1970-01-01T00:00:00 TAI,
+    /// the timescale used by PTP. Requires the `leapseconds` reported
+    /// by the receiver.
+    #[cfg(feature = "std")]
+    pub fn tai_time(&self) -> Option<Duration> {
+        Some(self.gps_time()? + Duration::from_secs(GPS_EPOCH_UNIX_SECS + TAI_GPS_OFFSET_SECS))
+    }
+}
+
+/// Converts GPS time, as returned by [`Tpv::gps_time`], to UTC system
+/// time with the given GPS-UTC offset `leapseconds`.
+#[cfg(feature = "std")]
+pub fn gps_to_utc(gps_time: Duration, leapseconds: u32) -> std::time::SystemTime {
+    std::time::UNIX_EPOCH + Duration::from_secs(GPS_EPOCH_UNIX_SECS) + gps_time
+        - Duration::from_secs(u64::from(leapseconds))
+}
+
+/// Detailed satellite information.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Default, Deserialize, Clone)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct Satellite {
+    /// PRN ID of the satellite. 1-63 are GNSS satellites, 64-96 are
+    /// GLONASS satellites, 100-164 are SBAS satellites.
+    #[serde(rename = "PRN")]
+    pub prn: i16,
+    /// Elevation in degrees.
+    pub el: Option<f32>,
+    /// Azimuth, degrees from true north.
+    pub az: Option<f32>,
+    /// Signal strength in dB.
+    pub ss: Option<f32>,
+    /// Used in current solution? (SBAS/WAAS/EGNOS satellites may be
+    /// flagged used if the solution has corrections from them, but
+    /// not all drivers make this information available.).
+    pub used: bool,
+    pub gnssid: Option<u8>,
+    pub svid: Option<u16>,
+    /// GLONASS frequency ID of the signal, the frequency slot plus 7
+    /// as defined by u-blox. Only reported for GLONASS satellites.
+    pub freqid: Option<u8>,
+    /// Signal ID of the signal, as defined by u-blox, see
+    /// [`SignalId`].
+    pub sigid: Option<u8>,
+    /// Health of the satellite.
+    pub health: Option<Health>,
+    /// Signal quality indicator.
+    pub qual: Option<Quality>,
+}
+
+/// Satellites information.
+///
+/// A SKY object reports a sky view of the GPS satellite
+/// positions. If there is no GPS device available, or no skyview
+/// has been reported yet.
+///
+/// Many devices compute dilution of precision factors but do not
+/// include them in their reports. Many that do report DOPs report
+/// only HDOP, two-dimensional circular error. gpsd always passes
+/// through whatever the device actually reports, then attempts to
+/// fill in other DOPs by calculating the appropriate determinants
+/// in a covariance matrix based on the satellite view. DOPs may
+/// be missing if some of these determinants are singular. It can
+/// even happen that the device reports an error estimate in
+/// meters when the corresponding DOP is unavailable; some devices
+/// use more sophisticated error modeling than the covariance
+/// calculation.
+#[derive(Debug, Deserialize, Clone)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[cfg_attr(feature = "serialize", derive(Serialize))]
 pub struct Sky {
     /// Name of originating device.
-    pub device: Option<String>,
+    #[cfg_attr(feature = "schemars", schemars(with = "Option<String>"))]
+    pub device: Option<CompactString>,
+    /// Time/date stamp in ISO8601 format, UTC. May be absent.
+    pub time: Option<Iso8601Timestamp>,
     /// Longitudinal dilution of precision, a dimensionless factor
     /// which should be multiplied by a base UERE to get an error
     /// estimate.
@@ -370,6 +1529,27 @@ pub struct Sky {
     pub satellites: Option<Vec<Satellite>>,
 }
 
+#[cfg(feature = "defmt")]
+impl defmt::Format for Sky {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "Sky {{ device: {}, time: {}, xdop: {}, ydop: {}, vdop: {}, tdop: {}, \
+             hdop: {}, gdop: {}, pdop: {}, satellites: {} }}",
+            self.device.as_deref(),
+            self.time,
+            self.xdop,
+            self.ydop,
+            self.vdop,
+            self.tdop,
+            self.hdop,
+            self.gdop,
+            self.pdop,
+            self.satellites
+        )
+    }
+}
+
 /// This message is emitted each time the daemon sees a valid PPS (Pulse Per
 /// Second) strobe from a device.
 ///
@@ -407,31 +1587,127 @@ pub struct Sky {
 /// supports RFC 2783. USB1.1-to-serial control-line emulation is limited to
 /// about 1 millisecond.
 #[derive(Debug, Deserialize, Clone)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[cfg_attr(feature = "serialize", derive(Serialize))]
 pub struct Pps {
     /// Name of originating device.
-    pub device: String,
+    #[cfg_attr(feature = "schemars", schemars(with = "String"))]
+    pub device: CompactString,
     /// Seconds from the PPS source.
-    pub real_sec: f32,
+    pub real_sec: i64,
     /// Nanoseconds from the PPS source.
-    pub real_nsec: f32,
+    pub real_nsec: i64,
     /// Seconds from the system clock.
-    pub clock_sec: f32,
+    pub clock_sec: i64,
     /// Nanoseconds from the system clock.
-    pub clock_nsec: f32,
+    pub clock_nsec: i64,
     /// NTP style estimate of PPS precision.
     pub precision: f32,
+    /// Quantization error of the PPS edge in picoseconds, also known
+    /// as sawtooth error. Reported by some receivers only.
+    #[serde(rename = "qErr")]
+    pub q_err: Option<i64>,
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for Pps {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "Pps {{ device: {}, real_sec: {}, real_nsec: {}, clock_sec: {}, \
+             clock_nsec: {}, precision: {}, q_err: {} }}",
+            self.device.as_str(),
+            self.real_sec,
+            self.real_nsec,
+            self.clock_sec,
+            self.clock_nsec,
+            self.precision,
+            self.q_err
+        )
+    }
+}
+
+impl Pps {
+    /// Offset of the GPS time ahead of the system clock at the PPS
+    /// edge, in nanoseconds.
+    pub fn offset_ns(&self) -> i64 {
+        offset_ns(
+            self.real_sec,
+            self.real_nsec,
+            self.clock_sec,
+            self.clock_nsec,
+        )
+    }
+}
+
+/// Time offset report, emitted once per cycle when PPS reports are
+/// enabled, see [`Pps`].
+///
+/// real_sec and real_nsec contain the time the GPS reported at the
+/// start of the cycle; clock_sec and clock_nsec contain the time of the
+/// system clock when the start of the cycle was detected. Unlike PPS the
+/// time is derived from the serial data stream and much less precise.
+#[derive(Debug, Deserialize, Clone)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct Toff {
+    /// Name of originating device.
+    #[cfg_attr(feature = "schemars", schemars(with = "String"))]
+    pub device: CompactString,
+    /// Seconds from the GPS.
+    pub real_sec: i64,
+    /// Nanoseconds from the GPS.
+    pub real_nsec: i64,
+    /// Seconds from the system clock.
+    pub clock_sec: i64,
+    /// Nanoseconds from the system clock.
+    pub clock_nsec: i64,
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for Toff {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "Toff {{ device: {}, real_sec: {}, real_nsec: {}, clock_sec: {}, \
+             clock_nsec: {} }}",
+            self.device.as_str(),
+            self.real_sec,
+            self.real_nsec,
+            self.clock_sec,
+            self.clock_nsec
+        )
+    }
+}
+
+impl Toff {
+    /// Offset of the GPS time ahead of the system clock at the start
+    /// of the cycle, in nanoseconds.
+    pub fn offset_ns(&self) -> i64 {
+        offset_ns(
+            self.real_sec,
+            self.real_nsec,
+            self.clock_sec,
+            self.clock_nsec,
+        )
+    }
+}
+
+fn offset_ns(real_sec: i64, real_nsec: i64, clock_sec: i64, clock_nsec: i64) -> i64 {
+    (real_sec - clock_sec) * 1_000_000_000 + real_nsec - clock_nsec
 }
 
 /// Pseudorange noise report.
 #[derive(Debug, Deserialize, Clone)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[cfg_attr(feature = "serialize", derive(Serialize))]
 pub struct Gst {
     /// Name of originating device.
-    pub device: Option<String>,
+    #[cfg_attr(feature = "schemars", schemars(with = "Option<String>"))]
+    pub device: Option<CompactString>,
     /// Time/date stamp in ISO8601 format, UTC. May have a fractional part of up
     /// to .001 sec precision.
-    pub time: Option<String>,
+    pub time: Option<Iso8601Timestamp>,
     /// Value of the standard deviation of the range inputs to the navigation
     /// process (range inputs include pseudoranges and DGPS corrections).
     pub rms: Option<f32>,
@@ -450,39 +1726,1196 @@ pub struct Gst {
     pub alt: Option<f32>,
 }
 
-/// Responses from `gpsd` after handshake (i.e. the payload)
-#[derive(Debug, Deserialize, Clone)]
+#[cfg(feature = "defmt")]
+impl defmt::Format for Gst {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "Gst {{ device: {}, time: {}, rms: {}, major: {}, minor: {}, orient: {}, \
+             lat: {}, lon: {}, alt: {} }}",
+            self.device.as_deref(),
+            self.time,
+            self.rms,
+            self.major,
+            self.minor,
+            self.orient,
+            self.lat,
+            self.lon,
+            self.alt
+        )
+    }
+}
+
+/// Vehicle attitude report, as reported by ATT and IMU messages.
+///
+/// Reported by devices with a digital compass, gyroscope or
+/// accelerometer.
+#[cfg(feature = "att")]
+#[derive(Debug, Deserialize, Clone)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[cfg_attr(feature = "serialize", derive(Serialize))]
-#[serde(tag = "class")]
-#[serde(rename_all = "UPPERCASE")]
-pub enum ResponseData {
-    Device(Device),
-    Tpv(Tpv),
-    Sky(Sky),
-    Pps(Pps),
-    Gst(Gst),
+pub struct Att {
+    /// Name of originating device.
+    #[cfg_attr(feature = "schemars", schemars(with = "Option<String>"))]
+    pub device: Option<CompactString>,
+    /// Time/date stamp in ISO8601 format, UTC.
+    pub time: Option<Iso8601Timestamp>,
+    /// Arbitrary time tag of the measurement.
+    #[serde(rename = "timeTag")]
+    pub time_tag: Option<u64>,
+    /// Heading, degrees from true north.
+    pub heading: Option<f32>,
+    /// Magnetometer status.
+    pub mag_st: Option<String>,
+    /// Heading, degrees from magnetic north.
+    pub mheading: Option<f32>,
+    /// Pitch in degrees.
+    pub pitch: Option<f32>,
+    /// Pitch sensor status.
+    pub pitch_st: Option<String>,
+    /// Rate of turn in degrees per minute.
+    pub rot: Option<f32>,
+    /// Yaw in degrees.
+    pub yaw: Option<f32>,
+    /// Yaw sensor status.
+    pub yaw_st: Option<String>,
+    /// Roll in degrees.
+    pub roll: Option<f32>,
+    /// Roll sensor status.
+    pub roll_st: Option<String>,
+    /// Local magnetic inclination, degrees, positive when the
+    /// magnetic field points downward (into the Earth).
+    pub dip: Option<f32>,
+    /// Scalar magnetic field strength.
+    pub mag_len: Option<f32>,
+    /// X component of magnetic field strength.
+    pub mag_x: Option<f32>,
+    /// Y component of magnetic field strength.
+    pub mag_y: Option<f32>,
+    /// Z component of magnetic field strength.
+    pub mag_z: Option<f32>,
+    /// Scalar acceleration.
+    pub acc_len: Option<f32>,
+    /// X component of acceleration.
+    pub acc_x: Option<f32>,
+    /// Y component of acceleration.
+    pub acc_y: Option<f32>,
+    /// Z component of acceleration.
+    pub acc_z: Option<f32>,
+    /// Temperature at the gyroscope, degrees Celsius.
+    pub gyro_temp: Option<f32>,
+    /// X component of angular rate, degrees per second.
+    pub gyro_x: Option<f32>,
+    /// Y component of angular rate, degrees per second.
+    pub gyro_y: Option<f32>,
+    /// Z component of angular rate, degrees per second.
+    pub gyro_z: Option<f32>,
+    /// Water depth in meters.
+    pub depth: Option<f32>,
+    /// Temperature at the sensor, degrees Celsius.
+    pub temp: Option<f32>,
 }
 
-/// All known `gpsd` responses (handshake + normal operation).
+#[cfg(all(feature = "defmt", feature = "att"))]
+impl defmt::Format for Att {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "Att {{ device: {}, time: {}, time_tag: {}, heading: {}, mag_st: {}, \
+             mheading: {}, pitch: {}, pitch_st: {}, rot: {}, yaw: {}, yaw_st: {}, \
+             roll: {}, roll_st: {}, dip: {}, mag_len: {}, mag_x: {}, mag_y: {}, \
+             mag_z: {}, acc_len: {}, acc_x: {}, acc_y: {}, acc_z: {}, gyro_temp: {}, \
+             gyro_x: {}, gyro_y: {}, gyro_z: {}, depth: {}, temp: {} }}",
+            self.device.as_deref(),
+            self.time,
+            self.time_tag,
+            self.heading,
+            self.mag_st,
+            self.mheading,
+            self.pitch,
+            self.pitch_st,
+            self.rot,
+            self.yaw,
+            self.yaw_st,
+            self.roll,
+            self.roll_st,
+            self.dip,
+            self.mag_len,
+            self.mag_x,
+            self.mag_y,
+            self.mag_z,
+            self.acc_len,
+            self.acc_x,
+            self.acc_y,
+            self.acc_z,
+            self.gyro_temp,
+            self.gyro_x,
+            self.gyro_y,
+            self.gyro_z,
+            self.depth,
+            self.temp
+        )
+    }
+}
+
+/// Oscillator report of a GPS-disciplined oscillator (GPSDO).
+#[cfg(feature = "osc")]
 #[derive(Debug, Deserialize, Clone)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[cfg_attr(feature = "serialize", derive(Serialize))]
-#[serde(tag = "class")]
-#[serde(rename_all = "UPPERCASE")]
+pub struct Osc {
+    /// Name of originating device.
+    #[cfg_attr(feature = "schemars", schemars(with = "Option<String>"))]
+    pub device: Option<CompactString>,
+    /// If true, the oscillator is currently running. Oscillators may
+    /// require warm-up time at the start of the day.
+    pub running: bool,
+    /// If true, the oscillator is receiving a GPS PPS signal.
+    pub reference: bool,
+    /// If true, the GPS PPS signal is sufficiently stable and is being
+    /// used to discipline the local oscillator.
+    pub disciplined: bool,
+    /// The time difference (in nanoseconds) between the GPS-disciplined
+    /// oscillator PPS output pulse and the most recent GPS PPS input
+    /// pulse.
+    pub delta: i64,
+}
+
+#[cfg(all(feature = "defmt", feature = "osc"))]
+impl defmt::Format for Osc {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "Osc {{ device: {}, running: {}, reference: {}, disciplined: {}, \
+             delta: {} }}",
+            self.device.as_deref(),
+            self.running,
+            self.reference,
+            self.disciplined,
+            self.delta
+        )
+    }
+}
+
+/// Raw measurement of a single signal, see [`Raw`].
+#[cfg(feature = "raw")]
+#[derive(Debug, Deserialize, Clone)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct RawMeasurement {
+    /// GNSS ID of the constellation of the satellite.
+    pub gnssid: u8,
+    /// Satellite ID within the constellation.
+    pub svid: u16,
+    /// Signal ID, see [`SignalId`].
+    pub sigid: Option<u8>,
+    /// Carrier to noise ratio in dB-Hz.
+    pub snr: Option<f32>,
+    /// RINEX 3 observation code of the signal, e.g. `"C1C"`.
+    #[cfg_attr(feature = "schemars", schemars(with = "Option<String>"))]
+    pub obs: Option<CompactString>,
+    /// Loss of lock indicator.
+    pub lli: Option<u8>,
+    /// Time the signal has been tracked without loss of lock, in
+    /// milliseconds.
+    pub locktime: Option<u32>,
+    /// Carrier phase in cycles.
+    pub carrierphase: Option<f64>,
+    /// Pseudorange in meters.
+    pub pseudorange: Option<f64>,
+    /// Doppler shift in Hz, positive for approaching satellites.
+    pub doppler: Option<f64>,
+}
+
+#[cfg(all(feature = "defmt", feature = "raw"))]
+impl defmt::Format for RawMeasurement {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "RawMeasurement {{ gnssid: {}, svid: {}, sigid: {}, snr: {}, obs: {}, \
+             lli: {}, locktime: {}, carrierphase: {}, pseudorange: {}, doppler: {} }}",
+            self.gnssid,
+            self.svid,
+            self.sigid,
+            self.snr,
+            self.obs.as_deref(),
+            self.lli,
+            self.locktime,
+            self.carrierphase,
+            self.pseudorange,
+            self.doppler
+        )
+    }
+}
+
+/// Raw measurements of the receiver, with one entry per satellite
+/// signal.
+#[cfg(feature = "raw")]
+#[derive(Debug, Deserialize, Clone)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct Raw {
+    /// Name of originating device.
+    #[cfg_attr(feature = "schemars", schemars(with = "Option<String>"))]
+    pub device: Option<CompactString>,
+    /// Seconds since the Unix epoch of the measurements.
+    pub time: Option<i64>,
+    /// Nanoseconds of the measurement time.
+    pub nsec: Option<i64>,
+    /// Measurements of the signals tracked.
+    #[serde(default)]
+    pub rawdata: Vec<RawMeasurement>,
+}
+
+#[cfg(all(feature = "defmt", feature = "raw"))]
+impl defmt::Format for Raw {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "Raw {{ device: {}, time: {}, nsec: {}, rawdata: {} }}",
+            self.device.as_deref(),
+            self.time,
+            self.nsec,
+            self.rawdata
+        )
+    }
+}
+
+/// RTCM2 correction message. Only the header is decoded.
+#[cfg(feature = "rtcm")]
+#[derive(Debug, Deserialize, Clone)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct Rtcm2 {
+    /// Name of originating device.
+    #[cfg_attr(feature = "schemars", schemars(with = "Option<String>"))]
+    pub device: Option<CompactString>,
+    /// Message type.
+    #[serde(rename = "type")]
+    pub msg_type: u16,
+    /// Station ID of the reference station.
+    pub station_id: Option<u16>,
+    /// Modified Z-count, seconds.
+    pub zcount: Option<f64>,
+    /// Sequence number.
+    pub seqnum: Option<u8>,
+    /// Number of words after the header.
+    pub length: Option<u16>,
+    /// Station transmission status.
+    pub station_health: Option<u8>,
+}
+
+#[cfg(all(feature = "defmt", feature = "rtcm"))]
+impl defmt::Format for Rtcm2 {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "Rtcm2 {{ device: {}, msg_type: {}, station_id: {}, zcount: {}, \
+             seqnum: {}, length: {}, station_health: {} }}",
+            self.device.as_deref(),
+            self.msg_type,
+            self.station_id,
+            self.zcount,
+            self.seqnum,
+            self.length,
+            self.station_health
+        )
+    }
+}
+
+/// RTCM3 correction message. Only the header is decoded.
+#[cfg(feature = "rtcm")]
+#[derive(Debug, Deserialize, Clone)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct Rtcm3 {
+    /// Name of originating device.
+    #[cfg_attr(feature = "schemars", schemars(with = "Option<String>"))]
+    pub device: Option<CompactString>,
+    /// Message type.
+    #[serde(rename = "type")]
+    pub msg_type: u16,
+    /// Payload length in bytes.
+    pub length: Option<u16>,
+}
+
+#[cfg(all(feature = "defmt", feature = "rtcm"))]
+impl defmt::Format for Rtcm3 {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "Rtcm3 {{ device: {}, msg_type: {}, length: {} }}",
+            self.device.as_deref(),
+            self.msg_type,
+            self.length
+        )
+    }
+}
+
+/// AIS message. The common attributes and those of position and
+/// static data reports are decoded.
+///
+/// Values are in natural units (knots, degrees) only if the watch
+/// requested scaled output, otherwise they are the raw AIS integers.
+#[cfg(feature = "ais")]
+#[derive(Debug, Deserialize, Clone)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct Ais {
+    /// Name of originating device.
+    #[cfg_attr(feature = "schemars", schemars(with = "Option<String>"))]
+    pub device: Option<CompactString>,
+    /// AIS message type.
+    #[serde(rename = "type")]
+    pub msg_type: u8,
+    /// Repeat indicator.
+    pub repeat: Option<u8>,
+    /// Maritime Mobile Service Identity of the vessel.
+    pub mmsi: u32,
+    /// Whether the values have been scaled.
+    pub scaled: Option<bool>,
+    /// Navigation status.
+    pub status: Option<u8>,
+    /// Speed over ground.
+    pub speed: Option<f64>,
+    /// Position accuracy, true if better than 10 m.
+    pub accuracy: Option<bool>,
+    /// Longitude.
+    pub lon: Option<f64>,
+    /// Latitude.
+    pub lat: Option<f64>,
+    /// Course over ground.
+    pub course: Option<f64>,
+    /// True heading in degrees.
+    pub heading: Option<u16>,
+    /// UTC second of the report.
+    pub second: Option<u8>,
+    /// IMO number of the vessel.
+    pub imo: Option<u32>,
+    /// Call sign of the vessel.
+    pub callsign: Option<String>,
+    /// Name of the vessel.
+    pub shipname: Option<String>,
+    /// Destination of the vessel.
+    pub destination: Option<String>,
+}
+
+#[cfg(all(feature = "defmt", feature = "ais"))]
+impl defmt::Format for Ais {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "Ais {{ device: {}, msg_type: {}, repeat: {}, mmsi: {}, scaled: {}, \
+             status: {}, speed: {}, accuracy: {}, lon: {}, lat: {}, course: {}, \
+             heading: {}, second: {}, imo: {}, callsign: {}, shipname: {}, \
+             destination: {} }}",
+            self.device.as_deref(),
+            self.msg_type,
+            self.repeat,
+            self.mmsi,
+            self.scaled,
+            self.status,
+            self.speed,
+            self.accuracy,
+            self.lon,
+            self.lat,
+            self.course,
+            self.heading,
+            self.second,
+            self.imo,
+            self.callsign,
+            self.shipname,
+            self.destination
+        )
+    }
+}
+
+/// Responses from `gpsd` after handshake (i.e. the payload)
+///
+/// Large messages are boxed, keeping the enum cheap to move.
+#[derive(Debug, Clone)]
+pub enum ResponseData {
+    Device(Box<Device>),
+    Tpv(Box<Tpv>),
+    Sky(Box<Sky>),
+    Pps(Box<Pps>),
+    Toff(Box<Toff>),
+    Gst(Box<Gst>),
+    #[cfg(feature = "att")]
+    Att(Box<Att>),
+    #[cfg(feature = "att")]
+    Imu(Box<Att>),
+    #[cfg(feature = "osc")]
+    Osc(Osc),
+    /// Satellite navigation message subframe, undecoded.
+    #[cfg(feature = "raw")]
+    Raw(Box<Raw>),
+    #[cfg(feature = "subframe")]
+    Subframe(Box<RawValue>),
+    #[cfg(feature = "rtcm")]
+    Rtcm2(Box<Rtcm2>),
+    #[cfg(feature = "rtcm")]
+    Rtcm3(Rtcm3),
+    #[cfg(feature = "ais")]
+    Ais(Box<Ais>),
+}
+
+/// All known `gpsd` responses (handshake + normal operation).
+///
+/// Messages with a `class` not known to this crate are preserved as
+/// `Unknown`, so forward-compatible consumers can log or route them
+/// instead of failing to decode.
+///
+/// Large messages are boxed, keeping the enum cheap to move.
+#[derive(Debug, Clone)]
 pub enum UnifiedResponse {
-    Version(Version),
+    Version(Box<Version>),
     Devices(Devices),
     Watch(Watch),
-    Device(Device),
-    Tpv(Tpv),
-    Sky(Sky),
-    Pps(Pps),
-    Gst(Gst),
+    Device(Box<Device>),
+    Tpv(Box<Tpv>),
+    Sky(Box<Sky>),
+    Pps(Box<Pps>),
+    Toff(Box<Toff>),
+    Gst(Box<Gst>),
+    #[cfg(feature = "att")]
+    Att(Box<Att>),
+    #[cfg(feature = "att")]
+    Imu(Box<Att>),
+    #[cfg(feature = "osc")]
+    Osc(Osc),
+    /// Satellite navigation message subframe, undecoded.
+    #[cfg(feature = "raw")]
+    Raw(Box<Raw>),
+    #[cfg(feature = "subframe")]
+    Subframe(Box<RawValue>),
+    #[cfg(feature = "rtcm")]
+    Rtcm2(Box<Rtcm2>),
+    #[cfg(feature = "rtcm")]
+    Rtcm3(Rtcm3),
+    #[cfg(feature = "ais")]
+    Ais(Box<Ais>),
+    /// Message of an unrecognized class, or of a class whose cargo
+    /// feature is disabled.
+    Unknown {
+        /// Value of the `class` attribute.
+        class: String,
+        /// The complete message as received.
+        raw: Box<RawValue>,
+    },
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for ResponseData {
+    fn format(&self, f: defmt::Formatter) {
+        match self {
+            ResponseData::Device(m) => defmt::write!(f, "Device({})", m),
+            ResponseData::Tpv(m) => defmt::write!(f, "Tpv({})", m),
+            ResponseData::Sky(m) => defmt::write!(f, "Sky({})", m),
+            ResponseData::Pps(m) => defmt::write!(f, "Pps({})", m),
+            ResponseData::Toff(m) => defmt::write!(f, "Toff({})", m),
+            ResponseData::Gst(m) => defmt::write!(f, "Gst({})", m),
+            #[cfg(feature = "att")]
+            ResponseData::Att(m) => defmt::write!(f, "Att({})", m),
+            #[cfg(feature = "att")]
+            ResponseData::Imu(m) => defmt::write!(f, "Imu({})", m),
+            #[cfg(feature = "osc")]
+            ResponseData::Osc(m) => defmt::write!(f, "Osc({})", m),
+            #[cfg(feature = "raw")]
+            ResponseData::Raw(m) => defmt::write!(f, "Raw({})", m),
+            #[cfg(feature = "subframe")]
+            ResponseData::Subframe(raw) => defmt::write!(f, "Subframe({=str})", raw.get()),
+            #[cfg(feature = "rtcm")]
+            ResponseData::Rtcm2(m) => defmt::write!(f, "Rtcm2({})", m),
+            #[cfg(feature = "rtcm")]
+            ResponseData::Rtcm3(m) => defmt::write!(f, "Rtcm3({})", m),
+            #[cfg(feature = "ais")]
+            ResponseData::Ais(m) => defmt::write!(f, "Ais({})", m),
+        }
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for UnifiedResponse {
+    fn format(&self, f: defmt::Formatter) {
+        match self {
+            UnifiedResponse::Version(m) => defmt::write!(f, "Version({})", m),
+            UnifiedResponse::Devices(m) => defmt::write!(f, "Devices({})", m),
+            UnifiedResponse::Watch(m) => defmt::write!(f, "Watch({})", m),
+            UnifiedResponse::Device(m) => defmt::write!(f, "Device({})", m),
+            UnifiedResponse::Tpv(m) => defmt::write!(f, "Tpv({})", m),
+            UnifiedResponse::Sky(m) => defmt::write!(f, "Sky({})", m),
+            UnifiedResponse::Pps(m) => defmt::write!(f, "Pps({})", m),
+            UnifiedResponse::Toff(m) => defmt::write!(f, "Toff({})", m),
+            UnifiedResponse::Gst(m) => defmt::write!(f, "Gst({})", m),
+            #[cfg(feature = "att")]
+            UnifiedResponse::Att(m) => defmt::write!(f, "Att({})", m),
+            #[cfg(feature = "att")]
+            UnifiedResponse::Imu(m) => defmt::write!(f, "Imu({})", m),
+            #[cfg(feature = "osc")]
+            UnifiedResponse::Osc(m) => defmt::write!(f, "Osc({})", m),
+            #[cfg(feature = "raw")]
+            UnifiedResponse::Raw(m) => defmt::write!(f, "Raw({})", m),
+            #[cfg(feature = "subframe")]
+            UnifiedResponse::Subframe(raw) => defmt::write!(f, "Subframe({=str})", raw.get()),
+            #[cfg(feature = "rtcm")]
+            UnifiedResponse::Rtcm2(m) => defmt::write!(f, "Rtcm2({})", m),
+            #[cfg(feature = "rtcm")]
+            UnifiedResponse::Rtcm3(m) => defmt::write!(f, "Rtcm3({})", m),
+            #[cfg(feature = "ais")]
+            UnifiedResponse::Ais(m) => defmt::write!(f, "Ais({})", m),
+            UnifiedResponse::Unknown { class, raw } => defmt::write!(
+                f,
+                "Unknown {{ class: {=str}, raw: {=str} }}",
+                class.as_str(),
+                raw.get()
+            ),
+        }
+    }
+}
+
+impl UnifiedResponse {
+    /// Value of the `class` attribute of the message.
+    pub fn class(&self) -> &str {
+        match self {
+            UnifiedResponse::Version(_) => "VERSION",
+            UnifiedResponse::Devices(_) => "DEVICES",
+            UnifiedResponse::Watch(_) => "WATCH",
+            UnifiedResponse::Device(_) => "DEVICE",
+            UnifiedResponse::Tpv(_) => "TPV",
+            UnifiedResponse::Sky(_) => "SKY",
+            UnifiedResponse::Pps(_) => "PPS",
+            UnifiedResponse::Toff(_) => "TOFF",
+            UnifiedResponse::Gst(_) => "GST",
+            #[cfg(feature = "att")]
+            UnifiedResponse::Att(_) => "ATT",
+            #[cfg(feature = "att")]
+            UnifiedResponse::Imu(_) => "IMU",
+            #[cfg(feature = "osc")]
+            UnifiedResponse::Osc(_) => "OSC",
+            #[cfg(feature = "raw")]
+            UnifiedResponse::Raw(_) => "RAW",
+            #[cfg(feature = "subframe")]
+            UnifiedResponse::Subframe(_) => "SUBFRAME",
+            #[cfg(feature = "rtcm")]
+            UnifiedResponse::Rtcm2(_) => "RTCM2",
+            #[cfg(feature = "rtcm")]
+            UnifiedResponse::Rtcm3(_) => "RTCM3",
+            #[cfg(feature = "ais")]
+            UnifiedResponse::Ais(_) => "AIS",
+            UnifiedResponse::Unknown { class, .. } => class,
+        }
+    }
+}
+
+impl UnifiedResponse {
+    /// Parses a single message.
+    ///
+    /// This is faster than going through the `Deserialize`
+    /// implementation: the `class` attribute is extracted first and
+    /// then only the deserializer of that class runs on the input,
+    /// without buffering the message.
+    pub fn from_slice(data: &[u8]) -> Result<Self, serde_json::Error> {
+        let class = scan_class(data)?;
+        let mut de = serde_json::Deserializer::from_slice(data);
+        let msg = match UnifiedResponse::deserialize_class(&class, &mut de)? {
+            Some(msg) => msg,
+            None => UnifiedResponse::Unknown {
+                class: class.into_owned(),
+                raw: Deserialize::deserialize(&mut de)?,
+            },
+        };
+        de.end()?;
+        Ok(msg)
+    }
+
+    /// Deserializes a message of a known class, or returns `None`.
+    fn deserialize_class<'de, D>(class: &str, d: D) -> Result<Option<Self>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(Some(match class {
+            "VERSION" => UnifiedResponse::Version(Deserialize::deserialize(d)?),
+            "DEVICES" => UnifiedResponse::Devices(Deserialize::deserialize(d)?),
+            "WATCH" => UnifiedResponse::Watch(Deserialize::deserialize(d)?),
+            "DEVICE" => UnifiedResponse::Device(Deserialize::deserialize(d)?),
+            "TPV" => UnifiedResponse::Tpv(Deserialize::deserialize(d)?),
+            "SKY" => UnifiedResponse::Sky(Deserialize::deserialize(d)?),
+            "PPS" => UnifiedResponse::Pps(Deserialize::deserialize(d)?),
+            "TOFF" => UnifiedResponse::Toff(Deserialize::deserialize(d)?),
+            "GST" => UnifiedResponse::Gst(Deserialize::deserialize(d)?),
+            #[cfg(feature = "att")]
+            "ATT" => UnifiedResponse::Att(Deserialize::deserialize(d)?),
+            #[cfg(feature = "att")]
+            "IMU" => UnifiedResponse::Imu(Deserialize::deserialize(d)?),
+            #[cfg(feature = "osc")]
+            "OSC" => UnifiedResponse::Osc(Deserialize::deserialize(d)?),
+            #[cfg(feature = "raw")]
+            "RAW" => UnifiedResponse::Raw(Deserialize::deserialize(d)?),
+            #[cfg(feature = "subframe")]
+            "SUBFRAME" => UnifiedResponse::Subframe(Deserialize::deserialize(d)?),
+            #[cfg(feature = "rtcm")]
+            "RTCM2" => UnifiedResponse::Rtcm2(Deserialize::deserialize(d)?),
+            #[cfg(feature = "rtcm")]
+            "RTCM3" => UnifiedResponse::Rtcm3(Deserialize::deserialize(d)?),
+            #[cfg(feature = "ais")]
+            "AIS" => UnifiedResponse::Ais(Deserialize::deserialize(d)?),
+            _ => return Ok(None),
+        }))
+    }
+}
+
+/// Extracts the `class` attribute of a message. `gpsd` sends it as
+/// the first attribute, which is found without parsing the rest of
+/// the message. Otherwise the message is scanned for it.
+pub(crate) fn scan_class(data: &[u8]) -> Result<Cow<'_, str>, serde_json::Error> {
+    fn skip_ws(data: &[u8]) -> &[u8] {
+        let n = data.iter().take_while(|b| b.is_ascii_whitespace()).count();
+        &data[n..]
+    }
+
+    fn leading_class(data: &[u8]) -> Option<&str> {
+        let rest = skip_ws(data).strip_prefix(b"{")?;
+        let rest = skip_ws(rest).strip_prefix(b"\"class\"")?;
+        let rest = skip_ws(rest).strip_prefix(b":")?;
+        let rest = skip_ws(rest).strip_prefix(b"\"")?;
+        let end = rest.iter().position(|b| *b == b'"' || *b == b'\\')?;
+        if rest[end] != b'"' {
+            return None;
+        }
+        core::str::from_utf8(&rest[..end]).ok()
+    }
+
+    #[derive(Deserialize)]
+    struct ClassTag<'a> {
+        #[serde(borrow)]
+        class: Cow<'a, str>,
+    }
+
+    match leading_class(data) {
+        Some(class) => Ok(Cow::Borrowed(class)),
+        None => serde_json::from_slice::<ClassTag>(data).map(|tag| tag.class),
+    }
+}
+
+/// Name of the `class` attribute, borrowed from the input if possible.
+struct ClassName<'de>(Cow<'de, str>);
+
+impl<'de> Deserialize<'de> for ClassName<'de> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct ClassNameVisitor;
+
+        impl<'de> Visitor<'de> for ClassNameVisitor {
+            type Value = ClassName<'de>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a string")
+            }
+
+            fn visit_borrowed_str<E: Error>(self, v: &'de str) -> Result<Self::Value, E> {
+                Ok(ClassName(Cow::Borrowed(v)))
+            }
+
+            fn visit_str<E: Error>(self, v: &str) -> Result<Self::Value, E> {
+                Ok(ClassName(Cow::Owned(v.to_owned())))
+            }
+        }
+
+        deserializer.deserialize_str(ClassNameVisitor)
+    }
+}
+
+/// Dispatches on the `class` attribute, see `UnifiedResponse`.
+struct ResponseVisitor;
+
+impl<'de> Visitor<'de> for ResponseVisitor {
+    type Value = UnifiedResponse;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a gpsd message")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut fields = serde_json::Map::new();
+        let class = match map.next_key::<ClassName>()? {
+            Some(key) if key.0 == "class" => map.next_value::<ClassName>()?.0,
+            Some(key) => {
+                // Slow path: the class is looked up in the buffered message.
+                let value = map.next_value()?;
+                fields.insert(key.0.into_owned(), value);
+                while let Some((key, value)) = map.next_entry()? {
+                    fields.insert(key, value);
+                }
+                let value = serde_json::Value::Object(fields);
+                let class = match value.get("class").and_then(serde_json::Value::as_str) {
+                    Some(class) => class.to_owned(),
+                    None => return Err(A::Error::missing_field("class")),
+                };
+                #[cfg(feature = "subframe")]
+                if class == "SUBFRAME" {
+                    return Ok(UnifiedResponse::Subframe(raw_value(&value)?));
+                }
+                return match UnifiedResponse::deserialize_class(&class, &value) {
+                    Ok(Some(msg)) => Ok(msg),
+                    Ok(None) => Ok(UnifiedResponse::Unknown {
+                        raw: raw_value(&value)?,
+                        class,
+                    }),
+                    Err(e) => Err(A::Error::custom(e)),
+                };
+            }
+            None => return Err(A::Error::missing_field("class")),
+        };
+        let keep_raw = cfg!(feature = "subframe") && class == "SUBFRAME";
+        if !keep_raw {
+            let de = value::MapAccessDeserializer::new(&mut map);
+            if let Some(msg) = UnifiedResponse::deserialize_class(&class, de)? {
+                return Ok(msg);
+            }
+        }
+        // Payloads kept raw are buffered, as other deserializers than
+        // `serde_json` have no notion of raw values.
+        fields.insert("class".into(), class.as_ref().into());
+        while let Some((key, value)) = map.next_entry()? {
+            fields.insert(key, value);
+        }
+        let raw = raw_value(&fields)?;
+        #[cfg(feature = "subframe")]
+        if keep_raw {
+            return Ok(UnifiedResponse::Subframe(raw));
+        }
+        Ok(UnifiedResponse::Unknown {
+            class: class.into_owned(),
+            raw,
+        })
+    }
+}
+
+fn raw_value<T: serde::Serialize, E: Error>(value: &T) -> Result<Box<RawValue>, E> {
+    serde_json::value::to_raw_value(value).map_err(E::custom)
+}
+
+/// Dispatches on the `class` attribute while deserializing, so only
+/// the deserializer of that class runs, also with other backends like
+/// `simd-json`. `gpsd` sends the class as first attribute; messages
+/// with the class elsewhere, unknown messages and SUBFRAME payloads are
+/// buffered, which is slower. Prefer `UnifiedResponse::from_slice`
+/// with `serde_json`, which keeps raw payloads without buffering.
+impl<'de> Deserialize<'de> for UnifiedResponse {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_map(ResponseVisitor)
+    }
+}
+
+/// Like the implementation of `UnifiedResponse`; messages of other
+/// classes than payload ones are rejected after parsing.
+impl<'de> Deserialize<'de> for ResponseData {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        ResponseData::try_from(UnifiedResponse::deserialize(deserializer)?)
+            .map_err(|msg| D::Error::custom(format!("unexpected class `{}`", msg.class())))
+    }
+}
+
+impl ResponseData {
+    /// Parses a single payload message, see
+    /// `UnifiedResponse::from_slice`.
+    pub fn from_slice(data: &[u8]) -> Result<Self, serde_json::Error> {
+        ResponseData::try_from(UnifiedResponse::from_slice(data)?)
+            .map_err(|msg| serde_json::Error::custom(format!("unexpected class `{}`", msg.class())))
+    }
+}
+
+impl From<ResponseData> for UnifiedResponse {
+    fn from(msg: ResponseData) -> Self {
+        match msg {
+            ResponseData::Device(d) => UnifiedResponse::Device(d),
+            ResponseData::Tpv(t) => UnifiedResponse::Tpv(t),
+            ResponseData::Sky(s) => UnifiedResponse::Sky(s),
+            ResponseData::Pps(p) => UnifiedResponse::Pps(p),
+            ResponseData::Toff(t) => UnifiedResponse::Toff(t),
+            ResponseData::Gst(g) => UnifiedResponse::Gst(g),
+            #[cfg(feature = "att")]
+            ResponseData::Att(a) => UnifiedResponse::Att(a),
+            #[cfg(feature = "att")]
+            ResponseData::Imu(a) => UnifiedResponse::Imu(a),
+            #[cfg(feature = "osc")]
+            ResponseData::Osc(o) => UnifiedResponse::Osc(o),
+            #[cfg(feature = "raw")]
+            ResponseData::Raw(r) => UnifiedResponse::Raw(r),
+            #[cfg(feature = "subframe")]
+            ResponseData::Subframe(raw) => UnifiedResponse::Subframe(raw),
+            #[cfg(feature = "rtcm")]
+            ResponseData::Rtcm2(r) => UnifiedResponse::Rtcm2(r),
+            #[cfg(feature = "rtcm")]
+            ResponseData::Rtcm3(r) => UnifiedResponse::Rtcm3(r),
+            #[cfg(feature = "ais")]
+            ResponseData::Ais(a) => UnifiedResponse::Ais(a),
+        }
+    }
+}
+
+/// Converts payload messages; other messages are given back as error.
+impl TryFrom<UnifiedResponse> for ResponseData {
+    type Error = UnifiedResponse;
+
+    fn try_from(msg: UnifiedResponse) -> Result<Self, Self::Error> {
+        Ok(match msg {
+            UnifiedResponse::Device(d) => ResponseData::Device(d),
+            UnifiedResponse::Tpv(t) => ResponseData::Tpv(t),
+            UnifiedResponse::Sky(s) => ResponseData::Sky(s),
+            UnifiedResponse::Pps(p) => ResponseData::Pps(p),
+            UnifiedResponse::Toff(t) => ResponseData::Toff(t),
+            UnifiedResponse::Gst(g) => ResponseData::Gst(g),
+            #[cfg(feature = "att")]
+            UnifiedResponse::Att(a) => ResponseData::Att(a),
+            #[cfg(feature = "att")]
+            UnifiedResponse::Imu(a) => ResponseData::Imu(a),
+            #[cfg(feature = "osc")]
+            UnifiedResponse::Osc(o) => ResponseData::Osc(o),
+            #[cfg(feature = "raw")]
+            UnifiedResponse::Raw(r) => ResponseData::Raw(r),
+            #[cfg(feature = "subframe")]
+            UnifiedResponse::Subframe(raw) => ResponseData::Subframe(raw),
+            #[cfg(feature = "rtcm")]
+            UnifiedResponse::Rtcm2(r) => ResponseData::Rtcm2(r),
+            #[cfg(feature = "rtcm")]
+            UnifiedResponse::Rtcm3(r) => ResponseData::Rtcm3(r),
+            #[cfg(feature = "ais")]
+            UnifiedResponse::Ais(a) => ResponseData::Ais(a),
+            msg => return Err(msg),
+        })
+    }
+}
+
+/// Borrowed message, serialized with the `class` attribute.
+#[cfg(feature = "serialize")]
+#[derive(Serialize)]
+#[serde(tag = "class")]
+#[serde(rename_all = "UPPERCASE")]
+enum Tagged<'a> {
+    Version(&'a Version),
+    Devices(&'a Devices),
+    Watch(&'a Watch),
+    Device(&'a Device),
+    Tpv(&'a Tpv),
+    Sky(&'a Sky),
+    Pps(&'a Pps),
+    Toff(&'a Toff),
+    Gst(&'a Gst),
+    #[cfg(feature = "att")]
+    Att(&'a Att),
+    #[cfg(feature = "att")]
+    Imu(&'a Att),
+    #[cfg(feature = "osc")]
+    Osc(&'a Osc),
+    #[cfg(feature = "raw")]
+    Raw(&'a Raw),
+    #[cfg(feature = "rtcm")]
+    Rtcm2(&'a Rtcm2),
+    #[cfg(feature = "rtcm")]
+    Rtcm3(&'a Rtcm3),
+    #[cfg(feature = "ais")]
+    Ais(&'a Ais),
+}
+
+#[cfg(feature = "serialize")]
+impl serde::Serialize for UnifiedResponse {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match self {
+            UnifiedResponse::Version(v) => Tagged::Version(v).serialize(serializer),
+            UnifiedResponse::Devices(d) => Tagged::Devices(d).serialize(serializer),
+            UnifiedResponse::Watch(w) => Tagged::Watch(w).serialize(serializer),
+            UnifiedResponse::Device(d) => Tagged::Device(d).serialize(serializer),
+            UnifiedResponse::Tpv(t) => Tagged::Tpv(t).serialize(serializer),
+            UnifiedResponse::Sky(s) => Tagged::Sky(s).serialize(serializer),
+            UnifiedResponse::Pps(p) => Tagged::Pps(p).serialize(serializer),
+            UnifiedResponse::Toff(t) => Tagged::Toff(t).serialize(serializer),
+            UnifiedResponse::Gst(g) => Tagged::Gst(g).serialize(serializer),
+            #[cfg(feature = "att")]
+            UnifiedResponse::Att(a) => Tagged::Att(a).serialize(serializer),
+            #[cfg(feature = "att")]
+            UnifiedResponse::Imu(a) => Tagged::Imu(a).serialize(serializer),
+            #[cfg(feature = "osc")]
+            UnifiedResponse::Osc(o) => Tagged::Osc(o).serialize(serializer),
+            #[cfg(feature = "raw")]
+            UnifiedResponse::Raw(r) => Tagged::Raw(r).serialize(serializer),
+            #[cfg(feature = "subframe")]
+            UnifiedResponse::Subframe(raw) => raw.serialize(serializer),
+            #[cfg(feature = "rtcm")]
+            UnifiedResponse::Rtcm2(r) => Tagged::Rtcm2(r).serialize(serializer),
+            #[cfg(feature = "rtcm")]
+            UnifiedResponse::Rtcm3(r) => Tagged::Rtcm3(r).serialize(serializer),
+            #[cfg(feature = "ais")]
+            UnifiedResponse::Ais(a) => Tagged::Ais(a).serialize(serializer),
+            UnifiedResponse::Unknown { raw, .. } => raw.serialize(serializer),
+        }
+    }
+}
+
+#[cfg(feature = "serialize")]
+impl serde::Serialize for ResponseData {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match self {
+            ResponseData::Device(d) => Tagged::Device(d).serialize(serializer),
+            ResponseData::Tpv(t) => Tagged::Tpv(t).serialize(serializer),
+            ResponseData::Sky(s) => Tagged::Sky(s).serialize(serializer),
+            ResponseData::Pps(p) => Tagged::Pps(p).serialize(serializer),
+            ResponseData::Toff(t) => Tagged::Toff(t).serialize(serializer),
+            ResponseData::Gst(g) => Tagged::Gst(g).serialize(serializer),
+            #[cfg(feature = "att")]
+            ResponseData::Att(a) => Tagged::Att(a).serialize(serializer),
+            #[cfg(feature = "att")]
+            ResponseData::Imu(a) => Tagged::Imu(a).serialize(serializer),
+            #[cfg(feature = "osc")]
+            ResponseData::Osc(o) => Tagged::Osc(o).serialize(serializer),
+            #[cfg(feature = "raw")]
+            ResponseData::Raw(r) => Tagged::Raw(r).serialize(serializer),
+            #[cfg(feature = "subframe")]
+            ResponseData::Subframe(raw) => raw.serialize(serializer),
+            #[cfg(feature = "rtcm")]
+            ResponseData::Rtcm2(r) => Tagged::Rtcm2(r).serialize(serializer),
+            #[cfg(feature = "rtcm")]
+            ResponseData::Rtcm3(r) => Tagged::Rtcm3(r).serialize(serializer),
+            #[cfg(feature = "ais")]
+            ResponseData::Ais(a) => Tagged::Ais(a).serialize(serializer),
+        }
+    }
+}
+
+/// Set of message classes, e.g. `ClassSet::TPV | ClassSet::SKY`.
+///
+/// Used to filter messages by their class before they are parsed, see
+/// [`GpsdClient::with_class_filter`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct ClassSet(u32);
+
+impl ClassSet {
+    /// No class at all.
+    pub const NONE: ClassSet = ClassSet(0);
+    /// `VERSION` messages.
+    pub const VERSION: ClassSet = ClassSet(1 << 0);
+    /// `DEVICES` messages.
+    pub const DEVICES: ClassSet = ClassSet(1 << 1);
+    /// `WATCH` messages.
+    pub const WATCH: ClassSet = ClassSet(1 << 2);
+    /// `DEVICE` messages.
+    pub const DEVICE: ClassSet = ClassSet(1 << 3);
+    /// `TPV` messages.
+    pub const TPV: ClassSet = ClassSet(1 << 4);
+    /// `SKY` messages.
+    pub const SKY: ClassSet = ClassSet(1 << 5);
+    /// `PPS` messages.
+    pub const PPS: ClassSet = ClassSet(1 << 6);
+    /// `GST` messages.
+    pub const GST: ClassSet = ClassSet(1 << 7);
+    /// `ATT` messages.
+    pub const ATT: ClassSet = ClassSet(1 << 8);
+    /// `IMU` messages.
+    pub const IMU: ClassSet = ClassSet(1 << 9);
+    /// `OSC` messages.
+    pub const OSC: ClassSet = ClassSet(1 << 10);
+    /// `SUBFRAME` messages.
+    pub const SUBFRAME: ClassSet = ClassSet(1 << 11);
+    /// `RTCM2` messages.
+    pub const RTCM2: ClassSet = ClassSet(1 << 12);
+    /// `RTCM3` messages.
+    pub const RTCM3: ClassSet = ClassSet(1 << 13);
+    /// `AIS` messages.
+    pub const AIS: ClassSet = ClassSet(1 << 14);
+    /// `TOFF` messages.
+    pub const TOFF: ClassSet = ClassSet(1 << 15);
+    /// `RAW` messages.
+    pub const RAW: ClassSet = ClassSet(1 << 16);
+    /// Messages of any other class, including classes whose cargo
+    /// feature is disabled.
+    pub const OTHER: ClassSet = ClassSet(1 << 17);
+    /// All classes.
+    pub const ALL: ClassSet = ClassSet((1 << 18) - 1);
+
+    /// Returns the set containing only the class named `class`.
+    pub fn from_class(class: &str) -> ClassSet {
+        match class {
+            "VERSION" => ClassSet::VERSION,
+            "DEVICES" => ClassSet::DEVICES,
+            "WATCH" => ClassSet::WATCH,
+            "DEVICE" => ClassSet::DEVICE,
+            "TPV" => ClassSet::TPV,
+            "SKY" => ClassSet::SKY,
+            "PPS" => ClassSet::PPS,
+            "TOFF" => ClassSet::TOFF,
+            "GST" => ClassSet::GST,
+            #[cfg(feature = "att")]
+            "ATT" => ClassSet::ATT,
+            #[cfg(feature = "att")]
+            "IMU" => ClassSet::IMU,
+            #[cfg(feature = "osc")]
+            "OSC" => ClassSet::OSC,
+            #[cfg(feature = "raw")]
+            "RAW" => ClassSet::RAW,
+            #[cfg(feature = "subframe")]
+            "SUBFRAME" => ClassSet::SUBFRAME,
+            #[cfg(feature = "rtcm")]
+            "RTCM2" => ClassSet::RTCM2,
+            #[cfg(feature = "rtcm")]
+            "RTCM3" => ClassSet::RTCM3,
+            #[cfg(feature = "ais")]
+            "AIS" => ClassSet::AIS,
+            _ => ClassSet::OTHER,
+        }
+    }
+
+    /// Returns `true` if all classes of `other` are in the set.
+    pub fn contains(self, other: ClassSet) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Returns `true` if the class named `class` is in the set.
+    pub fn contains_class(self, class: &str) -> bool {
+        self.contains(ClassSet::from_class(class))
+    }
+}
+
+impl Default for ClassSet {
+    fn default() -> Self {
+        ClassSet::ALL
+    }
+}
+
+impl core::ops::BitOr for ClassSet {
+    type Output = ClassSet;
+
+    fn bitor(self, rhs: ClassSet) -> ClassSet {
+        ClassSet(self.0 | rhs.0)
+    }
+}
+
+impl core::ops::BitOrAssign for ClassSet {
+    fn bitor_assign(&mut self, rhs: ClassSet) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl core::ops::Sub for ClassSet {
+    type Output = ClassSet;
+
+    fn sub(self, rhs: ClassSet) -> ClassSet {
+        ClassSet(self.0 & !rhs.0)
+    }
+}
+
+/// Handling of enumerated values (`mode`, `status`, `qual`,
+/// `health`) not known to this crate.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum UnknownValuePolicy {
+    /// Keep the message; the value is mapped to the `Unrecognized`
+    /// variant of the respective enum.
+    #[default]
+    Lenient,
+    /// Reject the message with `GpsdError::UnrecognizedValue`.
+    Strict,
+}
+
+/// Options controlling how messages are parsed.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct ParserOptions {
+    /// Handling of unknown enumerated values.
+    pub unknown_values: UnknownValuePolicy,
+}
+
+impl ParserOptions {
+    /// Checks a parsed message against the options.
+    pub fn check(&self, msg: &UnifiedResponse) -> Result<(), GpsdError> {
+        match msg {
+            UnifiedResponse::Tpv(tpv) => self.check_tpv(tpv),
+            UnifiedResponse::Sky(sky) => self.check_sky(sky),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks a parsed payload message against the options.
+    pub fn check_data(&self, msg: &ResponseData) -> Result<(), GpsdError> {
+        match msg {
+            ResponseData::Tpv(tpv) => self.check_tpv(tpv),
+            ResponseData::Sky(sky) => self.check_sky(sky),
+            _ => Ok(()),
+        }
+    }
+
+    fn check_tpv(&self, tpv: &Tpv) -> Result<(), GpsdError> {
+        if let Mode::Unrecognized(value) = tpv.mode {
+            self.unrecognized("mode", value)?;
+        }
+        if let Some(Status::Unrecognized(value)) = tpv.status {
+            self.unrecognized("status", value)?;
+        }
+        Ok(())
+    }
+
+    fn check_sky(&self, sky: &Sky) -> Result<(), GpsdError> {
+        for sat in sky.satellites.iter().flatten() {
+            if let Some(Quality::Unrecognized(value)) = sat.qual {
+                self.unrecognized("qual", value)?;
+            }
+            if let Some(Health::Unrecognized(value)) = sat.health {
+                self.unrecognized("health", value)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn unrecognized(&self, field: &'static str, value: u8) -> Result<(), GpsdError> {
+        match self.unknown_values {
+            UnknownValuePolicy::Lenient => Ok(()),
+            UnknownValuePolicy::Strict => Err(GpsdError::UnrecognizedValue { field, value }),
+        }
+    }
+}
+
+/// Operation during which a timeout occurred.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TimeoutPhase {
+    /// Establishing the connection.
+    Connect,
+    /// Initial handshake.
+    Handshake,
+    /// Command sent to `gpsd` and its reply.
+    Command,
+    /// Reading data.
+    Read,
+}
+
+impl fmt::Display for TimeoutPhase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TimeoutPhase::Connect => write!(f, "Connect"),
+            TimeoutPhase::Handshake => write!(f, "Handshake"),
+            TimeoutPhase::Command => write!(f, "Command"),
+            TimeoutPhase::Read => write!(f, "Read"),
+        }
+    }
 }
 
 /// Errors during handshake or data acquisition.
 #[derive(Debug)]
 pub enum GpsdError {
     /// Generic I/O error.
+    #[cfg(feature = "std")]
     IoError(io::Error),
     /// JSON error.
     JsonError(serde_json::Error),
@@ -491,9 +2924,48 @@ pub enum GpsdError {
     /// Unexpected reply of `gpsd`.
     UnexpectedGpsdReply(String),
     /// Failed to enable watch.
-    WatchFail(String),
+    WatchFail(WatchFailure),
+    /// `gpsd` replied with plain text instead of JSON.
+    DaemonText(String),
+    /// `gpsd` has no devices, see `NoDevicesPolicy`.
+    NoDevices,
+    /// An operation did not complete in time.
+    Timeout {
+        /// Operation which timed out.
+        phase: TimeoutPhase,
+        /// Time passed since the operation started.
+        elapsed: Duration,
+    },
+    /// An enumerated attribute carries a value not known to this
+    /// crate and `UnknownValuePolicy::Strict` is in effect.
+    UnrecognizedValue {
+        /// Name of the attribute.
+        field: &'static str,
+        /// Value received.
+        value: u8,
+    },
 }
 
+#[cfg(feature = "std")]
+impl GpsdError {
+    /// Maps I/O errors caused by a timeout (`WouldBlock` or
+    /// `TimedOut`, depending on the platform) to `GpsdError::Timeout`.
+    pub(crate) fn timed_out(self, phase: TimeoutPhase, start: Instant) -> GpsdError {
+        match self {
+            GpsdError::IoError(e)
+                if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut =>
+            {
+                GpsdError::Timeout {
+                    phase,
+                    elapsed: start.elapsed(),
+                }
+            }
+            e => e,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
 impl From<io::Error> for GpsdError {
     fn from(err: io::Error) -> GpsdError {
         GpsdError::IoError(err)
@@ -509,6 +2981,7 @@ impl From<serde_json::Error> for GpsdError {
 impl fmt::Display for GpsdError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
+            #[cfg(feature = "std")]
             GpsdError::IoError(e) => write!(f, "IoError: {}", e),
             GpsdError::JsonError(e) => write!(f, "JsonError: {}", e),
             GpsdError::UnsupportedGpsdProtocolVersion => {
@@ -516,110 +2989,605 @@ impl fmt::Display for GpsdError {
             }
             GpsdError::UnexpectedGpsdReply(e) => write!(f, "UnexpectedGpsdReply: {}", e),
             GpsdError::WatchFail(e) => write!(f, "WatchFail: {}", e),
+            GpsdError::DaemonText(e) => write!(f, "DaemonText: {}", e),
+            GpsdError::NoDevices => write!(f, "NoDevices"),
+            GpsdError::Timeout { phase, elapsed } => {
+                write!(f, "Timeout: {} after {:?}", phase, elapsed)
+            }
+            GpsdError::UnrecognizedValue { field, value } => {
+                write!(f, "UnrecognizedValue: {} = {}", field, value)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for GpsdError {
+    fn format(&self, f: defmt::Formatter) {
+        match self {
+            #[cfg(feature = "std")]
+            GpsdError::IoError(e) => defmt::write!(f, "IoError: {}", defmt::Display2Format(e)),
+            GpsdError::JsonError(e) => {
+                defmt::write!(f, "JsonError at {=usize}:{=usize}", e.line(), e.column())
+            }
+            GpsdError::UnsupportedGpsdProtocolVersion => {
+                defmt::write!(f, "UnsupportedGpsdProtocolVersion")
+            }
+            GpsdError::UnexpectedGpsdReply(e) => defmt::write!(f, "UnexpectedGpsdReply: {=str}", e),
+            GpsdError::WatchFail(e) => defmt::write!(f, "WatchFail: {}", e),
+            GpsdError::DaemonText(e) => defmt::write!(f, "DaemonText: {=str}", e),
+            GpsdError::NoDevices => defmt::write!(f, "NoDevices"),
+            GpsdError::Timeout { phase, elapsed } => {
+                defmt::write!(f, "Timeout: {} after {}", phase, elapsed)
+            }
+            GpsdError::UnrecognizedValue { field, value } => {
+                defmt::write!(f, "UnrecognizedValue: {=str} = {=u8}", field, value)
+            }
+        }
+    }
+}
+
+/// Traces a line received from `gpsd`. Costs nothing unless trace
+/// logging is enabled, and doesn't copy the line if it's valid UTF-8.
+#[cfg(feature = "std")]
+pub(crate) fn trace_line(data: &[u8]) {
+    if log_enabled!(log::Level::Trace) {
+        trace!("{}", String::from_utf8_lossy(data));
+    }
+}
+
+/// Detects replies which are not JSON at all, like the plain-text
+/// error messages of very old daemons and some forwarders.
+#[cfg(feature = "std")]
+pub(crate) fn check_daemon_text(data: &[u8]) -> Result<(), GpsdError> {
+    match data.iter().find(|b| !b.is_ascii_whitespace()) {
+        Some(b'{') | None => Ok(()),
+        Some(_) => Err(GpsdError::DaemonText(
+            String::from_utf8_lossy(data).trim().to_owned(),
+        )),
+    }
+}
+
+/// Performs the initial handshake with `gpsd`.
+///
+/// The following sequence of messages is expected: get VERSION, set
+/// WATCH, get DEVICES, get WATCH.
+///
+/// # Arguments
+///
+/// * `debug` - enable debug printing of raw JSON data received
+/// * `reader` - reader to fetch data from `gpsd`
+/// * `writer` - write to send data to `gpsd`
+///
+/// # Errors
+///
+/// If the handshake fails, this functions returns an error that
+/// indicates the type of error.
+#[cfg(feature = "std")]
+pub fn handshake(
+    reader: &mut dyn io::BufRead,
+    writer: &mut dyn io::Write,
+) -> Result<(), GpsdError> {
+    handshake_with_options(reader, writer, &HandshakeOptions::default())
+}
+
+/// Performs the initial handshake with `gpsd` using the given
+/// options.
+///
+/// Like `handshake`, but sends the WATCH command built from
+/// `options.watch` and verifies that the policy acknowledged by
+/// `gpsd` matches the requested one.
+///
+/// # Errors
+///
+/// If the acknowledged policy differs, or the requested device is not
+/// in the DEVICES list, `GpsdError::WatchFail` tells the reason. A
+/// read timing out, e.g. due to a read timeout set on the socket, is
+/// reported as `GpsdError::Timeout`.
+///
+/// Reports received before the handshake completed, like a DEVICE
+/// before the WATCH reply, are dropped. [`GpsdClient::handshake`]
+/// returns them from the stream instead.
+#[cfg(feature = "std")]
+pub fn handshake_with_options(
+    reader: &mut dyn io::BufRead,
+    writer: &mut dyn io::Write,
+    options: &HandshakeOptions,
+) -> Result<(), GpsdError> {
+    handshake_queued(reader, writer, options, &mut VecDeque::new())
+}
+
+/// Performs the handshake like `handshake_with_options`, adding the
+/// lines of the reports received before it completed to `queued`.
+#[cfg(feature = "std")]
+pub(crate) fn handshake_queued(
+    reader: &mut dyn io::BufRead,
+    writer: &mut dyn io::Write,
+    options: &HandshakeOptions,
+    queued: &mut VecDeque<Vec<u8>>,
+) -> Result<(), GpsdError> {
+    let start = Instant::now();
+    handshake_steps(reader, writer, options, queued)
+        .map_err(|e| e.timed_out(TimeoutPhase::Handshake, start))
+}
+
+#[cfg(feature = "std")]
+fn handshake_steps(
+    reader: &mut dyn io::BufRead,
+    writer: &mut dyn io::Write,
+    options: &HandshakeOptions,
+    queued: &mut VecDeque<Vec<u8>>,
+) -> Result<(), GpsdError> {
+    // Get VERSION
+    let mut data = Vec::new();
+    reader.read_until(b'\n', &mut data)?;
+    trace_line(&data);
+    check_daemon_text(&data)?;
+    let msg: ResponseHandshake = serde_json::from_slice(&data)?;
+    match msg {
+        ResponseHandshake::Version(v) => {
+            if v.proto_major < PROTO_MAJOR_MIN {
+                return Err(GpsdError::UnsupportedGpsdProtocolVersion);
+            }
         }
+        _ => {
+            return Err(GpsdError::UnexpectedGpsdReply(
+                String::from_utf8(data).unwrap(),
+            ))
+        }
+    }
+
+    // Enable WATCH
+    writer.write_all(options.watch.to_command().as_bytes())?;
+    writer.flush()?;
+
+    // Get DEVICES and WATCH. Some versions of `gpsd` send them in a
+    // different order or interleave other messages, which is
+    // tolerated within the reorder window.
+    let mut wait_for_device = None;
+    let mut devices_seen = false;
+    let mut watch = None;
+    let mut out_of_order = 0;
+    while !devices_seen || watch.is_none() {
+        let mut data = Vec::new();
+        reader.read_until(b'\n', &mut data)?;
+        trace_line(&data);
+        check_daemon_text(&data)?;
+        let msg = UnifiedResponse::from_slice(&data)?;
+        let report = !matches!(
+            msg,
+            UnifiedResponse::Version(_) | UnifiedResponse::Devices(_) | UnifiedResponse::Watch(_)
+        );
+        let in_order = match msg {
+            UnifiedResponse::Devices(d) if !devices_seen => {
+                devices_seen = true;
+                if d.devices.is_empty() {
+                    match options.no_devices {
+                        NoDevicesPolicy::Accept => {}
+                        NoDevicesPolicy::Fail => return Err(GpsdError::NoDevices),
+                        NoDevicesPolicy::Wait(timeout) => wait_for_device = Some(timeout),
+                    }
+                }
+                if let Some(device) = &options.watch.device {
+                    if !d
+                        .devices
+                        .iter()
+                        .any(|i| i.path.as_deref() == Some(device.as_str()))
+                    {
+                        return Err(GpsdError::WatchFail(WatchFailure::DeviceNotFound(
+                            device.clone(),
+                        )));
+                    }
+                }
+                true
+            }
+            UnifiedResponse::Watch(w) if watch.is_none() => {
+                watch = Some(w);
+                devices_seen
+            }
+            _ => false,
+        };
+        if !in_order {
+            out_of_order += 1;
+            if out_of_order > options.reorder_window {
+                return Err(GpsdError::UnexpectedGpsdReply(
+                    String::from_utf8(data).unwrap(),
+                ));
+            }
+            debug!("Out of order handshake message ({})", out_of_order);
+            if report {
+                queued.push_back(data);
+            }
+        }
+    }
+    if let Some(w) = watch {
+        options.watch.check(&w).map_err(GpsdError::WatchFail)?;
+    }
+
+    if let Some(timeout) = wait_for_device {
+        wait_for_device_activation(reader, timeout, queued)?;
+    }
+
+    Ok(())
+}
+
+/// Reads messages until a DEVICE message reports an activated device,
+/// adding their lines to `queued`.
+#[cfg(feature = "std")]
+fn wait_for_device_activation(
+    reader: &mut dyn io::BufRead,
+    timeout: Duration,
+    queued: &mut VecDeque<Vec<u8>>,
+) -> Result<(), GpsdError> {
+    let start = Instant::now();
+    loop {
+        let mut data = Vec::new();
+        match reader.read_until(b'\n', &mut data) {
+            Ok(0) => return Err(GpsdError::NoDevices),
+            Ok(_) => {}
+            Err(e)
+                if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut =>
+            {
+                return Err(GpsdError::NoDevices)
+            }
+            Err(e) => return Err(e.into()),
+        }
+        trace_line(&data);
+        let activated = matches!(
+            UnifiedResponse::from_slice(&data)?,
+            UnifiedResponse::Device(d) if d.activated.is_some()
+        );
+        queued.push_back(data);
+        if activated {
+            return Ok(());
+        }
+        if start.elapsed() >= timeout {
+            return Err(GpsdError::NoDevices);
+        }
+    }
+}
+
+/// Get one payload entry from `gpsd`.
+///
+/// # Arguments
+///
+/// * `reader` - reader to fetch data from `gpsd`
+/// * `writer` - write to send data to `gpsd`
+#[cfg(feature = "std")]
+pub fn get_data(reader: &mut dyn io::BufRead) -> Result<ResponseData, GpsdError> {
+    get_data_into(reader, &mut Vec::new())
+}
+
+/// Get one payload entry from `gpsd`, reading into a caller-provided
+/// buffer.
+///
+/// Like `get_data`, but the buffer can be reused for consecutive
+/// calls to avoid an allocation per message on high-rate streams. The
+/// buffer is cleared first and holds the line read afterwards, unless
+/// the `simd-json` feature is enabled, which parses in place and
+/// leaves the buffer contents unspecified.
+///
+/// # Arguments
+///
+/// * `reader` - reader to fetch data from `gpsd`
+/// * `buf` - buffer for the line read
+#[cfg(feature = "std")]
+pub fn get_data_into(
+    reader: &mut dyn io::BufRead,
+    buf: &mut Vec<u8>,
+) -> Result<ResponseData, GpsdError> {
+    let start = Instant::now();
+    buf.clear();
+    reader
+        .read_until(b'\n', buf)
+        .map_err(|e| GpsdError::from(e).timed_out(TimeoutPhase::Read, start))?;
+    trace_line(buf);
+    let msg: ResponseData = payload_from_slice(buf)?;
+    Ok(msg)
+}
+
+/// Deserializes a payload message with the configured JSON backend.
+#[cfg(all(feature = "std", not(feature = "simd-json")))]
+fn payload_from_slice(buf: &mut [u8]) -> Result<ResponseData, serde_json::Error> {
+    ResponseData::from_slice(buf)
+}
+
+/// Deserializes a payload message with the configured JSON backend.
+#[cfg(feature = "simd-json")]
+fn payload_from_slice(buf: &mut [u8]) -> Result<ResponseData, serde_json::Error> {
+    // Only serde_json keeps raw values verbatim.
+    #[cfg(feature = "subframe")]
+    if scan_class(buf)? == "SUBFRAME" {
+        return ResponseData::from_slice(buf);
+    }
+    simd_json::serde::from_slice(buf).map_err(serde_json::Error::custom)
+}
+
+/// Get one payload entry from `gpsd`, checked against `options`.
+///
+/// # Arguments
+///
+/// * `reader` - reader to fetch data from `gpsd`
+/// * `options` - options to apply to the parsed message
+#[cfg(feature = "std")]
+pub fn get_data_with_options(
+    reader: &mut dyn io::BufRead,
+    options: &ParserOptions,
+) -> Result<ResponseData, GpsdError> {
+    let msg = get_data(reader)?;
+    options.check_data(&msg)?;
+    Ok(msg)
+}
+
+/// Parses a buffer of newline-delimited messages, e.g. a recorded
+/// `gpsd` log.
+///
+/// Blank lines are skipped. Each remaining line yields either the
+/// parsed message or the error it failed with, so a single broken line
+/// doesn't abort the whole batch.
+pub fn parse_lines(data: &[u8]) -> impl Iterator<Item = Result<UnifiedResponse, GpsdError>> + '_ {
+    data.split(|b| *b == b'\n')
+        .filter(|line| line.iter().any(|b| !b.is_ascii_whitespace()))
+        .map(|line| UnifiedResponse::from_slice(line).map_err(GpsdError::from))
+}
+
+/// Parses a buffer of newline-delimited messages like [`parse_lines`],
+/// spreading the lines over the rayon thread pool.
+///
+/// Meant for offline processing of large captures, the results are
+/// in the order of the lines.
+#[cfg(feature = "rayon")]
+pub fn parse_lines_parallel(data: &[u8]) -> Vec<Result<UnifiedResponse, GpsdError>> {
+    use rayon::prelude::*;
+
+    data.par_split(|b| *b == b'\n')
+        .filter(|line| line.iter().any(|b| !b.is_ascii_whitespace()))
+        .map(|line| UnifiedResponse::from_slice(line).map_err(GpsdError::from))
+        .collect()
+}
+
+/// Parses a stream of concatenated messages.
+///
+/// Unlike [`parse_lines`] this doesn't rely on newline framing: messages
+/// may be separated by any whitespace, including none at all, and may
+/// span several lines, e.g. when pretty-printed. Messages are decoded
+/// straight from `reader` without an intermediate line buffer, so
+/// pass a buffered reader.
+///
+/// Without framing there is no way to resynchronize after malformed
+/// input, hence the iterator ends after yielding the first error.
+#[cfg(feature = "std")]
+pub fn parse_stream<R: io::Read>(
+    reader: R,
+) -> impl Iterator<Item = Result<UnifiedResponse, GpsdError>> {
+    let mut stream = serde_json::Deserializer::from_reader(reader).into_iter::<UnifiedResponse>();
+    let mut failed = false;
+    std::iter::from_fn(move || {
+        if failed {
+            return None;
+        }
+        let item = stream.next()?;
+        failed = item.is_err();
+        Some(item.map_err(GpsdError::from))
+    })
+}
+
+/// Message types which can be parsed by [`parse_in_place`].
+pub trait MessageClass: for<'de> Deserialize<'de> {
+    /// Value of the `class` attribute of the message.
+    const CLASS: &'static str;
+}
+
+impl MessageClass for Device {
+    const CLASS: &'static str = "DEVICE";
+}
+
+impl MessageClass for Tpv {
+    const CLASS: &'static str = "TPV";
+}
+
+impl MessageClass for Sky {
+    const CLASS: &'static str = "SKY";
+}
+
+impl MessageClass for Pps {
+    const CLASS: &'static str = "PPS";
+}
+
+impl MessageClass for Toff {
+    const CLASS: &'static str = "TOFF";
+}
+
+impl MessageClass for Gst {
+    const CLASS: &'static str = "GST";
+}
+
+#[cfg(feature = "raw")]
+impl MessageClass for Raw {
+    const CLASS: &'static str = "RAW";
+}
+
+/// Parses a message into `place`, reusing its allocations, e.g. the
+/// satellite list of a `Sky`.
+///
+/// Keeping a `Tpv` or `Sky` around and parsing each message of that
+/// class into it avoids allocating a new message every time. All
+/// attributes are overwritten, those absent in `data` are reset.
+///
+/// # Errors
+///
+/// Fails if `data` is not a message of class `T::CLASS`. `place` may
+/// be partially updated on error.
+pub fn parse_in_place<T: MessageClass>(data: &[u8], place: &mut T) -> Result<(), GpsdError> {
+    let class = scan_class(data)?;
+    if class != T::CLASS {
+        return Err(serde_json::Error::custom(format!("unexpected class `{}`", class)).into());
     }
+    let mut de = serde_json::Deserializer::from_slice(data);
+    T::deserialize_in_place(&mut de, place)?;
+    de.end()?;
+    Ok(())
 }
 
-/// Performs the initial handshake with `gpsd`.
-///
-/// The following sequence of messages is expected: get VERSION, set
-/// WATCH, get DEVICES, get WATCH.
-///
-/// # Arguments
+/// Attributes of a POLL response besides the reports, see
+/// [`parse_poll`].
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PollHeader {
+    /// Time/date stamp in ISO8601 format, UTC.
+    pub time: Option<Iso8601Timestamp>,
+    /// Count of active devices.
+    pub active: Option<u32>,
+}
+
+/// A single report of a POLL response, see [`parse_poll`].
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PollEntry {
+    Tpv(Box<Tpv>),
+    Sky(Box<Sky>),
+    Gst(Box<Gst>),
+}
+
+/// Parses a POLL response, passing each report to `f` as soon as it
+/// has been decoded.
 ///
-/// * `debug` - enable debug printing of raw JSON data received
-/// * `reader` - reader to fetch data from `gpsd`
-/// * `writer` - write to send data to `gpsd`
+/// The report arrays are never materialized, so only a single report
+/// is held in memory at a time regardless of the number of devices.
 ///
 /// # Errors
 ///
-/// If the handshake fails, this functions returns an error that
-/// indicates the type of error.
-pub fn handshake(
-    reader: &mut dyn io::BufRead,
-    writer: &mut dyn io::Write,
-) -> Result<(), GpsdError> {
-    // Get VERSION
-    let mut data = Vec::new();
-    reader.read_until(b'\n', &mut data)?;
-    trace!("{}", String::from_utf8(data.clone()).unwrap());
-    let msg: ResponseHandshake = serde_json::from_slice(&data)?;
-    match msg {
-        ResponseHandshake::Version(v) => {
-            if v.proto_major < PROTO_MAJOR_MIN {
-                return Err(GpsdError::UnsupportedGpsdProtocolVersion);
-            }
+/// Fails if `data` is not a POLL response. Reports decoded before the
+/// error have been passed to `f` already.
+pub fn parse_poll<F>(data: &[u8], f: F) -> Result<PollHeader, GpsdError>
+where
+    F: FnMut(PollEntry),
+{
+    struct PollVisitor<F>(F);
+
+    impl<'de, F: FnMut(PollEntry)> Visitor<'de> for PollVisitor<F> {
+        type Value = PollHeader;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a POLL response")
         }
-        _ => {
-            return Err(GpsdError::UnexpectedGpsdReply(
-                String::from_utf8(data).unwrap(),
-            ))
+
+        fn visit_map<A>(mut self, mut map: A) -> Result<Self::Value, A::Error>
+        where
+            A: MapAccess<'de>,
+        {
+            let mut header = PollHeader::default();
+            let mut class = false;
+            while let Some(key) = map.next_key::<Cow<str>>()? {
+                match key.as_ref() {
+                    "class" => {
+                        let value = map.next_value::<Cow<str>>()?;
+                        if value != "POLL" {
+                            return Err(A::Error::invalid_value(Unexpected::Str(&value), &"POLL"));
+                        }
+                        class = true;
+                    }
+                    "time" => header.time = map.next_value()?,
+                    "active" => header.active = map.next_value()?,
+                    "tpv" => map.next_value_seed(Entries(&mut self.0, PollEntry::Tpv))?,
+                    "sky" => map.next_value_seed(Entries(&mut self.0, PollEntry::Sky))?,
+                    "gst" => map.next_value_seed(Entries(&mut self.0, PollEntry::Gst))?,
+                    _ => {
+                        map.next_value::<IgnoredAny>()?;
+                    }
+                }
+            }
+            if !class {
+                return Err(A::Error::missing_field("class"));
+            }
+            Ok(header)
         }
     }
 
-    // Enable WATCH
-    writer.write_all(ENABLE_WATCH_CMD.as_bytes())?;
-    writer.flush()?;
+    /// Passes the elements of a report array to the callback.
+    struct Entries<'a, F, T>(&'a mut F, fn(Box<T>) -> PollEntry);
 
-    // Get DEVICES
-    let mut data = Vec::new();
-    reader.read_until(b'\n', &mut data)?;
-    trace!("{}", String::from_utf8(data.clone()).unwrap());
-    let msg: ResponseHandshake = serde_json::from_slice(&data)?;
-    match msg {
-        ResponseHandshake::Devices(_) => {}
-        _ => {
-            return Err(GpsdError::UnexpectedGpsdReply(
-                String::from_utf8(data).unwrap(),
-            ))
+    impl<'de, F, T> DeserializeSeed<'de> for Entries<'_, F, T>
+    where
+        F: FnMut(PollEntry),
+        T: Deserialize<'de>,
+    {
+        type Value = ();
+
+        fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+            deserializer.deserialize_seq(self)
         }
     }
 
-    // Get WATCH
-    let mut data = Vec::new();
-    reader.read_until(b'\n', &mut data)?;
-    trace!("{}", String::from_utf8(data.clone()).unwrap());
-    let msg: ResponseHandshake = serde_json::from_slice(&data)?;
-    match msg {
-        ResponseHandshake::Watch(w) => {
-            if let (false, false, true) = (
-                w.enable.unwrap_or(false),
-                w.json.unwrap_or(false),
-                w.nmea.unwrap_or(false),
-            ) {
-                return Err(GpsdError::WatchFail(
-                    String::from_utf8(data).unwrap(),
-                ));
-            }
+    impl<'de, F, T> Visitor<'de> for Entries<'_, F, T>
+    where
+        F: FnMut(PollEntry),
+        T: Deserialize<'de>,
+    {
+        type Value = ();
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("an array of reports")
         }
-        _ => {
-            return Err(GpsdError::UnexpectedGpsdReply(
-                String::from_utf8(data).unwrap(),
-            ))
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+            while let Some(entry) = seq.next_element::<Box<T>>()? {
+                (self.0)((self.1)(entry));
+            }
+            Ok(())
         }
     }
 
-    Ok(())
+    let mut de = serde_json::Deserializer::from_slice(data);
+    let header = de.deserialize_map(PollVisitor(f))?;
+    de.end()?;
+    Ok(header)
 }
 
-/// Get one payload entry from `gpsd`.
+/// A parsed message together with the line it was decoded from.
+#[derive(Debug, Clone)]
+pub struct WithRaw<T> {
+    /// The typed message.
+    pub message: T,
+    /// The original JSON line as received, without the line
+    /// terminator.
+    pub raw: String,
+}
+
+/// Get one payload entry from `gpsd` along with its raw JSON.
+///
+/// Like `get_data`, but keeps the original line, so applications can
+/// archive the exact wire data while working with the typed structs.
 ///
 /// # Arguments
 ///
 /// * `reader` - reader to fetch data from `gpsd`
-/// * `writer` - write to send data to `gpsd`
-pub fn get_data(reader: &mut dyn io::BufRead) -> Result<ResponseData, GpsdError> {
+#[cfg(feature = "std")]
+pub fn get_data_with_raw(reader: &mut dyn io::BufRead) -> Result<WithRaw<ResponseData>, GpsdError> {
+    let start = Instant::now();
     let mut data = Vec::new();
-    reader.read_until(b'\n', &mut data)?;
-    trace!("{}", String::from_utf8(data.clone()).unwrap());
-    let msg: ResponseData = serde_json::from_slice(&data)?;
-    Ok(msg)
+    reader
+        .read_until(b'\n', &mut data)
+        .map_err(|e| GpsdError::from(e).timed_out(TimeoutPhase::Read, start))?;
+    trace_line(&data);
+    let message = ResponseData::from_slice(&data)?;
+    let raw = String::from_utf8_lossy(&data)
+        .trim_end_matches(['\r', '\n'])
+        .to_owned();
+    Ok(WithRaw { message, raw })
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests {
-    use super::{get_data, handshake, GpsdError, Mode, ResponseData, ENABLE_WATCH_CMD};
+    use super::{
+        get_data, get_data_into, get_data_with_options, get_data_with_raw, gps_to_utc, handshake,
+        handshake_with_options, parse_in_place, parse_lines, parse_poll, parse_stream, ClassSet,
+        GpsdError, HandshakeOptions, Health, Iso8601Timestamp, Mode, NoDevicesPolicy,
+        ParserOptions, PollEntry, Quality, ResponseData, Sky, Status, TimeoutPhase, Tpv,
+        UnifiedResponse, UnknownValuePolicy, Watch, WatchFailure, WatchOptions, ENABLE_WATCH_CMD,
+    };
     use std::io::BufWriter;
+    use std::time::Duration;
 
     #[test]
     fn handshake_ok() {
@@ -665,6 +3633,192 @@ mod tests {
         assert_eq!(writer.get_mut().as_slice(), empty);
     }
 
+    #[test]
+    fn handshake_watch_policy_mismatch() {
+        let mut reader: &[u8] = b"{\"class\":\"VERSION\",\"release\":\"blah\",\"rev\":\"blurp\",\"proto_major\":3,\"proto_minor\":12}\x0d
+{\"class\":\"DEVICES\",\"devices\":[{\"path\":\"/dev/gps\",\"activated\":\"true\"}]}
+{\"class\":\"WATCH\",\"enable\":true,\"json\":false,\"nmea\":false}
+";
+        let mut writer = BufWriter::new(Vec::<u8>::new());
+        let options = HandshakeOptions {
+            watch: WatchOptions {
+                pps: Some(true),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        match handshake_with_options(&mut reader, &mut writer, &options) {
+            Err(GpsdError::WatchFail(e)) => assert_eq!(e, WatchFailure::JsonNotEnabled),
+            _ => panic!("expected WatchFail"),
+        }
+        assert_eq!(
+            writer.get_mut().as_slice(),
+            b"?WATCH={\"enable\":true,\"json\":true,\"pps\":true};\r\n"
+        );
+    }
+
+    #[test]
+    fn handshake_watch_device_not_found() {
+        let mut reader: &[u8] = b"{\"class\":\"VERSION\",\"release\":\"blah\",\"rev\":\"blurp\",\"proto_major\":3,\"proto_minor\":12}\x0d
+{\"class\":\"DEVICES\",\"devices\":[{\"path\":\"/dev/gps\",\"activated\":\"true\"}]}
+";
+        let mut writer = BufWriter::new(Vec::<u8>::new());
+        let options = HandshakeOptions {
+            watch: WatchOptions {
+                device: Some("/dev/ttyACM0".to_owned()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        match handshake_with_options(&mut reader, &mut writer, &options) {
+            Err(GpsdError::WatchFail(WatchFailure::DeviceNotFound(d))) => {
+                assert_eq!(d, "/dev/ttyACM0")
+            }
+            _ => panic!("expected DeviceNotFound"),
+        }
+    }
+
+    #[test]
+    fn watch_options_check() {
+        let reported: Watch = serde_json::from_str(
+            "{\"class\":\"WATCH\",\"enable\":true,\"json\":true,\"nmea\":true}",
+        )
+        .unwrap();
+        assert_eq!(
+            WatchOptions::default().check(&reported),
+            Err(WatchFailure::PolicyMismatch { field: "nmea" })
+        );
+        let disabled: Watch = serde_json::from_str("{\"enable\":false}").unwrap();
+        assert_eq!(
+            WatchOptions::default().check(&disabled),
+            Err(WatchFailure::Disabled)
+        );
+    }
+
+    #[test]
+    fn watch_options_command() {
+        assert_eq!(WatchOptions::default().to_command(), ENABLE_WATCH_CMD);
+        let options = WatchOptions {
+            nmea: true,
+            raw: Some(1),
+            device: Some("/dev/gps\"0".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(
+            options.to_command(),
+            "?WATCH={\"enable\":true,\"json\":true,\"nmea\":true,\"raw\":1,\"device\":\"/dev/gps\\\"0\"};\r\n"
+        );
+
+        let mut buf = [0; 64];
+        let n = WatchOptions::default().encode_command(&mut buf).unwrap();
+        assert_eq!(&buf[..n], ENABLE_WATCH_CMD.as_bytes());
+        assert!(options.encode_command(&mut buf).is_err());
+        let options = WatchOptions {
+            device: Some("a\\b\n".to_owned()),
+            ..Default::default()
+        };
+        let mut cmd = String::new();
+        options.write_command(&mut cmd).unwrap();
+        assert!(cmd.contains(",\"device\":\"a\\\\b\\n\"}"));
+    }
+
+    const HANDSHAKE_NO_DEVICES: &[u8] = b"{\"class\":\"VERSION\",\"release\":\"blah\",\"rev\":\"blurp\",\"proto_major\":3,\"proto_minor\":12}
+{\"class\":\"DEVICES\",\"devices\":[]}
+{\"class\":\"WATCH\",\"enable\":true,\"json\":true,\"nmea\":false}
+";
+
+    #[test]
+    fn handshake_no_devices() {
+        let mut writer = BufWriter::new(Vec::<u8>::new());
+        assert!(handshake(&mut &HANDSHAKE_NO_DEVICES[..], &mut writer).is_ok());
+
+        let options = HandshakeOptions {
+            no_devices: NoDevicesPolicy::Fail,
+            ..Default::default()
+        };
+        let r = handshake_with_options(&mut &HANDSHAKE_NO_DEVICES[..], &mut writer, &options);
+        assert!(matches!(r, Err(GpsdError::NoDevices)));
+    }
+
+    #[test]
+    fn handshake_no_devices_wait() {
+        let options = HandshakeOptions {
+            no_devices: NoDevicesPolicy::Wait(Duration::from_secs(60)),
+            ..Default::default()
+        };
+        let mut writer = BufWriter::new(Vec::<u8>::new());
+        let r = handshake_with_options(&mut &HANDSHAKE_NO_DEVICES[..], &mut writer, &options);
+        assert!(matches!(r, Err(GpsdError::NoDevices)));
+
+        let mut data = HANDSHAKE_NO_DEVICES.to_vec();
+        data.extend_from_slice(b"{\"class\":\"TPV\",\"mode\":1}\n");
+        data.extend_from_slice(
+            b"{\"class\":\"DEVICE\",\"path\":\"/dev/gps\",\"activated\":\"2024-01-01T00:00:00.000Z\"}\n",
+        );
+        let r = handshake_with_options(&mut &data[..], &mut writer, &options);
+        assert!(r.is_ok());
+    }
+
+    #[test]
+    fn handshake_out_of_order() {
+        let data = b"{\"class\":\"VERSION\",\"release\":\"blah\",\"rev\":\"blurp\",\"proto_major\":3,\"proto_minor\":12}
+{\"class\":\"DEVICE\",\"path\":\"/dev/gps\"}
+{\"class\":\"WATCH\",\"enable\":true,\"json\":true,\"nmea\":false}
+{\"class\":\"DEVICES\",\"devices\":[{\"path\":\"/dev/gps\",\"activated\":\"true\"}]}
+";
+        let mut writer = BufWriter::new(Vec::<u8>::new());
+        assert!(handshake(&mut &data[..], &mut writer).is_ok());
+
+        let options = HandshakeOptions {
+            reorder_window: 1,
+            ..Default::default()
+        };
+        let r = handshake_with_options(&mut &data[..], &mut writer, &options);
+        assert!(matches!(r, Err(GpsdError::UnexpectedGpsdReply(_))));
+    }
+
+    struct TimingOut;
+
+    impl std::io::Read for TimingOut {
+        fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
+            Err(std::io::ErrorKind::WouldBlock.into())
+        }
+    }
+
+    #[test]
+    fn handshake_timeout() {
+        let mut reader = std::io::BufReader::new(TimingOut);
+        let mut writer = BufWriter::new(Vec::<u8>::new());
+        let r = handshake(&mut reader, &mut writer);
+        assert!(matches!(
+            r,
+            Err(GpsdError::Timeout {
+                phase: TimeoutPhase::Handshake,
+                ..
+            })
+        ));
+        let r = get_data(&mut reader);
+        assert!(matches!(
+            r,
+            Err(GpsdError::Timeout {
+                phase: TimeoutPhase::Read,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn handshake_daemon_text() {
+        let mut reader: &[u8] = b"{\"class\":\"VERSION\",\"release\":\"blah\",\"rev\":\"blurp\",\"proto_major\":3,\"proto_minor\":12}\x0d
+GPSD,W=?\x0d
+";
+        let mut writer = BufWriter::new(Vec::<u8>::new());
+        match handshake(&mut reader, &mut writer) {
+            Err(GpsdError::DaemonText(text)) => assert_eq!(text, "GPSD,W=?"),
+            _ => panic!("expected DaemonText"),
+        }
+    }
+
     #[test]
     fn handshake_json_error() {
         let mut reader: &[u8] = b"{\"class\":broken";
@@ -679,6 +3833,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::match_like_matches_macro)]
     fn get_data_tpv() {
         let mut reader: &[u8] = b"{\"class\":\"TPV\",\"mode\":3,\"lat\":66.123}\x0d\x0a";
         let r = get_data(&mut reader).unwrap();
@@ -697,6 +3852,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn get_data_sky() {
         let mut reader: &[u8] = b"{\"class\":\"SKY\",\"device\":\"aDevice\",\"satellites\":[{\"PRN\":123,\"el\":1.0,\"az\":2.0,\"ss\":3.0,\"used\":true,\"gnssid\":1,\"svid\":271,\"health\":1}]}\x0d\x0a";
 
@@ -712,7 +3868,7 @@ mod tests {
                 assert_eq!(actual.used, true);
                 assert_eq!(actual.gnssid, Some(1));
                 assert_eq!(actual.svid, Some(271));
-                assert_eq!(actual.health, Some(1));
+                assert_eq!(actual.health, Some(Health::Ok));
                 Ok(())
             }
             _ => Err(()),
@@ -720,10 +3876,400 @@ mod tests {
         assert_eq!(test, Ok(()));
     }
 
+    #[test]
+    fn get_data_into_reuses_buffer() {
+        let mut reader: &[u8] =
+            b"{\"class\":\"TPV\",\"mode\":3}\x0d\x0a{\"class\":\"SKY\"}\x0d\x0a";
+        let mut buf = Vec::new();
+        let r = get_data_into(&mut reader, &mut buf).unwrap();
+        assert!(matches!(r, ResponseData::Tpv(_)));
+        let r = get_data_into(&mut reader, &mut buf).unwrap();
+        assert!(matches!(r, ResponseData::Sky(_)));
+        #[cfg(not(feature = "simd-json"))]
+        assert_eq!(buf, b"{\"class\":\"SKY\"}\x0d\x0a");
+    }
+
+    #[test]
+    fn get_data_with_raw_tpv() {
+        let mut reader: &[u8] = b"{\"class\":\"TPV\",\"mode\":3,\"lat\":66.123}\x0d\x0a";
+        let r = get_data_with_raw(&mut reader).unwrap();
+        assert!(matches!(r.message, ResponseData::Tpv(_)));
+        assert_eq!(r.raw, "{\"class\":\"TPV\",\"mode\":3,\"lat\":66.123}");
+    }
+
+    #[test]
+    fn get_data_unrecognized_values_lenient() {
+        let mut reader: &[u8] = b"{\"class\":\"TPV\",\"mode\":7,\"status\":42}\x0d\x0a";
+        let r = get_data_with_options(&mut reader, &ParserOptions::default()).unwrap();
+        match r {
+            ResponseData::Tpv(tpv) => {
+                assert_eq!(tpv.mode, Mode::Unrecognized(7));
+                assert_eq!(tpv.status, Some(Status::Unrecognized(42)));
+            }
+            _ => panic!("expected TPV"),
+        }
+    }
+
+    #[test]
+    fn get_data_unrecognized_values_strict() {
+        let options = ParserOptions {
+            unknown_values: UnknownValuePolicy::Strict,
+        };
+        let mut reader: &[u8] = b"{\"class\":\"TPV\",\"mode\":3,\"status\":2}\x0d\x0a";
+        assert!(get_data_with_options(&mut reader, &options).is_ok());
+
+        let mut reader: &[u8] =
+            b"{\"class\":\"SKY\",\"satellites\":[{\"PRN\":1,\"used\":false,\"health\":9}]}\x0d\x0a";
+        match get_data_with_options(&mut reader, &options) {
+            Err(GpsdError::UnrecognizedValue { field, value }) => {
+                assert_eq!(field, "health");
+                assert_eq!(value, 9);
+            }
+            _ => panic!("expected UnrecognizedValue"),
+        }
+    }
+
     #[test]
     fn mode_to_string() {
         assert_eq!("NoFix", Mode::NoFix.to_string());
         assert_eq!("2d", Mode::Fix2d.to_string());
         assert_eq!("3d", Mode::Fix3d.to_string());
+        assert_eq!("Unrecognized(9)", Mode::Unrecognized(9).to_string());
+    }
+
+    #[test]
+    fn int_enums_round_trip() {
+        for v in 0..=12 {
+            assert_eq!(
+                u8::from(serde_json::from_str::<Mode>(&v.to_string()).unwrap()),
+                v
+            );
+            assert_eq!(
+                u8::from(serde_json::from_str::<Status>(&v.to_string()).unwrap()),
+                v
+            );
+            assert_eq!(
+                u8::from(serde_json::from_str::<Health>(&v.to_string()).unwrap()),
+                v
+            );
+            assert_eq!(
+                u8::from(serde_json::from_str::<Quality>(&v.to_string()).unwrap()),
+                v
+            );
+        }
+        assert_eq!(serde_json::from_str::<Mode>("0").unwrap(), Mode::NotSeen);
+        assert!(serde_json::from_str::<Mode>("\"3\"").is_err());
+    }
+
+    #[test]
+    fn parse_lines_batch() {
+        let data = b"{\"class\":\"TPV\",\"mode\":3}\r\n\r\n{\"class\":broken\n{\"class\":\"SKY\"}";
+        let r: Vec<_> = parse_lines(data).collect();
+        assert_eq!(r.len(), 3);
+        assert!(matches!(r[0], Ok(UnifiedResponse::Tpv(_))));
+        assert!(matches!(r[1], Err(GpsdError::JsonError(_))));
+        assert!(matches!(r[2], Ok(UnifiedResponse::Sky(_))));
+    }
+
+    #[test]
+    fn unified_response_known_class() {
+        let msg: UnifiedResponse =
+            serde_json::from_str("{\"class\":\"TPV\",\"mode\":2,\"lat\":66.123}").unwrap();
+        match msg {
+            UnifiedResponse::Tpv(tpv) => assert_eq!(tpv.lat, Some(66.123)),
+            _ => panic!("expected TPV"),
+        }
+    }
+
+    #[test]
+    fn unified_response_unknown_class() {
+        let msg: UnifiedResponse = serde_json::from_str("{\"class\":\"FOO\",\"bar\":1}").unwrap();
+        match msg {
+            UnifiedResponse::Unknown { class, raw } => {
+                assert_eq!(class, "FOO");
+                let raw: serde_json::Value = serde_json::from_str(raw.get()).unwrap();
+                assert_eq!(raw["bar"], 1);
+            }
+            _ => panic!("expected Unknown"),
+        }
+    }
+
+    #[test]
+    fn unified_response_class_not_first() {
+        let json = "{\"mode\":3,\"class\":\"TPV\",\"lat\":48.1}";
+        let msg: UnifiedResponse = serde_json::from_str(json).unwrap();
+        assert!(matches!(msg, UnifiedResponse::Tpv(tpv) if tpv.lat == Some(48.1)));
+        // Deserializers other than `serde_json`, here a `Value`.
+        let value: serde_json::Value = serde_json::from_str(json).unwrap();
+        let msg: UnifiedResponse = serde_json::from_value(value).unwrap();
+        assert!(matches!(msg, UnifiedResponse::Tpv(tpv) if tpv.mode == Mode::Fix3d));
+        let value = serde_json::json!({"class": "FOO", "bar": 1});
+        let msg: UnifiedResponse = serde_json::from_value(value).unwrap();
+        assert!(matches!(msg, UnifiedResponse::Unknown { class, .. } if class == "FOO"));
+        assert!(serde_json::from_str::<UnifiedResponse>("{\"mode\":3}").is_err());
+    }
+
+    #[cfg(feature = "osc")]
+    #[test]
+    fn unified_response_osc() {
+        let msg: UnifiedResponse = serde_json::from_str(
+            "{\"class\":\"OSC\",\"device\":\"/dev/ttyS0\",\"running\":true,\"reference\":true,\"disciplined\":false,\"delta\":-2}",
+        )
+        .unwrap();
+        match msg {
+            UnifiedResponse::Osc(osc) => {
+                assert!(osc.running);
+                assert!(!osc.disciplined);
+                assert_eq!(osc.delta, -2);
+            }
+            _ => panic!("expected OSC"),
+        }
+    }
+
+    #[cfg(feature = "raw")]
+    #[test]
+    fn unified_response_raw() {
+        let msg: UnifiedResponse = serde_json::from_str(
+            "{\"class\":\"RAW\",\"device\":\"/dev/ttyACM0\",\"time\":1700000000,\"nsec\":0,\"rawdata\":[{\"gnssid\":0,\"svid\":12,\"sigid\":0,\"snr\":42,\"obs\":\"C1C\",\"lli\":0,\"locktime\":5000,\"carrierphase\":118234567.125,\"pseudorange\":22498765.5,\"doppler\":-1234.5}]}",
+        )
+        .unwrap();
+        match msg {
+            UnifiedResponse::Raw(raw) => {
+                assert_eq!(raw.time, Some(1_700_000_000));
+                assert_eq!(raw.rawdata.len(), 1);
+                assert_eq!(raw.rawdata[0].svid, 12);
+                assert_eq!(raw.rawdata[0].doppler, Some(-1234.5));
+            }
+            _ => panic!("expected RAW"),
+        }
+    }
+
+    #[cfg(feature = "att")]
+    #[test]
+    fn get_data_att() {
+        let mut reader: &[u8] =
+            b"{\"class\":\"ATT\",\"heading\":14223.00,\"pitch\":169.00,\"depth\":3.5}\x0d\x0a";
+        match get_data(&mut reader).unwrap() {
+            ResponseData::Att(att) => {
+                assert_eq!(att.heading, Some(14223.0));
+                assert_eq!(att.depth, Some(3.5));
+            }
+            _ => panic!("expected ATT"),
+        }
+    }
+
+    #[cfg(feature = "subframe")]
+    #[test]
+    fn get_data_subframe_raw() {
+        let line = "{\"class\":\"SUBFRAME\",\"device\":\"/dev/ttyS0\",\"tSV\":25,\"frame\":4}";
+        let data = format!("{}\r\n", line);
+        match get_data(&mut data.as_bytes()).unwrap() {
+            ResponseData::Subframe(raw) => assert_eq!(raw.get(), line),
+            _ => panic!("expected SUBFRAME"),
+        }
+    }
+
+    #[cfg(not(feature = "ais"))]
+    #[test]
+    fn unified_response_disabled_class() {
+        let msg: UnifiedResponse =
+            serde_json::from_str("{\"class\":\"AIS\",\"type\":1,\"mmsi\":1}").unwrap();
+        assert!(matches!(msg, UnifiedResponse::Unknown { .. }));
+    }
+
+    #[test]
+    fn unified_response_from_slice() {
+        // Class not being the first attribute takes the slow path.
+        for data in [
+            &b" { \"class\" : \"TPV\", \"mode\":2,\"lat\":66.123}\r\n"[..],
+            &b"{\"mode\":2,\"lat\":66.123,\"class\":\"TPV\"}"[..],
+        ] {
+            match UnifiedResponse::from_slice(data).unwrap() {
+                UnifiedResponse::Tpv(tpv) => assert_eq!(tpv.lat, Some(66.123)),
+                _ => panic!("expected TPV"),
+            }
+        }
+        match UnifiedResponse::from_slice(b"{\"class\":\"FOO\",\"bar\":1}").unwrap() {
+            UnifiedResponse::Unknown { class, raw } => {
+                assert_eq!(class, "FOO");
+                assert_eq!(raw.get(), "{\"class\":\"FOO\",\"bar\":1}");
+            }
+            _ => panic!("expected Unknown"),
+        }
+        assert!(UnifiedResponse::from_slice(b"{\"mode\":2}").is_err());
+        assert!(UnifiedResponse::from_slice(b"{\"class\":\"TPV\",\"mode\":2} x").is_err());
+        assert!(ResponseData::from_slice(b"{\"class\":\"VERSION\"}").is_err());
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn parse_lines_parallel_keeps_order() {
+        let mut data = Vec::new();
+        for i in 0..1000 {
+            data.extend_from_slice(
+                format!("{{\"class\":\"TPV\",\"mode\":3,\"lat\":{}}}\n", i).as_bytes(),
+            );
+        }
+        data.extend_from_slice(b"{\"class\":broken\n\n");
+        let r = super::parse_lines_parallel(&data);
+        assert_eq!(r.len(), 1001);
+        for (i, msg) in r[..1000].iter().enumerate() {
+            match msg {
+                Ok(UnifiedResponse::Tpv(tpv)) => assert_eq!(tpv.lat, Some(i as f64)),
+                _ => panic!("expected TPV"),
+            }
+        }
+        assert!(r[1000].is_err());
+    }
+
+    #[test]
+    fn parse_stream_without_framing() {
+        let data = b"{\"class\":\"TPV\",\"mode\":3}{\"class\":\"SKY\"}
+{
+  \"class\": \"TPV\",
+  \"mode\": 2
+}
+{\"class\":broken}{\"class\":\"SKY\"}";
+        let msgs: Vec<_> = parse_stream(std::io::BufReader::new(&data[..])).collect();
+        assert_eq!(msgs.len(), 4);
+        assert!(matches!(msgs[0], Ok(UnifiedResponse::Tpv(_))));
+        assert!(matches!(msgs[1], Ok(UnifiedResponse::Sky(_))));
+        match &msgs[2] {
+            Ok(UnifiedResponse::Tpv(tpv)) => assert_eq!(tpv.mode, Mode::Fix2d),
+            _ => panic!("expected TPV"),
+        }
+        assert!(matches!(msgs[3], Err(GpsdError::JsonError(_))));
+    }
+
+    #[test]
+    fn iso8601_timestamp() {
+        let t = Iso8601Timestamp::new("2005-06-08T10:34:48.283Z").unwrap();
+        assert_eq!(t, "2005-06-08T10:34:48.283Z");
+        assert_eq!((t.year(), t.month(), t.day()), (2005, 6, 8));
+        assert_eq!((t.hour(), t.minute(), t.second()), (10, 34, 48));
+        assert_eq!(t.nanosecond(), 283_000_000);
+        assert_eq!(
+            t.to_system_time(),
+            std::time::UNIX_EPOCH + Duration::from_millis(1_118_226_888_283)
+        );
+        assert_eq!(Iso8601Timestamp::from_system_time(t.to_system_time()), t);
+        let t = Iso8601Timestamp::new("2005-06-08T10:34:48Z").unwrap();
+        assert_eq!(t.nanosecond(), 0);
+        let t = Iso8601Timestamp::new("1969-12-31T23:59:59.5Z").unwrap();
+        assert_eq!(
+            t.to_system_time(),
+            std::time::UNIX_EPOCH - Duration::from_millis(500)
+        );
+        let t = Iso8601Timestamp::new("2005-06-08T10:34:48.1234567891Z").unwrap();
+        assert_eq!(t.nanosecond(), 123_456_789);
+
+        assert!(Iso8601Timestamp::new("2005-06-08 10:34:48Z").is_none());
+        assert!(Iso8601Timestamp::new("2005-06-08T10:34:48.Z").is_none());
+        assert!(Iso8601Timestamp::new("2005-06-08T10:34:48").is_none());
+        assert!(Iso8601Timestamp::new("2005-06-08T10:34:48.12345678901234Z").is_none());
+
+        let tpv: Tpv =
+            serde_json::from_str(r#"{"mode":3,"time":"2005-06-08T10:34:48.283Z"}"#).unwrap();
+        assert_eq!(tpv.time.unwrap().as_str(), "2005-06-08T10:34:48.283Z");
+        assert!(serde_json::from_str::<Tpv>(r#"{"mode":3,"time":"yesterday"}"#).is_err());
+    }
+
+    #[test]
+    fn response_enums_are_small() {
+        assert!(std::mem::size_of::<UnifiedResponse>() <= 64);
+        assert!(std::mem::size_of::<ResponseData>() <= 64);
+    }
+
+    #[test]
+    fn parse_poll_entries() {
+        let data = br#"{"class":"POLL","time":"2010-06-04T10:31:00.289Z","active":2,
+            "tpv":[{"class":"TPV","device":"/dev/ttyUSB0","mode":3},
+                   {"class":"TPV","device":"/dev/ttyUSB1","mode":2}],
+            "sky":[{"class":"SKY","device":"/dev/ttyUSB0"}],"extra":{"x":[1]}}"#;
+        let mut entries = Vec::new();
+        let header = parse_poll(data, |e| entries.push(e)).unwrap();
+        assert_eq!(header.active, Some(2));
+        assert_eq!(header.time.unwrap(), "2010-06-04T10:31:00.289Z");
+        assert_eq!(entries.len(), 3);
+        match &entries[1] {
+            PollEntry::Tpv(tpv) => assert_eq!(tpv.mode, Mode::Fix2d),
+            _ => panic!("expected TPV"),
+        }
+        assert!(matches!(entries[2], PollEntry::Sky(_)));
+
+        assert!(parse_poll(br#"{"class":"TPV","mode":3}"#, |_| ()).is_err());
+        assert!(parse_poll(br#"{"active":0}"#, |_| ()).is_err());
+    }
+
+    #[test]
+    fn parse_in_place_reuses_message() {
+        let mut sky: Sky = serde_json::from_str(r#"{"hdop":1.5,"satellites":[]}"#).unwrap();
+        parse_in_place(
+            br#"{"class":"SKY","device":"/dev/ttyUSB0","satellites":[{"PRN":1,"used":true}]}"#,
+            &mut sky,
+        )
+        .unwrap();
+        assert_eq!(sky.device.as_deref(), Some("/dev/ttyUSB0"));
+        assert_eq!(sky.hdop, None);
+        assert_eq!(sky.satellites.as_ref().map(Vec::len), Some(1));
+
+        let mut tpv: Tpv = serde_json::from_str(r#"{"mode":1}"#).unwrap();
+        parse_in_place(br#"{"class":"TPV","mode":3,"lat":66.123}"#, &mut tpv).unwrap();
+        assert_eq!(tpv.mode, Mode::Fix3d);
+        assert_eq!(tpv.lat, Some(66.123));
+        assert!(parse_in_place(br#"{"class":"SKY"}"#, &mut tpv).is_err());
+    }
+
+    #[test]
+    fn class_set() {
+        let set = ClassSet::TPV | ClassSet::SKY;
+        assert!(set.contains(ClassSet::TPV));
+        assert!(set.contains_class("SKY"));
+        assert!(!set.contains_class("GST"));
+        assert!(!set.contains_class("FOO"));
+        assert!(ClassSet::ALL.contains_class("FOO"));
+        assert!(!(ClassSet::ALL - ClassSet::OTHER).contains_class("FOO"));
+        assert!(ClassSet::default().contains(set));
+        assert!(!ClassSet::NONE.contains_class("TPV"));
+    }
+
+    #[test]
+    fn unified_response_missing_class() {
+        assert!(serde_json::from_str::<UnifiedResponse>("{\"mode\":2}").is_err());
+    }
+
+    #[test]
+    fn gps_time_conversion() {
+        let tpv: Tpv = serde_json::from_str(
+            r#"{"mode":3,"time":"2024-01-01T00:00:00.500Z","leapseconds":18}"#,
+        )
+        .unwrap();
+        let gps = tpv.gps_time().unwrap();
+        // GPS week 2295 started on 2023-12-31.
+        assert_eq!(gps.as_secs(), 2295 * 604_800 + 86_400 + 18);
+        assert_eq!(gps.subsec_millis(), 500);
+        assert_eq!(tpv.tai_time().unwrap().as_secs(), 1_704_067_200 + 18 + 19);
+        assert_eq!(gps_to_utc(gps, 18), tpv.utc_time().unwrap());
+
+        let tpv: Tpv =
+            serde_json::from_str(r#"{"mode":3,"time":"2024-01-01T00:00:00.500Z"}"#).unwrap();
+        assert!(tpv.utc_time().is_some());
+        assert_eq!(tpv.gps_time(), None);
+    }
+
+    #[test]
+    fn week_rollover() {
+        let not_before = std::time::UNIX_EPOCH + Duration::from_secs(1_704_067_200);
+        let ts = Iso8601Timestamp::new("2005-06-08T10:34:48.283Z").unwrap();
+        assert_eq!(
+            ts.correct_week_rollover(not_before),
+            "2025-01-22T10:34:48.283Z"
+        );
+        let ts = Iso8601Timestamp::new("1986-02-10T23:59:59Z").unwrap();
+        assert_eq!(ts.correct_week_rollover(not_before), "2025-05-12T23:59:59Z");
+        let ts = Iso8601Timestamp::new("2024-02-29T00:00:00Z").unwrap();
+        assert_eq!(ts.correct_week_rollover(not_before), ts);
+        let far = std::time::UNIX_EPOCH + Duration::from_secs(400_000_000_000);
+        assert_eq!(ts.correct_week_rollover(far).year(), 9992);
     }
 }
@@ -0,0 +1,591 @@
+//! Ephemeris and almanac records assembled from SUBFRAME reports.
+
+use crate::geo::look_angles;
+use crate::UnifiedResponse;
+use std::collections::BTreeMap;
+use std::f64::consts::PI;
+
+/// Gravitational constant of the earth in m³/s², as defined by
+/// IS-GPS-200.
+const GM: f64 = 3.986_005e14;
+
+/// Rotation rate of the earth in rad/s.
+const EARTH_ROTATION: f64 = 7.292_115_146_7e-5;
+
+/// Half a week in seconds.
+const HALF_WEEK: f64 = 302_400.0;
+
+/// Keplerian elements with harmonic corrections, shared by ephemeris
+/// and almanac.
+struct Orbit {
+    toe: f64,
+    sqrt_a: f64,
+    e: f64,
+    m0: f64,
+    delta_n: f64,
+    omega0: f64,
+    omega_dot: f64,
+    omega: f64,
+    i0: f64,
+    idot: f64,
+    cuc: f64,
+    cus: f64,
+    crc: f64,
+    crs: f64,
+    cic: f64,
+    cis: f64,
+}
+
+impl Orbit {
+    /// ECEF position at `tow` seconds of the week, following the
+    /// algorithm of IS-GPS-200, table 20-IV.
+    fn position_ecef(&self, tow: f64) -> (f64, f64, f64) {
+        let mut tk = tow - self.toe;
+        if tk > HALF_WEEK {
+            tk -= 2.0 * HALF_WEEK;
+        } else if tk < -HALF_WEEK {
+            tk += 2.0 * HALF_WEEK;
+        }
+        let a = self.sqrt_a * self.sqrt_a;
+        let n = (GM / (a * a * a)).sqrt() + self.delta_n;
+        let m = self.m0 + n * tk;
+        let mut ecc = m;
+        for _ in 0..10 {
+            ecc = m + self.e * ecc.sin();
+        }
+        let (sin_e, cos_e) = ecc.sin_cos();
+        let nu = ((1.0 - self.e * self.e).sqrt() * sin_e).atan2(cos_e - self.e);
+        let phi = nu + self.omega;
+        let (sin_2phi, cos_2phi) = (2.0 * phi).sin_cos();
+        let u = phi + self.cus * sin_2phi + self.cuc * cos_2phi;
+        let r = a * (1.0 - self.e * cos_e) + self.crs * sin_2phi + self.crc * cos_2phi;
+        let i = self.i0 + self.idot * tk + self.cis * sin_2phi + self.cic * cos_2phi;
+        let (x, y) = (r * u.cos(), r * u.sin());
+        let node = self.omega0 + (self.omega_dot - EARTH_ROTATION) * tk - EARTH_ROTATION * self.toe;
+        let (sin_node, cos_node) = node.sin_cos();
+        let (sin_i, cos_i) = i.sin_cos();
+        (
+            x * cos_node - y * cos_i * sin_node,
+            x * sin_node + y * cos_i * cos_node,
+            y * sin_i,
+        )
+    }
+}
+
+/// Broadcast ephemeris of a GPS satellite, from subframes 1 to 3.
+///
+/// Angles are in radians, times in seconds of the GPS week, as scaled
+/// by `gpsd`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct Ephemeris {
+    /// PRN of the satellite.
+    pub prn: u8,
+    /// GPS week number, modulo 1024.
+    pub week: u16,
+    /// Issue of data, clock.
+    pub iodc: u16,
+    /// Issue of data, ephemeris.
+    pub iode: u8,
+    /// Satellite health, 0 if all signals are ok.
+    pub health: u8,
+    /// User range accuracy index.
+    pub ura: u8,
+    /// Group delay differential in seconds.
+    pub tgd: f64,
+    /// Clock data reference time.
+    pub toc: f64,
+    /// Clock bias in seconds.
+    pub af0: f64,
+    /// Clock drift in s/s.
+    pub af1: f64,
+    /// Clock drift rate in s/s².
+    pub af2: f64,
+    /// Ephemeris reference time.
+    pub toe: f64,
+    /// Square root of the semi-major axis in √m.
+    pub sqrt_a: f64,
+    /// Eccentricity.
+    pub e: f64,
+    /// Mean anomaly at `toe`.
+    pub m0: f64,
+    /// Mean motion difference in rad/s.
+    pub delta_n: f64,
+    /// Longitude of the ascending node at the start of the week.
+    pub omega0: f64,
+    /// Rate of right ascension in rad/s.
+    pub omega_dot: f64,
+    /// Argument of perigee.
+    pub omega: f64,
+    /// Inclination at `toe`.
+    pub i0: f64,
+    /// Rate of inclination in rad/s.
+    pub idot: f64,
+    /// Harmonic corrections of the argument of latitude in radians.
+    pub cuc: f64,
+    pub cus: f64,
+    /// Harmonic corrections of the orbit radius in meters.
+    pub crc: f64,
+    pub crs: f64,
+    /// Harmonic corrections of the inclination in radians.
+    pub cic: f64,
+    pub cis: f64,
+}
+
+/// Almanac entry of a GPS satellite, from subframes 4 and 5.
+///
+/// Angles are in radians, as scaled by `gpsd`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct AlmanacEntry {
+    /// PRN of the satellite described, not of the one broadcasting.
+    pub prn: u8,
+    /// Satellite health, 0 if all signals are ok.
+    pub health: u8,
+    /// Almanac reference time in seconds of the GPS week.
+    pub toa: f64,
+    /// Square root of the semi-major axis in √m.
+    pub sqrt_a: f64,
+    /// Eccentricity.
+    pub e: f64,
+    /// Inclination relative to 0.3 semicircles.
+    pub delta_i: f64,
+    /// Longitude of the ascending node at the start of the week.
+    pub omega0: f64,
+    /// Rate of right ascension in rad/s.
+    pub omega_dot: f64,
+    /// Argument of perigee.
+    pub omega: f64,
+    /// Mean anomaly at `toa`.
+    pub m0: f64,
+    /// Clock bias in seconds.
+    pub af0: f64,
+    /// Clock drift in s/s.
+    pub af1: f64,
+}
+
+impl Ephemeris {
+    /// ECEF position in meters at `tow` seconds of the GPS week.
+    pub fn position_ecef(&self, tow: f64) -> (f64, f64, f64) {
+        Orbit {
+            toe: self.toe,
+            sqrt_a: self.sqrt_a,
+            e: self.e,
+            m0: self.m0,
+            delta_n: self.delta_n,
+            omega0: self.omega0,
+            omega_dot: self.omega_dot,
+            omega: self.omega,
+            i0: self.i0,
+            idot: self.idot,
+            cuc: self.cuc,
+            cus: self.cus,
+            crc: self.crc,
+            crs: self.crs,
+            cic: self.cic,
+            cis: self.cis,
+        }
+        .position_ecef(tow)
+    }
+}
+
+impl AlmanacEntry {
+    /// Approximate ECEF position in meters at `tow` seconds of the GPS
+    /// week. The error is a few kilometers for a current almanac,
+    /// enough to predict visibility.
+    pub fn position_ecef(&self, tow: f64) -> (f64, f64, f64) {
+        Orbit {
+            toe: self.toa,
+            sqrt_a: self.sqrt_a,
+            e: self.e,
+            m0: self.m0,
+            delta_n: 0.0,
+            omega0: self.omega0,
+            omega_dot: self.omega_dot,
+            omega: self.omega,
+            i0: 0.3 * PI + self.delta_i,
+            idot: 0.0,
+            cuc: 0.0,
+            cus: 0.0,
+            crc: 0.0,
+            crs: 0.0,
+            cic: 0.0,
+            cis: 0.0,
+        }
+        .position_ecef(tow)
+    }
+}
+
+#[derive(Debug, Copy, Clone, Deserialize)]
+struct Ephem1 {
+    #[serde(rename = "WN")]
+    week: u16,
+    #[serde(rename = "IODC")]
+    iodc: u16,
+    ura: u8,
+    hlth: u8,
+    #[serde(rename = "Tgd")]
+    tgd: f64,
+    toc: f64,
+    af0: f64,
+    af1: f64,
+    af2: f64,
+}
+
+#[derive(Debug, Copy, Clone, Deserialize)]
+struct Ephem2 {
+    #[serde(rename = "IODE")]
+    iode: u8,
+    #[serde(rename = "Crs")]
+    crs: f64,
+    deltan: f64,
+    #[serde(rename = "M0")]
+    m0: f64,
+    #[serde(rename = "Cuc")]
+    cuc: f64,
+    e: f64,
+    #[serde(rename = "Cus")]
+    cus: f64,
+    #[serde(rename = "sqrtA")]
+    sqrt_a: f64,
+    toe: f64,
+}
+
+#[derive(Debug, Copy, Clone, Deserialize)]
+struct Ephem3 {
+    #[serde(rename = "IODE")]
+    iode: u8,
+    #[serde(rename = "IDOT")]
+    idot: f64,
+    #[serde(rename = "Cic")]
+    cic: f64,
+    #[serde(rename = "Omega0")]
+    omega0: f64,
+    #[serde(rename = "Cis")]
+    cis: f64,
+    i0: f64,
+    #[serde(rename = "Crc")]
+    crc: f64,
+    omega: f64,
+    #[serde(rename = "Omegad")]
+    omega_dot: f64,
+}
+
+#[derive(Debug, Copy, Clone, Deserialize)]
+struct Almanac {
+    #[serde(rename = "ID")]
+    id: u8,
+    #[serde(rename = "Health")]
+    health: u8,
+    e: f64,
+    toa: f64,
+    deltai: f64,
+    #[serde(rename = "Omegad")]
+    omega_dot: f64,
+    #[serde(rename = "sqrtA")]
+    sqrt_a: f64,
+    #[serde(rename = "Omega0")]
+    omega0: f64,
+    omega: f64,
+    #[serde(rename = "M0")]
+    m0: f64,
+    af0: f64,
+    af1: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct NavSubframe {
+    #[serde(rename = "tSV")]
+    tsv: u8,
+    #[serde(default)]
+    scaled: bool,
+    #[serde(rename = "EPHEM1")]
+    ephem1: Option<Ephem1>,
+    #[serde(rename = "EPHEM2")]
+    ephem2: Option<Ephem2>,
+    #[serde(rename = "EPHEM3")]
+    ephem3: Option<Ephem3>,
+    #[serde(rename = "ALMANAC")]
+    almanac: Option<Almanac>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Partial {
+    ephem1: Option<Ephem1>,
+    ephem2: Option<Ephem2>,
+    ephem3: Option<Ephem3>,
+}
+
+impl Partial {
+    /// The ephemeris, if all three subframes of the same issue are
+    /// present.
+    fn complete(&self, prn: u8) -> Option<Ephemeris> {
+        let (s1, s2, s3) = (self.ephem1?, self.ephem2?, self.ephem3?);
+        if s2.iode != s3.iode || (s1.iodc & 0xff) as u8 != s2.iode {
+            return None;
+        }
+        Some(Ephemeris {
+            prn,
+            week: s1.week,
+            iodc: s1.iodc,
+            iode: s2.iode,
+            health: s1.hlth,
+            ura: s1.ura,
+            tgd: s1.tgd,
+            toc: s1.toc,
+            af0: s1.af0,
+            af1: s1.af1,
+            af2: s1.af2,
+            toe: s2.toe,
+            sqrt_a: s2.sqrt_a,
+            e: s2.e,
+            m0: s2.m0,
+            delta_n: s2.deltan,
+            omega0: s3.omega0,
+            omega_dot: s3.omega_dot,
+            omega: s3.omega,
+            i0: s3.i0,
+            idot: s3.idot,
+            cuc: s2.cuc,
+            cus: s2.cus,
+            crc: s3.crc,
+            crs: s2.crs,
+            cic: s3.cic,
+            cis: s3.cis,
+        })
+    }
+}
+
+/// Record completed by [`NavDecoder::update`], by PRN.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum NavUpdate {
+    Ephemeris(u8),
+    Almanac(u8),
+}
+
+/// Accumulates the subframes of SUBFRAME reports into ephemeris and
+/// almanac records, keyed by satellite.
+///
+/// An ephemeris is complete once subframes 1 to 3 with matching issue
+/// of data have been received; it is replaced when a new issue is
+/// complete. Only subframes scaled by `gpsd` are used.
+#[derive(Debug, Clone, Default)]
+pub struct NavDecoder {
+    partial: BTreeMap<u8, Partial>,
+    ephemerides: BTreeMap<u8, Ephemeris>,
+    almanac: BTreeMap<u8, AlmanacEntry>,
+}
+
+impl NavDecoder {
+    /// Creates a decoder without records.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Latest complete ephemeris of satellite `prn`.
+    pub fn ephemeris(&self, prn: u8) -> Option<&Ephemeris> {
+        self.ephemerides.get(&prn)
+    }
+
+    /// Almanac entry of satellite `prn`.
+    pub fn almanac(&self, prn: u8) -> Option<&AlmanacEntry> {
+        self.almanac.get(&prn)
+    }
+
+    /// All complete ephemerides, ordered by PRN.
+    pub fn ephemerides(&self) -> impl Iterator<Item = &Ephemeris> {
+        self.ephemerides.values()
+    }
+
+    /// All almanac entries, ordered by PRN.
+    pub fn almanac_entries(&self) -> impl Iterator<Item = &AlmanacEntry> {
+        self.almanac.values()
+    }
+
+    /// ECEF position of satellite `prn` at `tow` seconds of the GPS
+    /// week, from its ephemeris or else its almanac entry.
+    pub fn position_ecef(&self, prn: u8, tow: f64) -> Option<(f64, f64, f64)> {
+        match self.ephemerides.get(&prn) {
+            Some(eph) => Some(eph.position_ecef(tow)),
+            None => Some(self.almanac.get(&prn)?.position_ecef(tow)),
+        }
+    }
+
+    /// Predicted sky view at `tow` seconds of the GPS week for an
+    /// `observer` at latitude, longitude and height above the
+    /// ellipsoid: PRN, azimuth and elevation in degrees of every known
+    /// satellite above the horizon.
+    pub fn sky_view(&self, observer: (f64, f64, f64), tow: f64) -> Vec<(u8, f64, f64)> {
+        let prns = self.ephemerides.keys().chain(self.almanac.keys());
+        let mut view: Vec<_> = prns
+            .filter_map(|&prn| {
+                let (az, el) = look_angles(observer, self.position_ecef(prn, tow)?);
+                (el > 0.0).then_some((prn, az, el))
+            })
+            .collect();
+        view.sort_by_key(|sat| sat.0);
+        view.dedup_by_key(|sat| sat.0);
+        view
+    }
+
+    /// Adds the subframe of `msg`, returning the record it completed or
+    /// changed.
+    pub fn update(&mut self, msg: &UnifiedResponse) -> Option<NavUpdate> {
+        let UnifiedResponse::Subframe(raw) = msg else {
+            return None;
+        };
+        let sub = serde_json::from_str::<NavSubframe>(raw.get()).ok()?;
+        if !sub.scaled {
+            return None;
+        }
+        if let Some(alm) = sub.almanac {
+            let entry = AlmanacEntry {
+                prn: alm.id,
+                health: alm.health,
+                toa: alm.toa,
+                sqrt_a: alm.sqrt_a,
+                e: alm.e,
+                delta_i: alm.deltai,
+                omega0: alm.omega0,
+                omega_dot: alm.omega_dot,
+                omega: alm.omega,
+                m0: alm.m0,
+                af0: alm.af0,
+                af1: alm.af1,
+            };
+            let previous = self.almanac.insert(alm.id, entry);
+            return (previous != Some(entry)).then_some(NavUpdate::Almanac(alm.id));
+        }
+
+        if sub.ephem1.is_none() && sub.ephem2.is_none() && sub.ephem3.is_none() {
+            return None;
+        }
+        let partial = self.partial.entry(sub.tsv).or_default();
+        partial.ephem1 = sub.ephem1.or(partial.ephem1);
+        partial.ephem2 = sub.ephem2.or(partial.ephem2);
+        partial.ephem3 = sub.ephem3.or(partial.ephem3);
+        let eph = partial.complete(sub.tsv)?;
+        let previous = self.ephemerides.insert(sub.tsv, eph);
+        (previous != Some(eph)).then_some(NavUpdate::Ephemeris(sub.tsv))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AlmanacEntry, Ephemeris, NavDecoder, NavUpdate};
+    use crate::parse_lines;
+
+    #[test]
+    fn assembles_ephemeris_and_almanac() {
+        let data = br#"{"class":"SUBFRAME","device":"/dev/ttyS0","tSV":12,"TOW17":1,"frame":1,"scaled":true,"EPHEM1":{"WN":245,"IODC":301,"L2":1,"ura":0,"hlth":0,"L2P":0,"Tgd":-1.1e-8,"toc":453600,"af2":0.0,"af1":-6.1e-12,"af0":2.4e-4}}
+{"class":"SUBFRAME","device":"/dev/ttyS0","tSV":12,"TOW17":2,"frame":2,"scaled":true,"EPHEM2":{"IODE":44,"Crs":-50.1,"deltan":4.5e-9,"M0":1.2,"Cuc":-2.6e-6,"e":0.0107,"Cus":7.9e-6,"sqrtA":5153.6,"toe":453600,"FIT":0,"AODO":27900}}
+{"class":"SUBFRAME","device":"/dev/ttyS0","tSV":12,"TOW17":3,"frame":3,"scaled":true,"EPHEM3":{"IODE":45,"IDOT":1.8e-10,"Cic":1.1e-7,"Omega0":-2.9,"Cis":5.2e-8,"i0":0.96,"Crc":224.5,"omega":0.67,"Omegad":-8.1e-9}}
+{"class":"SUBFRAME","device":"/dev/ttyS0","tSV":12,"TOW17":4,"frame":3,"scaled":true,"EPHEM3":{"IODE":45,"IDOT":1.8e-10,"Cic":1.1e-7,"Omega0":-2.9,"Cis":5.2e-8,"i0":0.96,"Crc":224.5,"omega":0.67,"Omegad":-8.1e-9}}
+{"class":"SUBFRAME","device":"/dev/ttyS0","tSV":12,"TOW17":5,"frame":5,"scaled":true,"ALMANAC":{"ID":7,"Health":0,"e":0.0149,"toa":405504,"deltai":0.0094,"Omegad":-8.0e-9,"sqrtA":5153.7,"Omega0":1.9,"omega":-2.1,"M0":0.44,"af0":-1.3e-4,"af1":0.0}}
+"#;
+        let mut nav = NavDecoder::new();
+        let updates: Vec<_> = parse_lines(data)
+            .map(|msg| nav.update(&msg.unwrap()))
+            .collect();
+        // Subframe 2 is of an older issue than subframes 1 and 3, the
+        // IODC 301 matches IODE 45.
+        assert_eq!(updates[..4], [None, None, None, None]);
+        assert_eq!(updates[4], Some(NavUpdate::Almanac(7)));
+        assert_eq!(nav.ephemeris(12), None);
+
+        let subframe2 = br#"{"class":"SUBFRAME","device":"/dev/ttyS0","tSV":12,"TOW17":7,"frame":2,"scaled":true,"EPHEM2":{"IODE":45,"Crs":-50.1,"deltan":4.5e-9,"M0":1.2,"Cuc":-2.6e-6,"e":0.0107,"Cus":7.9e-6,"sqrtA":5153.6,"toe":453600,"FIT":0,"AODO":27900}}
+"#;
+        let msg = parse_lines(subframe2).next().unwrap().unwrap();
+        assert_eq!(nav.update(&msg), Some(NavUpdate::Ephemeris(12)));
+        assert_eq!(nav.update(&msg), None);
+        let eph = nav.ephemeris(12).unwrap();
+        assert_eq!((eph.week, eph.iode, eph.toe), (245, 45, 453_600.0));
+        assert_eq!((eph.sqrt_a, eph.omega0), (5153.6, -2.9));
+        assert_eq!(nav.almanac(7).unwrap().sqrt_a, 5153.7);
+        assert_eq!(nav.ephemerides().count(), 1);
+    }
+
+    #[test]
+    fn predicts_positions() {
+        // A circular orbit in the equatorial plane, at the ascending
+        // node at toe.
+        let eph = Ephemeris {
+            prn: 1,
+            week: 0,
+            iodc: 0,
+            iode: 0,
+            health: 0,
+            ura: 0,
+            tgd: 0.0,
+            toc: 0.0,
+            af0: 0.0,
+            af1: 0.0,
+            af2: 0.0,
+            toe: 0.0,
+            sqrt_a: 26_560_000f64.sqrt(),
+            e: 0.0,
+            m0: 0.0,
+            delta_n: 0.0,
+            omega0: 0.0,
+            omega_dot: 0.0,
+            omega: 0.0,
+            i0: 0.0,
+            idot: 0.0,
+            cuc: 0.0,
+            cus: 0.0,
+            crc: 0.0,
+            crs: 0.0,
+            cic: 0.0,
+            cis: 0.0,
+        };
+        let (x, y, z) = eph.position_ecef(0.0);
+        assert!((x - 26_560_000.0).abs() < 1e-3 && y.abs() < 1e-3 && z == 0.0);
+        // Half a sidereal orbit later, on the other side of the earth,
+        // which has rotated meanwhile. The week wraps around.
+        let period = 2.0 * std::f64::consts::PI * (26_560_000f64.powi(3) / super::GM).sqrt();
+        let (x, y, _) = eph.position_ecef(604_800.0 + period / 2.0);
+        let angle = y.atan2(x) - std::f64::consts::PI;
+        let rotated = -super::EARTH_ROTATION * period / 2.0;
+        assert!((angle - rotated).abs() < 1e-9, "{} {}", angle, rotated);
+
+        let alm = AlmanacEntry {
+            prn: 2,
+            health: 0,
+            toa: 0.0,
+            sqrt_a: eph.sqrt_a,
+            e: 0.0,
+            delta_i: -0.3 * std::f64::consts::PI,
+            omega0: 0.0,
+            omega_dot: 0.0,
+            omega: 0.0,
+            m0: 0.0,
+            af0: 0.0,
+            af1: 0.0,
+        };
+        assert_eq!(alm.position_ecef(1000.0), eph.position_ecef(1000.0));
+
+        let mut nav = NavDecoder::new();
+        nav.ephemerides.insert(1, eph);
+        nav.almanac.insert(1, AlmanacEntry { prn: 1, ..alm });
+        nav.almanac.insert(2, AlmanacEntry { m0: 3.0, ..alm });
+        let view = nav.sky_view((0.0, 0.0, 0.0), 0.0);
+        assert_eq!(view.len(), 1);
+        assert_eq!(view[0].0, 1);
+        assert!((view[0].2 - 90.0).abs() < 1e-9);
+
+        #[cfg(feature = "spoofing")]
+        {
+            use crate::{Almanac, Constellation, GPS_EPOCH_UNIX_SECS};
+            use std::time::{Duration, UNIX_EPOCH};
+
+            // Week 0, second 0 of GPS time, 18 leap seconds behind.
+            let time = UNIX_EPOCH + Duration::from_secs(GPS_EPOCH_UNIX_SECS - 18);
+            let expected = nav.expected(0.0, 0.0, time).unwrap();
+            assert_eq!(expected.len(), 1);
+            assert!(expected[0].prn == 1 && expected[0].healthy);
+            assert!((expected[0].el - 90.0).abs() < 1e-3);
+            assert!(nav.covers(Constellation::Gps) && !nav.covers(Constellation::Galileo));
+            assert!(NavDecoder::new().expected(0.0, 0.0, time).is_none());
+        }
+    }
+}
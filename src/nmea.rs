@@ -0,0 +1,343 @@
+//! NMEA 0183 sentences rendered from TPV, SKY and GST reports.
+//!
+//! For chartplotters, autopilots and other devices only reading NMEA.
+//! Satellites are numbered as in NMEA 0183 before version 4.10: GPS
+//! 1-32, SBAS 33-64 and GLONASS 65-96. Other satellites are left out
+//! of GSA and GSV sentences.
+
+use crate::{Gst, Iso8601Timestamp, Mode, Sky, Status, Tpv, UnifiedResponse};
+use std::fmt::Write;
+
+/// XOR checksum of the sentence `body`, the characters between `$` and
+/// `*`.
+pub fn checksum(body: &str) -> u8 {
+    body.bytes().fold(0, |sum, b| sum ^ b)
+}
+
+/// Complete sentence of `body`, with `$`, checksum and line
+/// terminator.
+pub fn sentence(body: &str) -> String {
+    format!("${}*{:02X}\r\n", body, checksum(body))
+}
+
+/// Satellite number in NMEA of the `gpsd` PRN.
+fn nmea_prn(prn: i16) -> Option<i16> {
+    match prn {
+        1..=32 | 65..=96 => Some(prn),
+        120..=151 => Some(prn - 87),
+        _ => None,
+    }
+}
+
+/// Renders reports as NMEA 0183 sentences.
+///
+/// GGA and GSA need the satellites and DOPs of SKY reports, so the
+/// encoder keeps the latest SKY report passed to [`NmeaEncoder::encode`].
+#[derive(Debug, Clone)]
+pub struct NmeaEncoder {
+    talker: String,
+    sky: Option<Sky>,
+}
+
+impl Default for NmeaEncoder {
+    fn default() -> Self {
+        NmeaEncoder {
+            talker: "GP".into(),
+            sky: None,
+        }
+    }
+}
+
+impl NmeaEncoder {
+    /// Creates an encoder with the talker ID `GP`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the talker ID, e.g. `GN` for multi-constellation fixes.
+    pub fn with_talker(mut self, talker: &str) -> Self {
+        self.talker = talker.into();
+        self
+    }
+
+    /// Sentences of `msg`: RMC, GGA and GSA for TPV, GSV for SKY and
+    /// GST for GST reports. Nothing is returned for other classes and
+    /// TPV reports without time.
+    pub fn encode(&mut self, msg: &UnifiedResponse) -> Vec<String> {
+        match msg {
+            UnifiedResponse::Tpv(tpv) => {
+                let sky = self.sky.as_ref();
+                [self.rmc(tpv), self.gga(tpv, sky), self.gsa(tpv, sky)]
+                    .into_iter()
+                    .flatten()
+                    .collect()
+            }
+            UnifiedResponse::Sky(sky) => {
+                let sentences = self.gsv(sky);
+                self.sky = Some((**sky).clone());
+                sentences
+            }
+            UnifiedResponse::Gst(gst) => self.gst(gst).into_iter().collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// RMC sentence, recommended minimum data, of `tpv` with time.
+    pub fn rmc(&self, tpv: &Tpv) -> Option<String> {
+        let time = tpv.time?;
+        let fix = has_fix(tpv);
+        let mut body = format!(
+            "{}RMC,{},{},",
+            self.talker,
+            utc(&time),
+            if fix { 'A' } else { 'V' }
+        );
+        push_position(&mut body, tpv.lat.zip(tpv.lon).filter(|_| fix));
+        let knots = tpv
+            .speed
+            .filter(|_| fix)
+            .map(|v| f64::from(v) * 3600.0 / 1852.0);
+        push_opt(&mut body, knots, 1);
+        push_opt(&mut body, tpv.track.filter(|_| fix).map(f64::from), 1);
+        let _ = write!(
+            body,
+            "{:02}{:02}{:02},,,{}",
+            time.day(),
+            time.month(),
+            time.year() % 100,
+            mode_indicator(tpv)
+        );
+        Some(sentence(&body))
+    }
+
+    /// GGA sentence, fix data, of `tpv` with time, with the number of
+    /// satellites used and the HDOP of `sky`.
+    pub fn gga(&self, tpv: &Tpv, sky: Option<&Sky>) -> Option<String> {
+        let time = tpv.time?;
+        let fix = has_fix(tpv);
+        let mut body = format!("{}GGA,{},", self.talker, utc(&time));
+        push_position(&mut body, tpv.lat.zip(tpv.lon).filter(|_| fix));
+        let quality = match (fix, tpv.status) {
+            (false, _) => 0,
+            (true, Some(Status::Dgps)) => 2,
+            (true, Some(Status::RtkFixed)) => 4,
+            (true, Some(Status::RtkFloat)) => 5,
+            (true, Some(Status::DeadReckoning)) => 6,
+            (true, Some(Status::Simulated)) => 8,
+            (true, _) => 1,
+        };
+        let _ = write!(body, "{},", quality);
+        let used = sky
+            .and_then(|s| s.satellites.as_deref())
+            .map(|sats| sats.iter().filter(|s| s.used).count());
+        if let Some(used) = used {
+            let _ = write!(body, "{:02}", used);
+        }
+        body.push(',');
+        push_opt(&mut body, sky.and_then(|s| s.hdop).map(f64::from), 1);
+        let alt = tpv.altitude_msl().filter(|_| tpv.mode == Mode::Fix3d);
+        push_opt(&mut body, alt, 1);
+        body.push_str("M,");
+        push_opt(&mut body, tpv.geoid_sep.filter(|_| fix).map(f64::from), 1);
+        body.push_str("M,,");
+        Some(sentence(&body))
+    }
+
+    /// GSA sentence, DOP and active satellites, of the mode of `tpv`
+    /// and the satellites used in `sky`.
+    pub fn gsa(&self, tpv: &Tpv, sky: Option<&Sky>) -> Option<String> {
+        let sky = sky?;
+        let mode = match tpv.mode {
+            Mode::Fix2d => 2,
+            Mode::Fix3d => 3,
+            _ => 1,
+        };
+        let mut body = format!("{}GSA,A,{},", self.talker, mode);
+        let used = sky
+            .satellites
+            .as_deref()
+            .unwrap_or_default()
+            .iter()
+            .filter(|s| s.used)
+            .filter_map(|s| nmea_prn(s.prn));
+        let mut prns: Vec<_> = used.take(12).map(|prn| format!("{:02}", prn)).collect();
+        prns.resize(12, String::new());
+        for prn in prns {
+            let _ = write!(body, "{},", prn);
+        }
+        push_opt(&mut body, sky.pdop.map(f64::from), 1);
+        push_opt(&mut body, sky.hdop.map(f64::from), 1);
+        if let Some(vdop) = sky.vdop {
+            let _ = write!(body, "{:.1}", vdop);
+        }
+        Some(sentence(&body))
+    }
+
+    /// GSV sentences, satellites in view, of `sky`, four satellites per
+    /// sentence.
+    pub fn gsv(&self, sky: &Sky) -> Vec<String> {
+        let sats: Vec<_> = sky
+            .satellites
+            .as_deref()
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|s| Some((nmea_prn(s.prn)?, s)))
+            .collect();
+        let total = sats.len().div_ceil(4);
+        sats.chunks(4)
+            .enumerate()
+            .map(|(i, chunk)| {
+                let mut body = format!("{}GSV,{},{},{:02}", self.talker, total, i + 1, sats.len());
+                for (prn, sat) in chunk {
+                    let _ = write!(body, ",{:02},", prn);
+                    if let Some(el) = sat.el {
+                        let _ = write!(body, "{:02.0}", el);
+                    }
+                    body.push(',');
+                    if let Some(az) = sat.az {
+                        let _ = write!(body, "{:03.0}", az);
+                    }
+                    body.push(',');
+                    if let Some(ss) = sat.ss {
+                        let _ = write!(body, "{:02.0}", ss);
+                    }
+                }
+                sentence(&body)
+            })
+            .collect()
+    }
+
+    /// GST sentence, pseudorange error statistics, of `gst` with time.
+    pub fn gst(&self, gst: &Gst) -> Option<String> {
+        let time = gst.time?;
+        let mut body = format!("{}GST,{},", self.talker, utc(&time));
+        for v in [gst.rms, gst.major, gst.minor, gst.orient, gst.lat, gst.lon] {
+            push_opt(&mut body, v.map(f64::from), 1);
+        }
+        if let Some(alt) = gst.alt {
+            let _ = write!(body, "{:.1}", alt);
+        }
+        Some(sentence(&body))
+    }
+}
+
+fn has_fix(tpv: &Tpv) -> bool {
+    matches!(tpv.mode, Mode::Fix2d | Mode::Fix3d) && tpv.lat.is_some() && tpv.lon.is_some()
+}
+
+/// FAA mode indicator of RMC.
+fn mode_indicator(tpv: &Tpv) -> char {
+    if !has_fix(tpv) {
+        return 'N';
+    }
+    match tpv.status {
+        Some(Status::Dgps) => 'D',
+        Some(Status::RtkFixed) => 'R',
+        Some(Status::RtkFloat) => 'F',
+        Some(Status::DeadReckoning) => 'E',
+        Some(Status::Simulated) => 'S',
+        _ => 'A',
+    }
+}
+
+/// Time of day as `hhmmss.ss`.
+fn utc(time: &Iso8601Timestamp) -> String {
+    format!(
+        "{:02}{:02}{:02}.{:02}",
+        time.hour(),
+        time.minute(),
+        time.second(),
+        time.nanosecond() / 10_000_000
+    )
+}
+
+/// Appends `value` with `decimals` and a comma, or just the comma.
+fn push_opt(body: &mut String, value: Option<f64>, decimals: usize) {
+    if let Some(v) = value {
+        let _ = write!(body, "{:.*}", decimals, v);
+    }
+    body.push(',');
+}
+
+/// Appends latitude and longitude as degrees and minutes with
+/// hemisphere, four fields in total.
+fn push_position(body: &mut String, position: Option<(f64, f64)>) {
+    let Some((lat, lon)) = position else {
+        body.push_str(",,,,");
+        return;
+    };
+    for (value, width, hemispheres) in [(lat, 2, ['N', 'S']), (lon, 3, ['E', 'W'])] {
+        // In ten thousandths of a minute, rounded before splitting so
+        // the minutes never round up to 60.
+        let t = (value.abs() * 600_000.0).round() as u64;
+        let _ = write!(
+            body,
+            "{:0width$}{:02}.{:04},{},",
+            t / 600_000,
+            t % 600_000 / 10_000,
+            t % 10_000,
+            hemispheres[usize::from(value < 0.0)],
+            width = width
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{checksum, NmeaEncoder};
+    use crate::UnifiedResponse;
+
+    #[test]
+    fn encodes_sentences() {
+        assert_eq!(
+            checksum("GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,"),
+            0x47
+        );
+
+        let msg = |json: &str| serde_json::from_str::<UnifiedResponse>(json).unwrap();
+        let mut nmea = NmeaEncoder::new();
+        let gsv = nmea.encode(&msg(
+            r#"{"class":"SKY","hdop":0.9,"pdop":1.6,"vdop":1.3,"satellites":[
+                {"PRN":3,"el":45,"az":90,"ss":41,"used":true},
+                {"PRN":5,"el":10,"az":270,"ss":30,"used":false},
+                {"PRN":7,"el":60,"az":180,"ss":45,"used":true},
+                {"PRN":12,"el":20,"az":45,"used":true},
+                {"PRN":133,"el":30,"az":200,"ss":38,"used":true},
+                {"PRN":301,"el":50,"az":10,"ss":40,"used":true}
+            ]}"#,
+        ));
+        assert_eq!(gsv.len(), 2);
+        assert!(
+            gsv[0].starts_with("$GPGSV,2,1,05,03,45,090,41,05,10,270,30,07,60,180,45,12,20,045,*")
+        );
+        assert!(gsv[1].starts_with("$GPGSV,2,2,05,46,30,200,38*"));
+
+        let out = nmea.encode(&msg(
+            r#"{"class":"TPV","mode":3,"status":2,"time":"1994-03-23T12:35:19.500Z","lat":48.1173,"lon":-11.516666667,"altMSL":545.4,"geoidSep":46.9,"speed":11.523,"track":84.4}"#,
+        ));
+        assert_eq!(out.len(), 3);
+        assert!(
+            out[0].starts_with("$GPRMC,123519.50,A,4807.0380,N,01131.0000,W,22.4,84.4,230394,,,D*")
+        );
+        assert!(out[1]
+            .starts_with("$GPGGA,123519.50,4807.0380,N,01131.0000,W,2,05,0.9,545.4,M,46.9,M,,*"));
+        assert!(out[2].starts_with("$GPGSA,A,3,03,07,12,46,,,,,,,,,1.6,0.9,1.3*"));
+        for s in &out {
+            let (body, sum) = s[1..].trim_end().split_once('*').unwrap();
+            assert_eq!(format!("{:02X}", checksum(body)), sum);
+        }
+
+        let no_fix = nmea.encode(&msg(
+            r#"{"class":"TPV","mode":1,"time":"2024-05-01T00:00:00.000Z"}"#,
+        ));
+        assert!(no_fix[0].starts_with("$GPRMC,000000.00,V,,,,,,,010524,,,N*"));
+
+        let gst = nmea.encode(&msg(
+            r#"{"class":"GST","time":"2024-05-01T00:00:01.000Z","rms":1.5,"major":2.0,"minor":1.0,"orient":30.0,"lat":1.8,"lon":1.2,"alt":3.1}"#,
+        ));
+        assert!(gst[0].starts_with("$GPGST,000001.00,1.5,2.0,1.0,30.0,1.8,1.2,3.1*"));
+        assert!(nmea
+            .encode(&msg(r#"{"class":"ERROR","message":"x"}"#))
+            .is_empty());
+    }
+}
@@ -0,0 +1,155 @@
+//! NTP shared memory segments, as read by the SHM refclock driver of
+//! ntpd and chrony.
+
+use crate::{Pps, Toff};
+use std::io;
+use std::os::raw::{c_int, c_uint};
+use std::ptr::{addr_of_mut, write_volatile};
+use std::sync::atomic::{fence, Ordering};
+
+/// Key of the segment of unit 0, `NTP0` in ASCII.
+const SHM_KEY_BASE: libc::key_t = 0x4e54_5030;
+
+/// `struct shmTime` of ntpd's `refclock_shm.c`.
+#[repr(C)]
+struct ShmTime {
+    mode: c_int,
+    count: c_int,
+    clock_sec: libc::time_t,
+    clock_usec: c_int,
+    receive_sec: libc::time_t,
+    receive_usec: c_int,
+    leap: c_int,
+    precision: c_int,
+    nsamples: c_int,
+    valid: c_int,
+    clock_nsec: c_uint,
+    receive_nsec: c_uint,
+    dummy: [c_int; 8],
+}
+
+/// Writer of time samples into an NTP shared memory segment, the way
+/// `gpsd` does it.
+///
+/// Units 0 and 1 are only accessible by root, as ntpd expects for them;
+/// units 2 and above are world writable. Configure ntpd with e.g.
+/// `server 127.127.28.0` for unit 0.
+#[derive(Debug)]
+pub struct NtpShm {
+    shm: *mut ShmTime,
+}
+
+// The segment is only written through `&mut self`.
+unsafe impl Send for NtpShm {}
+
+impl NtpShm {
+    /// Attaches to the segment of `unit`, creating it if needed.
+    pub fn attach(unit: u8) -> io::Result<Self> {
+        let perms = if unit < 2 { 0o600 } else { 0o666 };
+        let key = SHM_KEY_BASE + libc::key_t::from(unit);
+        // SAFETY: plain system calls; the segment is at least as large
+        // as `ShmTime`, otherwise `shmget` fails.
+        unsafe {
+            let id = libc::shmget(key, std::mem::size_of::<ShmTime>(), libc::IPC_CREAT | perms);
+            if id == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            let shm = libc::shmat(id, std::ptr::null(), 0);
+            if shm as isize == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(NtpShm {
+                shm: shm as *mut ShmTime,
+            })
+        }
+    }
+
+    /// Writes a sample: the GPS time `real` and the system time `clock`
+    /// as seconds and nanoseconds since the Unix epoch. `precision` is
+    /// the log2 of the precision in seconds, e.g. -20 for PPS and -1
+    /// for serial time.
+    pub fn put(&mut self, real: (i64, i64), clock: (i64, i64), precision: i32) {
+        let shm = self.shm;
+        // SAFETY: `shm` points to the attached segment until dropped.
+        // Readers in other processes synchronize with `count` and
+        // `valid`, hence every access is volatile.
+        unsafe {
+            write_volatile(addr_of_mut!((*shm).valid), 0);
+            let count = std::ptr::read_volatile(addr_of_mut!((*shm).count));
+            write_volatile(addr_of_mut!((*shm).count), count.wrapping_add(1));
+            fence(Ordering::SeqCst);
+            write_volatile(addr_of_mut!((*shm).mode), 1);
+            write_volatile(addr_of_mut!((*shm).clock_sec), real.0 as libc::time_t);
+            write_volatile(addr_of_mut!((*shm).clock_usec), (real.1 / 1000) as c_int);
+            write_volatile(addr_of_mut!((*shm).clock_nsec), real.1 as c_uint);
+            write_volatile(addr_of_mut!((*shm).receive_sec), clock.0 as libc::time_t);
+            write_volatile(addr_of_mut!((*shm).receive_usec), (clock.1 / 1000) as c_int);
+            write_volatile(addr_of_mut!((*shm).receive_nsec), clock.1 as c_uint);
+            write_volatile(addr_of_mut!((*shm).leap), 0);
+            write_volatile(addr_of_mut!((*shm).precision), precision);
+            write_volatile(addr_of_mut!((*shm).nsamples), 3);
+            fence(Ordering::SeqCst);
+            write_volatile(addr_of_mut!((*shm).count), count.wrapping_add(2));
+            write_volatile(addr_of_mut!((*shm).valid), 1);
+        }
+    }
+
+    /// Writes the sample of a PPS report with its precision.
+    pub fn put_pps(&mut self, pps: &Pps) {
+        self.put(
+            (pps.real_sec, pps.real_nsec),
+            (pps.clock_sec, pps.clock_nsec),
+            pps.precision as i32,
+        );
+    }
+
+    /// Writes the sample of a TOFF report.
+    pub fn put_toff(&mut self, toff: &Toff) {
+        self.put(
+            (toff.real_sec, toff.real_nsec),
+            (toff.clock_sec, toff.clock_nsec),
+            -1,
+        );
+    }
+}
+
+impl Drop for NtpShm {
+    fn drop(&mut self) {
+        // SAFETY: `shm` has been returned by `shmat`. The segment itself
+        // is kept for the NTP daemon.
+        unsafe {
+            libc::shmdt(self.shm as *const libc::c_void);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NtpShm;
+    use crate::Toff;
+
+    // Shared memory may be unavailable in sandboxes, run with
+    // `cargo test -- --ignored`.
+    #[test]
+    #[ignore = "needs System V shared memory"]
+    fn writes_sample() {
+        let mut shm = NtpShm::attach(97).unwrap();
+        let toff: Toff = serde_json::from_str(
+            r#"{"device":"/dev/ttyS0","real_sec":1700000000,"real_nsec":500,"clock_sec":1700000001,"clock_nsec":250000000}"#,
+        )
+        .unwrap();
+        let count = unsafe { (*shm.shm).count };
+        shm.put_toff(&toff);
+        let t = unsafe { &*shm.shm };
+        assert_eq!((t.mode, t.valid, t.count), (1, 1, count.wrapping_add(2)));
+        assert_eq!(
+            (t.clock_sec, t.clock_usec, t.clock_nsec),
+            (1_700_000_000, 0, 500)
+        );
+        assert_eq!(
+            (t.receive_sec, t.receive_usec, t.receive_nsec),
+            (1_700_000_001, 250_000, 250_000_000)
+        );
+        assert_eq!(t.precision, -1);
+    }
+}
@@ -0,0 +1,421 @@
+//! `gpsd` sessions reassembled from packet captures.
+
+use crate::{parse_lines, GpsdError, UnifiedResponse};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Default TCP port of `gpsd`.
+pub const GPSD_PORT: u16 = 2947;
+
+/// Largest captured packet accepted, the largest snapshot length of
+/// `tcpdump`. Larger lengths come from corrupt captures.
+const MAX_CAPLEN: usize = 256 * 1024;
+
+/// Number of out of order segments kept waiting for a missing segment,
+/// beyond which the segment is taken as lost.
+const REORDER_WINDOW: usize = 64;
+
+/// Data sent by `gpsd` on one TCP connection of a capture.
+#[derive(Debug, Clone)]
+pub struct PcapStream {
+    /// Address of `gpsd`.
+    pub server: SocketAddr,
+    /// Address of the client.
+    pub client: SocketAddr,
+    /// Reassembled payload, in sequence order.
+    pub data: Vec<u8>,
+    /// Capture time of each segment, with the offset of its end in
+    /// `data`.
+    segments: Vec<(usize, SystemTime)>,
+}
+
+impl PcapStream {
+    /// Parses the messages of the stream, see [`parse_lines`].
+    pub fn messages(&self) -> impl Iterator<Item = Result<UnifiedResponse, GpsdError>> + '_ {
+        parse_lines(&self.data)
+    }
+
+    /// Parses the messages of the stream together with the capture
+    /// time of the segment completing them.
+    pub fn timestamped_messages(
+        &self,
+    ) -> impl Iterator<Item = (SystemTime, Result<UnifiedResponse, GpsdError>)> + '_ {
+        let mut start = 0;
+        self.data
+            .split_inclusive(|&b| b == b'\n')
+            .filter_map(move |line| {
+                start += line.len();
+                let end = start;
+                let msg = line.trim_ascii();
+                if msg.is_empty() {
+                    return None;
+                }
+                let i = self.segments.partition_point(|&(seg_end, _)| seg_end < end);
+                let time = self.segments.get(i).or(self.segments.last()).map(|s| s.1);
+                let msg = UnifiedResponse::from_slice(msg).map_err(GpsdError::from);
+                Some((time.unwrap_or(UNIX_EPOCH), msg))
+            })
+    }
+}
+
+#[derive(Default)]
+struct Flow {
+    next_seq: Option<u32>,
+    pending: BTreeMap<u32, (Vec<u8>, SystemTime)>,
+    data: Vec<u8>,
+    segments: Vec<(usize, SystemTime)>,
+}
+
+impl Flow {
+    fn add(&mut self, seq: u32, syn: bool, payload: &[u8], time: SystemTime) {
+        let next = *self
+            .next_seq
+            .get_or_insert(seq.wrapping_add(u32::from(syn)));
+        if payload.is_empty() {
+            return;
+        }
+        let seq = seq.wrapping_add(u32::from(syn));
+        let ahead = seq.wrapping_sub(next) as i32;
+        if ahead > 0 {
+            // Out of order, kept until the gap is filled.
+            self.pending.insert(seq, (payload.to_vec(), time));
+            if self.pending.len() > REORDER_WINDOW {
+                self.skip_gap();
+            }
+            return;
+        }
+        self.append(ahead.unsigned_abs() as usize, payload, time);
+        self.drain(time);
+    }
+
+    /// Appends the pending segments following the data received, as
+    /// completed at `time` at the earliest.
+    fn drain(&mut self, time: SystemTime) {
+        while let Some(entry) = self.pending.first_entry() {
+            let ahead = entry.key().wrapping_sub(self.next_seq.unwrap_or(0)) as i32;
+            if ahead > 0 {
+                break;
+            }
+            let (payload, received) = entry.remove();
+            self.append(ahead.unsigned_abs() as usize, &payload, time.max(received));
+        }
+    }
+
+    /// Gives up on the data missing before the first pending segment.
+    fn skip_gap(&mut self) {
+        if let Some((&seq, &(_, time))) = self.pending.first_key_value() {
+            self.next_seq = Some(seq);
+            self.drain(time);
+        }
+    }
+
+    /// The reassembled stream, skipping the gaps left.
+    fn finish(mut self, server: SocketAddr, client: SocketAddr) -> PcapStream {
+        while !self.pending.is_empty() {
+            self.skip_gap();
+        }
+        PcapStream {
+            server,
+            client,
+            data: self.data,
+            segments: self.segments,
+        }
+    }
+
+    /// Appends `payload` without its first `skip` bytes, which have
+    /// been received already.
+    fn append(&mut self, skip: usize, payload: &[u8], time: SystemTime) {
+        if skip >= payload.len() {
+            return;
+        }
+        self.data.extend_from_slice(&payload[skip..]);
+        self.segments.push((self.data.len(), time));
+        let next = self.next_seq.unwrap_or(0);
+        self.next_seq = Some(next.wrapping_add((payload.len() - skip) as u32));
+    }
+}
+
+fn invalid(msg: &str) -> GpsdError {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_owned()).into()
+}
+
+/// Reads the classic libpcap capture at `path` and reassembles the
+/// data sent by `gpsd` on `port`, see [`read_pcap`].
+pub fn open_pcap<P: AsRef<Path>>(path: P, port: u16) -> Result<Vec<PcapStream>, GpsdError> {
+    read_pcap(BufReader::new(File::open(path)?), port)
+}
+
+/// Reads a classic libpcap capture, e.g. of `tcpdump -w`, and
+/// reassembles the data sent from TCP `port`, usually [`GPSD_PORT`],
+/// per connection.
+///
+/// Ethernet, Linux cooked, loopback and raw IP captures with IPv4 or
+/// IPv6 are supported, pcapng is not. Retransmitted and reordered
+/// segments are handled. Data of segments missing in the capture is
+/// skipped at the end of the connection or once more than 64 later
+/// segments are waiting, so that only the message spanning the gap
+/// fails to parse. Records of packets longer than 256 KiB are rejected
+/// as corrupt.
+pub fn read_pcap<R: Read>(mut reader: R, port: u16) -> Result<Vec<PcapStream>, GpsdError> {
+    let mut header = [0; 24];
+    reader.read_exact(&mut header)?;
+    let magic = u32::from_le_bytes(header[..4].try_into().unwrap());
+    let (little_endian, nanos) = match magic {
+        0xa1b2_c3d4 => (true, false),
+        0xa1b2_3c4d => (true, true),
+        0xd4c3_b2a1 => (false, false),
+        0x4d3c_b2a1 => (false, true),
+        0x0a0d_0d0a => return Err(invalid("pcapng captures are not supported")),
+        _ => return Err(invalid("not a pcap capture")),
+    };
+    let u32_at = |b: &[u8], at: usize| {
+        let bytes = b[at..at + 4].try_into().unwrap();
+        if little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        }
+    };
+    let linktype = u32_at(&header, 20) & 0xffff;
+
+    let mut flows: BTreeMap<(SocketAddr, SocketAddr), Flow> = BTreeMap::new();
+    let mut record = [0; 16];
+    let mut packet = Vec::new();
+    loop {
+        match reader.read_exact(&mut record) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e.into()),
+        }
+        let frac = u64::from(u32_at(&record, 4));
+        let time = UNIX_EPOCH
+            + Duration::from_secs(u64::from(u32_at(&record, 0)))
+            + Duration::from_nanos(if nanos { frac } else { frac * 1000 });
+        let caplen = u32_at(&record, 8) as usize;
+        if caplen > MAX_CAPLEN {
+            return Err(invalid("packet record exceeds the maximum capture length"));
+        }
+        packet.resize(caplen, 0);
+        match reader.read_exact(&mut packet) {
+            Ok(()) => {}
+            // Truncated at the end, e.g. by an interrupted capture.
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e.into()),
+        }
+        let Some(ip) = link_payload(linktype, &packet) else {
+            continue;
+        };
+        let Some((src, dst, tcp)) = ip_tcp(ip) else {
+            continue;
+        };
+        if tcp.len() < 20 {
+            continue;
+        }
+        let src_port = u16::from_be_bytes([tcp[0], tcp[1]]);
+        let dst_port = u16::from_be_bytes([tcp[2], tcp[3]]);
+        if src_port != port {
+            continue;
+        }
+        let seq = u32::from_be_bytes(tcp[4..8].try_into().unwrap());
+        let offset = usize::from(tcp[12] >> 4) * 4;
+        let syn = tcp[13] & 0x02 != 0;
+        let Some(payload) = tcp.get(offset..) else {
+            continue;
+        };
+        let key = (
+            SocketAddr::new(src, src_port),
+            SocketAddr::new(dst, dst_port),
+        );
+        flows.entry(key).or_default().add(seq, syn, payload, time);
+    }
+
+    Ok(flows
+        .into_iter()
+        .map(|((server, client), flow)| flow.finish(server, client))
+        .collect())
+}
+
+/// The IP packet of a link layer frame.
+fn link_payload(linktype: u32, frame: &[u8]) -> Option<&[u8]> {
+    match linktype {
+        // Ethernet, skipping VLAN tags.
+        1 => {
+            let mut at = 12;
+            while frame.get(at..at + 2)? == [0x81, 0x00] {
+                at += 4;
+            }
+            frame.get(at + 2..)
+        }
+        // BSD loopback, raw IP.
+        0 => frame.get(4..),
+        12 | 101 | 228 | 229 => Some(frame),
+        // Linux cooked captures v1 and v2.
+        113 => frame.get(16..),
+        276 => frame.get(20..),
+        _ => None,
+    }
+}
+
+/// Source, destination and TCP segment of an IP packet.
+fn ip_tcp(ip: &[u8]) -> Option<(IpAddr, IpAddr, &[u8])> {
+    match ip.first()? >> 4 {
+        4 => {
+            let ihl = usize::from(ip[0] & 0x0f) * 4;
+            let total = usize::from(u16::from_be_bytes(ip.get(2..4)?.try_into().ok()?));
+            let fragment = u16::from_be_bytes(ip.get(6..8)?.try_into().ok()?);
+            // Fragments are rare on TCP and not reassembled.
+            if ip.get(9)? != &6 || fragment & 0x3fff != 0 {
+                return None;
+            }
+            let src: [u8; 4] = ip.get(12..16)?.try_into().ok()?;
+            let dst: [u8; 4] = ip.get(16..20)?.try_into().ok()?;
+            // The length is 0 in captures of segmentation offloading.
+            let end = if total == 0 {
+                ip.len()
+            } else {
+                total.min(ip.len())
+            };
+            let tcp = ip.get(ihl..end)?;
+            Some((Ipv4Addr::from(src).into(), Ipv4Addr::from(dst).into(), tcp))
+        }
+        6 => {
+            let len = usize::from(u16::from_be_bytes(ip.get(4..6)?.try_into().ok()?));
+            if ip.get(6)? != &6 {
+                return None;
+            }
+            let src: [u8; 16] = ip.get(8..24)?.try_into().ok()?;
+            let dst: [u8; 16] = ip.get(24..40)?.try_into().ok()?;
+            let tcp = ip.get(40..(40 + len).min(ip.len()))?;
+            Some((Ipv6Addr::from(src).into(), Ipv6Addr::from(dst).into(), tcp))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_pcap, GPSD_PORT};
+    use crate::UnifiedResponse;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    /// Capture of version 2.4 with Ethernet link type of `packets`, one
+    /// per second.
+    fn capture(packets: &[Vec<u8>]) -> Vec<u8> {
+        let mut pcap = 0xa1b2_c3d4u32.to_le_bytes().to_vec();
+        pcap.extend_from_slice(&[2, 0, 4, 0]);
+        for v in [0u32, 0, 65535, 1] {
+            pcap.extend_from_slice(&v.to_le_bytes());
+        }
+        for (i, p) in packets.iter().enumerate() {
+            for v in [1_700_000_000 + i as u32, 0, p.len() as u32, p.len() as u32] {
+                pcap.extend_from_slice(&v.to_le_bytes());
+            }
+            pcap.extend_from_slice(p);
+        }
+        pcap
+    }
+
+    /// Ethernet frame of an IPv4 TCP segment from gpsd to the client.
+    fn frame(seq: u32, flags: u8, payload: &[u8]) -> Vec<u8> {
+        let mut f = vec![0; 12];
+        f.extend_from_slice(&[0x08, 0x00]);
+        let total = (20 + 20 + payload.len()) as u16;
+        f.extend_from_slice(&[0x45, 0, 0, 0, 0, 0, 0x40, 0, 64, 6, 0, 0]);
+        f[16..18].copy_from_slice(&total.to_be_bytes());
+        f.extend_from_slice(&[192, 168, 1, 10, 192, 168, 1, 20]);
+        f.extend_from_slice(&GPSD_PORT.to_be_bytes());
+        f.extend_from_slice(&40000u16.to_be_bytes());
+        f.extend_from_slice(&seq.to_be_bytes());
+        f.extend_from_slice(&[0, 0, 0, 0, 0x50, flags, 0xff, 0xff, 0, 0, 0, 0]);
+        f.extend_from_slice(payload);
+        f
+    }
+
+    #[test]
+    fn reassembles_session() {
+        let tpv = b"{\"class\":\"TPV\",\"mode\":3}\n";
+        let sky = b"{\"class\":\"SKY\"}\n";
+        let packets = [
+            frame(999, 0x12, b""),
+            frame(1000, 0x18, &tpv[..10]),
+            // Out of order.
+            frame(1000 + tpv.len() as u32, 0x18, sky),
+            frame(1010, 0x18, &tpv[10..]),
+            // Retransmission.
+            frame(1000, 0x18, &tpv[..10]),
+            // Half the sequence space behind.
+            frame(
+                (1000 + (tpv.len() + sky.len()) as u32).wrapping_add(1 << 31),
+                0x18,
+                b"x",
+            ),
+        ];
+        let pcap = capture(&packets);
+
+        let streams = read_pcap(&pcap[..], GPSD_PORT).unwrap();
+        assert_eq!(streams.len(), 1);
+        assert_eq!(streams[0].server.port(), GPSD_PORT);
+        assert_eq!(streams[0].client.to_string(), "192.168.1.20:40000");
+        let msgs: Vec<_> = streams[0].timestamped_messages().collect();
+        assert_eq!(msgs.len(), 2);
+        assert!(matches!(msgs[0].1, Ok(UnifiedResponse::Tpv(_))));
+        assert!(matches!(msgs[1].1, Ok(UnifiedResponse::Sky(_))));
+        // Both are complete with the fourth packet.
+        let fourth = UNIX_EPOCH + Duration::from_secs(1_700_000_003);
+        assert_eq!((msgs[0].0, msgs[1].0), (fourth, fourth));
+        assert_eq!(streams[0].messages().count(), 2);
+
+        let mut pcapng = vec![0x0a, 0x0d, 0x0d, 0x0a];
+        pcapng.resize(24, 0);
+        assert!(read_pcap(&pcapng[..], GPSD_PORT).is_err());
+
+        // Corrupt record claiming a packet of 4 GiB.
+        let mut corrupt = pcap[..24].to_vec();
+        for v in [1_700_000_000, 0, u32::MAX, u32::MAX] {
+            corrupt.extend_from_slice(&v.to_le_bytes());
+        }
+        corrupt.push(0);
+        assert!(read_pcap(&corrupt[..], GPSD_PORT).is_err());
+    }
+
+    #[test]
+    fn skips_missing_segments() {
+        let tpv = b"{\"class\":\"TPV\",\"mode\":3}\n";
+        let sky = b"{\"class\":\"SKY\"}\n";
+        let mut data = tpv.to_vec();
+        data.extend_from_slice(sky);
+        data.extend_from_slice(tpv);
+        data.extend_from_slice(sky);
+        // Segments of 8 bytes, the third one lost.
+        let mut packets = vec![frame(999, 0x12, b"")];
+        for (i, chunk) in data.chunks(8).enumerate() {
+            if i != 2 {
+                packets.push(frame(1000 + 8 * i as u32, 0x18, chunk));
+            }
+        }
+        let streams = read_pcap(&capture(&packets)[..], GPSD_PORT).unwrap();
+        let msgs: Vec<_> = streams[0].timestamped_messages().collect();
+        assert_eq!(msgs.len(), 4);
+        assert!(msgs[0].1.is_err());
+        assert!(matches!(msgs[1].1, Ok(UnifiedResponse::Sky(_))));
+        assert!(matches!(msgs[2].1, Ok(UnifiedResponse::Tpv(_))));
+        assert!(matches!(msgs[3].1, Ok(UnifiedResponse::Sky(_))));
+        // Completed with the ninth packet, not when the gap is skipped.
+        let ninth = UNIX_EPOCH + Duration::from_secs(1_700_000_008);
+        assert_eq!(msgs[2].0, ninth);
+
+        // Lost beyond the reorder window.
+        let mut packets = vec![frame(999, 0x12, b"")];
+        for i in 0..100u32 {
+            if i != 1 {
+                packets.push(frame(1000 + i * sky.len() as u32, 0x18, sky));
+            }
+        }
+        let streams = read_pcap(&capture(&packets)[..], GPSD_PORT).unwrap();
+        assert_eq!(streams[0].messages().filter(Result::is_ok).count(), 99);
+    }
+}
@@ -0,0 +1,338 @@
+//! Quality gate of the fixes delivered to logging pipelines.
+
+use crate::export::horizontal_error;
+use crate::{Mode, Sky, Tpv, UnifiedResponse};
+use std::collections::{BTreeMap, VecDeque};
+
+/// Criterion of a [`QualityGate`] not met by a fix.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum QualityFailure {
+    /// The mode is below the minimum mode.
+    Mode,
+    /// The horizontal error estimate, `eph` or the larger of `epx` and
+    /// `epy`, is missing or too large.
+    Eph,
+    /// Too few satellites are used, or no SKY report has been seen.
+    Satellites,
+    /// The DOP is missing or too large.
+    Dop,
+}
+
+/// Change of the quality of the fixes of a device, see
+/// [`QualityGate::events`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum QualityEvent {
+    /// The fixes of the device meet the criteria, for the first time
+    /// or after not having met them.
+    Entered {
+        /// Path of the device, `""` for reports without device.
+        device: String,
+    },
+    /// The fixes of the device don't meet the criteria, for the first
+    /// fix or after having met them.
+    Left {
+        /// Path of the device, `""` for reports without device.
+        device: String,
+        /// First criterion not met.
+        failure: QualityFailure,
+    },
+}
+
+#[derive(Debug, Default, Clone)]
+struct DeviceQuality {
+    used: Option<usize>,
+    dop: Option<f32>,
+    acceptable: Option<bool>,
+}
+
+/// Passes only the TPV reports of fixes meeting configurable criteria,
+/// as front door of logging pipelines.
+///
+/// By default a 3D fix is required. Limits on the horizontal error
+/// estimate `eph`, or the larger of `epx` and `epy` for receivers not
+/// reporting it, the number of satellites used and the DOP can be
+/// added, the latter two are taken from the last SKY report of the
+/// device, using the PDOP or, for receivers not reporting it, the
+/// HDOP. A fix lacking a value needed by a criterion fails it.
+/// Messages of other classes are passed.
+///
+/// Whenever the fixes of a device enter or leave the acceptable band a
+/// [`QualityEvent`] is queued, see [`QualityGate::events`].
+///
+/// ```
+/// use gpsd_proto::QualityGate;
+///
+/// let gate = QualityGate::new()
+///     .with_max_eph(5.0)
+///     .with_min_satellites(6)
+///     .with_max_dop(3.0);
+/// ```
+#[derive(Debug, Clone)]
+pub struct QualityGate {
+    min_mode: Mode,
+    max_eph: Option<f32>,
+    min_satellites: Option<usize>,
+    max_dop: Option<f32>,
+    devices: BTreeMap<String, DeviceQuality>,
+    events: VecDeque<QualityEvent>,
+    dropped: u64,
+}
+
+impl Default for QualityGate {
+    fn default() -> Self {
+        QualityGate {
+            min_mode: Mode::Fix3d,
+            max_eph: None,
+            min_satellites: None,
+            max_dop: None,
+            devices: BTreeMap::new(),
+            events: VecDeque::new(),
+            dropped: 0,
+        }
+    }
+}
+
+fn rank(mode: Mode) -> u8 {
+    match mode {
+        Mode::Fix2d => 2,
+        Mode::Fix3d => 3,
+        _ => 0,
+    }
+}
+
+impl QualityGate {
+    /// Creates a gate requiring a 3D fix.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the minimum mode, e.g. [`Mode::Fix2d`] for vehicles whose
+    /// altitude isn't logged.
+    pub fn with_min_mode(mut self, mode: Mode) -> Self {
+        self.min_mode = mode;
+        self
+    }
+
+    /// Requires a horizontal error estimate below `eph` meters, see
+    /// [`QualityFailure::Eph`].
+    pub fn with_max_eph(mut self, eph: f32) -> Self {
+        self.max_eph = Some(eph);
+        self
+    }
+
+    /// Requires at least `satellites` satellites used in the solution.
+    pub fn with_min_satellites(mut self, satellites: usize) -> Self {
+        self.min_satellites = Some(satellites);
+        self
+    }
+
+    /// Requires a DOP of at most `dop`.
+    pub fn with_max_dop(mut self, dop: f32) -> Self {
+        self.max_dop = Some(dop);
+        self
+    }
+
+    /// Returns `true` if `msg` is to be delivered.
+    pub fn accept(&mut self, msg: &UnifiedResponse) -> bool {
+        match msg {
+            UnifiedResponse::Tpv(tpv) => self.accept_tpv(tpv),
+            UnifiedResponse::Sky(sky) => {
+                self.update_sky(sky);
+                true
+            }
+            _ => true,
+        }
+    }
+
+    /// Adds the satellites used and the DOP of `sky` for the criteria
+    /// of the following fixes of the device.
+    pub fn update_sky(&mut self, sky: &Sky) {
+        let device = sky.device.as_deref().unwrap_or_default();
+        let quality = self.devices.entry(device.to_owned()).or_default();
+        if let Some(satellites) = &sky.satellites {
+            quality.used = Some(satellites.iter().filter(|s| s.used).count());
+        }
+        if let Some(dop) = sky.pdop.or(sky.hdop) {
+            quality.dop = Some(dop);
+        }
+    }
+
+    /// Returns `true` if `tpv` is to be delivered.
+    pub fn accept_tpv(&mut self, tpv: &Tpv) -> bool {
+        let device = tpv.device.as_deref().unwrap_or_default();
+        let quality = self.devices.entry(device.to_owned()).or_default();
+        let failure = if rank(tpv.mode) == 0 || rank(tpv.mode) < rank(self.min_mode) {
+            Some(QualityFailure::Mode)
+        } else if self
+            .max_eph
+            .is_some_and(|max| horizontal_error(tpv).is_none_or(|eph| eph >= max))
+        {
+            Some(QualityFailure::Eph)
+        } else if self
+            .min_satellites
+            .is_some_and(|min| quality.used.is_none_or(|used| used < min))
+        {
+            Some(QualityFailure::Satellites)
+        } else if self
+            .max_dop
+            .is_some_and(|max| quality.dop.is_none_or(|dop| dop > max))
+        {
+            Some(QualityFailure::Dop)
+        } else {
+            None
+        };
+        let accept = failure.is_none();
+        if quality.acceptable.replace(accept) != Some(accept) {
+            let device = device.to_owned();
+            let event = match failure {
+                None => QualityEvent::Entered { device },
+                Some(failure) => QualityEvent::Left { device, failure },
+            };
+            debug!("Quality gate: {:?}", event);
+            self.events.push_back(event);
+        }
+        if !accept {
+            self.dropped += 1;
+        }
+        accept
+    }
+
+    /// Takes the events queued since the last call.
+    pub fn events(&mut self) -> impl Iterator<Item = QualityEvent> + '_ {
+        self.events.drain(..)
+    }
+
+    /// Returns `true` if the last fix of the device at `path`, `""` for
+    /// reports without device, met the criteria.
+    pub fn is_acceptable(&self, path: &str) -> bool {
+        self.devices
+            .get(path)
+            .and_then(|q| q.acceptable)
+            .unwrap_or(false)
+    }
+
+    /// Number of reports dropped so far.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Forgets the devices seen, e.g. after a reconnect. Queued events
+    /// are kept.
+    pub fn reset(&mut self) {
+        self.devices.clear();
+    }
+
+    /// Wraps a stream of parse results, passing errors through, see
+    /// [`crate::Decimator::decimate`].
+    pub fn gate<I, E>(self, iter: I) -> Gated<I>
+    where
+        I: Iterator<Item = Result<UnifiedResponse, E>>,
+    {
+        Gated { iter, gate: self }
+    }
+}
+
+/// Stream of parse results without the fixes failing a
+/// [`QualityGate`], see [`QualityGate::gate`].
+#[derive(Debug)]
+pub struct Gated<I> {
+    iter: I,
+    gate: QualityGate,
+}
+
+impl<I> Gated<I> {
+    /// The gate, e.g. to take its events.
+    pub fn gate_mut(&mut self) -> &mut QualityGate {
+        &mut self.gate
+    }
+}
+
+impl<I, E> Iterator for Gated<I>
+where
+    I: Iterator<Item = Result<UnifiedResponse, E>>,
+{
+    type Item = Result<UnifiedResponse, E>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.find(|item| match item {
+            Ok(msg) => self.gate.accept(msg),
+            Err(_) => true,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{QualityEvent, QualityFailure, QualityGate};
+    use crate::{parse_lines, Mode, UnifiedResponse};
+
+    #[test]
+    fn gates_fixes() {
+        let data = concat!(
+            "{\"class\":\"TPV\",\"device\":\"ttyS0\",\"mode\":3,\"eph\":2.0}\n",
+            "{\"class\":\"SKY\",\"device\":\"ttyS0\",\"pdop\":1.8,\"satellites\":[",
+            "{\"PRN\":1,\"used\":true},{\"PRN\":2,\"used\":true},",
+            "{\"PRN\":3,\"used\":true},{\"PRN\":4,\"used\":false}]}\n",
+            "{\"class\":\"TPV\",\"device\":\"ttyS0\",\"mode\":3,\"eph\":2.0}\n",
+            "{\"class\":\"TPV\",\"device\":\"ttyS0\",\"mode\":3,\"eph\":1.5}\n",
+            "{\"class\":broken\n",
+            "{\"class\":\"TPV\",\"device\":\"ttyS0\",\"mode\":3,\"eph\":9.0}\n",
+            "{\"class\":\"TPV\",\"device\":\"ttyS0\",\"mode\":2,\"eph\":1.0}\n",
+            "{\"class\":\"SKY\",\"device\":\"ttyS0\",\"pdop\":4.5}\n",
+            "{\"class\":\"TPV\",\"device\":\"ttyS0\",\"mode\":3,\"eph\":1.0}\n",
+        );
+        let gate = QualityGate::new()
+            .with_max_eph(5.0)
+            .with_min_satellites(3)
+            .with_max_dop(3.0);
+        let mut stream = gate.gate(parse_lines(data.as_bytes()));
+        assert!(matches!(stream.next(), Some(Ok(UnifiedResponse::Sky(_)))));
+        let events: Vec<_> = stream.gate_mut().events().collect();
+        let left = |failure| QualityEvent::Left {
+            device: "ttyS0".into(),
+            failure,
+        };
+        assert_eq!(events, [left(QualityFailure::Satellites)]);
+        assert!(matches!(stream.next(), Some(Ok(UnifiedResponse::Tpv(_)))));
+        assert!(matches!(stream.next(), Some(Ok(UnifiedResponse::Tpv(_)))));
+        assert!(stream.gate_mut().is_acceptable("ttyS0"));
+        assert!(matches!(stream.next(), Some(Err(_))));
+        assert!(matches!(stream.next(), Some(Ok(UnifiedResponse::Sky(_)))));
+        assert!(stream.next().is_none());
+        let gate = stream.gate_mut();
+        let events: Vec<_> = gate.events().collect();
+        assert_eq!(
+            events,
+            [
+                QualityEvent::Entered {
+                    device: "ttyS0".into()
+                },
+                left(QualityFailure::Eph),
+            ]
+        );
+        assert_eq!(gate.dropped(), 4);
+        assert!(!gate.is_acceptable("ttyS0"));
+
+        let mut gate = QualityGate::new().with_min_mode(Mode::Fix2d);
+        let tpv = UnifiedResponse::from_slice(br#"{"class":"TPV","mode":2}"#).unwrap();
+        let no_fix = UnifiedResponse::from_slice(br#"{"class":"TPV","mode":1}"#).unwrap();
+        assert!(gate.accept(&tpv) && !gate.accept(&no_fix));
+        let events: Vec<_> = gate.events().collect();
+        assert_eq!(
+            events,
+            [
+                QualityEvent::Entered { device: "".into() },
+                QualityEvent::Left {
+                    device: "".into(),
+                    failure: QualityFailure::Mode
+                },
+            ]
+        );
+
+        let mut gate = QualityGate::new().with_max_eph(5.0);
+        let tpv = br#"{"class":"TPV","mode":3,"epx":2.0,"epy":4.5}"#;
+        assert!(gate.accept(&UnifiedResponse::from_slice(tpv).unwrap()));
+        let tpv = br#"{"class":"TPV","mode":3,"epx":2.0,"epy":6.0}"#;
+        assert!(!gate.accept(&UnifiedResponse::from_slice(tpv).unwrap()));
+    }
+}
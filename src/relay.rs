@@ -0,0 +1,423 @@
+//! Fan-out relay re-serving the stream of one `gpsd` to many clients.
+
+use crate::{scan_class, trace_line, ClassSet, GpsdError, PROTO_MAJOR_MIN};
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+/// Bytes queued for a client before it is considered stalled and
+/// disconnected.
+const MAX_BACKLOG: usize = 64 * 1024;
+
+/// Longest command line accepted from a client.
+const MAX_COMMAND: usize = 4096;
+
+struct Client {
+    stream: TcpStream,
+    peer: SocketAddr,
+    watching: bool,
+    input: Vec<u8>,
+    output: Vec<u8>,
+}
+
+impl Client {
+    /// Queues `data` and writes as much of the queue as the socket
+    /// takes without blocking. Fails if the client is gone or stalled.
+    fn send(&mut self, data: &[u8]) -> io::Result<()> {
+        self.output.extend_from_slice(data);
+        while !self.output.is_empty() {
+            match self.stream.write(&self.output) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(n) => {
+                    self.output.drain(..n);
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        if self.output.len() > MAX_BACKLOG {
+            return Err(io::Error::other("client stalled"));
+        }
+        Ok(())
+    }
+
+    /// Reads pending input and returns the complete command lines.
+    fn commands(&mut self) -> io::Result<Vec<String>> {
+        let mut buf = [0; 512];
+        loop {
+            match self.stream.read(&mut buf) {
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(n) => self.input.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        let mut commands = Vec::new();
+        while let Some(end) = self.input.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.input.drain(..=end).collect();
+            commands.push(String::from_utf8_lossy(&line).trim().to_owned());
+        }
+        if self.input.len() > MAX_COMMAND {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "command too long",
+            ));
+        }
+        Ok(commands)
+    }
+}
+
+/// TCP server re-serving the JSON stream of a single `gpsd` to any
+/// number of clients, so they don't all connect to the daemon itself.
+///
+/// Clients talk to the relay like to `gpsd`: they get a VERSION
+/// message on connect and receive the stream after enabling it with
+/// `?WATCH`, which is answered by DEVICES and WATCH. VERSION and
+/// DEVICES are the latest ones received from `gpsd`, if any. The
+/// stream is the same for all clients, only `enable` of their WATCH
+/// command is honored. Other commands are answered by an ERROR
+/// message.
+///
+/// Lines are forwarded as received, without parsing them. The stream
+/// can be restricted to some classes with [`Relay::with_classes`] and
+/// down-sampled with [`Relay::with_min_interval`]. Clients which can't
+/// keep up are disconnected.
+///
+/// The relay doesn't spawn threads and never blocks on its clients;
+/// [`Relay::run`] serves them in between the lines read from `gpsd`.
+///
+/// ```no_run
+/// use gpsd_proto::{handshake, ClassSet, Relay};
+/// use std::io::BufReader;
+/// use std::net::TcpStream;
+///
+/// let mut stream = TcpStream::connect("127.0.0.1:2947").unwrap();
+/// let mut reader = BufReader::new(stream.try_clone().unwrap());
+/// handshake(&mut reader, &mut stream).unwrap();
+/// let mut relay = Relay::bind("0.0.0.0:2948")
+///     .unwrap()
+///     .with_classes(ClassSet::TPV | ClassSet::SKY);
+/// relay.run(&mut reader).unwrap();
+/// ```
+pub struct Relay {
+    listener: TcpListener,
+    clients: Vec<Client>,
+    classes: ClassSet,
+    min_interval: Duration,
+    last_sent: BTreeMap<String, Instant>,
+    max_clients: Option<usize>,
+    version: Option<Vec<u8>>,
+    devices: Option<Vec<u8>>,
+}
+
+impl Relay {
+    /// Creates a relay listening on `addr`.
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        Self::from_listener(TcpListener::bind(addr)?)
+    }
+
+    /// Creates a relay accepting clients on `listener`.
+    pub fn from_listener(listener: TcpListener) -> io::Result<Self> {
+        listener.set_nonblocking(true)?;
+        Ok(Relay {
+            listener,
+            clients: Vec::new(),
+            classes: ClassSet::ALL,
+            min_interval: Duration::ZERO,
+            last_sent: BTreeMap::new(),
+            max_clients: None,
+            version: None,
+            devices: None,
+        })
+    }
+
+    /// Forwards only messages of the given classes. Lines without a
+    /// recognizable class are always forwarded.
+    pub fn with_classes(mut self, classes: ClassSet) -> Self {
+        self.classes = classes;
+        self
+    }
+
+    /// Forwards at most one message of each class per `interval`, e.g.
+    /// to serve a 10 Hz receiver at 1 Hz. DEVICE messages, announcing
+    /// device changes, are not down-sampled.
+    pub fn with_min_interval(mut self, interval: Duration) -> Self {
+        self.min_interval = interval;
+        self
+    }
+
+    /// Refuses clients beyond `max` connected ones.
+    pub fn with_max_clients(mut self, max: usize) -> Self {
+        self.max_clients = Some(max);
+        self
+    }
+
+    /// Local address the relay is listening on.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Number of connected clients.
+    pub fn clients(&self) -> usize {
+        self.clients.len()
+    }
+
+    /// Accepts pending clients and answers their commands, without
+    /// blocking. Failing clients are disconnected.
+    pub fn poll(&mut self) -> io::Result<()> {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, peer)) => self.accept(stream, peer),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        let devices = self.devices.as_deref().unwrap_or(EMPTY_DEVICES);
+        self.clients.retain_mut(|client| {
+            let result = client.commands().and_then(|commands| {
+                commands
+                    .iter()
+                    .try_for_each(|cmd| answer(client, cmd, devices))
+            });
+            if let Err(e) = &result {
+                debug!("Relay client {} disconnected: {}", client.peer, e);
+            }
+            result.is_ok()
+        });
+        Ok(())
+    }
+
+    fn accept(&mut self, stream: TcpStream, peer: SocketAddr) {
+        if self
+            .max_clients
+            .is_some_and(|max| self.clients.len() >= max)
+        {
+            debug!("Relay client {} refused", peer);
+            return;
+        }
+        if let Err(e) = stream.set_nonblocking(true) {
+            debug!("Relay client {} failed: {}", peer, e);
+            return;
+        }
+        let _ = stream.set_nodelay(true);
+        let mut client = Client {
+            stream,
+            peer,
+            watching: false,
+            input: Vec::new(),
+            output: Vec::new(),
+        };
+        let version = match &self.version {
+            Some(version) => version.clone(),
+            None => default_version("gpsd_proto relay"),
+        };
+        match client.send(&version) {
+            Ok(()) => {
+                debug!("Relay client {} connected", peer);
+                self.clients.push(client);
+            }
+            Err(e) => debug!("Relay client {} failed: {}", peer, e),
+        }
+    }
+
+    /// Forwards a line received from `gpsd` to the watching clients,
+    /// subject to the class filter and down-sampling.
+    pub fn forward(&mut self, line: &[u8]) {
+        let mut line = line.trim_ascii_end().to_vec();
+        if line.is_empty() {
+            return;
+        }
+        line.push(b'\n');
+        if let Ok(class) = scan_class(&line) {
+            match &*class {
+                "VERSION" => self.version = Some(line.clone()),
+                "DEVICES" => self.devices = Some(line.clone()),
+                _ => {}
+            }
+            if !self.classes.contains_class(&class) || self.is_sampled_out(&class) {
+                return;
+            }
+        }
+        self.clients.retain_mut(|client| {
+            if !client.watching {
+                return true;
+            }
+            let result = client.send(&line);
+            if let Err(e) = &result {
+                debug!("Relay client {} disconnected: {}", client.peer, e);
+            }
+            result.is_ok()
+        });
+    }
+
+    /// Checks whether a message of `class` is dropped to keep the
+    /// minimum interval, otherwise remembers it as sent.
+    fn is_sampled_out(&mut self, class: &str) -> bool {
+        if self.min_interval.is_zero() || class == "DEVICE" {
+            return false;
+        }
+        let now = Instant::now();
+        match self.last_sent.get_mut(class) {
+            Some(last) if now.duration_since(*last) < self.min_interval => true,
+            Some(last) => {
+                *last = now;
+                false
+            }
+            None => {
+                self.last_sent.insert(class.to_owned(), now);
+                false
+            }
+        }
+    }
+
+    /// Relays the lines read from `gpsd` until the connection is
+    /// closed, serving clients after each line.
+    ///
+    /// The reader is expected to be set up already, e.g. by
+    /// [`handshake`](crate::handshake). As clients are only served
+    /// when a line arrives, the latency of new clients depends on the
+    /// rate of messages.
+    pub fn run(&mut self, upstream: &mut dyn BufRead) -> Result<(), GpsdError> {
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            if upstream.read_until(b'\n', &mut line)? == 0 {
+                return Ok(());
+            }
+            trace_line(&line);
+            self.poll()?;
+            self.forward(&line);
+        }
+    }
+}
+
+pub(crate) const EMPTY_DEVICES: &[u8] = b"{\"class\":\"DEVICES\",\"devices\":[]}\n";
+
+/// VERSION message sent by the relay and the mock server by default,
+/// with `rev` naming the server.
+pub(crate) fn default_version(rev: &str) -> Vec<u8> {
+    format!(
+        "{{\"class\":\"VERSION\",\"release\":\"{}\",\"rev\":\"{}\",\"proto_major\":{},\"proto_minor\":0}}\n",
+        env!("CARGO_PKG_VERSION"),
+        rev,
+        PROTO_MAJOR_MIN
+    )
+    .into_bytes()
+}
+
+/// Reply of `gpsd` to a client command, as far as emulated by the
+/// relay and the mock server.
+pub(crate) enum Reply {
+    /// Empty command.
+    None,
+    /// ERROR message, including the line terminator.
+    Error(String),
+    /// WATCH message, including the line terminator, acknowledging
+    /// the WATCH command and whether it enables the stream.
+    Watch(bool, String),
+}
+
+/// Emulates the reply to `cmd`. WATCH commands are acknowledged as
+/// requested, others rejected.
+pub(crate) fn reply(cmd: &str) -> Reply {
+    let error = |message: String| {
+        let reply = serde_json::json!({"class": "ERROR", "message": message});
+        Reply::Error(format!("{}\n", reply))
+    };
+    let cmd = cmd.trim();
+    let cmd = cmd.strip_suffix(';').unwrap_or(cmd);
+    let Some(args) = cmd.strip_prefix("?WATCH") else {
+        if cmd.is_empty() {
+            return Reply::None;
+        }
+        return error(format!("Unrecognized request '{}'", cmd));
+    };
+    let mut watch = match args.strip_prefix('=') {
+        Some(json) => match serde_json::from_str(json) {
+            Ok(serde_json::Value::Object(watch)) => watch,
+            _ => return error("Invalid WATCH: expected JSON".into()),
+        },
+        None => serde_json::Map::new(),
+    };
+    let enable = watch
+        .get("enable")
+        .and_then(serde_json::Value::as_bool)
+        .unwrap_or(true);
+    watch.insert("enable".into(), enable.into());
+    let mut reply = serde_json::Map::new();
+    reply.insert("class".into(), "WATCH".into());
+    reply.append(&mut watch);
+    Reply::Watch(enable, format!("{}\n", serde_json::Value::Object(reply)))
+}
+
+/// Answers a command of a client.
+fn answer(client: &mut Client, cmd: &str, devices: &[u8]) -> io::Result<()> {
+    match reply(cmd) {
+        Reply::None => Ok(()),
+        Reply::Error(reply) => client.send(reply.as_bytes()),
+        Reply::Watch(enable, reply) => {
+            client.send(devices)?;
+            client.send(reply.as_bytes())?;
+            client.watching = enable;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Relay;
+    use crate::{handshake, ClassSet};
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpStream;
+    use std::time::Duration;
+
+    #[test]
+    fn relays_stream() {
+        let mut relay = Relay::bind("127.0.0.1:0")
+            .unwrap()
+            .with_classes(ClassSet::TPV | ClassSet::DEVICE)
+            .with_min_interval(Duration::from_secs(60));
+        let addr = relay.local_addr().unwrap();
+        relay.forward(br#"{"class":"DEVICES","devices":[{"path":"/dev/ttyS0"}]}"#);
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let client = std::thread::spawn(move || {
+            handshake(&mut reader, &mut stream).unwrap();
+            let mut lines = Vec::new();
+            for _ in 0..2 {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                lines.push(line);
+            }
+            lines
+        });
+
+        while relay.clients() == 0 || !relay.clients[0].watching {
+            relay.poll().unwrap();
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        relay.forward(br#"{"class":"SKY","hdop":1.2}"#);
+        relay.forward(b"{\"class\":\"TPV\",\"mode\":3}\r\n");
+        relay.forward(br#"{"class":"TPV","mode":2}"#);
+        relay.forward(br#"{"class":"DEVICE","path":"/dev/ttyS0"}"#);
+        assert_eq!(
+            client.join().unwrap(),
+            [
+                "{\"class\":\"TPV\",\"mode\":3}\n",
+                "{\"class\":\"DEVICE\",\"path\":\"/dev/ttyS0\"}\n",
+            ]
+        );
+        relay.forward(br#"{"class":"DEVICE","path":"/dev/ttyS0"}"#);
+        relay.poll().unwrap();
+        assert_eq!(relay.clients(), 0);
+    }
+}
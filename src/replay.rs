@@ -0,0 +1,456 @@
+//! Recording and replay of `gpsd` sessions.
+
+#[cfg(feature = "mmap")]
+use crate::parse_lines;
+use crate::{GpsdError, UnifiedResponse};
+#[cfg(feature = "mmap")]
+use memmap2::Mmap;
+#[cfg(feature = "mmap")]
+use std::fs::File;
+use std::io::{self, BufRead, Read, Seek, Write};
+#[cfg(feature = "mmap")]
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Reader adapter recording every line read from `gpsd`, prefixed with
+/// its receive time, for later replay with [`Replayer`].
+///
+/// Each record is a line of the form `1700000000.123456789 {...}`, the
+/// receive time as seconds since the Unix epoch followed by the
+/// message as sent by `gpsd`. The recorder is passed to
+/// [`GpsdClient::new`](crate::GpsdClient::new) in place of the reader.
+///
+/// A failure to write the record is logged and stops the recording,
+/// but not the reading.
+pub struct Recorder<R, W> {
+    inner: R,
+    log: Option<W>,
+    line: Vec<u8>,
+}
+
+impl<R: BufRead, W: Write> Recorder<R, W> {
+    /// Creates a recorder reading from `inner` and writing the records
+    /// to `log`.
+    pub fn new(inner: R, log: W) -> Self {
+        Recorder {
+            inner,
+            log: Some(log),
+            line: Vec::new(),
+        }
+    }
+
+    /// Returns the reader and the record writer, if still recording.
+    /// An incomplete last line is not recorded.
+    pub fn into_inner(self) -> (R, Option<W>) {
+        (self.inner, self.log)
+    }
+
+    fn record(&mut self) {
+        let Some(log) = self.log.as_mut() else {
+            return;
+        };
+        let since_epoch = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let written = write!(
+            log,
+            "{}.{:09} ",
+            since_epoch.as_secs(),
+            since_epoch.subsec_nanos()
+        )
+        .and_then(|()| log.write_all(&self.line));
+        if let Err(e) = written {
+            warn!("Recording stopped: {}", e);
+            self.log = None;
+        }
+        self.line.clear();
+    }
+}
+
+impl<R: BufRead, W: Write> Read for Recorder<R, W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = {
+            let data = self.fill_buf()?;
+            let n = data.len().min(buf.len());
+            buf[..n].copy_from_slice(&data[..n]);
+            n
+        };
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead, W: Write> BufRead for Recorder<R, W> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        let consumed = match self.inner.fill_buf() {
+            Ok(data) => data[..amt.min(data.len())].to_vec(),
+            Err(_) => Vec::new(),
+        };
+        self.inner.consume(amt);
+        for chunk in consumed.split_inclusive(|&b| b == b'\n') {
+            self.line.extend_from_slice(chunk);
+            if chunk.ends_with(b"\n") {
+                self.record();
+            }
+        }
+    }
+}
+
+/// Pace of a [`Replayer`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PlaybackSpeed {
+    /// Messages are delayed to their original pace.
+    Realtime,
+    /// The original pace sped up by a factor, e.g. `Scaled(10.0)` for
+    /// ten times faster or `Scaled(0.5)` for half the speed.
+    Scaled(f64),
+    /// Messages are returned as fast as they are read.
+    Unlimited,
+}
+
+impl PlaybackSpeed {
+    fn factor(self) -> Option<f64> {
+        match self {
+            PlaybackSpeed::Realtime => Some(1.0),
+            PlaybackSpeed::Scaled(f) if f > 0.0 => Some(f),
+            PlaybackSpeed::Scaled(_) | PlaybackSpeed::Unlimited => None,
+        }
+    }
+}
+
+/// Reader replaying a session recorded with [`Recorder`], yielding the
+/// messages without their receive times.
+///
+/// Lines without receive time, as in captures of `gpspipe -w`, are
+/// passed through unchanged. By default messages are returned as fast
+/// as possible, see [`Replayer::with_speed`] to keep the original pace.
+/// Besides reading from the replayer, e.g. with a
+/// [`GpsdClient`](crate::GpsdClient), messages can be taken one at a
+/// time with [`Replayer::step`] and skipped with [`Replayer::seek`].
+pub struct Replayer<R> {
+    inner: R,
+    line: Vec<u8>,
+    pos: usize,
+    speed: PlaybackSpeed,
+    anchor: Option<(Instant, Duration)>,
+    received_at: Option<SystemTime>,
+}
+
+impl<R: BufRead> Replayer<R> {
+    /// Creates a replayer reading the records from `inner`.
+    pub fn new(inner: R) -> Self {
+        Replayer {
+            inner,
+            line: Vec::new(),
+            pos: 0,
+            speed: PlaybackSpeed::Unlimited,
+            anchor: None,
+            received_at: None,
+        }
+    }
+
+    /// Sets the pace of the replay.
+    pub fn with_speed(mut self, speed: PlaybackSpeed) -> Self {
+        self.set_speed(speed);
+        self
+    }
+
+    /// Delays each message until the time elapsed since the first one
+    /// matches the recording, a shorthand for
+    /// [`PlaybackSpeed::Realtime`].
+    pub fn with_realtime(self, realtime: bool) -> Self {
+        self.with_speed(if realtime {
+            PlaybackSpeed::Realtime
+        } else {
+            PlaybackSpeed::Unlimited
+        })
+    }
+
+    /// Changes the pace of the replay, taking effect with the next
+    /// message.
+    pub fn set_speed(&mut self, speed: PlaybackSpeed) {
+        self.speed = speed;
+        self.anchor = None;
+    }
+
+    /// The current pace of the replay.
+    pub fn speed(&self) -> PlaybackSpeed {
+        self.speed
+    }
+
+    /// Recorded receive time of the message read last, if any.
+    pub fn received_at(&self) -> Option<SystemTime> {
+        self.received_at
+    }
+
+    /// Returns the next message without delay, regardless of the
+    /// speed, or `None` at the end of the recording. Empty lines are
+    /// skipped.
+    pub fn step(&mut self) -> Result<Option<UnifiedResponse>, GpsdError> {
+        loop {
+            if self.pos >= self.line.len() && !self.load_line()? {
+                return Ok(None);
+            }
+            let msg = self.line[self.pos..].trim_ascii();
+            let msg = (!msg.is_empty())
+                .then(|| UnifiedResponse::from_slice(msg))
+                .transpose();
+            self.pos = self.line.len();
+            self.anchor = None;
+            if let Some(msg) = msg? {
+                return Ok(Some(msg));
+            }
+        }
+    }
+
+    /// Skips all messages received before `time`. Returns `false` if
+    /// the end of the recording is reached. Only seeks forward, see
+    /// [`Replayer::rewind`].
+    pub fn seek(&mut self, time: SystemTime) -> io::Result<bool> {
+        loop {
+            if self.pos < self.line.len() && self.received_at.is_none_or(|at| at >= time) {
+                // The pace continues from the message sought to.
+                self.anchor = self.received_at.map(|at| {
+                    let time = at.duration_since(UNIX_EPOCH).unwrap_or_default();
+                    (Instant::now(), time)
+                });
+                return Ok(true);
+            }
+            if !self.load_line()? {
+                return Ok(false);
+            }
+        }
+    }
+
+    /// Reads the next record into `line`, returning `false` at the end.
+    fn load_line(&mut self) -> io::Result<bool> {
+        self.line.clear();
+        self.pos = 0;
+        if self.inner.read_until(b'\n', &mut self.line)? == 0 {
+            return Ok(false);
+        }
+        if let Some(space) = self.line.iter().position(|&b| b == b' ') {
+            let time = std::str::from_utf8(&self.line[..space])
+                .ok()
+                .and_then(parse_time);
+            if let Some(time) = time {
+                self.pos = space + 1;
+                self.received_at = Some(UNIX_EPOCH + time);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Waits until the message read last is due.
+    fn pace(&mut self) {
+        let (Some(factor), Some(at)) = (self.speed.factor(), self.received_at) else {
+            return;
+        };
+        let time = at.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let (start, first) = *self.anchor.get_or_insert((Instant::now(), time));
+        let due = start + time.saturating_sub(first).div_f64(factor);
+        if let Some(wait) = due.checked_duration_since(Instant::now()) {
+            std::thread::sleep(wait);
+        }
+    }
+}
+
+impl<R: BufRead + Seek> Replayer<R> {
+    /// Restarts the replay at the beginning of the recording.
+    pub fn rewind(&mut self) -> io::Result<()> {
+        self.inner.rewind()?;
+        self.line.clear();
+        self.pos = 0;
+        self.anchor = None;
+        self.received_at = None;
+        Ok(())
+    }
+}
+
+/// Parses the receive time of a record, seconds and nanoseconds.
+fn parse_time(s: &str) -> Option<Duration> {
+    let (secs, nanos) = s.split_once('.')?;
+    if nanos.len() != 9 {
+        return None;
+    }
+    Some(Duration::new(secs.parse().ok()?, nanos.parse().ok()?))
+}
+
+impl<R: BufRead> Read for Replayer<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = {
+            let data = self.fill_buf()?;
+            let n = data.len().min(buf.len());
+            buf[..n].copy_from_slice(&data[..n]);
+            n
+        };
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for Replayer<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pos >= self.line.len() && self.load_line()? {
+            self.pace();
+        }
+        Ok(&self.line[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = (self.pos + amt).min(self.line.len());
+    }
+}
+
+/// A capture of `gpsd` messages, e.g. recorded with `gpspipe -w`,
+/// mapped into memory.
+///
+/// Captures of [`Recorder`] contain receive times and have to be read
+/// with [`Replayer`] instead.
+///
+/// The file is parsed straight from the mapping without reading it
+/// into a buffer first, which makes scanning long logs fast.
+#[cfg(feature = "mmap")]
+pub struct ReplayFile {
+    map: Option<Mmap>,
+}
+
+#[cfg(feature = "mmap")]
+impl ReplayFile {
+    /// Maps the capture file at `path`.
+    ///
+    /// The mapping assumes the file is not modified while it is
+    /// mapped, as is the case for finished captures. Truncating the
+    /// file meanwhile may crash the process.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, GpsdError> {
+        let file = File::open(path)?;
+        if file.metadata()?.len() == 0 {
+            // Empty files can't be mapped on all platforms.
+            return Ok(ReplayFile { map: None });
+        }
+        // SAFETY: see the documentation above, the file is treated as
+        // immutable while mapped.
+        let map = unsafe { Mmap::map(&file)? };
+        Ok(ReplayFile { map: Some(map) })
+    }
+
+    /// The raw content of the capture.
+    pub fn as_bytes(&self) -> &[u8] {
+        self.map.as_deref().unwrap_or_default()
+    }
+
+    /// Parses the messages of the capture, see [`parse_lines`].
+    pub fn messages(&self) -> impl Iterator<Item = Result<UnifiedResponse, GpsdError>> + '_ {
+        parse_lines(self.as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PlaybackSpeed, Recorder, Replayer};
+    use crate::{GpsdClient, Mode, UnifiedResponse};
+    use std::io::{BufRead, Cursor};
+    use std::time::{Duration, Instant, UNIX_EPOCH};
+
+    #[test]
+    fn records_and_replays_session() {
+        let session: &[u8] =
+            b"{\"class\":\"TPV\",\"mode\":3}\n{\"class\":\"SKY\"}\n{\"class\":\"TP";
+        let mut recorder = Recorder::new(session, Vec::new());
+        let mut line = String::new();
+        while recorder.read_line(&mut line).unwrap() > 0 {}
+        let (_, log) = recorder.into_inner();
+        let log = log.unwrap();
+        let records: Vec<_> = log.lines().map(Result::unwrap).collect();
+        assert_eq!(records.len(), 2);
+        let (time, msg) = records[1].split_once(' ').unwrap();
+        assert!(super::parse_time(time).is_some());
+        assert_eq!(msg, "{\"class\":\"SKY\"}");
+
+        let mut log = log;
+        log.extend_from_slice(b"{\"class\":\"TPV\",\"mode\":2}\n");
+        let mut client = GpsdClient::new(Replayer::new(&log[..]), std::io::sink());
+        assert!(matches!(
+            client.next_response().unwrap(),
+            UnifiedResponse::Tpv(_)
+        ));
+        assert!(matches!(
+            client.next_response().unwrap(),
+            UnifiedResponse::Sky(_)
+        ));
+        match client.next_response().unwrap() {
+            UnifiedResponse::Tpv(tpv) => assert_eq!(tpv.mode, Mode::Fix2d),
+            _ => panic!("expected TPV"),
+        }
+    }
+
+    #[test]
+    fn controls_playback() {
+        let log = b"1000.000000000 {\"class\":\"TPV\",\"mode\":1}
+1000.500000000 {\"class\":\"TPV\",\"mode\":2}
+1001.000000000 {\"class\":\"SKY\"}
+1002.000000000 {\"class\":\"TPV\",\"mode\":3}
+";
+        let replayer = Replayer::new(&log[..]).with_speed(PlaybackSpeed::Scaled(20.0));
+        let start = Instant::now();
+        assert_eq!(replayer.lines().count(), 4);
+        // Two recorded seconds at twenty times the speed.
+        assert!(start.elapsed() >= Duration::from_millis(95));
+
+        let mut replayer = Replayer::new(Cursor::new(&log[..]));
+        assert!(matches!(
+            replayer.step().unwrap(),
+            Some(UnifiedResponse::Tpv(tpv)) if tpv.mode == Mode::NoFix
+        ));
+        assert!(replayer
+            .seek(UNIX_EPOCH + Duration::from_secs(1001))
+            .unwrap());
+        assert!(matches!(
+            replayer.step().unwrap(),
+            Some(UnifiedResponse::Sky(_))
+        ));
+        assert_eq!(
+            replayer.received_at(),
+            Some(UNIX_EPOCH + Duration::from_secs(1001))
+        );
+        assert!(replayer.step().unwrap().is_some());
+        assert!(replayer.step().unwrap().is_none());
+        assert!(!replayer.seek(UNIX_EPOCH).unwrap());
+
+        replayer.rewind().unwrap();
+        assert!(replayer
+            .seek(UNIX_EPOCH + Duration::from_millis(1_000_200))
+            .unwrap());
+        let mut client = GpsdClient::new(replayer, std::io::sink());
+        match client.next_response().unwrap() {
+            UnifiedResponse::Tpv(tpv) => assert_eq!(tpv.mode, Mode::Fix2d),
+            _ => panic!("expected TPV"),
+        }
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn parse_capture() {
+        use super::ReplayFile;
+        use std::io::Write;
+        let path = std::env::temp_dir().join(format!("gpsd_proto-{}.log", std::process::id()));
+        let mut file = std::fs::File::create(&path).unwrap();
+        file.write_all(b"{\"class\":\"TPV\",\"mode\":3}\n\n{\"class\":\"SKY\"}\n")
+            .unwrap();
+        drop(file);
+
+        let replay = ReplayFile::open(&path).unwrap();
+        let msgs: Vec<_> = replay.messages().collect();
+        assert_eq!(msgs.len(), 2);
+        assert!(matches!(msgs[0], Ok(UnifiedResponse::Tpv(_))));
+        assert!(matches!(msgs[1], Ok(UnifiedResponse::Sky(_))));
+
+        std::fs::File::create(&path).unwrap();
+        assert_eq!(ReplayFile::open(&path).unwrap().messages().count(), 0);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,281 @@
+//! Fixes shaped like the `sensor_msgs/NavSatFix` message of ROS.
+
+use crate::{Constellation, Gst, Mode, Sky, Status, Tpv};
+
+/// Time stamp of a ROS header, `builtin_interfaces/Time`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct RosTime {
+    /// Seconds since the Unix epoch.
+    pub sec: i32,
+    /// Nanoseconds of the second.
+    pub nanosec: u32,
+}
+
+/// `std_msgs/Header`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct RosHeader {
+    /// Time of the measurement.
+    pub stamp: RosTime,
+    /// Frame of the measurement, e.g. the antenna.
+    pub frame_id: String,
+}
+
+/// `sensor_msgs/NavSatStatus`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct NavSatStatus {
+    /// One of the `STATUS_*` constants.
+    pub status: i8,
+    /// Bits of the `SERVICE_*` constants of the systems used.
+    pub service: u16,
+}
+
+impl NavSatStatus {
+    /// No fix.
+    pub const STATUS_NO_FIX: i8 = -1;
+    /// Unaugmented fix.
+    pub const STATUS_FIX: i8 = 0;
+    /// Fix with satellite-based augmentation.
+    pub const STATUS_SBAS_FIX: i8 = 1;
+    /// Fix with ground-based augmentation.
+    pub const STATUS_GBAS_FIX: i8 = 2;
+
+    /// GPS used.
+    pub const SERVICE_GPS: u16 = 1;
+    /// GLONASS used.
+    pub const SERVICE_GLONASS: u16 = 2;
+    /// BeiDou, formerly Compass, used.
+    pub const SERVICE_COMPASS: u16 = 4;
+    /// Galileo used.
+    pub const SERVICE_GALILEO: u16 = 8;
+}
+
+/// `sensor_msgs/NavSatFix`.
+///
+/// The covariance is approximated from the error estimates of TPV,
+/// which `gpsd` reports at 95% confidence, or taken from the standard
+/// deviations of a GST report, see [`NavSatFix::with_gst`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct NavSatFix {
+    /// Time stamp and frame.
+    pub header: RosHeader,
+    /// Fix status and systems used.
+    pub status: NavSatStatus,
+    /// Latitude in degrees, NaN without fix.
+    pub latitude: f64,
+    /// Longitude in degrees, NaN without fix.
+    pub longitude: f64,
+    /// Height above the WGS 84 ellipsoid in meters, NaN if unknown.
+    pub altitude: f64,
+    /// Covariance of the east, north and up position in m², row
+    /// major.
+    pub position_covariance: [f64; 9],
+    /// One of the `COVARIANCE_TYPE_*` constants.
+    pub position_covariance_type: u8,
+}
+
+/// Ratio of the 95% confidence bound to the standard deviation of a
+/// normal distribution.
+const CONFIDENCE_95: f64 = 1.96;
+
+impl NavSatFix {
+    /// The covariance is unknown.
+    pub const COVARIANCE_TYPE_UNKNOWN: u8 = 0;
+    /// The covariance is approximated, e.g. from the error estimates.
+    pub const COVARIANCE_TYPE_APPROXIMATED: u8 = 1;
+    /// The variances are known, the covariances are zero.
+    pub const COVARIANCE_TYPE_DIAGONAL_KNOWN: u8 = 2;
+    /// The full covariance is known.
+    pub const COVARIANCE_TYPE_KNOWN: u8 = 3;
+
+    /// Converts `tpv`, stamped with its time and `frame_id`.
+    ///
+    /// The status is `STATUS_SBAS_FIX` for DGPS fixes, which `gpsd`
+    /// reports for SBAS too, and `STATUS_GBAS_FIX` for RTK fixes. The
+    /// service is GPS, see [`NavSatFix::with_sky`].
+    pub fn from_tpv(tpv: &Tpv, frame_id: &str) -> Self {
+        let stamp = tpv
+            .time
+            .and_then(|t| {
+                let since = t
+                    .to_system_time()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .ok()?;
+                Some(RosTime {
+                    sec: i32::try_from(since.as_secs()).ok()?,
+                    nanosec: since.subsec_nanos(),
+                })
+            })
+            .unwrap_or_default();
+        let fix = matches!(tpv.mode, Mode::Fix2d | Mode::Fix3d);
+        let status = match tpv.status {
+            _ if !fix => NavSatStatus::STATUS_NO_FIX,
+            Some(Status::Dgps) => NavSatStatus::STATUS_SBAS_FIX,
+            Some(Status::RtkFixed | Status::RtkFloat) => NavSatStatus::STATUS_GBAS_FIX,
+            _ => NavSatStatus::STATUS_FIX,
+        };
+        let altitude = tpv
+            .alt_hae
+            .map(f64::from)
+            .filter(|_| tpv.mode == Mode::Fix3d);
+
+        let mut position_covariance = [0.0; 9];
+        let mut position_covariance_type = Self::COVARIANCE_TYPE_UNKNOWN;
+        let variance = |ep: f64| (ep / CONFIDENCE_95).powi(2);
+        // Without `epx` and `epy`, `eph` is split evenly.
+        let horizontal = tpv.eph.map(|eph| f64::from(eph) / std::f64::consts::SQRT_2);
+        let east = tpv.epx.map(f64::from).or(horizontal).map(variance);
+        let north = tpv.epy.map(f64::from).or(horizontal).map(variance);
+        if let (true, Some(east), Some(north)) = (fix, east, north) {
+            position_covariance[0] = east;
+            position_covariance[4] = north;
+            // Without vertical estimate, the up axis is taken as
+            // uncertain as the worse horizontal axis.
+            position_covariance[8] = tpv
+                .epv
+                .map(|epv| variance(f64::from(epv)))
+                .unwrap_or(east.max(north));
+            position_covariance_type = Self::COVARIANCE_TYPE_APPROXIMATED;
+        }
+
+        NavSatFix {
+            header: RosHeader {
+                stamp,
+                frame_id: frame_id.into(),
+            },
+            status: NavSatStatus {
+                status,
+                service: NavSatStatus::SERVICE_GPS,
+            },
+            latitude: tpv.lat.filter(|_| fix).unwrap_or(f64::NAN),
+            longitude: tpv.lon.filter(|_| fix).unwrap_or(f64::NAN),
+            altitude: altitude.unwrap_or(f64::NAN),
+            position_covariance,
+            position_covariance_type,
+        }
+    }
+
+    /// Replaces the covariance by the standard deviations of `gst`,
+    /// of the same epoch as the fix. The covariance is known in full if
+    /// `gst` reports the error ellipse, otherwise only its diagonal.
+    pub fn with_gst(mut self, gst: &Gst) -> Self {
+        if self.status.status == NavSatStatus::STATUS_NO_FIX {
+            return self;
+        }
+        let var = |v: Option<f32>| v.map(|v| f64::from(v).powi(2));
+        let up = var(gst.alt).unwrap_or(self.position_covariance[8]);
+        if let (Some(major), Some(minor), Some(orient)) = (gst.major, gst.minor, gst.orient) {
+            let (a, b) = (f64::from(major).powi(2), f64::from(minor).powi(2));
+            let (sin, cos) = f64::from(orient).to_radians().sin_cos();
+            let en = (a - b) * sin * cos;
+            self.position_covariance = [
+                a * sin * sin + b * cos * cos,
+                en,
+                0.0,
+                en,
+                a * cos * cos + b * sin * sin,
+                0.0,
+                0.0,
+                0.0,
+                up,
+            ];
+            self.position_covariance_type = Self::COVARIANCE_TYPE_KNOWN;
+        } else if let (Some(east), Some(north)) = (var(gst.lon), var(gst.lat)) {
+            self.position_covariance = [east, 0.0, 0.0, 0.0, north, 0.0, 0.0, 0.0, up];
+            self.position_covariance_type = Self::COVARIANCE_TYPE_DIAGONAL_KNOWN;
+        }
+        self
+    }
+
+    /// Sets the service from the constellations of the satellites used
+    /// in `sky`, keeping GPS if none is known.
+    pub fn with_sky(mut self, sky: &Sky) -> Self {
+        let service = sky
+            .satellites
+            .as_deref()
+            .unwrap_or_default()
+            .iter()
+            .filter(|s| s.used)
+            .map(|s| match s.constellation() {
+                Constellation::Gps => NavSatStatus::SERVICE_GPS,
+                Constellation::Glonass => NavSatStatus::SERVICE_GLONASS,
+                Constellation::BeiDou => NavSatStatus::SERVICE_COMPASS,
+                Constellation::Galileo => NavSatStatus::SERVICE_GALILEO,
+                _ => 0,
+            })
+            .fold(0, |service, bit| service | bit);
+        if service != 0 {
+            self.status.service = service;
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{NavSatFix, NavSatStatus};
+    use crate::{Gst, Sky, Tpv};
+
+    #[test]
+    fn converts_navsatfix() {
+        let tpv: Tpv = serde_json::from_str(
+            r#"{"mode":3,"status":2,"time":"2024-05-01T12:00:00.250Z","lat":48.1,"lon":11.5,"altHAE":560.0,"epx":3.92,"epy":1.96,"epv":5.88}"#,
+        )
+        .unwrap();
+        let fix = NavSatFix::from_tpv(&tpv, "gps");
+        assert_eq!(fix.header.frame_id, "gps");
+        assert_eq!(
+            (fix.header.stamp.sec, fix.header.stamp.nanosec),
+            (1_714_564_800, 250_000_000)
+        );
+        assert_eq!(fix.status.status, NavSatStatus::STATUS_SBAS_FIX);
+        assert_eq!(
+            (fix.latitude, fix.longitude, fix.altitude),
+            (48.1, 11.5, 560.0)
+        );
+        assert_eq!(
+            fix.position_covariance_type,
+            NavSatFix::COVARIANCE_TYPE_APPROXIMATED
+        );
+        let cov = fix.position_covariance;
+        assert!((cov[0] - 4.0).abs() < 1e-6 && (cov[4] - 1.0).abs() < 1e-6);
+        assert!((cov[8] - 9.0).abs() < 1e-6);
+
+        let gst: Gst =
+            serde_json::from_str(r#"{"major":2.0,"minor":1.0,"orient":90.0,"alt":3.0}"#).unwrap();
+        let cov = fix.clone().with_gst(&gst).position_covariance;
+        assert!((cov[0] - 4.0).abs() < 1e-9 && (cov[4] - 1.0).abs() < 1e-9);
+        assert!(cov[1].abs() < 1e-9 && cov[8] == 9.0);
+        let gst: Gst = serde_json::from_str(r#"{"lat":1.5,"lon":0.5}"#).unwrap();
+        let diagonal = fix.clone().with_gst(&gst);
+        assert_eq!(
+            diagonal.position_covariance_type,
+            NavSatFix::COVARIANCE_TYPE_DIAGONAL_KNOWN
+        );
+        assert_eq!(
+            diagonal.position_covariance[..5],
+            [0.25, 0.0, 0.0, 0.0, 2.25]
+        );
+
+        let sky: Sky = serde_json::from_str(
+            r#"{"satellites":[{"PRN":3,"used":true},{"PRN":70,"used":true},{"PRN":301,"used":false}]}"#,
+        )
+        .unwrap();
+        let service = fix.with_sky(&sky).status.service;
+        assert_eq!(
+            service,
+            NavSatStatus::SERVICE_GPS | NavSatStatus::SERVICE_GLONASS
+        );
+
+        let no_fix = NavSatFix::from_tpv(&serde_json::from_str(r#"{"mode":1}"#).unwrap(), "gps");
+        assert_eq!(no_fix.status.status, NavSatStatus::STATUS_NO_FIX);
+        assert!(no_fix.latitude.is_nan());
+        assert_eq!(
+            no_fix.position_covariance_type,
+            NavSatFix::COVARIANCE_TYPE_UNKNOWN
+        );
+    }
+}
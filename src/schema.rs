@@ -0,0 +1,120 @@
+//! JSON schemas of the message classes, derived with `schemars`.
+//!
+//! The message types implement `schemars::JsonSchema`, which lacks the
+//! `class` attribute dispatched on by [`crate::UnifiedResponse`].
+//! [`message_schemas`] adds it.
+
+use schemars::{json_schema, JsonSchema, Schema};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Error deriving a schema, see [`message_schemas`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaError(String);
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "cannot derive schema: {}", self.0)
+    }
+}
+
+impl std::error::Error for SchemaError {}
+
+/// Schema of a message of `class`, with the `class` attribute.
+fn message_schema<T: JsonSchema>(class: &str) -> Result<Schema, SchemaError> {
+    let mut schema = schemars::schema_for!(T);
+    let Some(object) = schema.as_object_mut() else {
+        return Err(SchemaError(format!("schema of {} is not an object", class)));
+    };
+    object.insert("title".into(), class.into());
+    let properties = object
+        .entry("properties")
+        .or_insert_with(|| json!({}))
+        .as_object_mut()
+        .ok_or_else(|| SchemaError(format!("properties of {} are not an object", class)))?;
+    properties.insert("class".into(), json_schema!({"const": class}).into());
+    let mut required = vec![Value::from("class")];
+    if let Some(Value::Array(fields)) = object.get_mut("required") {
+        required.append(fields);
+    }
+    object.insert("required".into(), required.into());
+    Ok(schema)
+}
+
+/// JSON schemas of all message classes decoded with the enabled
+/// features, by class. SUBFRAME messages are kept undecoded and have
+/// no schema.
+pub fn message_schemas() -> Result<BTreeMap<&'static str, Schema>, SchemaError> {
+    let mut schemas = BTreeMap::new();
+    schemas.insert("VERSION", message_schema::<crate::Version>("VERSION")?);
+    schemas.insert("DEVICES", message_schema::<crate::Devices>("DEVICES")?);
+    schemas.insert("WATCH", message_schema::<crate::Watch>("WATCH")?);
+    schemas.insert("DEVICE", message_schema::<crate::Device>("DEVICE")?);
+    schemas.insert("TPV", message_schema::<crate::Tpv>("TPV")?);
+    schemas.insert("SKY", message_schema::<crate::Sky>("SKY")?);
+    schemas.insert("PPS", message_schema::<crate::Pps>("PPS")?);
+    schemas.insert("TOFF", message_schema::<crate::Toff>("TOFF")?);
+    schemas.insert("GST", message_schema::<crate::Gst>("GST")?);
+    #[cfg(feature = "att")]
+    schemas.insert("ATT", message_schema::<crate::Att>("ATT")?);
+    #[cfg(feature = "att")]
+    schemas.insert("IMU", message_schema::<crate::Att>("IMU")?);
+    #[cfg(feature = "osc")]
+    schemas.insert("OSC", message_schema::<crate::Osc>("OSC")?);
+    #[cfg(feature = "raw")]
+    schemas.insert("RAW", message_schema::<crate::Raw>("RAW")?);
+    #[cfg(feature = "rtcm")]
+    schemas.insert("RTCM2", message_schema::<crate::Rtcm2>("RTCM2")?);
+    #[cfg(feature = "rtcm")]
+    schemas.insert("RTCM3", message_schema::<crate::Rtcm3>("RTCM3")?);
+    #[cfg(feature = "ais")]
+    schemas.insert("AIS", message_schema::<crate::Ais>("AIS")?);
+    Ok(schemas)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::message_schemas;
+    use serde_json::json;
+
+    #[test]
+    fn derives_message_schemas() {
+        let schemas = message_schemas().unwrap();
+        let pps = schemas["PPS"].as_value();
+        assert_eq!(pps["title"], "PPS");
+        assert_eq!(pps["properties"]["class"], json!({"const": "PPS"}));
+        assert_eq!(pps["properties"]["real_sec"]["type"], "integer");
+        assert_eq!(
+            pps["properties"]["qErr"]["type"],
+            json!(["integer", "null"])
+        );
+        assert_eq!(
+            pps["required"],
+            json!([
+                "class",
+                "device",
+                "real_sec",
+                "real_nsec",
+                "clock_sec",
+                "clock_nsec",
+                "precision"
+            ])
+        );
+
+        let sky = schemas["SKY"].as_value();
+        let sat = &sky["$defs"]["Satellite"];
+        assert_eq!(sat["properties"]["PRN"]["type"], "integer");
+        assert_eq!(sat["properties"]["el"]["type"], json!(["number", "null"]));
+        assert_eq!(sat["required"], json!(["PRN", "used"]));
+        assert_eq!(sky["required"], json!(["class"]));
+        let tpv = schemas["TPV"].as_value();
+        assert_eq!(tpv["required"], json!(["class", "mode"]));
+        assert_eq!(tpv["$defs"]["Mode"]["type"], "integer");
+        assert_eq!(
+            tpv["properties"]["time"]["anyOf"][0],
+            json!({"$ref": "#/$defs/Iso8601Timestamp"})
+        );
+        assert_eq!(tpv["$defs"]["Iso8601Timestamp"]["format"], "date-time");
+    }
+}
@@ -0,0 +1,459 @@
+//! Simulation of receivers moving along a route, like `gpsfake` with
+//! generated instead of recorded data.
+
+use crate::geo::{geodesic_inverse, project, Geodesic};
+use crate::{Iso8601Timestamp, UnifiedResponse};
+use serde_json::{json, Value};
+use std::time::{Duration, SystemTime};
+
+/// Standard deviation of the range error in meters, scaled by the
+/// DOP to get the reported error estimates.
+const UERE: f64 = 2.0;
+
+/// Satellites below this elevation in degrees are not used.
+const ELEVATION_MASK: f64 = 5.0;
+
+#[derive(Debug, Clone, Copy)]
+struct SimSatellite {
+    prn: i16,
+    el: f64,
+    az: f64,
+}
+
+/// Description of a simulated drive, turned into the TPV and SKY
+/// reports a receiver would produce, see [`Scenario::messages`].
+///
+/// The receiver travels along the waypoints of the route at the speed
+/// of the speed profile, producing one TPV and one SKY report per
+/// epoch. The fix depends on the satellites left visible by
+/// obstructions: 3D with four or more, 2D with three, none otherwise,
+/// or during dropouts. The DOP and the error estimates are computed
+/// from the geometry of the satellites used. Positions are exact, so
+/// runs are reproducible.
+///
+/// The scenario ends on arrival at the last waypoint, or after the
+/// duration if set, see [`Scenario::with_duration`]. Without duration
+/// it also ends once the receiver stops short of the last waypoint for
+/// good, e.g. without any speed set.
+///
+/// ```
+/// use gpsd_proto::{Scenario, UnifiedResponse};
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// let scenario = Scenario::new(UNIX_EPOCH + Duration::from_secs(1_700_000_000))
+///     .waypoint(48.1, 11.5)
+///     .waypoint(48.1, 11.501)
+///     .with_speed(10.0)
+///     .with_satellites(8);
+/// let fixes = scenario
+///     .messages()
+///     .filter(|msg| matches!(msg, UnifiedResponse::Tpv(_)))
+///     .count();
+/// assert_eq!(fixes, 9);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Scenario {
+    start: SystemTime,
+    rate: Duration,
+    duration: Option<Duration>,
+    device: String,
+    route: Vec<(f64, f64)>,
+    altitude: f64,
+    speeds: Vec<(Duration, f64)>,
+    satellites: Vec<SimSatellite>,
+    obstructions: Vec<(Duration, Duration, f64)>,
+    dropouts: Vec<(Duration, Duration)>,
+}
+
+impl Scenario {
+    /// Creates a scenario starting at `start` with one epoch per
+    /// second, without route and satellites.
+    pub fn new(start: SystemTime) -> Self {
+        Scenario {
+            start,
+            rate: Duration::from_secs(1),
+            duration: None,
+            device: "/dev/ttySIM".into(),
+            route: Vec::new(),
+            altitude: 0.0,
+            speeds: Vec::new(),
+            satellites: Vec::new(),
+            obstructions: Vec::new(),
+            dropouts: Vec::new(),
+        }
+    }
+
+    /// Sets the time between epochs.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rate` is zero, as time wouldn't advance.
+    pub fn with_rate(mut self, rate: Duration) -> Self {
+        assert!(!rate.is_zero(), "zero epoch rate");
+        self.rate = rate;
+        self
+    }
+
+    /// Ends the scenario after `duration`, staying at the last waypoint
+    /// after arrival.
+    pub fn with_duration(mut self, duration: Duration) -> Self {
+        self.duration = Some(duration);
+        self
+    }
+
+    /// Sets the device path reported.
+    pub fn with_device(mut self, device: &str) -> Self {
+        self.device = device.into();
+        self
+    }
+
+    /// Appends a waypoint to the route, in degrees.
+    pub fn waypoint(mut self, lat: f64, lon: f64) -> Self {
+        self.route.push((lat, lon));
+        self
+    }
+
+    /// Sets the altitude above the ellipsoid in meters.
+    pub fn with_altitude(mut self, altitude: f64) -> Self {
+        self.altitude = altitude;
+        self
+    }
+
+    /// Sets the speed in m/s from the start on.
+    pub fn with_speed(self, speed: f64) -> Self {
+        self.speed_at(Duration::ZERO, speed)
+    }
+
+    /// Changes the speed to `speed` in m/s at `at` after the start.
+    pub fn speed_at(mut self, at: Duration, speed: f64) -> Self {
+        let i = self.speeds.partition_point(|(t, _)| *t <= at);
+        self.speeds.insert(i, (at, speed.max(0.0)));
+        self
+    }
+
+    /// Adds a GPS satellite at elevation `el` and azimuth `az` in
+    /// degrees.
+    pub fn satellite(mut self, prn: i16, el: f64, az: f64) -> Self {
+        self.satellites.push(SimSatellite { prn, el, az });
+        self
+    }
+
+    /// Adds `count` GPS satellites spread evenly across the sky.
+    pub fn with_satellites(mut self, count: usize) -> Self {
+        for i in 0..count {
+            let prn = (self.satellites.len() + 1) as i16;
+            let el = 10.0 + 75.0 * ((i * 7) % count.max(1)) as f64 / count.max(1) as f64;
+            let az = (i as f64 * 137.508).rem_euclid(360.0);
+            self.satellites.push(SimSatellite { prn, el, az });
+        }
+        self
+    }
+
+    /// Hides the satellites below `min_elevation` degrees for
+    /// `duration` from `from` after the start, e.g. in a street canyon.
+    pub fn obstruction(mut self, from: Duration, duration: Duration, min_elevation: f64) -> Self {
+        self.obstructions.push((from, duration, min_elevation));
+        self
+    }
+
+    /// Drops the fix for `duration` from `from` after the start,
+    /// regardless of the satellites, e.g. in a tunnel.
+    pub fn dropout(mut self, from: Duration, duration: Duration) -> Self {
+        self.dropouts.push((from, duration));
+        self
+    }
+
+    /// The reports of all epochs in order, as sent by `gpsd`.
+    pub fn lines(&self) -> impl Iterator<Item = String> + '_ {
+        self.epochs()
+            .flat_map(|[tpv, sky]| [tpv.to_string(), sky.to_string()])
+    }
+
+    /// The reports of all epochs in order.
+    pub fn messages(&self) -> impl Iterator<Item = UnifiedResponse> + '_ {
+        // The reports are generated in the layout of `gpsd`.
+        self.lines()
+            .map(|line| UnifiedResponse::from_slice(line.as_bytes()).unwrap())
+    }
+
+    /// Time between epochs.
+    pub(crate) fn rate(&self) -> Duration {
+        self.rate
+    }
+
+    /// TPV and SKY reports of the epochs.
+    pub(crate) fn epochs(&self) -> impl Iterator<Item = [Value; 2]> + '_ {
+        let legs: Vec<_> = self
+            .route
+            .windows(2)
+            .filter_map(|leg| Some((leg[0], leg[1], geodesic_inverse(leg[0], leg[1])?)))
+            .collect();
+        let length: f64 = legs.iter().map(|(_, _, g)| g.distance_m).sum();
+        let mut along = 0.0;
+        let mut epoch = 0u32;
+        let mut ended = false;
+        std::iter::from_fn(move || {
+            let t = self.rate * epoch;
+            match self.duration {
+                Some(duration) if t > duration => return None,
+                None if ended => return None,
+                _ => {}
+            }
+            let speed = if along < length { self.speed(t) } else { 0.0 };
+            let position = self.position(&legs, along);
+            let reports = self.epoch(t, position, speed);
+            ended = along >= length || self.stopped(t);
+            along = (along + speed * self.rate.as_secs_f64()).min(length);
+            epoch += 1;
+            Some(reports)
+        })
+    }
+
+    fn speed(&self, t: Duration) -> f64 {
+        let i = self.speeds.partition_point(|(from, _)| *from <= t);
+        i.checked_sub(1).map_or(0.0, |i| self.speeds[i].1)
+    }
+
+    /// Returns `true` if the receiver doesn't move from `t` on.
+    fn stopped(&self, t: Duration) -> bool {
+        self.speed(t) == 0.0
+            && !self
+                .speeds
+                .iter()
+                .any(|&(from, speed)| from > t && speed > 0.0)
+    }
+
+    /// Position and track at `along` meters on the route.
+    #[allow(clippy::type_complexity)]
+    fn position(
+        &self,
+        legs: &[((f64, f64), (f64, f64), Geodesic)],
+        mut along: f64,
+    ) -> Option<((f64, f64), f64)> {
+        let mut last = (*self.route.first()?, 0.0);
+        for &(from, to, geodesic) in legs {
+            if along < geodesic.distance_m {
+                return Some((
+                    project(from, geodesic.initial_bearing, along)?,
+                    geodesic.initial_bearing,
+                ));
+            }
+            along -= geodesic.distance_m;
+            last = (to, geodesic.final_bearing);
+        }
+        Some(last)
+    }
+
+    fn epoch(&self, t: Duration, position: Option<((f64, f64), f64)>, speed: f64) -> [Value; 2] {
+        let within = |from: Duration, len: Duration| from <= t && t < from + len;
+        let min_elevation = self
+            .obstructions
+            .iter()
+            .filter(|(from, len, _)| within(*from, *len))
+            .map(|(_, _, el)| *el)
+            .fold(0.0, f64::max);
+        let visible: Vec<_> = self
+            .satellites
+            .iter()
+            .filter(|s| s.el >= min_elevation && s.el >= 0.0)
+            .collect();
+        let dropout = self.dropouts.iter().any(|(from, len)| within(*from, *len));
+        let used = |s: &SimSatellite| !dropout && s.el >= ELEVATION_MASK;
+        let geometry: Vec<_> = visible
+            .iter()
+            .filter(|s| used(s))
+            .map(|s| (s.el, s.az))
+            .collect();
+        let dop = dop(&geometry);
+        let mode = match (position, geometry.len()) {
+            (None, _) | (_, 0..=2) => 1,
+            (_, 3) => 2,
+            _ => 3,
+        };
+        let time = Iso8601Timestamp::from_system_time(self.start + t);
+
+        let mut tpv = json!({
+            "class": "TPV",
+            "device": self.device,
+            "mode": mode,
+            "time": time.as_str(),
+        });
+        if let (Some(((lat, lon), track)), true) = (position, mode >= 2) {
+            // Rounded to the 9 decimals printed by `gpsd`.
+            let round = |deg: f64| (deg * 1e9).round() / 1e9;
+            tpv["lat"] = json!(round(lat));
+            tpv["lon"] = json!(round(lon));
+            tpv["track"] = json!((track * 10.0).round() / 10.0);
+            tpv["speed"] = json!(speed);
+            if let Some(dop) = &dop {
+                let ep = |dop: f64| (2.0 * UERE * dop * 1000.0).round() / 1000.0;
+                tpv["epx"] = json!(ep(dop.x));
+                tpv["epy"] = json!(ep(dop.y));
+                tpv["eph"] = json!(ep(dop.h));
+                if mode == 3 {
+                    tpv["epv"] = json!(ep(dop.v));
+                }
+            }
+            if mode == 3 {
+                tpv["altHAE"] = json!(self.altitude);
+                tpv["climb"] = json!(0.0);
+            }
+        }
+
+        let satellites: Vec<_> = visible
+            .iter()
+            .map(|s| {
+                json!({
+                    "PRN": s.prn,
+                    "el": s.el,
+                    "az": s.az,
+                    "ss": (20.0 + 0.3 * s.el).round(),
+                    "used": used(s),
+                })
+            })
+            .collect();
+        let mut sky = json!({
+            "class": "SKY",
+            "device": self.device,
+            "time": time.as_str(),
+            "satellites": satellites,
+        });
+        if let Some(dop) = dop {
+            let round = |dop: f64| (dop * 100.0).round() / 100.0;
+            sky["xdop"] = json!(round(dop.x));
+            sky["ydop"] = json!(round(dop.y));
+            sky["hdop"] = json!(round(dop.h));
+            sky["vdop"] = json!(round(dop.v));
+            sky["pdop"] = json!(round(dop.p));
+            sky["tdop"] = json!(round(dop.t));
+            sky["gdop"] = json!(round(dop.g));
+        }
+        [tpv, sky]
+    }
+}
+
+struct Dop {
+    x: f64,
+    y: f64,
+    h: f64,
+    v: f64,
+    p: f64,
+    t: f64,
+    g: f64,
+}
+
+/// DOP of satellites at `(elevation, azimuth)` in degrees, `None` if
+/// the geometry doesn't allow a 3D fix.
+fn dop(satellites: &[(f64, f64)]) -> Option<Dop> {
+    // Normal matrix of the line-of-sight vectors east, north, up and
+    // the clock, inverted by Gauss-Jordan elimination.
+    let mut m = [[0.0; 8]; 4];
+    for &(el, az) in satellites {
+        let (el, az) = (el.to_radians(), az.to_radians());
+        let row = [el.cos() * az.sin(), el.cos() * az.cos(), el.sin(), 1.0];
+        for (i, m) in m.iter_mut().enumerate() {
+            for (j, r) in row.iter().enumerate() {
+                m[j] += row[i] * r;
+            }
+        }
+    }
+    for (i, m) in m.iter_mut().enumerate() {
+        m[4 + i] = 1.0;
+    }
+    for col in 0..4 {
+        let pivot = (col..4).max_by(|&a, &b| m[a][col].abs().total_cmp(&m[b][col].abs()))?;
+        if m[pivot][col].abs() < 1e-9 {
+            return None;
+        }
+        m.swap(col, pivot);
+        let p = m[col][col];
+        m[col].iter_mut().for_each(|v| *v /= p);
+        let row = m[col];
+        for (i, m) in m.iter_mut().enumerate() {
+            if i != col {
+                let f = m[col];
+                m.iter_mut().zip(row).for_each(|(v, r)| *v -= f * r);
+            }
+        }
+    }
+    let q = |i: usize| m[i][4 + i];
+    Some(Dop {
+        x: q(0).sqrt(),
+        y: q(1).sqrt(),
+        h: (q(0) + q(1)).sqrt(),
+        v: q(2).sqrt(),
+        p: (q(0) + q(1) + q(2)).sqrt(),
+        t: q(3).sqrt(),
+        g: (q(0) + q(1) + q(2) + q(3)).sqrt(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Scenario;
+    use crate::geo::{geodesic_inverse, GeoPoint};
+    use crate::{Mode, UnifiedResponse};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn simulates_scenario() {
+        let secs = Duration::from_secs;
+        let scenario = Scenario::new(UNIX_EPOCH + secs(1_700_000_000))
+            .waypoint(48.1, 11.5)
+            .waypoint(48.1, 11.502)
+            .waypoint(48.101, 11.502)
+            .with_speed(10.0)
+            .speed_at(secs(10), 20.0)
+            .with_satellites(8)
+            .satellite(30, 3.0, 10.0)
+            .obstruction(secs(5), secs(2), 60.0)
+            .dropout(secs(12), secs(1))
+            .with_duration(secs(30));
+        let messages: Vec<_> = scenario.messages().collect();
+        assert_eq!(messages.len(), 62);
+        let tpvs: Vec<_> = messages
+            .iter()
+            .filter_map(|msg| match msg {
+                UnifiedResponse::Tpv(tpv) => Some(&**tpv),
+                _ => None,
+            })
+            .collect();
+
+        let first = tpvs[0];
+        assert_eq!(first.time.unwrap(), "2023-11-14T22:13:20.000Z");
+        assert_eq!(first.mode, Mode::Fix3d);
+        assert_eq!((first.lat, first.lon), (Some(48.1), Some(11.5)));
+        assert!((first.track.unwrap() - 90.0).abs() < 0.1);
+        let eph = first.eph.unwrap();
+        assert!(eph > 1.0 && eph < 20.0);
+        fn moved(a: impl GeoPoint, b: impl GeoPoint) -> f64 {
+            geodesic_inverse(a, b).unwrap().distance_m
+        }
+        assert!((moved((48.1, 11.5), tpvs[10]) - 100.0).abs() < 1e-3);
+        assert!((moved(tpvs[10], tpvs[11]) - 20.0).abs() < 1e-3);
+
+        assert_eq!(tpvs[5].mode, Mode::NoFix);
+        assert_eq!(tpvs[7].mode, Mode::Fix3d);
+        assert_eq!(tpvs[12].mode, Mode::NoFix);
+        assert!(tpvs[12].lat.is_none());
+        let last = tpvs[30];
+        assert!(moved((48.101, 11.502), last) < 1e-3);
+        assert_eq!(last.speed, Some(0.0));
+
+        let UnifiedResponse::Sky(sky) = &messages[1] else {
+            panic!("expected SKY");
+        };
+        let sats = sky.satellites.as_ref().unwrap();
+        assert_eq!(sats.len(), 9);
+        assert!(!sats.iter().find(|s| s.prn == 30).unwrap().used);
+        assert!(sky.hdop.unwrap() > 0.5 && sky.pdop >= sky.hdop);
+
+        // Without speed the receiver never arrives.
+        let parked = Scenario::new(UNIX_EPOCH)
+            .waypoint(48.1, 11.5)
+            .waypoint(48.1, 11.502);
+        assert_eq!(parked.messages().count(), 2);
+        let stopping = parked.with_speed(10.0).speed_at(secs(3), 0.0);
+        assert_eq!(stopping.messages().count(), 8);
+    }
+}
@@ -0,0 +1,719 @@
+//! Signal statistics of the satellites in view.
+
+use crate::{Satellite, Sky};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::time::Instant;
+
+/// Satellite navigation system of a satellite.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub enum Constellation {
+    /// GPS of the United States.
+    Gps,
+    /// Satellite based augmentation systems, like WAAS and EGNOS.
+    Sbas,
+    /// Galileo of the European Union.
+    Galileo,
+    /// BeiDou of China.
+    BeiDou,
+    /// Indoor messaging system of Japan.
+    Imes,
+    /// Quasi-zenith satellite system of Japan.
+    Qzss,
+    /// GLONASS of Russia.
+    Glonass,
+    /// NavIC of India, also known as IRNSS.
+    NavIc,
+    /// Unknown `gnssid` or PRN.
+    Other,
+}
+
+impl Constellation {
+    /// Constellation of a `gnssid` as reported by `gpsd`.
+    pub fn from_gnssid(gnssid: u8) -> Self {
+        match gnssid {
+            0 => Constellation::Gps,
+            1 => Constellation::Sbas,
+            2 => Constellation::Galileo,
+            3 => Constellation::BeiDou,
+            4 => Constellation::Imes,
+            5 => Constellation::Qzss,
+            6 => Constellation::Glonass,
+            7 => Constellation::NavIc,
+            _ => Constellation::Other,
+        }
+    }
+
+    /// Constellation of an NMEA style PRN, for `gpsd` versions not
+    /// reporting `gnssid`.
+    pub fn from_prn(prn: i16) -> Self {
+        match prn {
+            1..=63 => Constellation::Gps,
+            64..=96 => Constellation::Glonass,
+            100..=164 => Constellation::Sbas,
+            173..=182 => Constellation::Imes,
+            193..=200 => Constellation::Qzss,
+            201..=263 | 401..=463 => Constellation::BeiDou,
+            301..=336 => Constellation::Galileo,
+            _ => Constellation::Other,
+        }
+    }
+}
+
+/// Converts an NMEA style PRN, as reported by `gpsd`, to the `gnssid`
+/// and `svid` of u-blox receivers. BeiDou PRNs of both the old range
+/// 201-263 and the current range 401-463 are accepted; the former
+/// limits QZSS to 193-200.
+///
+/// Returns `None` for PRNs outside of the known ranges.
+pub fn prn_to_gnss(prn: i16) -> Option<(u8, u16)> {
+    let (gnssid, first) = match prn {
+        1..=63 => (0, 1),
+        65..=96 => (6, 65),
+        120..=158 => (1, 120),
+        173..=182 => (4, 173),
+        193..=200 => (5, 193),
+        201..=263 => (3, 201),
+        301..=336 => (2, 301),
+        401..=463 => (3, 401),
+        _ => return None,
+    };
+    // SBAS keeps its PRN as svid.
+    let svid = if gnssid == 1 { prn } else { prn - first + 1 };
+    Some((gnssid, svid as u16))
+}
+
+/// Converts a `gnssid` and `svid` to the NMEA style PRN used by current
+/// `gpsd` versions, the inverse of [`prn_to_gnss`].
+///
+/// Returns `None` for unknown systems, NavIC, and out of range `svid`s.
+pub fn gnss_to_prn(gnssid: u8, svid: u16) -> Option<i16> {
+    let (first, max) = match gnssid {
+        0 => (1, 63),
+        1 => return (120..=158).contains(&svid).then_some(svid as i16),
+        2 => (301, 36),
+        3 => (401, 63),
+        4 => (173, 10),
+        5 => (193, 8),
+        6 => (65, 32),
+        _ => return None,
+    };
+    (1..=max).contains(&svid).then(|| first + svid as i16 - 1)
+}
+
+/// GLONASS frequency slot, -7 to 6, of a `freqid` as reported by
+/// `gpsd`. Returns `None` for values out of range.
+pub fn glonass_slot(freqid: u8) -> Option<i8> {
+    (freqid <= 13).then(|| freqid as i8 - 7)
+}
+
+/// Center frequency in Hz of the GLONASS L1 FDMA signal of frequency
+/// `slot`.
+pub fn glonass_l1_hz(slot: i8) -> f64 {
+    1_602e6 + f64::from(slot) * 562.5e3
+}
+
+/// Center frequency in Hz of the GLONASS L2 FDMA signal of frequency
+/// `slot`.
+pub fn glonass_l2_hz(slot: i8) -> f64 {
+    1_246e6 + f64::from(slot) * 437.5e3
+}
+
+/// Signal of a satellite, the `gnssid` and `sigid` pair of u-blox
+/// receivers.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct SignalId {
+    /// GNSS ID of the constellation, see [`Constellation::from_gnssid`].
+    pub gnssid: u8,
+    /// Signal ID within the constellation.
+    pub sigid: u8,
+}
+
+impl SignalId {
+    /// Creates the signal ID of a `gnssid` and `sigid` pair.
+    pub fn new(gnssid: u8, sigid: u8) -> Self {
+        SignalId { gnssid, sigid }
+    }
+
+    /// Name of the signal, e.g. `"GPS L1C/A"`, or `None` if the pair
+    /// is not defined by u-blox.
+    pub fn name(self) -> Option<&'static str> {
+        let name = match (self.gnssid, self.sigid) {
+            (0, 0) => "GPS L1C/A",
+            (0, 3) => "GPS L2CL",
+            (0, 4) => "GPS L2CM",
+            (0, 6) => "GPS L5I",
+            (0, 7) => "GPS L5Q",
+            (1, 0) => "SBAS L1C/A",
+            (2, 0) => "Galileo E1C",
+            (2, 1) => "Galileo E1B",
+            (2, 3) => "Galileo E5aI",
+            (2, 4) => "Galileo E5aQ",
+            (2, 5) => "Galileo E5bI",
+            (2, 6) => "Galileo E5bQ",
+            (2, 8) => "Galileo E6B",
+            (2, 9) => "Galileo E6C",
+            (2, 10) => "Galileo E6A",
+            (3, 0) => "BeiDou B1I D1",
+            (3, 1) => "BeiDou B1I D2",
+            (3, 2) => "BeiDou B2I D1",
+            (3, 3) => "BeiDou B2I D2",
+            (3, 4) => "BeiDou B3I D1",
+            (3, 5) => "BeiDou B1Cp",
+            (3, 6) => "BeiDou B1Cd",
+            (3, 7) => "BeiDou B2ap",
+            (3, 8) => "BeiDou B2ad",
+            (3, 10) => "BeiDou B3I D2",
+            (4, 0) => "IMES L1",
+            (5, 0) => "QZSS L1C/A",
+            (5, 1) => "QZSS L1S",
+            (5, 4) => "QZSS L2CM",
+            (5, 5) => "QZSS L2CL",
+            (5, 8) => "QZSS L5I",
+            (5, 9) => "QZSS L5Q",
+            (5, 12) => "QZSS L1C/B",
+            (6, 0) => "GLONASS L1OF",
+            (6, 2) => "GLONASS L2OF",
+            (7, 0) => "NavIC L5A",
+            _ => return None,
+        };
+        Some(name)
+    }
+}
+
+impl fmt::Display for SignalId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.name() {
+            Some(name) => write!(f, "{}", name),
+            None => write!(f, "gnssid {} sigid {}", self.gnssid, self.sigid),
+        }
+    }
+}
+
+/// Satellite based augmentation system broadcasting corrections.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub enum SbasSystem {
+    /// Wide Area Augmentation System, North America.
+    Waas,
+    /// European Geostationary Navigation Overlay Service.
+    Egnos,
+    /// MTSAT Satellite Augmentation System, Japan.
+    Msas,
+    /// GPS Aided GEO Augmented Navigation, India.
+    Gagan,
+    /// System for Differential Corrections and Monitoring, Russia.
+    Sdcm,
+    /// BeiDou Satellite Based Augmentation System, China.
+    Bdsbas,
+    /// Korea Augmentation Satellite System.
+    Kass,
+    /// Southern Positioning Augmentation Network, Australia and New
+    /// Zealand.
+    SouthPan,
+}
+
+impl SbasSystem {
+    /// Short name of the system, e.g. `"WAAS"`.
+    pub fn name(self) -> &'static str {
+        match self {
+            SbasSystem::Waas => "WAAS",
+            SbasSystem::Egnos => "EGNOS",
+            SbasSystem::Msas => "MSAS",
+            SbasSystem::Gagan => "GAGAN",
+            SbasSystem::Sdcm => "SDCM",
+            SbasSystem::Bdsbas => "BDSBAS",
+            SbasSystem::Kass => "KASS",
+            SbasSystem::SouthPan => "SouthPAN",
+        }
+    }
+}
+
+/// Augmentation system and satellite name of an SBAS PRN, e.g.
+/// `(SbasSystem::Egnos, "Astra 5B")` for PRN 123.
+///
+/// The assignments follow the PRN list of the GPS directorate and
+/// change as satellites are replaced; `None` is returned for PRNs not
+/// assigned to an operational system.
+pub fn sbas_satellite(prn: i16) -> Option<(SbasSystem, &'static str)> {
+    let sat = match prn {
+        121 => (SbasSystem::Egnos, "Eutelsat 5WB"),
+        122 => (SbasSystem::SouthPan, "Inmarsat 4F1"),
+        123 => (SbasSystem::Egnos, "Astra 5B"),
+        125 => (SbasSystem::Sdcm, "Luch-5A"),
+        126 => (SbasSystem::Egnos, "Inmarsat 4F2"),
+        127 => (SbasSystem::Gagan, "GSAT-8"),
+        128 => (SbasSystem::Gagan, "GSAT-10"),
+        129 => (SbasSystem::Msas, "QZS-3"),
+        130 => (SbasSystem::Bdsbas, "BDS G6"),
+        131 => (SbasSystem::Waas, "Eutelsat 117 West B"),
+        132 => (SbasSystem::Gagan, "GSAT-15"),
+        133 => (SbasSystem::Waas, "SES-15"),
+        134 => (SbasSystem::Kass, "MEASAT-3D"),
+        135 => (SbasSystem::Waas, "Galaxy 30"),
+        136 => (SbasSystem::Egnos, "SES-5"),
+        137 => (SbasSystem::Msas, "QZS-3"),
+        140 => (SbasSystem::Sdcm, "Luch-5B"),
+        141 => (SbasSystem::Sdcm, "Luch-4"),
+        143 => (SbasSystem::Bdsbas, "BDS G3"),
+        144 => (SbasSystem::Bdsbas, "BDS G1"),
+        _ => return None,
+    };
+    Some(sat)
+}
+
+impl Satellite {
+    /// Constellation of the satellite, from `gnssid` or else the PRN.
+    pub fn constellation(&self) -> Constellation {
+        match self.gnssid {
+            Some(id) => Constellation::from_gnssid(id),
+            None => Constellation::from_prn(self.prn),
+        }
+    }
+
+    /// The `gnssid` and `svid` of the satellite, as reported or
+    /// derived from the PRN.
+    pub fn gnss_id(&self) -> Option<(u8, u16)> {
+        match (self.gnssid, self.svid) {
+            (Some(gnssid), Some(svid)) => Some((gnssid, svid)),
+            _ => prn_to_gnss(self.prn),
+        }
+    }
+
+    /// Signal of the satellite, if `gnssid` and `sigid` are reported.
+    pub fn signal(&self) -> Option<SignalId> {
+        Some(SignalId::new(self.gnssid?, self.sigid?))
+    }
+
+    /// GLONASS frequency slot of the satellite, see [`glonass_slot`].
+    pub fn glonass_slot(&self) -> Option<i8> {
+        if self.constellation() != Constellation::Glonass {
+            return None;
+        }
+        glonass_slot(self.freqid?)
+    }
+
+    /// Augmentation system and name of an SBAS satellite, see
+    /// [`sbas_satellite`].
+    pub fn sbas(&self) -> Option<(SbasSystem, &'static str)> {
+        match self.gnss_id()? {
+            (1, svid) => sbas_satellite(svid as i16),
+            _ => None,
+        }
+    }
+}
+
+/// Signal statistics of a single constellation, see [`SkyStats`].
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct ConstellationStats {
+    /// Number of satellites in view.
+    pub seen: usize,
+    /// Number of satellites used in the solution.
+    pub used: usize,
+    /// Mean signal strength (C/N0) in dB-Hz of the satellites in view
+    /// reporting one.
+    pub mean_ss: Option<f32>,
+    /// Strongest signal in dB-Hz.
+    pub max_ss: Option<f32>,
+}
+
+/// Signal statistics of a SKY report per constellation, e.g. to judge
+/// antenna placement or interference.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct SkyStats {
+    /// Statistics of the constellations with satellites in view.
+    pub constellations: BTreeMap<Constellation, ConstellationStats>,
+    /// The used satellite with the weakest signal.
+    pub weakest_used: Option<Satellite>,
+}
+
+impl SkyStats {
+    /// Computes the statistics of `sky`.
+    pub fn new(sky: &Sky) -> Self {
+        let mut stats = SkyStats::default();
+        let mut sums = BTreeMap::new();
+        for sat in sky.satellites.iter().flatten() {
+            let c = stats.constellations.entry(sat.constellation()).or_default();
+            c.seen += 1;
+            c.used += usize::from(sat.used);
+            if let Some(ss) = sat.ss {
+                c.max_ss = Some(c.max_ss.map_or(ss, |max| max.max(ss)));
+                let (sum, n) = sums.entry(sat.constellation()).or_insert((0.0, 0));
+                *sum += ss;
+                *n += 1;
+            }
+            if sat.used
+                && sat.ss.is_some()
+                && stats
+                    .weakest_used
+                    .as_ref()
+                    .is_none_or(|weakest| sat.ss < weakest.ss)
+            {
+                stats.weakest_used = Some(sat.clone());
+            }
+        }
+        for (constellation, (sum, n)) in sums {
+            if let Some(c) = stats.constellations.get_mut(&constellation) {
+                c.mean_ss = Some(sum / n as f32);
+            }
+        }
+        stats
+    }
+
+    /// Statistics of `constellation`, empty if no satellite is in view.
+    pub fn get(&self, constellation: Constellation) -> ConstellationStats {
+        self.constellations
+            .get(&constellation)
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+/// Quality class of the dilution of precision, see [`DopMonitor`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DopLevel {
+    /// The DOP is at most the degraded threshold.
+    Good,
+    /// The DOP is above the degraded threshold.
+    Degraded,
+    /// The DOP is above the unusable threshold.
+    Unusable,
+}
+
+/// Change of the [`DopLevel`], see [`DopMonitor::update`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DopEvent {
+    /// Previous level, `None` for the first report.
+    pub from: Option<DopLevel>,
+    /// New level.
+    pub to: DopLevel,
+    /// Smoothed DOP causing the change.
+    pub dop: f32,
+}
+
+/// Tracks the smoothed HDOP and PDOP of SKY reports and reports when
+/// the quality crosses a threshold.
+///
+/// The level is determined by the PDOP, or by the HDOP for receivers
+/// not reporting PDOP.
+#[derive(Debug, Clone)]
+pub struct DopMonitor {
+    weight: f32,
+    degraded: f32,
+    unusable: f32,
+    hdop: Option<f32>,
+    pdop: Option<f32>,
+    level: Option<DopLevel>,
+}
+
+impl DopMonitor {
+    /// Creates a monitor regarding a DOP above 5 as degraded and above
+    /// 10 as unusable, smoothing with a weight of 0.3 for new values.
+    pub fn new() -> Self {
+        DopMonitor {
+            weight: 0.3,
+            degraded: 5.0,
+            unusable: 10.0,
+            hdop: None,
+            pdop: None,
+            level: None,
+        }
+    }
+
+    /// Sets the DOPs above which the quality is degraded or unusable.
+    pub fn with_thresholds(mut self, degraded: f32, unusable: f32) -> Self {
+        self.degraded = degraded;
+        self.unusable = unusable;
+        self
+    }
+
+    /// Sets the weight of new values, from 0 (ignore) to 1 (no
+    /// smoothing).
+    pub fn with_weight(mut self, weight: f32) -> Self {
+        self.weight = weight.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Smoothed HDOP.
+    pub fn hdop(&self) -> Option<f32> {
+        self.hdop
+    }
+
+    /// Smoothed PDOP.
+    pub fn pdop(&self) -> Option<f32> {
+        self.pdop
+    }
+
+    /// Current level, `None` before the first report with a DOP.
+    pub fn level(&self) -> Option<DopLevel> {
+        self.level
+    }
+
+    /// Adds the DOPs of `sky` and returns the change of the level, if
+    /// any.
+    pub fn update(&mut self, sky: &Sky) -> Option<DopEvent> {
+        let smooth = |old: Option<f32>, new: Option<f32>| match (old, new) {
+            (Some(old), Some(new)) => Some(old + self.weight * (new - old)),
+            (old, new) => new.or(old),
+        };
+        self.hdop = smooth(self.hdop, sky.hdop);
+        self.pdop = smooth(self.pdop, sky.pdop);
+        let dop = self.pdop.or(self.hdop)?;
+        let level = if dop > self.unusable {
+            DopLevel::Unusable
+        } else if dop > self.degraded {
+            DopLevel::Degraded
+        } else {
+            DopLevel::Good
+        };
+        let from = self.level.replace(level);
+        (from != Some(level)).then_some(DopEvent {
+            from,
+            to: level,
+            dop,
+        })
+    }
+}
+
+impl Default for DopMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// History of a single satellite, see [`SatTracker`].
+#[derive(Debug, Clone)]
+pub struct SatHistory {
+    /// Constellation of the satellite.
+    pub constellation: Constellation,
+    /// Time of the first report listing the satellite.
+    pub first_seen: Instant,
+    /// Time of the latest report listing the satellite.
+    pub last_seen: Instant,
+    /// Recent signal strengths in dB-Hz, oldest first.
+    pub ss: VecDeque<(Instant, f32)>,
+    /// Number of reports listing the satellite.
+    pub seen: u64,
+    /// Number of reports in which the satellite has been used.
+    pub used: u64,
+}
+
+impl SatHistory {
+    /// Share of the reports in which the satellite has been used.
+    pub fn used_ratio(&self) -> f64 {
+        self.used as f64 / self.seen as f64
+    }
+}
+
+/// Keeps the history of every satellite listed in SKY reports, by PRN.
+#[derive(Debug, Clone)]
+pub struct SatTracker {
+    len: usize,
+    sats: BTreeMap<i16, SatHistory>,
+}
+
+impl SatTracker {
+    /// Creates a tracker keeping the latest `len` signal strengths of
+    /// each satellite.
+    pub fn new(len: usize) -> Self {
+        SatTracker {
+            len,
+            sats: BTreeMap::new(),
+        }
+    }
+
+    /// Adds a report received just now, see
+    /// [`SatTracker::update_at`].
+    pub fn update(&mut self, sky: &Sky) {
+        self.update_at(sky, Instant::now())
+    }
+
+    /// Adds the satellites of a report received at `received`.
+    pub fn update_at(&mut self, sky: &Sky, received: Instant) {
+        for sat in sky.satellites.iter().flatten() {
+            let history = self.sats.entry(sat.prn).or_insert_with(|| SatHistory {
+                constellation: sat.constellation(),
+                first_seen: received,
+                last_seen: received,
+                ss: VecDeque::new(),
+                seen: 0,
+                used: 0,
+            });
+            history.last_seen = received;
+            history.seen += 1;
+            history.used += u64::from(sat.used);
+            if let Some(ss) = sat.ss.filter(|_| self.len > 0) {
+                if history.ss.len() == self.len {
+                    history.ss.pop_front();
+                }
+                history.ss.push_back((received, ss));
+            }
+        }
+    }
+
+    /// History of the satellite with `prn`.
+    pub fn get(&self, prn: i16) -> Option<&SatHistory> {
+        self.sats.get(&prn)
+    }
+
+    /// Histories of all satellites seen, ordered by PRN.
+    pub fn iter(&self) -> impl Iterator<Item = (i16, &SatHistory)> {
+        self.sats.iter().map(|(prn, h)| (*prn, h))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        glonass_l1_hz, glonass_l2_hz, glonass_slot, gnss_to_prn, prn_to_gnss, sbas_satellite,
+        Constellation, DopEvent, DopLevel, DopMonitor, SatTracker, SbasSystem, SignalId, SkyStats,
+    };
+    use crate::Sky;
+
+    #[test]
+    fn per_constellation_stats() {
+        let sky: Sky = serde_json::from_str(
+            r#"{"satellites":[
+                {"PRN":5,"ss":40,"used":true,"gnssid":0,"svid":5},
+                {"PRN":7,"ss":30,"used":true,"gnssid":0,"svid":7},
+                {"PRN":12,"ss":20,"used":false,"gnssid":0,"svid":12},
+                {"PRN":68,"ss":35,"used":true},
+                {"PRN":301,"used":false,"gnssid":2,"svid":1}
+            ]}"#,
+        )
+        .unwrap();
+        let stats = SkyStats::new(&sky);
+        let gps = stats.get(Constellation::Gps);
+        assert_eq!((gps.seen, gps.used), (3, 2));
+        assert_eq!((gps.mean_ss, gps.max_ss), (Some(30.0), Some(40.0)));
+        let glonass = stats.get(Constellation::Glonass);
+        assert_eq!(
+            (glonass.seen, glonass.used, glonass.max_ss),
+            (1, 1, Some(35.0))
+        );
+        let galileo = stats.get(Constellation::Galileo);
+        assert_eq!((galileo.seen, galileo.mean_ss), (1, None));
+        assert_eq!(stats.get(Constellation::BeiDou).seen, 0);
+        assert_eq!(stats.weakest_used.unwrap().prn, 7);
+    }
+
+    #[test]
+    fn dop_thresholds() {
+        let sky = |pdop: f32| -> Sky {
+            serde_json::from_str(&format!(r#"{{"hdop":1.0,"pdop":{}}}"#, pdop)).unwrap()
+        };
+        let mut monitor = DopMonitor::new().with_weight(0.5);
+        assert_eq!(
+            monitor.update(&sky(2.0)),
+            Some(DopEvent {
+                from: None,
+                to: DopLevel::Good,
+                dop: 2.0
+            })
+        );
+        // A single outlier is smoothed away.
+        assert_eq!(monitor.update(&sky(7.0)), None);
+        assert_eq!(monitor.update(&sky(2.0)), None);
+        let levels: Vec<_> = [12.0, 20.0, 20.0, 20.0]
+            .into_iter()
+            .filter_map(|pdop| monitor.update(&sky(pdop)))
+            .map(|e| e.to)
+            .collect();
+        assert_eq!(levels, [DopLevel::Degraded, DopLevel::Unusable]);
+        assert_eq!(monitor.hdop(), Some(1.0));
+        assert_eq!(monitor.level(), Some(DopLevel::Unusable));
+    }
+
+    #[test]
+    fn tracks_satellites() {
+        use std::time::{Duration, Instant};
+
+        let data = br#"{"class":"SKY","satellites":[{"PRN":5,"ss":30,"used":true},{"PRN":68,"ss":20,"used":false}]}
+{"class":"SKY","satellites":[{"PRN":5,"ss":32,"used":true}]}
+{"class":"SKY","satellites":[{"PRN":5,"ss":34,"used":false},{"PRN":68,"used":false}]}
+"#;
+        let start = Instant::now();
+        let mut tracker = SatTracker::new(2);
+        for (i, msg) in crate::parse_lines(data).enumerate() {
+            if let crate::UnifiedResponse::Sky(sky) = msg.unwrap() {
+                tracker.update_at(&sky, start + Duration::from_secs(i as u64));
+            }
+        }
+        let gps = tracker.get(5).unwrap();
+        assert_eq!(gps.constellation, Constellation::Gps);
+        assert_eq!(
+            (gps.first_seen, gps.last_seen),
+            (start, start + Duration::from_secs(2))
+        );
+        let ss: Vec<_> = gps.ss.iter().map(|(_, ss)| *ss).collect();
+        assert_eq!(ss, [32.0, 34.0]);
+        assert!((gps.used_ratio() - 2.0 / 3.0).abs() < 1e-9);
+        let glonass = tracker.get(68).unwrap();
+        assert_eq!((glonass.seen, glonass.ss.len()), (2, 1));
+        assert_eq!(
+            tracker.iter().map(|(prn, _)| prn).collect::<Vec<_>>(),
+            [5, 68]
+        );
+    }
+
+    #[test]
+    fn converts_prn_numbering() {
+        for (prn, gnss) in [
+            (7, (0, 7)),
+            (131, (1, 131)),
+            (305, (2, 5)),
+            (412, (3, 12)),
+            (175, (4, 3)),
+            (194, (5, 2)),
+            (70, (6, 6)),
+        ] {
+            assert_eq!(prn_to_gnss(prn), Some(gnss), "{}", prn);
+            assert_eq!(gnss_to_prn(gnss.0, gnss.1), Some(prn), "{:?}", gnss);
+        }
+        assert_eq!(prn_to_gnss(212), Some((3, 12)));
+        assert_eq!(prn_to_gnss(0), None);
+        assert_eq!(gnss_to_prn(0, 64), None);
+        assert_eq!(gnss_to_prn(7, 1), None);
+
+        let sat: crate::Satellite = serde_json::from_str(r#"{"PRN":70,"used":true}"#).unwrap();
+        assert_eq!(sat.gnss_id(), Some((6, 6)));
+    }
+
+    #[test]
+    fn glonass_frequencies() {
+        let sat: crate::Satellite =
+            serde_json::from_str(r#"{"PRN":70,"used":true,"gnssid":6,"svid":6,"freqid":2}"#)
+                .unwrap();
+        let slot = sat.glonass_slot().unwrap();
+        assert_eq!(slot, -5);
+        assert_eq!(glonass_l1_hz(slot), 1_599.187_5e6);
+        assert_eq!(glonass_l2_hz(slot), 1_243.812_5e6);
+        assert_eq!(glonass_slot(14), None);
+    }
+
+    #[test]
+    fn names_signals() {
+        let sat: crate::Satellite =
+            serde_json::from_str(r#"{"PRN":305,"used":true,"gnssid":2,"svid":5,"sigid":5}"#)
+                .unwrap();
+        assert_eq!(sat.signal().unwrap().to_string(), "Galileo E5bI");
+        assert_eq!(SignalId::new(0, 0).name(), Some("GPS L1C/A"));
+        assert_eq!(SignalId::new(0, 1).to_string(), "gnssid 0 sigid 1");
+    }
+
+    #[test]
+    fn identifies_sbas() {
+        assert_eq!(sbas_satellite(123), Some((SbasSystem::Egnos, "Astra 5B")));
+        assert_eq!(sbas_satellite(138), None);
+        let sat: crate::Satellite =
+            serde_json::from_str(r#"{"PRN":135,"used":false,"gnssid":1,"svid":135}"#).unwrap();
+        let (system, name) = sat.sbas().unwrap();
+        assert_eq!((system.name(), name), ("WAAS", "Galaxy 30"));
+        let gps: crate::Satellite = serde_json::from_str(r#"{"PRN":5,"used":true}"#).unwrap();
+        assert_eq!(gps.sbas(), None);
+    }
+}
@@ -0,0 +1,325 @@
+//! Plausibility checks of SKY reports against signs of spoofing.
+
+use crate::{Constellation, Health, Sky, Tpv};
+#[cfg(feature = "subframe")]
+use crate::{NavDecoder, GPS_EPOCH_UNIX_SECS};
+use std::collections::BTreeMap;
+use std::time::SystemTime;
+#[cfg(feature = "subframe")]
+use std::time::UNIX_EPOCH;
+
+/// A satellite expected to be in view, see [`Almanac`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ExpectedSat {
+    /// PRN of the satellite, numbered as in SKY reports.
+    pub prn: i16,
+    /// Elevation in degrees.
+    pub el: f32,
+    /// Azimuth in degrees from true north.
+    pub az: f32,
+    /// Health flag of the almanac.
+    pub healthy: bool,
+}
+
+/// Source of the satellites expected in view at a position and time,
+/// usually computed from an almanac or ephemerides.
+pub trait Almanac {
+    /// Satellites above the horizon at `lat`, `lon` at `time`, or `None`
+    /// if the almanac does not cover `time`.
+    fn expected(&self, lat: f64, lon: f64, time: SystemTime) -> Option<Vec<ExpectedSat>>;
+
+    /// Returns `true` if the satellites of `constellation` are covered,
+    /// so satellites missing from [`Almanac::expected`] are unexpected.
+    /// All constellations by default.
+    fn covers(&self, constellation: Constellation) -> bool {
+        let _ = constellation;
+        true
+    }
+}
+
+/// No almanac, only the checks not needing one are done.
+impl Almanac for () {
+    fn expected(&self, _lat: f64, _lon: f64, _time: SystemTime) -> Option<Vec<ExpectedSat>> {
+        None
+    }
+}
+
+/// Offset of GPS time ahead of UTC in seconds, 18 since 2017. Assumed
+/// by the [`Almanac`] of [`NavDecoder`], as [`Almanac::expected`] is
+/// given UTC without the leap seconds.
+#[cfg(feature = "subframe")]
+const GPS_UTC_OFFSET_SECS: u64 = 18;
+
+/// Satellites predicted from the ephemerides and almanac entries of
+/// SUBFRAME reports, see [`NavDecoder::sky_view`]. Only GPS satellites
+/// are covered, at the height of the ellipsoid; `None` while no record
+/// has been decoded.
+#[cfg(feature = "subframe")]
+impl Almanac for NavDecoder {
+    fn expected(&self, lat: f64, lon: f64, time: SystemTime) -> Option<Vec<ExpectedSat>> {
+        if self.ephemerides().next().is_none() && self.almanac_entries().next().is_none() {
+            return None;
+        }
+        let unix = time.duration_since(UNIX_EPOCH).ok()?.as_secs_f64();
+        let gps = unix - GPS_EPOCH_UNIX_SECS as f64 + GPS_UTC_OFFSET_SECS as f64;
+        let tow = gps.rem_euclid(604_800.0);
+        let view = self.sky_view((lat, lon, 0.0), tow);
+        Some(
+            view.into_iter()
+                .map(|(prn, az, el)| ExpectedSat {
+                    prn: prn.into(),
+                    el: el as f32,
+                    az: az as f32,
+                    healthy: match self.ephemeris(prn) {
+                        Some(eph) => eph.health == 0,
+                        None => self.almanac(prn).is_none_or(|alm| alm.health == 0),
+                    },
+                })
+                .collect(),
+        )
+    }
+
+    fn covers(&self, constellation: Constellation) -> bool {
+        constellation == Constellation::Gps
+    }
+}
+
+/// Suspicious finding of [`SpoofDetector::check`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SpoofIndicator {
+    /// All satellites of a constellation are received at nearly the
+    /// same signal strength, as from a single transmitter.
+    UniformSignal {
+        constellation: Constellation,
+        /// Difference of the strongest and weakest signal in dB.
+        spread_db: f32,
+    },
+    /// Elevation or azimuth out of range, or a satellite below the
+    /// horizon used in the solution.
+    ImpossibleGeometry(i16),
+    /// Satellite not expected above the elevation mask.
+    UnexpectedSatellite(i16),
+    /// Satellite seen too far from its expected direction.
+    Misplaced {
+        prn: i16,
+        /// Angle between the observed and expected direction in
+        /// degrees.
+        error_deg: f32,
+    },
+    /// Satellite unhealthy according to the almanac but used in the
+    /// solution.
+    UnhealthyUsed(i16),
+}
+
+/// Compares the satellites of SKY reports with the expectation of an
+/// [`Almanac`] for the reported position and time.
+///
+/// Each indicator alone may have harmless causes, e.g. multipath or a
+/// stale almanac; several at once are a strong sign of spoofing.
+#[derive(Debug, Clone)]
+pub struct SpoofDetector<A> {
+    almanac: A,
+    min_spread_db: f32,
+    min_sats: usize,
+    elevation_mask: f32,
+    max_angle_error: f32,
+}
+
+impl<A: Almanac> SpoofDetector<A> {
+    /// Creates a detector using `almanac`; pass `()` to only check the
+    /// signal strengths and geometry.
+    pub fn new(almanac: A) -> Self {
+        SpoofDetector {
+            almanac,
+            min_spread_db: 2.0,
+            min_sats: 5,
+            elevation_mask: 10.0,
+            max_angle_error: 10.0,
+        }
+    }
+
+    /// Sets the smallest spread of signal strengths in dB considered
+    /// natural, 2 dB by default, and the number of satellites of a
+    /// constellation needed for the check, 5 by default.
+    pub fn with_min_spread(mut self, min_spread_db: f32, min_sats: usize) -> Self {
+        self.min_spread_db = min_spread_db;
+        self.min_sats = min_sats;
+        self
+    }
+
+    /// Sets the elevation in degrees below which unexpected satellites
+    /// are tolerated, 10° by default.
+    pub fn with_elevation_mask(mut self, elevation_mask: f32) -> Self {
+        self.elevation_mask = elevation_mask;
+        self
+    }
+
+    /// Sets the largest angle in degrees between the observed and
+    /// expected direction of a satellite, 10° by default.
+    pub fn with_max_angle_error(mut self, max_angle_error: f32) -> Self {
+        self.max_angle_error = max_angle_error;
+        self
+    }
+
+    /// Checks `sky` at the position and time of `tpv`. Without a fix
+    /// only the checks not needing the almanac are done.
+    pub fn check(&self, tpv: &Tpv, sky: &Sky) -> Vec<SpoofIndicator> {
+        let position = match (tpv.lat, tpv.lon, tpv.time) {
+            (Some(lat), Some(lon), Some(time)) => Some((lat, lon, time.to_system_time())),
+            _ => None,
+        };
+        self.check_at(sky, position)
+    }
+
+    /// Checks `sky` at `position`, given as latitude, longitude and
+    /// time, if known.
+    pub fn check_at(
+        &self,
+        sky: &Sky,
+        position: Option<(f64, f64, SystemTime)>,
+    ) -> Vec<SpoofIndicator> {
+        let mut indicators = Vec::new();
+        let sats = sky.satellites.as_deref().unwrap_or_default();
+
+        let mut ranges: BTreeMap<_, (f32, f32, usize)> = BTreeMap::new();
+        // Receivers report 0 for satellites tracked without signal.
+        let signals = sats.iter().filter_map(|s| Some((s.constellation(), s.ss?)));
+        for ss in signals.filter(|ss| ss.1 > 0.0) {
+            let (min, max, n) = ranges.entry(ss.0).or_insert((ss.1, ss.1, 0));
+            *min = min.min(ss.1);
+            *max = max.max(ss.1);
+            *n += 1;
+        }
+        for (constellation, (min, max, n)) in ranges {
+            if n >= self.min_sats && max - min < self.min_spread_db {
+                indicators.push(SpoofIndicator::UniformSignal {
+                    constellation,
+                    spread_db: max - min,
+                });
+            }
+        }
+
+        for sat in sats {
+            let el_ok = sat.el.is_none_or(|el| (-90.0..=90.0).contains(&el));
+            let az_ok = sat.az.is_none_or(|az| (0.0..=360.0).contains(&az));
+            if !el_ok || !az_ok || (sat.used && sat.el.is_some_and(|el| el < 0.0)) {
+                indicators.push(SpoofIndicator::ImpossibleGeometry(sat.prn));
+            }
+        }
+
+        let Some((lat, lon, time)) = position else {
+            return indicators;
+        };
+        let Some(expected) = self.almanac.expected(lat, lon, time) else {
+            return indicators;
+        };
+        for sat in sats {
+            // Corrections of SBAS satellites are not part of the almanac.
+            if sat.constellation() == Constellation::Sbas
+                || !self.almanac.covers(sat.constellation())
+            {
+                continue;
+            }
+            let Some(exp) = expected.iter().find(|e| e.prn == sat.prn) else {
+                if sat.el.is_none_or(|el| el >= self.elevation_mask) {
+                    indicators.push(SpoofIndicator::UnexpectedSatellite(sat.prn));
+                }
+                continue;
+            };
+            if let (Some(el), Some(az)) = (sat.el, sat.az) {
+                let error_deg = angle_deg((el, az), (exp.el, exp.az));
+                if error_deg > self.max_angle_error {
+                    indicators.push(SpoofIndicator::Misplaced {
+                        prn: sat.prn,
+                        error_deg,
+                    });
+                }
+            }
+            if sat.used && (!exp.healthy || sat.health == Some(Health::Unhealthy)) {
+                indicators.push(SpoofIndicator::UnhealthyUsed(sat.prn));
+            }
+        }
+        indicators
+    }
+}
+
+impl Default for SpoofDetector<()> {
+    fn default() -> Self {
+        Self::new(())
+    }
+}
+
+/// Angle between two directions given as elevation and azimuth.
+fn angle_deg(a: (f32, f32), b: (f32, f32)) -> f32 {
+    let (el1, az1) = (f64::from(a.0).to_radians(), f64::from(a.1).to_radians());
+    let (el2, az2) = (f64::from(b.0).to_radians(), f64::from(b.1).to_radians());
+    let cos = el1.sin() * el2.sin() + el1.cos() * el2.cos() * (az1 - az2).cos();
+    cos.clamp(-1.0, 1.0).acos().to_degrees() as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Almanac, ExpectedSat, SpoofDetector, SpoofIndicator};
+    use crate::{Constellation, Sky, Tpv};
+    use std::time::SystemTime;
+
+    struct Fixed(Vec<ExpectedSat>);
+
+    impl Almanac for Fixed {
+        fn expected(&self, _: f64, _: f64, _: SystemTime) -> Option<Vec<ExpectedSat>> {
+            Some(self.0.clone())
+        }
+    }
+
+    #[test]
+    fn flags_suspicious_sky() {
+        let sky: Sky = serde_json::from_str(
+            r#"{"satellites":[
+                {"PRN":3,"el":45,"az":90,"ss":41.0,"used":true},
+                {"PRN":5,"el":60,"az":180,"ss":41.5,"used":true},
+                {"PRN":7,"el":20,"az":270,"ss":40.8,"used":true},
+                {"PRN":9,"el":70,"az":10,"ss":41.2,"used":true},
+                {"PRN":11,"el":-5,"az":300,"ss":41.0,"used":true},
+                {"PRN":13,"el":40,"az":120,"ss":0,"used":false},
+                {"PRN":133,"el":30,"az":200,"ss":38,"used":false}
+            ]}"#,
+        )
+        .unwrap();
+        let tpv: Tpv = serde_json::from_str(
+            r#"{"mode":3,"time":"2024-05-01T12:00:00Z","lat":50.0,"lon":8.0}"#,
+        )
+        .unwrap();
+
+        let heuristics = SpoofDetector::default().check(&tpv, &sky);
+        assert_eq!(heuristics.len(), 2, "{:?}", heuristics);
+        assert!(matches!(
+            heuristics[0],
+            SpoofIndicator::UniformSignal {
+                constellation: Constellation::Gps,
+                spread_db,
+            } if (spread_db - 0.7).abs() < 1e-3
+        ));
+        assert_eq!(heuristics[1], SpoofIndicator::ImpossibleGeometry(11));
+
+        let sat = |prn, el, az, healthy| ExpectedSat {
+            prn,
+            el,
+            az,
+            healthy,
+        };
+        let almanac = Fixed(vec![
+            sat(3, 44.0, 92.0, true),
+            sat(5, 30.0, 180.0, false),
+            sat(7, 21.0, 268.0, true),
+            sat(11, 2.0, 300.0, true),
+            sat(13, 40.0, 120.0, true),
+        ]);
+        let indicators = SpoofDetector::new(almanac).check(&tpv, &sky);
+        assert_eq!(indicators.len(), 5, "{:?}", indicators);
+        assert!(indicators.contains(&SpoofIndicator::UnexpectedSatellite(9)));
+        assert!(indicators.contains(&SpoofIndicator::UnhealthyUsed(5)));
+        assert!(indicators
+            .iter()
+            .any(|i| matches!(i, SpoofIndicator::Misplaced { prn: 5, error_deg } if (*error_deg - 30.0).abs() < 0.01)));
+    }
+}
@@ -0,0 +1,488 @@
+//! Durable track logging into an SQLite database, using the SQLite
+//! library of the system.
+
+use crate::{Mode, Sky, Tpv, UnifiedResponse};
+use std::ffi::{CStr, CString};
+use std::io;
+use std::os::raw::{c_char, c_int, c_void};
+use std::path::Path;
+use std::ptr;
+use std::time::UNIX_EPOCH;
+
+#[repr(C)]
+struct Sqlite3 {
+    _opaque: [u8; 0],
+}
+
+#[repr(C)]
+struct Sqlite3Stmt {
+    _opaque: [u8; 0],
+}
+
+const SQLITE_OK: c_int = 0;
+const SQLITE_ROW: c_int = 100;
+const SQLITE_DONE: c_int = 101;
+const SQLITE_OPEN_READWRITE: c_int = 0x02;
+const SQLITE_OPEN_CREATE: c_int = 0x04;
+
+/// `SQLITE_TRANSIENT`, making SQLite copy bound text.
+fn sqlite_transient() -> *const c_void {
+    -1isize as *const c_void
+}
+
+#[link(name = "sqlite3")]
+extern "C" {
+    fn sqlite3_open_v2(
+        filename: *const c_char,
+        db: *mut *mut Sqlite3,
+        flags: c_int,
+        vfs: *const c_char,
+    ) -> c_int;
+    fn sqlite3_close(db: *mut Sqlite3) -> c_int;
+    fn sqlite3_errmsg(db: *mut Sqlite3) -> *const c_char;
+    fn sqlite3_exec(
+        db: *mut Sqlite3,
+        sql: *const c_char,
+        callback: *const c_void,
+        arg: *mut c_void,
+        errmsg: *mut *mut c_char,
+    ) -> c_int;
+    fn sqlite3_prepare_v2(
+        db: *mut Sqlite3,
+        sql: *const c_char,
+        len: c_int,
+        stmt: *mut *mut Sqlite3Stmt,
+        tail: *mut *const c_char,
+    ) -> c_int;
+    fn sqlite3_bind_null(stmt: *mut Sqlite3Stmt, index: c_int) -> c_int;
+    fn sqlite3_bind_int64(stmt: *mut Sqlite3Stmt, index: c_int, value: i64) -> c_int;
+    fn sqlite3_bind_double(stmt: *mut Sqlite3Stmt, index: c_int, value: f64) -> c_int;
+    fn sqlite3_bind_text(
+        stmt: *mut Sqlite3Stmt,
+        index: c_int,
+        text: *const c_char,
+        len: c_int,
+        destructor: *const c_void,
+    ) -> c_int;
+    fn sqlite3_step(stmt: *mut Sqlite3Stmt) -> c_int;
+    fn sqlite3_reset(stmt: *mut Sqlite3Stmt) -> c_int;
+    fn sqlite3_column_int64(stmt: *mut Sqlite3Stmt, column: c_int) -> i64;
+    fn sqlite3_finalize(stmt: *mut Sqlite3Stmt) -> c_int;
+    fn sqlite3_get_autocommit(db: *mut Sqlite3) -> c_int;
+}
+
+/// Schema created by [`TrackLogger::open`], see there.
+pub const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS tpv (
+    id INTEGER PRIMARY KEY,
+    time TEXT,
+    unix_ms INTEGER,
+    device TEXT,
+    mode INTEGER NOT NULL,
+    status INTEGER,
+    lat REAL,
+    lon REAL,
+    alt_msl REAL,
+    alt_hae REAL,
+    speed REAL,
+    track REAL,
+    climb REAL,
+    eph REAL,
+    epv REAL
+);
+CREATE INDEX IF NOT EXISTS tpv_unix_ms ON tpv (unix_ms);
+CREATE TABLE IF NOT EXISTS sky (
+    id INTEGER PRIMARY KEY,
+    time TEXT,
+    unix_ms INTEGER,
+    device TEXT,
+    hdop REAL,
+    vdop REAL,
+    pdop REAL,
+    satellites INTEGER NOT NULL,
+    used INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS sky_unix_ms ON sky (unix_ms);
+";
+
+const INSERT_TPV: &str = "INSERT INTO tpv (time, unix_ms, device, mode, status, lat, lon, \
+    alt_msl, alt_hae, speed, track, climb, eph, epv) \
+    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)";
+
+const INSERT_SKY: &str = "INSERT INTO sky (time, unix_ms, device, hdop, vdop, pdop, \
+    satellites, used) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)";
+
+/// Value bound to a statement parameter.
+enum Param<'a> {
+    Int(Option<i64>),
+    Real(Option<f64>),
+    Text(Option<&'a str>),
+}
+
+/// Logger of TPV and SKY summaries into an SQLite database.
+///
+/// The database is put into WAL mode with `synchronous=NORMAL`, so
+/// readers are not blocked and a crash loses at most the latest
+/// transaction. Rows are inserted in transactions of
+/// [`TrackLogger::with_batch_size`] rows, committed when full, by
+/// [`TrackLogger::flush`] and on drop.
+///
+/// The tables, see [`SCHEMA`]:
+///
+/// - `tpv`: one row per TPV report with `time` as reported, `unix_ms`
+///   the time in milliseconds since the Unix epoch, `device`, `mode`,
+///   `status`, `lat` and `lon` in degrees, `alt_msl` and `alt_hae` in
+///   meters, `speed` and `climb` in m/s, `track` in degrees, and the
+///   horizontal and vertical error estimates `eph` and `epv` in meters.
+/// - `sky`: one row per SKY report with `time`, `unix_ms` and `device`
+///   as above, `hdop`, `vdop` and `pdop`, and the number of
+///   `satellites` in view and `used` in the solution.
+#[derive(Debug)]
+pub struct TrackLogger {
+    db: *mut Sqlite3,
+    insert_tpv: *mut Sqlite3Stmt,
+    insert_sky: *mut Sqlite3Stmt,
+    batch_size: usize,
+    pending: usize,
+    in_transaction: bool,
+    fixes_only: bool,
+}
+
+// The connection is only used through `&mut self`; SQLite is built
+// thread safe by default.
+unsafe impl Send for TrackLogger {}
+
+impl TrackLogger {
+    /// Opens or creates the database at `path` and creates the tables
+    /// if missing.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_str().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "path is not valid UTF-8")
+        })?;
+        let path =
+            CString::new(path).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let mut db = ptr::null_mut();
+        // SAFETY: `path` is a valid C string, `db` receives the handle
+        // which must be closed even on failure.
+        let rc = unsafe {
+            sqlite3_open_v2(
+                path.as_ptr(),
+                &mut db,
+                SQLITE_OPEN_READWRITE | SQLITE_OPEN_CREATE,
+                ptr::null(),
+            )
+        };
+        let mut logger = TrackLogger {
+            db,
+            insert_tpv: ptr::null_mut(),
+            insert_sky: ptr::null_mut(),
+            batch_size: 100,
+            pending: 0,
+            in_transaction: false,
+            fixes_only: false,
+        };
+        if db.is_null() {
+            return Err(io::Error::new(
+                io::ErrorKind::OutOfMemory,
+                "sqlite3_open_v2",
+            ));
+        }
+        logger.check(rc)?;
+        logger.exec("PRAGMA journal_mode=WAL; PRAGMA synchronous=NORMAL;")?;
+        logger.exec(SCHEMA)?;
+        logger.insert_tpv = logger.prepare(INSERT_TPV)?;
+        logger.insert_sky = logger.prepare(INSERT_SKY)?;
+        Ok(logger)
+    }
+
+    /// Sets the number of rows per transaction, 100 by default. Larger
+    /// batches are faster, but lose more rows on a crash.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Skips TPV reports without a 2D or 3D fix, logged by default.
+    pub fn with_fixes_only(mut self, fixes_only: bool) -> Self {
+        self.fixes_only = fixes_only;
+        self
+    }
+
+    /// Logs `msg` if it is a TPV or SKY report, returning `false` if it
+    /// has been skipped.
+    pub fn log(&mut self, msg: &UnifiedResponse) -> io::Result<bool> {
+        match msg {
+            UnifiedResponse::Tpv(tpv) => self.log_tpv(tpv),
+            UnifiedResponse::Sky(sky) => self.log_sky(sky).map(|()| true),
+            _ => Ok(false),
+        }
+    }
+
+    /// Logs a TPV report, returning `false` if it has been skipped.
+    pub fn log_tpv(&mut self, tpv: &Tpv) -> io::Result<bool> {
+        if self.fixes_only && !matches!(tpv.mode, Mode::Fix2d | Mode::Fix3d) {
+            return Ok(false);
+        }
+        let real = |v: Option<f32>| Param::Real(v.map(f64::from));
+        let eph = tpv.eph.or_else(|| Some(tpv.epx?.max(tpv.epy?)));
+        let params = [
+            Param::Text(tpv.time.as_ref().map(|t| t.as_str())),
+            Param::Int(tpv.time.and_then(|t| unix_ms(t.to_system_time()))),
+            Param::Text(tpv.device.as_deref()),
+            Param::Int(Some(u8::from(tpv.mode).into())),
+            Param::Int(tpv.status.map(|s| u8::from(s).into())),
+            Param::Real(tpv.lat),
+            Param::Real(tpv.lon),
+            Param::Real(tpv.altitude_msl()),
+            real(tpv.alt_hae),
+            real(tpv.speed),
+            real(tpv.track),
+            real(tpv.climb),
+            real(eph),
+            real(tpv.epv),
+        ];
+        self.insert(self.insert_tpv, &params)?;
+        Ok(true)
+    }
+
+    /// Logs a summary of a SKY report.
+    pub fn log_sky(&mut self, sky: &Sky) -> io::Result<()> {
+        let sats = sky.satellites.as_deref().unwrap_or_default();
+        let real = |v: Option<f32>| Param::Real(v.map(f64::from));
+        let params = [
+            Param::Text(sky.time.as_ref().map(|t| t.as_str())),
+            Param::Int(sky.time.and_then(|t| unix_ms(t.to_system_time()))),
+            Param::Text(sky.device.as_deref()),
+            real(sky.hdop),
+            real(sky.vdop),
+            real(sky.pdop),
+            Param::Int(Some(sats.len() as i64)),
+            Param::Int(Some(sats.iter().filter(|s| s.used).count() as i64)),
+        ];
+        self.insert(self.insert_sky, &params)
+    }
+
+    /// Commits the pending rows. If the commit fails, e.g. because the
+    /// database is locked, the rows stay pending.
+    pub fn flush(&mut self) -> io::Result<()> {
+        if self.in_transaction {
+            if let Err(e) = self.exec("COMMIT") {
+                self.sync_transaction();
+                return Err(e);
+            }
+            self.in_transaction = false;
+            self.pending = 0;
+        }
+        Ok(())
+    }
+
+    /// Number of rows in the `tpv` and `sky` tables, including pending
+    /// rows.
+    pub fn counts(&mut self) -> io::Result<(u64, u64)> {
+        let stmt = self.prepare("SELECT (SELECT count(*) FROM tpv), (SELECT count(*) FROM sky)")?;
+        // SAFETY: `stmt` has been prepared on `self.db` and is
+        // finalized before returning.
+        unsafe {
+            let rc = sqlite3_step(stmt);
+            let counts = (
+                sqlite3_column_int64(stmt, 0) as u64,
+                sqlite3_column_int64(stmt, 1) as u64,
+            );
+            sqlite3_finalize(stmt);
+            if rc != SQLITE_ROW {
+                return Err(self.error());
+            }
+            Ok(counts)
+        }
+    }
+
+    /// Inserts a row in the open transaction, beginning one if needed.
+    /// If the insert fails, the transaction is rolled back unless it
+    /// holds pending rows, so a failure, e.g. because the database is
+    /// locked, doesn't leave an empty transaction behind.
+    fn insert(&mut self, stmt: *mut Sqlite3Stmt, params: &[Param]) -> io::Result<()> {
+        if !self.in_transaction {
+            self.exec("BEGIN")?;
+            self.in_transaction = true;
+        }
+        // SAFETY: `stmt` has been prepared on `self.db`.
+        let result = unsafe { self.bind_and_step(stmt, params) };
+        // SAFETY: as above; the statement is reset after failed binds
+        // and steps as well.
+        unsafe { sqlite3_reset(stmt) };
+        if let Err(e) = result {
+            if self.pending == 0 {
+                let _ = self.exec("ROLLBACK");
+            }
+            self.sync_transaction();
+            return Err(e);
+        }
+        self.pending += 1;
+        if self.pending >= self.batch_size {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Binds `params` to `stmt` and runs it.
+    ///
+    /// # Safety
+    ///
+    /// `stmt` must have been prepared on `self.db`. Text is copied by
+    /// SQLite, so the borrowed strings may go away after binding.
+    unsafe fn bind_and_step(&self, stmt: *mut Sqlite3Stmt, params: &[Param]) -> io::Result<()> {
+        for (i, param) in params.iter().enumerate() {
+            let index = i as c_int + 1;
+            let rc = match *param {
+                Param::Int(Some(v)) => sqlite3_bind_int64(stmt, index, v),
+                Param::Real(Some(v)) => sqlite3_bind_double(stmt, index, v),
+                Param::Text(Some(v)) => sqlite3_bind_text(
+                    stmt,
+                    index,
+                    v.as_ptr() as *const c_char,
+                    v.len() as c_int,
+                    sqlite_transient(),
+                ),
+                Param::Int(None) | Param::Real(None) | Param::Text(None) => {
+                    sqlite3_bind_null(stmt, index)
+                }
+            };
+            self.check(rc)?;
+        }
+        if sqlite3_step(stmt) != SQLITE_DONE {
+            return Err(self.error());
+        }
+        Ok(())
+    }
+
+    /// Takes over whether a transaction is open from SQLite, which
+    /// rolls back by itself on some errors, dropping the pending rows.
+    fn sync_transaction(&mut self) {
+        // SAFETY: `self.db` is an open connection.
+        self.in_transaction = unsafe { sqlite3_get_autocommit(self.db) } == 0;
+        if !self.in_transaction {
+            self.pending = 0;
+        }
+    }
+
+    fn exec(&mut self, sql: &str) -> io::Result<()> {
+        let sql = CString::new(sql).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        // SAFETY: `self.db` is an open connection, `sql` a C string.
+        let rc = unsafe {
+            sqlite3_exec(
+                self.db,
+                sql.as_ptr(),
+                ptr::null(),
+                ptr::null_mut(),
+                ptr::null_mut(),
+            )
+        };
+        self.check(rc)
+    }
+
+    fn prepare(&mut self, sql: &str) -> io::Result<*mut Sqlite3Stmt> {
+        let mut stmt = ptr::null_mut();
+        // SAFETY: `self.db` is an open connection; `sql` is passed with
+        // its length, so it needs no terminator.
+        let rc = unsafe {
+            sqlite3_prepare_v2(
+                self.db,
+                sql.as_ptr() as *const c_char,
+                sql.len() as c_int,
+                &mut stmt,
+                ptr::null_mut(),
+            )
+        };
+        self.check(rc)?;
+        Ok(stmt)
+    }
+
+    fn check(&self, rc: c_int) -> io::Result<()> {
+        if rc == SQLITE_OK {
+            Ok(())
+        } else {
+            Err(self.error())
+        }
+    }
+
+    fn error(&self) -> io::Error {
+        // SAFETY: `sqlite3_errmsg` returns a C string owned by SQLite,
+        // which is copied right away.
+        let msg = unsafe { CStr::from_ptr(sqlite3_errmsg(self.db)) };
+        io::Error::other(msg.to_string_lossy().into_owned())
+    }
+}
+
+impl Drop for TrackLogger {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush() {
+            warn!("Failed to commit track log: {}", e);
+        }
+        // SAFETY: the statements have been prepared on `self.db`,
+        // finalizing null pointers is a no-op. All statements are
+        // finalized before closing the connection.
+        unsafe {
+            sqlite3_finalize(self.insert_tpv);
+            sqlite3_finalize(self.insert_sky);
+            sqlite3_close(self.db);
+        }
+    }
+}
+
+fn unix_ms(time: std::time::SystemTime) -> Option<i64> {
+    i64::try_from(time.duration_since(UNIX_EPOCH).ok()?.as_millis()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TrackLogger;
+    use crate::UnifiedResponse;
+
+    #[test]
+    fn logs_track() {
+        let path = std::env::temp_dir().join(format!("gpsd_proto-{}.sqlite", std::process::id()));
+        let msg = |json: &str| serde_json::from_str::<UnifiedResponse>(json).unwrap();
+        {
+            let mut logger = TrackLogger::open(&path)
+                .unwrap()
+                .with_batch_size(2)
+                .with_fixes_only(true);
+            let tpv = msg(
+                r#"{"class":"TPV","device":"/dev/ttyS0","mode":3,"time":"2024-05-01T12:00:00.000Z","lat":48.1,"lon":11.5,"speed":1.5}"#,
+            );
+            assert!(logger.log(&tpv).unwrap());
+            assert!(!logger.log(&msg(r#"{"class":"TPV","mode":1}"#)).unwrap());
+            assert!(logger
+                .log(&msg(
+                    r#"{"class":"SKY","hdop":1.2,"satellites":[{"PRN":3,"used":true},{"PRN":5,"used":false}]}"#
+                ))
+                .unwrap());
+            assert!(!logger
+                .log(&msg(r#"{"class":"ERROR","message":"x"}"#))
+                .unwrap());
+            assert!(logger.log(&tpv).unwrap());
+            assert_eq!(logger.counts().unwrap(), (2, 1));
+        }
+        // The pending row has been committed on drop.
+        let mut logger = TrackLogger::open(&path).unwrap();
+        assert_eq!(logger.counts().unwrap(), (2, 1));
+
+        // Inserts failing on a database locked by another writer don't
+        // leave a transaction open.
+        let mut other = TrackLogger::open(&path).unwrap();
+        let sky = msg(r#"{"class":"SKY"}"#);
+        assert!(other.log(&sky).unwrap());
+        assert!(logger.log(&sky).is_err());
+        assert!(logger.log(&sky).is_err());
+        other.flush().unwrap();
+        assert!(logger.log(&sky).unwrap());
+        logger.flush().unwrap();
+        assert_eq!(logger.counts().unwrap(), (2, 3));
+        drop((logger, other));
+        for suffix in ["", "-wal", "-shm"] {
+            let mut file = path.clone().into_os_string();
+            file.push(suffix);
+            let _ = std::fs::remove_file(file);
+        }
+    }
+}
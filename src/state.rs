@@ -0,0 +1,193 @@
+//! Latest reports of each device.
+
+#[cfg(feature = "att")]
+use crate::Att;
+use crate::{CompactString, Device, Mode, Sky, Tpv, UnifiedResponse};
+use std::collections::BTreeMap;
+use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard};
+
+/// Latest reports of a single device, see [`GpsState`].
+#[derive(Debug, Clone, Default)]
+pub struct DeviceState {
+    pub device: Option<Box<Device>>,
+    pub tpv: Option<Box<Tpv>>,
+    pub sky: Option<Box<Sky>>,
+    #[cfg(feature = "att")]
+    pub att: Option<Box<Att>>,
+}
+
+/// Most recent TPV, SKY, ATT and DEVICE report per device path.
+///
+/// Feed every received message to [`GpsState::update`] and query the
+/// state whenever needed, e.g. to serve requests. Reports without
+/// device are kept under the path `""`.
+#[derive(Debug, Clone, Default)]
+pub struct GpsState {
+    devices: BTreeMap<CompactString, DeviceState>,
+}
+
+impl GpsState {
+    /// Creates an empty state.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records `msg` if it is one of the tracked reports, other
+    /// messages are ignored.
+    pub fn update<M: Into<UnifiedResponse>>(&mut self, msg: M) {
+        match msg.into() {
+            UnifiedResponse::Device(d) => {
+                let path = d.path.clone();
+                self.entry(path).device = Some(d);
+            }
+            UnifiedResponse::Tpv(t) => {
+                let path = t.device.clone();
+                self.entry(path).tpv = Some(t);
+            }
+            UnifiedResponse::Sky(s) => {
+                let path = s.device.clone();
+                self.entry(path).sky = Some(s);
+            }
+            #[cfg(feature = "att")]
+            UnifiedResponse::Att(a) => {
+                let path = a.device.clone();
+                self.entry(path).att = Some(a);
+            }
+            _ => (),
+        }
+    }
+
+    fn entry(&mut self, path: Option<CompactString>) -> &mut DeviceState {
+        self.devices.entry(path.unwrap_or_default()).or_default()
+    }
+
+    /// Paths of all devices reports have been received from.
+    pub fn devices(&self) -> impl Iterator<Item = &str> {
+        self.devices.keys().map(CompactString::as_str)
+    }
+
+    /// Latest reports of the device at `path`.
+    pub fn get(&self, path: &str) -> Option<&DeviceState> {
+        self.devices.get(path)
+    }
+
+    /// Latest TPV report of the device at `path`.
+    pub fn tpv(&self, path: &str) -> Option<&Tpv> {
+        self.get(path)?.tpv.as_deref()
+    }
+
+    /// Latest SKY report of the device at `path`.
+    pub fn sky(&self, path: &str) -> Option<&Sky> {
+        self.get(path)?.sky.as_deref()
+    }
+
+    /// Latest ATT report of the device at `path`.
+    #[cfg(feature = "att")]
+    pub fn att(&self, path: &str) -> Option<&Att> {
+        self.get(path)?.att.as_deref()
+    }
+
+    /// Latest DEVICE report of the device at `path`.
+    pub fn device(&self, path: &str) -> Option<&Device> {
+        self.get(path)?.device.as_deref()
+    }
+
+    /// Latitude and longitude of the device at `path` in degrees, if
+    /// its latest TPV report has a fix.
+    pub fn position(&self, path: &str) -> Option<(f64, f64)> {
+        let tpv = self.tpv(path)?;
+        match tpv.mode {
+            Mode::Fix2d | Mode::Fix3d => Some((tpv.lat?, tpv.lon?)),
+            _ => None,
+        }
+    }
+}
+
+/// [`GpsState`] shared between a reader thread and any number of
+/// consumer threads.
+///
+/// The handle is cheap to clone. The reader feeds messages with
+/// [`SharedGpsState::update_from`], consumers query the state with
+/// [`SharedGpsState::read`].
+#[derive(Debug, Clone, Default)]
+pub struct SharedGpsState {
+    state: Arc<RwLock<GpsState>>,
+}
+
+impl SharedGpsState {
+    /// Creates an empty state.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records `msg`, see [`GpsState::update`].
+    pub fn update_from<M: Into<UnifiedResponse>>(&self, msg: M) {
+        // Converting first keeps the lock as short as possible.
+        let msg = msg.into();
+        self.state
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .update(msg);
+    }
+
+    /// Locks the state for reading. Updates wait until the guard is
+    /// dropped, so don't hold on to it.
+    pub fn read(&self) -> RwLockReadGuard<'_, GpsState> {
+        // A panicking reader can't leave the state inconsistent.
+        self.state.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Copy of the current state.
+    pub fn snapshot(&self) -> GpsState {
+        self.read().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GpsState, SharedGpsState};
+    use crate::parse_lines;
+
+    #[test]
+    fn keeps_latest_per_device() {
+        let data = br#"{"class":"TPV","device":"/dev/a","mode":3,"lat":1.0,"lon":2.0}
+{"class":"TPV","device":"/dev/b","mode":3,"lat":3.0,"lon":4.0}
+{"class":"SKY","device":"/dev/a","hdop":1.5}
+{"class":"DEVICE","path":"/dev/a","driver":"u-blox"}
+{"class":"TPV","device":"/dev/b","mode":1}
+{"class":"TPV","mode":2,"lat":5.0,"lon":6.0}
+"#;
+        let mut state = GpsState::new();
+        for msg in parse_lines(data) {
+            state.update(msg.unwrap());
+        }
+        assert_eq!(
+            state.devices().collect::<Vec<_>>(),
+            ["", "/dev/a", "/dev/b"]
+        );
+        assert_eq!(state.position("/dev/a"), Some((1.0, 2.0)));
+        assert_eq!(state.position("/dev/b"), None);
+        assert!(state.tpv("/dev/b").is_some());
+        assert_eq!(state.position(""), Some((5.0, 6.0)));
+        assert_eq!(state.sky("/dev/a").unwrap().hdop, Some(1.5));
+        assert!(state.device("/dev/a").is_some());
+        assert!(state.get("/dev/c").is_none());
+    }
+
+    #[test]
+    fn shared_between_threads() {
+        let shared = SharedGpsState::new();
+        let feeder = shared.clone();
+        std::thread::spawn(move || {
+            for msg in
+                parse_lines(br#"{"class":"TPV","device":"/dev/a","mode":2,"lat":1.0,"lon":2.0}"#)
+            {
+                feeder.update_from(msg.unwrap());
+            }
+        })
+        .join()
+        .unwrap();
+        assert_eq!(shared.read().position("/dev/a"), Some((1.0, 2.0)));
+        assert!(shared.snapshot().tpv("/dev/a").is_some());
+    }
+}
@@ -0,0 +1,370 @@
+//! Mock `gpsd` for integration tests of applications.
+
+use crate::relay::{default_version, reply, Reply, EMPTY_DEVICES};
+use crate::Scenario;
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+enum Step {
+    Send(String),
+    Delay(Duration),
+}
+
+/// Scripted `gpsd` serving a single client over TCP or an in-memory
+/// connection, for tests without a running daemon.
+///
+/// The mock sends VERSION on connect and answers commands until the
+/// client enables the stream with `?WATCH`, which is answered by
+/// DEVICES and WATCH like `gpsd` does. Then the scripted lines are sent,
+/// paced by [`MockGpsd::with_interval`] and [`MockGpsd::delay`], and
+/// the connection is closed. Commands sent after the WATCH are
+/// recorded, but not answered.
+///
+/// ```
+/// use gpsd_proto::{GpsdClient, MockGpsd, UnifiedResponse};
+///
+/// let mock = MockGpsd::new()
+///     .send(r#"{"class":"TPV","mode":3,"lat":48.1,"lon":11.5}"#)
+///     .listen()
+///     .unwrap();
+/// let mut client = GpsdClient::connect(mock.local_addr().unwrap()).unwrap();
+/// client.handshake().unwrap();
+/// assert!(matches!(client.next_response(), Ok(UnifiedResponse::Tpv(_))));
+/// ```
+pub struct MockGpsd {
+    version: Vec<u8>,
+    devices: Vec<u8>,
+    interval: Duration,
+    script: Vec<Step>,
+}
+
+impl Default for MockGpsd {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockGpsd {
+    /// Creates a mock without devices and with an empty script.
+    pub fn new() -> Self {
+        MockGpsd {
+            version: default_version("gpsd_proto mock"),
+            devices: EMPTY_DEVICES.to_vec(),
+            interval: Duration::ZERO,
+            script: Vec::new(),
+        }
+    }
+
+    /// Sets the VERSION message sent on connect.
+    pub fn with_version(mut self, version: &str) -> Self {
+        self.version = line(version);
+        self
+    }
+
+    /// Sets the DEVICES message sent in reply to WATCH.
+    pub fn with_devices(mut self, devices: &str) -> Self {
+        self.devices = line(devices);
+        self
+    }
+
+    /// Waits `interval` before each scripted line.
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Appends the lines of `data`, one message per line, to the
+    /// script.
+    pub fn send(mut self, data: &str) -> Self {
+        self.script.extend(
+            data.lines()
+                .filter(|l| !l.trim().is_empty())
+                .map(|l| Step::Send(l.trim_end().to_owned())),
+        );
+        self
+    }
+
+    /// Appends the reports of `scenario` to the script, pausing for
+    /// its rate after each epoch.
+    pub fn scenario(mut self, scenario: &Scenario) -> Self {
+        for [tpv, sky] in scenario.epochs() {
+            self.script.push(Step::Send(tpv.to_string()));
+            self.script.push(Step::Send(sky.to_string()));
+            self.script.push(Step::Delay(scenario.rate()));
+        }
+        self
+    }
+
+    /// Appends a pause of `delay` to the script.
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.script.push(Step::Delay(delay));
+        self
+    }
+
+    /// Serves the first client connecting to a local TCP port in a
+    /// thread.
+    pub fn listen(self) -> io::Result<MockServer> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        let thread = thread::spawn(move || {
+            let (stream, _) = listener.accept()?;
+            let _ = stream.set_nodelay(true);
+            let reader = BufReader::new(stream.try_clone()?);
+            self.serve(reader, TcpWriter(stream))
+        });
+        Ok(MockServer {
+            addr: Some(addr),
+            thread,
+        })
+    }
+
+    /// Serves the returned in-memory connection in a thread.
+    pub fn duplex(self) -> (BufReader<MockReader>, MockWriter, MockServer) {
+        let (to_client, from_server) = pipe();
+        let (to_server, from_client) = pipe();
+        let thread = thread::spawn(move || self.serve(BufReader::new(from_client), to_client));
+        let server = MockServer { addr: None, thread };
+        (BufReader::new(from_server), to_server, server)
+    }
+
+    fn serve<R: BufRead, W: Write>(self, mut reader: R, mut writer: W) -> io::Result<Vec<String>> {
+        let mut commands = Vec::new();
+        writer.write_all(&self.version)?;
+        writer.flush()?;
+        let mut watching = false;
+        while !watching {
+            let mut cmd = String::new();
+            if reader.read_line(&mut cmd)? == 0 {
+                return Ok(commands);
+            }
+            match reply(&cmd) {
+                Reply::None => {}
+                Reply::Error(reply) => writer.write_all(reply.as_bytes())?,
+                Reply::Watch(enable, reply) => {
+                    writer.write_all(&self.devices)?;
+                    writer.write_all(reply.as_bytes())?;
+                    watching = enable;
+                }
+            }
+            writer.flush()?;
+            commands.push(cmd.trim().to_owned());
+        }
+
+        for step in &self.script {
+            match step {
+                Step::Send(data) => {
+                    thread::sleep(self.interval);
+                    writer.write_all(data.as_bytes())?;
+                    writer.write_all(b"\n")?;
+                    writer.flush()?;
+                }
+                Step::Delay(delay) => thread::sleep(*delay),
+            }
+        }
+        drop(writer);
+
+        // Record the remaining commands until the client hangs up.
+        for cmd in reader.lines() {
+            match cmd {
+                Ok(cmd) => commands.push(cmd.trim().to_owned()),
+                Err(_) => break,
+            }
+        }
+        Ok(commands)
+    }
+}
+
+/// Writing half of the TCP connection, closed when dropped while the
+/// reading half stays open.
+struct TcpWriter(TcpStream);
+
+impl Write for TcpWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl Drop for TcpWriter {
+    fn drop(&mut self) {
+        let _ = self.0.shutdown(Shutdown::Write);
+    }
+}
+
+fn line(data: &str) -> Vec<u8> {
+    let mut line = data.trim_end().as_bytes().to_vec();
+    line.push(b'\n');
+    line
+}
+
+/// Thread serving a [`MockGpsd`].
+pub struct MockServer {
+    addr: Option<SocketAddr>,
+    thread: JoinHandle<io::Result<Vec<String>>>,
+}
+
+impl MockServer {
+    /// Address to connect to, `None` for in-memory connections.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.addr
+    }
+
+    /// Waits until the client has disconnected and returns the
+    /// commands it sent, without line terminators.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the connection failed before the script was complete.
+    pub fn join(self) -> io::Result<Vec<String>> {
+        self.thread
+            .join()
+            .unwrap_or_else(|_| Err(io::Error::other("mock gpsd panicked")))
+    }
+}
+
+#[derive(Default)]
+struct Pipe {
+    data: VecDeque<u8>,
+    reader_closed: bool,
+    writer_closed: bool,
+}
+
+type SharedPipe = Arc<(Mutex<Pipe>, Condvar)>;
+
+fn pipe() -> (MockWriter, MockReader) {
+    let pipe = SharedPipe::default();
+    (MockWriter(pipe.clone()), MockReader(pipe))
+}
+
+/// Reading end of an in-memory connection, see [`MockGpsd::duplex`].
+/// Reads block until data is available or the writer is dropped.
+pub struct MockReader(SharedPipe);
+
+impl Read for MockReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let (pipe, ready) = &*self.0;
+        let mut pipe = pipe.lock().unwrap();
+        while pipe.data.is_empty() && !pipe.writer_closed {
+            pipe = ready.wait(pipe).unwrap();
+        }
+        let n = buf.len().min(pipe.data.len());
+        for (b, d) in buf.iter_mut().zip(pipe.data.drain(..n)) {
+            *b = d;
+        }
+        Ok(n)
+    }
+}
+
+impl Drop for MockReader {
+    fn drop(&mut self) {
+        self.0 .0.lock().unwrap().reader_closed = true;
+    }
+}
+
+/// Writing end of an in-memory connection, see [`MockGpsd::duplex`].
+/// Writes fail with `BrokenPipe` once the reader is dropped.
+pub struct MockWriter(SharedPipe);
+
+impl Write for MockWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let (pipe, ready) = &*self.0;
+        let mut pipe = pipe.lock().unwrap();
+        if pipe.reader_closed {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+        pipe.data.extend(buf);
+        ready.notify_all();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for MockWriter {
+    fn drop(&mut self) {
+        let (pipe, ready) = &*self.0;
+        pipe.lock().unwrap().writer_closed = true;
+        ready.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MockGpsd;
+    use crate::{GpsdClient, Scenario, UnifiedResponse, WatchOptions};
+    use std::time::{Duration, Instant, UNIX_EPOCH};
+
+    #[test]
+    fn serves_script() {
+        let script = concat!(
+            r#"{"class":"TPV","mode":3,"lat":48.1,"lon":11.5}"#,
+            "\n",
+            r#"{"class":"SKY","hdop":1.2}"#,
+            "\n",
+        );
+        let (reader, writer, mock) = MockGpsd::new()
+            .with_devices(r#"{"class":"DEVICES","devices":[{"path":"/dev/ttyS0"}]}"#)
+            .with_interval(Duration::from_millis(20))
+            .send(script)
+            .duplex();
+        assert!(mock.local_addr().is_none());
+        let start = Instant::now();
+        let mut client = GpsdClient::new(reader, writer);
+        client.handshake().unwrap();
+        assert!(matches!(
+            client.next_response(),
+            Ok(UnifiedResponse::Tpv(_))
+        ));
+        assert!(matches!(
+            client.next_response(),
+            Ok(UnifiedResponse::Sky(_))
+        ));
+        assert!(start.elapsed() >= Duration::from_millis(40));
+        assert!(client.next_response().is_err());
+        drop(client);
+        assert_eq!(
+            mock.join().unwrap(),
+            [WatchOptions::default().to_command().trim()]
+        );
+
+        let scenario = Scenario::new(UNIX_EPOCH)
+            .waypoint(48.1, 11.5)
+            .with_satellites(6)
+            .with_rate(Duration::from_millis(10));
+        let mock = MockGpsd::new()
+            .send(script)
+            .scenario(&scenario)
+            .listen()
+            .unwrap();
+        let mut client = GpsdClient::connect(mock.local_addr().unwrap()).unwrap();
+        client.handshake().unwrap();
+        assert!(matches!(
+            client.next_response(),
+            Ok(UnifiedResponse::Tpv(_))
+        ));
+        assert!(matches!(
+            client.next_response(),
+            Ok(UnifiedResponse::Sky(_))
+        ));
+        match client.next_response() {
+            Ok(UnifiedResponse::Tpv(tpv)) => assert_eq!(tpv.lat, Some(48.1)),
+            msg => panic!("unexpected {:?}", msg),
+        }
+        assert!(matches!(
+            client.next_response(),
+            Ok(UnifiedResponse::Sky(_))
+        ));
+        assert!(client.next_response().is_err());
+        drop(client);
+        assert_eq!(mock.join().unwrap().len(), 1);
+    }
+}
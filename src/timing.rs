@@ -0,0 +1,787 @@
+//! Offset of the system clock to GPS time from PPS and TOFF reports.
+//!
+//! Watch with `pps` enabled, see [`WatchOptions`](crate::WatchOptions),
+//! for `gpsd` to send these reports.
+
+#[cfg(feature = "osc")]
+use crate::Osc;
+use crate::{CompactString, Pps, Toff, Tpv, UnifiedResponse};
+use std::collections::{BTreeMap, VecDeque};
+#[cfg(feature = "osc")]
+use std::time::Instant;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Origin of a clock offset, see [`ClockOffset`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OffsetSource {
+    /// Derived from the PPS edge, precise to microseconds.
+    Pps,
+    /// Derived from the serial data stream, precise to milliseconds.
+    Toff,
+}
+
+/// Offset of the system clock of a single device.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ClockOffset {
+    /// Reports the offset is derived from.
+    pub source: OffsetSource,
+    /// Latest offset of the GPS time ahead of the system clock in
+    /// nanoseconds. Add it to the system time to get GPS derived UTC.
+    pub offset_ns: i64,
+    /// Rate at which the offset changes in parts per million, positive
+    /// if the system clock runs slow. Zero with a single sample.
+    pub drift_ppm: f64,
+    /// Root mean square deviation of the offsets from the drift line
+    /// in nanoseconds.
+    pub jitter_ns: f64,
+    /// Number of samples in the window.
+    pub samples: usize,
+    /// Latest difference of the PPS and the TOFF offset of the same
+    /// second in nanoseconds, i.e. the delay of the serial data.
+    pub toff_delay_ns: Option<i64>,
+}
+
+/// Samples of a single source, pairs of system clock time and offset
+/// in nanoseconds.
+#[derive(Debug, Clone, Default)]
+struct Samples(VecDeque<(i64, i64)>);
+
+impl Samples {
+    fn push(&mut self, clock_ns: i64, offset_ns: i64, len: usize) {
+        if self.0.len() == len {
+            self.0.pop_front();
+        }
+        self.0.push_back((clock_ns, offset_ns));
+    }
+
+    fn latest_clock(&self) -> Option<i64> {
+        self.0.back().map(|&(clock, _)| clock)
+    }
+
+    /// Least squares fit of the offsets over the clock time.
+    fn offset(&self, source: OffsetSource, toff_delay_ns: Option<i64>) -> Option<ClockOffset> {
+        let &(t0, offset_ns) = self.0.back()?;
+        let n = self.0.len() as f64;
+        // Relative to the latest sample, in seconds and nanoseconds.
+        let points = || {
+            self.0
+                .iter()
+                .map(move |&(t, o)| ((t - t0) as f64 * 1e-9, (o - offset_ns) as f64))
+        };
+        let (mean_t, mean_o) =
+            points().fold((0.0, 0.0), |(st, so), (t, o)| (st + t / n, so + o / n));
+        let (stt, sto) = points().fold((0.0, 0.0), |(stt, sto), (t, o)| {
+            let dt = t - mean_t;
+            (stt + dt * dt, sto + dt * (o - mean_o))
+        });
+        let slope = if stt > 0.0 { sto / stt } else { 0.0 };
+        let sq = points()
+            .map(|(t, o)| (o - mean_o - slope * (t - mean_t)).powi(2))
+            .sum::<f64>();
+        Some(ClockOffset {
+            source,
+            offset_ns,
+            drift_ppm: slope * 1e-3,
+            jitter_ns: (sq / n).sqrt(),
+            samples: self.0.len(),
+            toff_delay_ns,
+        })
+    }
+}
+
+/// Combined clock status of a single device, see
+/// [`ClockTracker::discipline`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ClockDiscipline {
+    /// The clock follows the GPS: the oscillator is disciplined or,
+    /// without OSC reports, PPS reports are arriving.
+    pub locked: bool,
+    /// The clock has been locked but lost its reference and runs
+    /// free.
+    pub holdover: bool,
+    /// Current offset of the system clock, see [`ClockOffset`].
+    pub offset: Option<ClockOffset>,
+    /// Latest sawtooth (quantization) error of the PPS edge in
+    /// picoseconds.
+    pub sawtooth_ps: Option<i64>,
+    /// Latest difference of the oscillator output pulse to the GPS
+    /// PPS in nanoseconds.
+    pub osc_delta_ns: Option<i64>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct DeviceClock {
+    pps: Samples,
+    toff: Samples,
+    last_pps: Option<(i64, i64)>,
+    last_toff: Option<(i64, i64)>,
+    /// Time of the latest TPV, PPS or TOFF report as Unix time in
+    /// seconds.
+    latest_sec: Option<i64>,
+    toff_delay_ns: Option<i64>,
+    q_err: Option<i64>,
+    precision: Option<f32>,
+    ept: Option<f32>,
+    #[cfg(feature = "osc")]
+    osc: Option<Osc>,
+    #[cfg(feature = "osc")]
+    was_disciplined: bool,
+}
+
+impl DeviceClock {
+    /// Checks whether PPS reports are arriving: the latest one is of at
+    /// most two seconds before the latest TPV, PPS or TOFF report.
+    fn pps_recent(&self) -> bool {
+        match (self.last_pps, self.latest_sec) {
+            (Some((pps_sec, _)), Some(latest)) => latest - pps_sec <= 2,
+            _ => false,
+        }
+    }
+
+    fn update_latest(&mut self, sec: i64) {
+        self.latest_sec = Some(self.latest_sec.map_or(sec, |latest| latest.max(sec)));
+    }
+
+    /// Locked and holdover state and oscillator delta, taken from OSC
+    /// reports if there are any.
+    fn status(&self) -> (bool, bool, Option<i64>) {
+        #[cfg(feature = "osc")]
+        if let Some(osc) = &self.osc {
+            return (
+                osc.running && osc.disciplined,
+                osc.running && !osc.disciplined && self.was_disciplined,
+                Some(osc.delta),
+            );
+        }
+        let pps_recent = self.pps_recent();
+        (
+            pps_recent,
+            !pps_recent && self.pps.latest_clock().is_some(),
+            None,
+        )
+    }
+
+    /// Pairs the latest PPS and TOFF report if they are of the same
+    /// GPS second.
+    fn pair(&mut self) {
+        if let (Some((pps_sec, pps)), Some((toff_sec, toff))) = (self.last_pps, self.last_toff) {
+            if pps_sec == toff_sec {
+                self.toff_delay_ns = Some(pps - toff);
+            }
+        }
+    }
+}
+
+/// Tracks the offset, drift and jitter of the system clock per device
+/// over a sliding window of PPS and TOFF reports.
+///
+/// PPS derived offsets are preferred; TOFF derived offsets are used
+/// while no recent PPS report is available. OSC reports of a GPSDO
+/// are taken into account for the [`ClockDiscipline`].
+#[derive(Debug, Clone)]
+pub struct ClockTracker {
+    window: usize,
+    devices: BTreeMap<CompactString, DeviceClock>,
+}
+
+impl ClockTracker {
+    /// Creates a tracker keeping the latest `window` reports of each
+    /// source and device.
+    pub fn new(window: usize) -> Self {
+        ClockTracker {
+            window: window.max(1),
+            devices: BTreeMap::new(),
+        }
+    }
+
+    /// Records `msg` if it is a PPS, TOFF, OSC or TPV report, other
+    /// messages are ignored.
+    pub fn update<M: Into<UnifiedResponse>>(&mut self, msg: M) {
+        match msg.into() {
+            UnifiedResponse::Tpv(t) => self.update_tpv(&t),
+            UnifiedResponse::Pps(p) => self.update_pps(&p),
+            UnifiedResponse::Toff(t) => self.update_toff(&t),
+            #[cfg(feature = "osc")]
+            UnifiedResponse::Osc(o) => self.update_osc(o),
+            _ => {}
+        }
+    }
+
+    /// Records an OSC report. Reports without device are kept under
+    /// the path `""`.
+    #[cfg(feature = "osc")]
+    pub fn update_osc(&mut self, osc: Osc) {
+        let path = osc.device.clone().unwrap_or_default();
+        let dev = self.devices.entry(path).or_default();
+        dev.was_disciplined |= osc.disciplined;
+        dev.osc = Some(osc);
+    }
+
+    /// Records the time and the time error `ept` of a TPV report, the
+    /// former to tell whether PPS reports are still arriving. Reports
+    /// without device are kept under the path `""`.
+    pub fn update_tpv(&mut self, tpv: &Tpv) {
+        let sec = tpv.time.and_then(|time| {
+            let unix = time.to_system_time().duration_since(UNIX_EPOCH).ok()?;
+            i64::try_from(unix.as_secs()).ok()
+        });
+        if tpv.ept.is_none() && sec.is_none() {
+            return;
+        }
+        let path = tpv.device.clone().unwrap_or_default();
+        let dev = self.devices.entry(path).or_default();
+        dev.ept = tpv.ept.or(dev.ept);
+        if let Some(sec) = sec {
+            dev.update_latest(sec);
+        }
+    }
+
+    /// Records a PPS report.
+    pub fn update_pps(&mut self, pps: &Pps) {
+        let dev = self.devices.entry(pps.device.clone()).or_default();
+        let clock = pps.clock_sec * 1_000_000_000 + pps.clock_nsec;
+        dev.pps.push(clock, pps.offset_ns(), self.window);
+        dev.last_pps = Some((pps.real_sec, pps.offset_ns()));
+        dev.update_latest(pps.real_sec);
+        dev.q_err = pps.q_err.or(dev.q_err);
+        dev.precision = Some(pps.precision);
+        dev.pair();
+    }
+
+    /// Records a TOFF report.
+    pub fn update_toff(&mut self, toff: &Toff) {
+        let dev = self.devices.entry(toff.device.clone()).or_default();
+        let clock = toff.clock_sec * 1_000_000_000 + toff.clock_nsec;
+        dev.toff.push(clock, toff.offset_ns(), self.window);
+        dev.last_toff = Some((toff.real_sec, toff.offset_ns()));
+        dev.update_latest(toff.real_sec);
+        dev.pair();
+    }
+
+    /// Current offset of the system clock according to the device at
+    /// `path`.
+    pub fn offset(&self, path: &str) -> Option<ClockOffset> {
+        let dev = self.devices.get(path)?;
+        if dev.pps_recent() {
+            dev.pps.offset(OffsetSource::Pps, dev.toff_delay_ns)
+        } else {
+            dev.toff.offset(OffsetSource::Toff, dev.toff_delay_ns)
+        }
+    }
+
+    /// Combined status of the clock of the device at `path`.
+    pub fn discipline(&self, path: &str) -> Option<ClockDiscipline> {
+        let dev = self.devices.get(path)?;
+        let (locked, holdover, osc_delta_ns) = dev.status();
+        Some(ClockDiscipline {
+            locked,
+            holdover,
+            offset: self.offset(path),
+            sawtooth_ps: dev.q_err,
+            osc_delta_ns,
+        })
+    }
+
+    /// Estimated error of the time derived from the device at `path`,
+    /// a conservative bound at about 95% confidence.
+    ///
+    /// While PPS reports arrive the error is the PPS precision,
+    /// otherwise the `ept` of the latest TPV report. Twice the jitter
+    /// of the offsets is added to either. Returns `None` if neither is
+    /// known.
+    pub fn estimated_time_error(&self, path: &str) -> Option<Duration> {
+        let dev = self.devices.get(path)?;
+        let base = match dev.precision {
+            Some(precision) if dev.pps_recent() => 2f64.powf(f64::from(precision)),
+            _ => f64::from(dev.ept?),
+        };
+        let jitter = self.offset(path).map_or(0.0, |o| o.jitter_ns * 1e-9);
+        Duration::try_from_secs_f64(base + 2.0 * jitter).ok()
+    }
+
+    /// Paths of all devices with reports.
+    pub fn devices(&self) -> impl Iterator<Item = &str> {
+        self.devices.keys().map(|k| k.as_str())
+    }
+}
+
+impl Default for ClockTracker {
+    fn default() -> Self {
+        Self::new(16)
+    }
+}
+
+/// Latency statistics over the window of a [`LatencyTracker`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct LatencyStats {
+    /// Latest latency.
+    pub last: Duration,
+    /// Smallest latency in the window.
+    pub min: Duration,
+    /// Largest latency in the window.
+    pub max: Duration,
+    /// Mean latency over the window.
+    pub mean: Duration,
+    /// Number of latencies in the window.
+    pub samples: usize,
+}
+
+/// Measures the delay of TPV reports from the time of the fix to their
+/// reception, over a sliding window.
+///
+/// Growing latencies point to buffering between the receiver and the
+/// application. The host clock has to be synchronized for the
+/// measurement to be meaningful: reports seemingly from the future, or
+/// older than the limit, are rejected as implausible.
+#[derive(Debug, Clone)]
+pub struct LatencyTracker {
+    window: usize,
+    max_latency: Duration,
+    latencies: VecDeque<Duration>,
+    rejected: u64,
+}
+
+impl LatencyTracker {
+    /// Creates a tracker keeping the latest `window` latencies,
+    /// rejecting latencies above 10 s.
+    pub fn new(window: usize) -> Self {
+        LatencyTracker {
+            window: window.max(1),
+            max_latency: Duration::from_secs(10),
+            latencies: VecDeque::new(),
+            rejected: 0,
+        }
+    }
+
+    /// Sets the largest plausible latency.
+    pub fn with_max_latency(mut self, max_latency: Duration) -> Self {
+        self.max_latency = max_latency;
+        self
+    }
+
+    /// Measures a report received just now, see
+    /// [`LatencyTracker::observe_at`].
+    pub fn observe(&mut self, tpv: &Tpv) -> Option<Duration> {
+        self.observe_at(tpv, SystemTime::now())
+    }
+
+    /// Measures a report received at the wall clock time `received`.
+    /// Returns `None` for reports without time or with an implausible
+    /// latency.
+    pub fn observe_at(&mut self, tpv: &Tpv, received: SystemTime) -> Option<Duration> {
+        let time = tpv.time?.to_system_time();
+        let latency = match received.duration_since(time) {
+            Ok(latency) if latency <= self.max_latency => latency,
+            _ => {
+                self.rejected += 1;
+                return None;
+            }
+        };
+        if self.latencies.len() == self.window {
+            self.latencies.pop_front();
+        }
+        self.latencies.push_back(latency);
+        Some(latency)
+    }
+
+    /// Statistics of the window, `None` if empty.
+    pub fn stats(&self) -> Option<LatencyStats> {
+        let last = *self.latencies.back()?;
+        let samples = self.latencies.len();
+        Some(LatencyStats {
+            last,
+            min: self.latencies.iter().copied().min()?,
+            max: self.latencies.iter().copied().max()?,
+            mean: self.latencies.iter().sum::<Duration>() / samples as u32,
+            samples,
+        })
+    }
+
+    /// Number of reports rejected as implausible.
+    pub fn rejected(&self) -> u64 {
+        self.rejected
+    }
+}
+
+impl Default for LatencyTracker {
+    fn default() -> Self {
+        Self::new(16)
+    }
+}
+
+/// Alert of an [`OscMonitor`].
+#[cfg(feature = "osc")]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OscAlert {
+    /// The oscillator stopped receiving the GPS PPS.
+    ReferenceLost,
+    /// The oscillator receives the GPS PPS again.
+    ReferenceRestored,
+    /// The oscillator pulse deviates from the GPS PPS by more than the
+    /// limit, in nanoseconds.
+    DeltaExceeded(i64),
+    /// The oscillator has not been disciplined for this long.
+    Undisciplined(Duration),
+}
+
+/// Watches the OSC reports of a GPS-disciplined oscillator and raises
+/// alerts on changes of its state.
+///
+/// Every alert is raised once when its condition starts; it is raised
+/// again only after the condition has ended in between.
+#[cfg(feature = "osc")]
+#[derive(Debug, Clone)]
+pub struct OscMonitor {
+    max_delta_ns: i64,
+    max_undisciplined: Duration,
+    reference: Option<bool>,
+    delta_exceeded: bool,
+    undisciplined_since: Option<Instant>,
+    undisciplined_alerted: bool,
+}
+
+#[cfg(feature = "osc")]
+impl OscMonitor {
+    /// Creates a monitor alerting on a delta of more than 100 ns and
+    /// after 60 s without discipline.
+    pub fn new() -> Self {
+        OscMonitor {
+            max_delta_ns: 100,
+            max_undisciplined: Duration::from_secs(60),
+            reference: None,
+            delta_exceeded: false,
+            undisciplined_since: None,
+            undisciplined_alerted: false,
+        }
+    }
+
+    /// Sets the largest tolerated delta in nanoseconds, either sign.
+    pub fn with_max_delta(mut self, max_delta_ns: i64) -> Self {
+        self.max_delta_ns = max_delta_ns;
+        self
+    }
+
+    /// Sets how long the oscillator may be undisciplined before an
+    /// alert is raised, e.g. during warm-up.
+    pub fn with_max_undisciplined(mut self, max_undisciplined: Duration) -> Self {
+        self.max_undisciplined = max_undisciplined;
+        self
+    }
+
+    /// Checks a report received just now, see
+    /// [`OscMonitor::update_at`].
+    pub fn update(&mut self, osc: &Osc) -> Vec<OscAlert> {
+        self.update_at(osc, Instant::now())
+    }
+
+    /// Checks a report received at `received` and returns the alerts
+    /// raised by it.
+    pub fn update_at(&mut self, osc: &Osc, received: Instant) -> Vec<OscAlert> {
+        let mut alerts = Vec::new();
+        match self.reference.replace(osc.reference) {
+            Some(true) if !osc.reference => alerts.push(OscAlert::ReferenceLost),
+            Some(false) if osc.reference => alerts.push(OscAlert::ReferenceRestored),
+            None if !osc.reference => alerts.push(OscAlert::ReferenceLost),
+            _ => {}
+        }
+
+        let exceeded = osc.delta.abs() > self.max_delta_ns;
+        if exceeded && !self.delta_exceeded {
+            alerts.push(OscAlert::DeltaExceeded(osc.delta));
+        }
+        self.delta_exceeded = exceeded;
+
+        if osc.disciplined {
+            self.undisciplined_since = None;
+            self.undisciplined_alerted = false;
+        } else {
+            let since = *self.undisciplined_since.get_or_insert(received);
+            let duration = received.saturating_duration_since(since);
+            if duration > self.max_undisciplined && !self.undisciplined_alerted {
+                self.undisciplined_alerted = true;
+                alerts.push(OscAlert::Undisciplined(duration));
+            }
+        }
+        alerts
+    }
+}
+
+#[cfg(feature = "osc")]
+impl Default for OscMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Leap second announced by the GPS navigation message.
+#[cfg(feature = "subframe")]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct LeapSecond {
+    /// Current offset of GPS time ahead of UTC in seconds.
+    pub current: i32,
+    /// Offset after the leap second.
+    pub future: i32,
+    /// GPS week of the leap second, modulo 256.
+    pub week: u8,
+    /// Day of the week, 1 (Sunday) to 7, at whose end the leap second
+    /// occurs.
+    pub day: u8,
+}
+
+#[cfg(feature = "subframe")]
+impl LeapSecond {
+    /// Number of seconds inserted, negative if deleted.
+    pub fn change(&self) -> i32 {
+        self.future - self.current
+    }
+}
+
+/// UTC parameters of subframe 4, page 18.
+#[cfg(feature = "subframe")]
+#[derive(Deserialize)]
+struct Iono {
+    ls: i32,
+    lsf: i32,
+    #[serde(rename = "WNlsf")]
+    wnlsf: u32,
+    #[serde(rename = "DN")]
+    dn: u8,
+}
+
+#[cfg(feature = "subframe")]
+#[derive(Deserialize)]
+struct UtcSubframe {
+    #[serde(rename = "IONO")]
+    iono: Option<Iono>,
+}
+
+/// Detects upcoming leap seconds in the UTC parameters of SUBFRAME
+/// reports.
+///
+/// `gpsd` only sends SUBFRAME reports of receivers which provide the
+/// raw navigation message, and for many only if watched with `raw`.
+#[cfg(feature = "subframe")]
+#[derive(Debug, Clone, Default)]
+pub struct LeapSecondWatch {
+    pending: Option<LeapSecond>,
+}
+
+#[cfg(feature = "subframe")]
+impl LeapSecondWatch {
+    /// Creates a watch without pending leap second.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The announced leap second, if any.
+    pub fn pending(&self) -> Option<LeapSecond> {
+        self.pending
+    }
+
+    /// Checks `msg` for UTC parameters and returns the leap second when
+    /// it is announced for the first time.
+    pub fn update(&mut self, msg: &UnifiedResponse) -> Option<LeapSecond> {
+        let UnifiedResponse::Subframe(raw) = msg else {
+            return None;
+        };
+        let iono = serde_json::from_str::<UtcSubframe>(raw.get()).ok()?.iono?;
+        let leap = (iono.lsf != iono.ls).then_some(LeapSecond {
+            current: iono.ls,
+            future: iono.lsf,
+            week: (iono.wnlsf % 256) as u8,
+            day: iono.dn,
+        });
+        let previous = std::mem::replace(&mut self.pending, leap);
+        leap.filter(|l| previous != Some(*l))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ClockTracker, OffsetSource};
+    use crate::{parse_lines, UnifiedResponse};
+    use std::time::Duration;
+
+    fn msgs(data: &[u8]) -> impl Iterator<Item = UnifiedResponse> + '_ {
+        parse_lines(data).map(Result::unwrap)
+    }
+
+    #[test]
+    fn tracks_offset_and_drift() {
+        let mut tracker = ClockTracker::new(8);
+        // The system clock runs 10 ppm slow, i.e. falls behind by
+        // 10 µs per second, with an offset of 2 ms. TOFF is 1 ms late.
+        for sec in 0..10i64 {
+            let line = format!(
+                "{{\"class\":\"TOFF\",\"device\":\"/dev/ttyS0\",\"real_sec\":{},\"real_nsec\":0,\"clock_sec\":{},\"clock_nsec\":{}}}\n\
+                 {{\"class\":\"PPS\",\"device\":\"/dev/ttyS0\",\"real_sec\":{},\"real_nsec\":0,\"clock_sec\":{},\"clock_nsec\":{},\"precision\":-20}}\n",
+                1000 + sec,
+                999 + sec,
+                999_000_000 - sec * 10_000 + if sec % 2 == 0 { 40_000 } else { 0 },
+                1000 + sec,
+                999 + sec,
+                998_000_000 - sec * 10_000,
+            );
+            msgs(line.as_bytes()).for_each(|m| tracker.update(m));
+        }
+        let offset = tracker.offset("/dev/ttyS0").unwrap();
+        assert_eq!(offset.source, OffsetSource::Pps);
+        assert_eq!(offset.offset_ns, 2_000_000 + 9 * 10_000);
+        assert!(
+            (offset.drift_ppm - 10.0).abs() < 1e-3,
+            "{}",
+            offset.drift_ppm
+        );
+        assert!(offset.jitter_ns < 1.0);
+        assert_eq!(offset.samples, 8);
+        assert_eq!(offset.toff_delay_ns, Some(1_000_000));
+        assert_eq!(tracker.devices().collect::<Vec<_>>(), ["/dev/ttyS0"]);
+
+        // Without PPS the TOFF reports are used.
+        for sec in 10..13i64 {
+            let line = format!(
+                "{{\"class\":\"TOFF\",\"device\":\"/dev/ttyS0\",\"real_sec\":{},\"real_nsec\":0,\"clock_sec\":{},\"clock_nsec\":0}}\n",
+                1000 + sec,
+                999 + sec,
+            );
+            msgs(line.as_bytes()).for_each(|m| tracker.update(m));
+        }
+        let offset = tracker.offset("/dev/ttyS0").unwrap();
+        assert_eq!(offset.source, OffsetSource::Toff);
+        assert_eq!(offset.offset_ns, 1_000_000_000);
+        assert!(offset.jitter_ns > 0.0);
+        assert_eq!(tracker.offset("/dev/ttyS1"), None);
+    }
+
+    #[cfg(feature = "osc")]
+    #[test]
+    fn reports_discipline() {
+        let mut tracker = ClockTracker::default();
+        let data = b"{\"class\":\"PPS\",\"device\":\"/dev/ttyS0\",\"real_sec\":1000,\"real_nsec\":0,\"clock_sec\":1000,\"clock_nsec\":500,\"precision\":-20,\"qErr\":-1234}
+{\"class\":\"OSC\",\"device\":\"/dev/ttyS0\",\"running\":true,\"reference\":true,\"disciplined\":true,\"delta\":-12}
+";
+        msgs(data).for_each(|m| tracker.update(m));
+        let d = tracker.discipline("/dev/ttyS0").unwrap();
+        assert!(d.locked && !d.holdover);
+        assert_eq!(d.offset.unwrap().offset_ns, -500);
+        assert_eq!((d.sawtooth_ps, d.osc_delta_ns), (Some(-1234), Some(-12)));
+
+        let data = b"{\"class\":\"OSC\",\"device\":\"/dev/ttyS0\",\"running\":true,\"reference\":false,\"disciplined\":false,\"delta\":-40}\n";
+        msgs(data).for_each(|m| tracker.update(m));
+        let d = tracker.discipline("/dev/ttyS0").unwrap();
+        assert!(!d.locked && d.holdover);
+        assert_eq!(tracker.discipline("/dev/ttyS1"), None);
+    }
+
+    #[cfg(feature = "osc")]
+    #[test]
+    fn monitors_oscillator() {
+        use super::{OscAlert, OscMonitor};
+        use crate::Osc;
+        use std::time::{Duration, Instant};
+
+        let osc = |reference: bool, disciplined: bool, delta: i64| -> Osc {
+            serde_json::from_str(&format!(
+                r#"{{"running":true,"reference":{},"disciplined":{},"delta":{}}}"#,
+                reference, disciplined, delta
+            ))
+            .unwrap()
+        };
+        let mut monitor = OscMonitor::new().with_max_undisciplined(Duration::from_secs(2));
+        let start = Instant::now();
+        let at = |sec: u64| start + Duration::from_secs(sec);
+        assert!(monitor.update_at(&osc(true, true, 5), at(0)).is_empty());
+        assert_eq!(
+            monitor.update_at(&osc(false, false, 500), at(1)),
+            [OscAlert::ReferenceLost, OscAlert::DeltaExceeded(500)]
+        );
+        assert!(monitor.update_at(&osc(false, false, 600), at(2)).is_empty());
+        assert_eq!(
+            monitor.update_at(&osc(false, false, 600), at(4)),
+            [OscAlert::Undisciplined(Duration::from_secs(3))]
+        );
+        assert!(monitor.update_at(&osc(false, false, 600), at(5)).is_empty());
+        assert_eq!(
+            monitor.update_at(&osc(true, true, 0), at(6)),
+            [OscAlert::ReferenceRestored]
+        );
+    }
+
+    #[test]
+    fn estimates_time_error() {
+        let mut tracker = ClockTracker::default();
+        let tpv = b"{\"class\":\"TPV\",\"device\":\"/dev/ttyS0\",\"mode\":3,\"ept\":0.005}\n";
+        msgs(tpv).for_each(|m| tracker.update(m));
+        assert_eq!(
+            tracker.estimated_time_error("/dev/ttyS0"),
+            Some(Duration::from_millis(5))
+        );
+
+        let pps = b"{\"class\":\"PPS\",\"device\":\"/dev/ttyS0\",\"real_sec\":1000,\"real_nsec\":0,\"clock_sec\":1000,\"clock_nsec\":0,\"precision\":-20}\n";
+        msgs(pps).for_each(|m| tracker.update(m));
+        let error = tracker.estimated_time_error("/dev/ttyS0").unwrap();
+        assert_eq!(error.as_nanos(), 954);
+        assert_eq!(tracker.estimated_time_error("/dev/ttyS1"), None);
+
+        // The PPS reports stop while the TPV reports go on.
+        let tpv = b"{\"class\":\"TPV\",\"device\":\"/dev/ttyS0\",\"mode\":3,\"time\":\"1970-01-01T00:16:42.000Z\"}\n";
+        msgs(tpv).for_each(|m| tracker.update(m));
+        let error = tracker.estimated_time_error("/dev/ttyS0").unwrap();
+        assert_eq!(error.as_nanos(), 954);
+        let tpv = b"{\"class\":\"TPV\",\"device\":\"/dev/ttyS0\",\"mode\":3,\"time\":\"1970-01-01T00:16:43.000Z\"}\n";
+        msgs(tpv).for_each(|m| tracker.update(m));
+        assert_eq!(
+            tracker.estimated_time_error("/dev/ttyS0"),
+            Some(Duration::from_millis(5))
+        );
+        let d = tracker.discipline("/dev/ttyS0").unwrap();
+        assert!(!d.locked && d.holdover);
+    }
+
+    #[cfg(feature = "subframe")]
+    #[test]
+    fn detects_pending_leap_second() {
+        use super::{LeapSecond, LeapSecondWatch};
+
+        let data = br#"{"class":"SUBFRAME","device":"/dev/ttyS0","tSV":1,"TOW17":1,"frame":4,"scaled":true,"pageid":56,"IONO":{"a0":1.1e-08,"A1":0,"A0":0,"tot":405504,"WNt":2,"ls":18,"WNlsf":137,"DN":7,"lsf":19}}
+{"class":"SUBFRAME","device":"/dev/ttyS0","tSV":1,"TOW17":1,"frame":4,"scaled":true,"pageid":56,"IONO":{"ls":18,"WNlsf":137,"DN":7,"lsf":19}}
+{"class":"SUBFRAME","device":"/dev/ttyS0","tSV":1,"frame":5,"scaled":true}
+{"class":"SUBFRAME","device":"/dev/ttyS0","tSV":1,"frame":4,"IONO":{"ls":19,"WNlsf":137,"DN":7,"lsf":19}}
+"#;
+        let mut watch = LeapSecondWatch::new();
+        let events: Vec<_> = msgs(data).map(|m| watch.update(&m)).collect();
+        let leap = LeapSecond {
+            current: 18,
+            future: 19,
+            week: 137,
+            day: 7,
+        };
+        assert_eq!(events, [Some(leap), None, None, None]);
+        assert_eq!(leap.change(), 1);
+        assert_eq!(watch.pending(), None);
+    }
+
+    #[test]
+    fn measures_latency() {
+        use super::LatencyTracker;
+        use crate::Tpv;
+
+        let tpv: Tpv =
+            serde_json::from_str(r#"{"mode":3,"time":"2024-01-01T00:00:00.000Z"}"#).unwrap();
+        let time = tpv.time.unwrap().to_system_time();
+        let mut tracker = LatencyTracker::new(2);
+        let ms = Duration::from_millis;
+        assert_eq!(tracker.observe_at(&tpv, time + ms(300)), Some(ms(300)));
+        assert_eq!(tracker.observe_at(&tpv, time - ms(1)), None);
+        assert_eq!(tracker.observe_at(&tpv, time + ms(11_000)), None);
+        assert_eq!(tracker.observe_at(&tpv, time + ms(100)), Some(ms(100)));
+        assert_eq!(tracker.observe_at(&tpv, time + ms(200)), Some(ms(200)));
+        let stats = tracker.stats().unwrap();
+        assert_eq!(
+            (stats.last, stats.min, stats.max),
+            (ms(200), ms(100), ms(200))
+        );
+        assert_eq!((stats.mean, stats.samples), (ms(150), 2));
+        assert_eq!(tracker.rejected(), 2);
+    }
+}
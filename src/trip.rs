@@ -0,0 +1,242 @@
+//! Trip odometer fed by TPV reports.
+
+use crate::geo::distance_m;
+use crate::{Iso8601Timestamp, Mode, Tpv};
+use std::time::Duration;
+
+/// Last accepted fix of a trip.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+struct TripPoint {
+    time: Iso8601Timestamp,
+    lat: f64,
+    lon: f64,
+}
+
+/// Distance, moving time, speed and altitude statistics of a trip.
+///
+/// Feed every TPV report to [`TripStats::update`]. Fixes whose
+/// horizontal error exceeds the limit are ignored, as is the altitude
+/// of fixes with a too large vertical error. Distance and time only
+/// accumulate while moving, so that the position jitter of a standing
+/// receiver does not add up. With the `serialize` feature a trip can
+/// be stored and resumed later; the gating limits are not part of the
+/// stored state and are reset to their defaults.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct TripStats {
+    /// Distance traveled in meters.
+    pub distance_m: f64,
+    /// Time spent moving.
+    pub moving_time: Duration,
+    /// Maximum speed in m/s.
+    pub max_speed: f64,
+    /// Accumulated climb in meters.
+    pub ascent_m: f64,
+    /// Accumulated descent in meters.
+    pub descent_m: f64,
+    last: Option<TripPoint>,
+    alt_ref: Option<f64>,
+    #[serde(skip, default = "TripLimits::default")]
+    limits: TripLimits,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct TripLimits {
+    max_eph: f64,
+    max_epv: f64,
+    min_speed: f64,
+    alt_threshold: f64,
+}
+
+impl Default for TripLimits {
+    fn default() -> Self {
+        TripLimits {
+            max_eph: 25.0,
+            max_epv: 15.0,
+            min_speed: 0.5,
+            alt_threshold: 3.0,
+        }
+    }
+}
+
+impl TripStats {
+    /// Creates an empty trip.
+    pub fn new() -> Self {
+        TripStats {
+            distance_m: 0.0,
+            moving_time: Duration::ZERO,
+            max_speed: 0.0,
+            ascent_m: 0.0,
+            descent_m: 0.0,
+            last: None,
+            alt_ref: None,
+            limits: TripLimits::default(),
+        }
+    }
+
+    /// Sets the largest horizontal error in meters of fixes taken
+    /// into account, 25 m by default. The error is `eph` or the larger
+    /// of `epx` and `epy`; fixes without an error estimate are
+    /// accepted.
+    pub fn with_max_eph(mut self, max_eph: f64) -> Self {
+        self.limits.max_eph = max_eph;
+        self
+    }
+
+    /// Sets the largest vertical error `epv` in meters of altitudes
+    /// taken into account, 15 m by default.
+    pub fn with_max_epv(mut self, max_epv: f64) -> Self {
+        self.limits.max_epv = max_epv;
+        self
+    }
+
+    /// Sets the speed in m/s from which on the receiver is considered
+    /// moving, 0.5 m/s by default.
+    pub fn with_min_speed(mut self, min_speed: f64) -> Self {
+        self.limits.min_speed = min_speed;
+        self
+    }
+
+    /// Sets the altitude change in meters required before it counts as
+    /// ascent or descent, 3 m by default. This keeps altitude noise
+    /// from adding up.
+    pub fn with_alt_threshold(mut self, alt_threshold: f64) -> Self {
+        self.limits.alt_threshold = alt_threshold;
+        self
+    }
+
+    /// Average speed while moving in m/s.
+    pub fn avg_speed(&self) -> f64 {
+        let secs = self.moving_time.as_secs_f64();
+        if secs > 0.0 {
+            self.distance_m / secs
+        } else {
+            0.0
+        }
+    }
+
+    /// Clears the statistics, keeping the limits.
+    pub fn reset(&mut self) {
+        let limits = self.limits.clone();
+        *self = TripStats {
+            limits,
+            ..TripStats::new()
+        };
+    }
+
+    /// Adds `tpv` to the trip. Returns `false` if the report is
+    /// ignored because it has no fix, no time or a too large error.
+    pub fn update(&mut self, tpv: &Tpv) -> bool {
+        if !matches!(tpv.mode, Mode::Fix2d | Mode::Fix3d) {
+            return false;
+        }
+        let (Some(time), Some(lat), Some(lon)) = (tpv.time, tpv.lat, tpv.lon) else {
+            return false;
+        };
+        let eph = tpv
+            .eph
+            .or_else(|| Some(tpv.epx?.max(tpv.epy?)))
+            .map(f64::from);
+        if eph.is_some_and(|e| e > self.limits.max_eph) {
+            return false;
+        }
+
+        let point = TripPoint { time, lat, lon };
+        if let Some(last) = self.last.replace(point) {
+            let dt = time
+                .to_system_time()
+                .duration_since(last.time.to_system_time())
+                .unwrap_or_default();
+            let d = distance_m((last.lat, last.lon), (lat, lon)).unwrap_or_default();
+            let speed = match tpv.speed {
+                Some(v) => f64::from(v),
+                None if dt > Duration::ZERO => d / dt.as_secs_f64(),
+                None => 0.0,
+            };
+            if speed >= self.limits.min_speed {
+                self.distance_m += d;
+                self.moving_time += dt;
+                self.max_speed = self.max_speed.max(speed);
+            }
+        }
+
+        if tpv.mode == Mode::Fix3d && tpv.epv.is_none_or(|e| f64::from(e) <= self.limits.max_epv) {
+            if let Some(alt) = tpv.alt_msl.or(tpv.alt).or(tpv.alt_hae) {
+                self.update_alt(f64::from(alt));
+            }
+        }
+        true
+    }
+
+    fn update_alt(&mut self, alt: f64) {
+        let Some(alt_ref) = self.alt_ref else {
+            self.alt_ref = Some(alt);
+            return;
+        };
+        let diff = alt - alt_ref;
+        if diff.abs() >= self.limits.alt_threshold {
+            if diff > 0.0 {
+                self.ascent_m += diff;
+            } else {
+                self.descent_m -= diff;
+            }
+            self.alt_ref = Some(alt);
+        }
+    }
+}
+
+impl Default for TripStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TripStats;
+    use crate::Tpv;
+
+    fn tpv(sec: u32, lat: f64, alt: f32) -> Tpv {
+        serde_json::from_str(&format!(
+            r#"{{"mode":3,"time":"2024-05-01T12:00:{:02}Z","lat":{},"lon":8.0,"altMSL":{},"eph":5,"epv":8}}"#,
+            sec, lat, alt
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn accumulates_trip() {
+        let mut trip = TripStats::new();
+        // 0.0001° of latitude are about 11 m.
+        assert!(trip.update(&tpv(0, 50.0, 100.0)));
+        assert!(trip.update(&tpv(1, 50.0001, 101.0)));
+        assert!(trip.update(&tpv(2, 50.0002, 104.0)));
+        // Standing still, jitter does not count.
+        assert!(trip.update(&tpv(3, 50.000_200_1, 104.0)));
+        let mut noisy = tpv(4, 50.1, 90.0);
+        noisy.eph = Some(100.0);
+        assert!(!trip.update(&noisy));
+        assert!(trip.update(&tpv(5, 50.0003, 99.0)));
+
+        assert!((trip.distance_m - 33.4).abs() < 0.5, "{}", trip.distance_m);
+        assert_eq!(trip.moving_time.as_secs(), 4);
+        assert!((trip.max_speed - 11.1).abs() < 0.2, "{}", trip.max_speed);
+        assert!((trip.avg_speed() - 8.35).abs() < 0.2);
+        assert_eq!(trip.ascent_m, 4.0);
+        assert_eq!(trip.descent_m, 5.0);
+    }
+
+    #[cfg(feature = "serialize")]
+    #[test]
+    fn resumes_serialized_trip() {
+        let mut trip = TripStats::new();
+        trip.update(&tpv(0, 50.0, 100.0));
+        trip.update(&tpv(1, 50.0001, 100.0));
+        let json = serde_json::to_string(&trip).unwrap();
+        let mut resumed: TripStats = serde_json::from_str(&json).unwrap();
+        assert_eq!(resumed, trip);
+        resumed.update(&tpv(2, 50.0002, 100.0));
+        assert!(resumed.distance_m > trip.distance_m);
+    }
+}